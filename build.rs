@@ -0,0 +1,47 @@
+// generates `wasmertest.h` for the c interface into `OUT_DIR` when building
+// with feature `ffi`, see `src/ffi.rs`. the checked-in `include/wasmertest.h`
+// is only updated when `WASMERTEST_HEADER_DIR` names the directory to copy to
+fn main() {
+  println!("cargo:rerun-if-changed=build.rs");
+  #[cfg(feature = "ffi")]
+  generate_header();
+}
+
+#[cfg(feature = "ffi")]
+fn generate_header() {
+  let crate_dir = std::env::var("CARGO_MANIFEST_DIR").unwrap();
+  let out_dir = std::env::var("OUT_DIR").unwrap();
+  println!("cargo:rerun-if-changed=src/ffi.rs");
+  println!("cargo:rerun-if-changed=cbindgen.toml");
+  println!("cargo:rerun-if-env-changed=WASMERTEST_HEADER_DIR");
+
+  let config = cbindgen::Config::from_file(format!("{}/cbindgen.toml", crate_dir)).unwrap();
+  let bindings = cbindgen::Builder::new()
+    .with_config(config)
+    .with_src(format!("{}/src/ffi.rs", crate_dir))
+    .generate();
+  let header = format!("{}/wasmertest.h", out_dir);
+  match bindings {
+    Ok(bindings) => {
+      bindings.write_to_file(&header);
+    }
+    Err(error) => {
+      println!("cargo:warning=generating wasmertest.h failed: {}", error);
+      return;
+    }
+  }
+
+  let target_dir = match std::env::var("WASMERTEST_HEADER_DIR") {
+    Ok(dir) => dir,
+    Err(_) => return,
+  };
+  let target = std::path::Path::new(&target_dir).join("wasmertest.h");
+  if let Err(error) =
+    std::fs::create_dir_all(&target_dir).and_then(|_| std::fs::copy(&header, &target))
+  {
+    println!(
+      "cargo:warning=copying wasmertest.h to {} failed: {}",
+      target_dir, error
+    );
+  }
+}
@@ -0,0 +1,21 @@
+import "wasi";
+
+// strings are passed with `StringAbi::Utf8ArrayBuffer`, the host allocates
+// the buffers with `malloc` and reads the byte length from the runtime header
+
+let config = "";
+
+export function malloc(length: u32): ArrayBuffer {
+  return new ArrayBuffer(length);
+}
+
+export function init(configBuffer: ArrayBuffer): void {
+  config = String.UTF8.decode(configBuffer);
+}
+
+export function transform(keyBuffer: ArrayBuffer, payloadBuffer: ArrayBuffer): ArrayBuffer {
+  const key = String.UTF8.decode(keyBuffer);
+  const payload = String.UTF8.decode(payloadBuffer);
+
+  return String.UTF8.encode("transform: " + key + " for payload " + payload);
+}
@@ -0,0 +1,6 @@
+#!/bin/sh
+# builds the AssemblyScript example guest (needs `npm install` in this directory)
+set -e
+cd "$(dirname "$0")"
+mkdir -p build
+npx --no-install asc assembly/index.ts --runtime minimal --exportRuntime --explicitStart --optimizeLevel 3 --binaryFile build/plugin.wasm
@@ -0,0 +1,7 @@
+#!/bin/sh
+# builds the rust example guest (needs the `wasm32-wasi` target)
+set -e
+cd "$(dirname "$0")"
+mkdir -p build
+cargo build --target wasm32-wasi --release
+cp target/wasm32-wasi/release/rust_guest.wasm build/plugin.wasm
@@ -0,0 +1,16 @@
+// example guest for the rust abi profile, built by `build.sh`
+use std::sync::Mutex;
+
+use assemblytest_guest::plugin_export;
+
+static CONFIG: Mutex<String> = Mutex::new(String::new());
+
+#[plugin_export]
+fn init(config: &str) {
+  *CONFIG.lock().unwrap() = String::from(config);
+}
+
+#[plugin_export]
+fn transform(key: &str, payload: &str) -> String {
+  format!("transform: {} for payload {}", key, payload)
+}
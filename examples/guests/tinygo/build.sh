@@ -0,0 +1,6 @@
+#!/bin/sh
+# builds the TinyGo example guest as wasi reactor (needs TinyGo 0.33 or newer)
+set -e
+cd "$(dirname "$0")"
+mkdir -p build
+tinygo build -o build/plugin.wasm -target=wasi -buildmode=c-shared -scheduler=none -no-debug .
@@ -0,0 +1,3 @@
+module tinygoplugin
+
+go 1.21
@@ -0,0 +1,47 @@
+package main
+
+/*
+#include <stdlib.h>
+*/
+import "C"
+
+import (
+	"fmt"
+	"unsafe"
+)
+
+// strings are passed with `StringAbi::Utf8LengthPrefixed`:
+// the pointer points behind a little endian u32 length,
+// the block itself was allocated with the exported `malloc`
+
+var config string
+
+func readString(ptr uint32) string {
+	length := *(*uint32)(unsafe.Pointer(uintptr(ptr - 4)))
+	return string(unsafe.Slice((*byte)(unsafe.Pointer(uintptr(ptr))), length))
+}
+
+// the host takes over the block and releases it with `free`
+func writeString(s string) uint32 {
+	block := C.malloc(C.size_t(len(s) + 4))
+	*(*uint32)(block) = uint32(len(s))
+	data := unsafe.Add(block, 4)
+	copy(unsafe.Slice((*byte)(data), len(s)), s)
+	return uint32(uintptr(data))
+}
+
+//export init
+func initPlugin(configPtr uint32) {
+	config = readString(configPtr)
+	fmt.Println(config)
+}
+
+//export transform
+func transform(keyPtr uint32, payloadPtr uint32) uint32 {
+	key := readString(keyPtr)
+	payload := readString(payloadPtr)
+	return writeString("transform: " + key + " for payload " + payload)
+}
+
+// not called in reactor mode
+func main() {}
@@ -0,0 +1,42 @@
+use std::process::Command;
+
+use wasmertest::plugin::compiler::{compile_to_file, CompileOptions};
+use wasmertest::plugin::default::DefaultPlugin;
+use wasmertest::plugin::profile::AbiProfile;
+use wasmertest::plugin::{Plugin, PluginOptions};
+
+// cargo run --example tinygo
+// builds the guest in `examples/guests/tinygo` with TinyGo and runs it with the tinygo abi profile
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+  let status = Command::new("./examples/guests/tinygo/build.sh").status()?;
+  if !status.success() {
+    panic!("building the TinyGo guest failed");
+  }
+
+  let plugin_file_name = String::from("./examples/guests/tinygo/build/plugin.so");
+  compile_to_file(
+    &String::from("./examples/guests/tinygo/build/plugin.wasm"),
+    &plugin_file_name,
+    &CompileOptions::new(),
+  )
+  .unwrap();
+
+  let mut options = PluginOptions::new(
+    &String::from("tinygo_plugin"),
+    &plugin_file_name,
+    &String::from("transform"),
+  );
+  options.apply_abi_profile(&AbiProfile::tinygo());
+
+  let plugin = DefaultPlugin::create(options).unwrap();
+  plugin.init(&String::from("Some input we have")).unwrap();
+
+  let result = plugin
+    .execute(
+      &String::from("/some/test"),
+      &String::from("{\"temperature\": 1 }"),
+    )
+    .unwrap();
+  println!("{}", result);
+  Ok(())
+}
@@ -0,0 +1,20 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use wasmertest::plugin::default::DefaultPlugin;
+use wasmertest::plugin::fuzz::fuzz_execute;
+use wasmertest::plugin::{Plugin, PluginOptions};
+
+// FUZZ_PLUGIN=./optimized.so cargo fuzz run execute
+thread_local! {
+  static PLUGIN: DefaultPlugin = {
+    let file = std::env::var("FUZZ_PLUGIN").unwrap_or_else(|_| String::from("../optimized.so"));
+    let options = PluginOptions::new(&String::from("fuzz"), &file, &String::from("transform"));
+    let plugin = DefaultPlugin::create(options).unwrap();
+    plugin.init(&String::new()).unwrap();
+    plugin
+  };
+}
+
+fuzz_target!(|data: &[u8]| {
+  PLUGIN.with(|plugin| fuzz_execute(plugin, data));
+});
@@ -0,0 +1,19 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use wasmertest::plugin::fuzz::fuzz_get_string;
+use wasmertest::plugin::string_abi::StringAbi;
+
+// first 4 bytes are the pointer, the rest is the guest memory
+fuzz_target!(|data: &[u8]| {
+  if data.len() < 4 {
+    return;
+  }
+  let ptr = u32::from_le_bytes([data[0], data[1], data[2], data[3]]);
+  for abi in [
+    StringAbi::Utf8ArrayBuffer,
+    StringAbi::Utf16String,
+    StringAbi::Utf8LengthPrefixed,
+  ] {
+    fuzz_get_string(&data[4..], ptr, abi);
+  }
+});
@@ -0,0 +1,7 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use wasmertest::plugin::fuzz::fuzz_manifest;
+
+fuzz_target!(|data: &[u8]| {
+  fuzz_manifest(data);
+});
@@ -0,0 +1,78 @@
+use proc_macro::TokenStream;
+use quote::{format_ident, quote};
+use syn::{parse_macro_input, FnArg, ItemFn, LitStr, ReturnType, Type};
+
+// exports a function with `&str` parameters and `()` or `String` result with
+// the string abi of the host, eg `fn transform(key: &str, payload: &str) -> String`
+// the export name is the function name unless given as `#[plugin_export("name")]`
+#[proc_macro_attribute]
+pub fn plugin_export(attr: TokenStream, item: TokenStream) -> TokenStream {
+  let function = parse_macro_input!(item as ItemFn);
+  let name = &function.sig.ident;
+  let export_name = if attr.is_empty() {
+    name.to_string()
+  } else {
+    parse_macro_input!(attr as LitStr).value()
+  };
+  let wrapper = format_ident!("__plugin_export_{}", name);
+
+  let mut params = vec![];
+  let mut args = vec![];
+  for (index, input) in function.sig.inputs.iter().enumerate() {
+    match input {
+      FnArg::Typed(arg) if is_str_ref(&arg.ty) => {
+        let param = format_ident!("ptr{}", index);
+        let value = format_ident!("arg{}", index);
+        params.push(quote! { #param: u32 });
+        args.push(quote! { #value });
+      }
+      _ => {
+        return syn::Error::new_spanned(input, "plugin exports only take `&str` parameters")
+          .to_compile_error()
+          .into()
+      }
+    }
+  }
+  let reads = (0..args.len()).map(|index| {
+    let param = format_ident!("ptr{}", index);
+    let value = format_ident!("arg{}", index);
+    quote! { let #value = unsafe { ::assemblytest_guest::read_string(#param) }; }
+  });
+  let borrowed = args.iter().map(|arg| quote! { &#arg });
+
+  let body = match &function.sig.output {
+    ReturnType::Default => quote! {
+      #name(#(#borrowed),*);
+    },
+    ReturnType::Type(_, _) => quote! {
+      let result: String = #name(#(#borrowed),*);
+      ::assemblytest_guest::write_string(&result)
+    },
+  };
+  let result = match &function.sig.output {
+    ReturnType::Default => quote! {},
+    ReturnType::Type(_, _) => quote! { -> u32 },
+  };
+
+  quote! {
+    #function
+
+    #[doc(hidden)]
+    #[export_name = #export_name]
+    pub extern "C" fn #wrapper(#(#params),*) #result {
+      #(#reads)*
+      #body
+    }
+  }
+  .into()
+}
+
+fn is_str_ref(ty: &Type) -> bool {
+  match ty {
+    Type::Reference(reference) => match reference.elem.as_ref() {
+      Type::Path(path) => path.path.is_ident("str"),
+      _ => false,
+    },
+    _ => false,
+  }
+}
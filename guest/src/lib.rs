@@ -0,0 +1,84 @@
+// guest side of the wasmertest plugin abi for plugins written in rust
+//
+// use assemblytest_guest::plugin_export;
+//
+// #[plugin_export]
+// fn init(config: &str) {}
+//
+// #[plugin_export]
+// fn transform(key: &str, payload: &str) -> String {
+//   format!("{} {}", key, payload)
+// }
+//
+// build with `cargo build --target wasm32-wasi --release` as `cdylib` and load
+// the plugin with `PluginOptions::apply_abi_profile(&AbiProfile::rust())`
+//
+// strings are passed length prefixed (`StringAbi::Utf8LengthPrefixed`):
+// the pointer points behind a little endian u32 byte length, the whole block
+// is allocated with `plugin_alloc` and released with `plugin_free`
+
+use std::alloc::{alloc, dealloc, Layout};
+
+pub use assemblytest_guest_macros::plugin_export;
+
+const HEADER_SIZE: usize = 4;
+
+fn layout(size: usize) -> Layout {
+  Layout::from_size_align(size.max(HEADER_SIZE), HEADER_SIZE).unwrap()
+}
+
+// called by the host with the byte length + 4, the host writes the length header
+#[no_mangle]
+pub extern "C" fn plugin_alloc(size: u32) -> u32 {
+  unsafe { alloc(layout(size as usize)) as u32 }
+}
+
+/// called by the host with the start of the block, so in front of the length header
+///
+/// # Safety
+/// `block` must be a block returned by `plugin_alloc` or `write_string`
+#[no_mangle]
+pub unsafe extern "C" fn plugin_free(block: u32) {
+  let length = *(block as *const u32) as usize;
+  dealloc(block as *mut u8, layout(length + HEADER_SIZE));
+}
+
+// the guest is built as library without `main`, so nothing to do here
+#[no_mangle]
+pub extern "C" fn _initialize() {}
+
+/// # Safety
+/// `ptr` must be a string pointer handed over by the host
+pub unsafe fn read_string(ptr: u32) -> String {
+  let length = *((ptr as usize - HEADER_SIZE) as *const u32) as usize;
+  let bytes = std::slice::from_raw_parts(ptr as *const u8, length);
+  String::from_utf8_lossy(bytes).into_owned()
+}
+
+// copies the string into a new block which is handed over to the host
+pub fn write_string(value: &str) -> u32 {
+  let bytes = value.as_bytes();
+  unsafe {
+    let block = alloc(layout(bytes.len() + HEADER_SIZE));
+    *(block as *mut u32) = bytes.len() as u32;
+    let data = block.add(HEADER_SIZE);
+    std::ptr::copy_nonoverlapping(bytes.as_ptr(), data, bytes.len());
+    data as u32
+  }
+}
+
+#[link(wasm_import_module = "custom")]
+extern "C" {
+  #[link_name = "raise_error"]
+  fn host_raise_error(code: i32, message: u32);
+}
+
+// fails the running call with `PluginError::GuestError` on the host, the
+// return value of the call is ignored afterwards
+pub fn raise_error(code: i32, message: &str) {
+  let ptr = write_string(message);
+  unsafe {
+    host_raise_error(code, ptr);
+    plugin_free(ptr - HEADER_SIZE as u32);
+  }
+}
@@ -0,0 +1,96 @@
+#ifndef WASMERTEST_H
+#define WASMERTEST_H
+
+/* generated by build.rs with cbindgen from src/ffi.rs, do not edit */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+#define PLUGIN_OK 0
+
+#define PLUGIN_ERROR_INVALID_ARGUMENT -1
+
+#define PLUGIN_ERROR_LOADING -2
+
+#define PLUGIN_ERROR_INIT -3
+
+#define PLUGIN_ERROR_EXECUTE -4
+
+#define PLUGIN_ERROR_GUEST -5
+
+#define PLUGIN_ERROR_TOO_LARGE -6
+
+#define PLUGIN_ERROR_RATE_LIMITED -7
+
+#define PLUGIN_ERROR_PANIC -8
+
+typedef struct WasmPlugin WasmPlugin;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+/**
+ * creates a plugin from a compiled artifact, `*out` is set on success and
+ * released with `plugin_destroy`
+ *
+ * # Safety
+ * the strings must be null terminated, `out` must be valid for writes
+ */
+int32_t plugin_create(const char *name,
+                      const char *file,
+                      const char *execute_function,
+                      struct WasmPlugin **out);
+
+/**
+ * calls the init function of the guest with the config
+ *
+ * # Safety
+ * `plugin` must come from `plugin_create`, `config` must be null terminated
+ */
+int32_t plugin_init(const struct WasmPlugin *plugin, const char *config);
+
+/**
+ * executes the plugin, `*result` is set to a null terminated string on
+ * success which is released with `plugin_free_string`
+ *
+ * # Safety
+ * `plugin` must come from `plugin_create`, `key` and `payload` must be null
+ * terminated, `result` must be valid for writes
+ */
+int32_t plugin_execute(const struct WasmPlugin *plugin,
+                       const char *key,
+                       const char *payload,
+                       char **result);
+
+/**
+ * releases a result of `plugin_execute`, null is ignored
+ *
+ * # Safety
+ * `value` must come from `plugin_execute` and must not be used afterwards
+ */
+void plugin_free_string(char *value);
+
+/**
+ * releases the plugin, null is ignored
+ *
+ * # Safety
+ * `plugin` must come from `plugin_create`, no call may run on it and it must
+ * not be used afterwards
+ */
+void plugin_destroy(struct WasmPlugin *plugin);
+
+/**
+ * message of the last failed call on this thread, null if there was none
+ * the pointer is valid until the next failing call on this thread
+ */
+const char *plugin_last_error(void);
+
+#ifdef __cplusplus
+} // extern "C"
+#endif // __cplusplus
+
+#endif /* WASMERTEST_H */
@@ -0,0 +1,181 @@
+use std::io::{stdin, stdout};
+use std::path::PathBuf;
+use std::process::exit;
+
+use wasmertest::logging::{LogFormat, LoggingBuilder};
+use wasmertest::plugin::manager::{DiscoveryOptions, PluginManager};
+use wasmertest::plugin::repl::Repl;
+use wasmertest::plugin::PluginOptions;
+
+const USAGE: &str = "usage:
+  assemblytest check <dir> [--recursive] [--extension so] [--execute transform] [--log-level warn]
+  assemblytest repl <module> [--execute transform] [--config <init config>] [--log-level warn]
+
+commands:
+  check   loads, instantiates and self tests every plugin of <dir> and prints
+          a json summary, exits with 1 if a plugin failed
+  repl    loads a .wasm, .wat or compiled module and executes the lines
+          `<key> <payload>` typed in, `:reload` picks up a rebuilt module";
+
+struct CheckArgs {
+  dir: PathBuf,
+  discovery: DiscoveryOptions,
+  log_level: String,
+}
+
+fn parse_check_args(args: &[String]) -> Result<CheckArgs, String> {
+  let mut dir = None;
+  let mut discovery = DiscoveryOptions::new();
+  let mut log_level = String::from("warn");
+  let mut args = args.iter();
+  while let Some(arg) = args.next() {
+    let mut value = |name: &str| match args.next() {
+      Some(value) => Ok(value.clone()),
+      None => Err(format!("missing value for {}", name)),
+    };
+    match arg.as_str() {
+      "--recursive" => {
+        discovery.set_recursive(true);
+      }
+      "--extension" => {
+        discovery.set_extension(&value(arg)?);
+      }
+      "--execute" => {
+        discovery.set_execute_function_name(&value(arg)?);
+      }
+      "--log-level" => log_level = value(arg)?,
+      flag if flag.starts_with("--") => return Err(format!("unknown option {}", flag)),
+      path if dir.is_none() => dir = Some(PathBuf::from(path)),
+      other => return Err(format!("unexpected argument {}", other)),
+    }
+  }
+  match dir {
+    Some(dir) => Ok(CheckArgs {
+      dir,
+      discovery,
+      log_level,
+    }),
+    None => Err(String::from("missing plugin directory")),
+  }
+}
+
+fn check(args: CheckArgs) -> i32 {
+  // logs go to stderr, stdout only carries the summary
+  let _logger = LoggingBuilder::new()
+    .set_level(&args.log_level)
+    .set_module_level(&String::from("wasmer_wasi::syscalls"), &String::from("off"))
+    .set_module_level(&String::from("wasmer_wasi::state"), &String::from("off"))
+    .set_format(LogFormat::Default)
+    .start()
+    .unwrap();
+
+  let manager = PluginManager::new();
+  let load = manager.load_dir(&args.dir, &args.discovery);
+  let report = manager.validate_all().with_load_report(&load);
+  println!("{}", report.to_json());
+  if report.passed() {
+    0
+  } else {
+    1
+  }
+}
+
+struct ReplArgs {
+  file: String,
+  execute: String,
+  config: String,
+  log_level: String,
+}
+
+fn parse_repl_args(args: &[String]) -> Result<ReplArgs, String> {
+  let mut file = None;
+  let mut execute = String::from("transform");
+  let mut config = String::new();
+  let mut log_level = String::from("warn");
+  let mut args = args.iter();
+  while let Some(arg) = args.next() {
+    let mut value = |name: &str| match args.next() {
+      Some(value) => Ok(value.clone()),
+      None => Err(format!("missing value for {}", name)),
+    };
+    match arg.as_str() {
+      "--execute" => execute = value(arg)?,
+      "--config" => config = value(arg)?,
+      "--log-level" => log_level = value(arg)?,
+      flag if flag.starts_with("--") => return Err(format!("unknown option {}", flag)),
+      path if file.is_none() => file = Some(String::from(path)),
+      other => return Err(format!("unexpected argument {}", other)),
+    }
+  }
+  match file {
+    Some(file) => Ok(ReplArgs {
+      file,
+      execute,
+      config,
+      log_level,
+    }),
+    None => Err(String::from("missing module file")),
+  }
+}
+
+fn repl(args: ReplArgs) -> i32 {
+  // guest output is printed by the repl below each result
+  let _logger = LoggingBuilder::new()
+    .set_level(&args.log_level)
+    .set_module_level(&String::from("wasm"), &String::from("off"))
+    .set_module_level(&String::from("wasmer_wasi::syscalls"), &String::from("off"))
+    .set_module_level(&String::from("wasmer_wasi::state"), &String::from("off"))
+    .set_format(LogFormat::Default)
+    .start()
+    .unwrap();
+
+  let name = PathBuf::from(&args.file)
+    .file_stem()
+    .and_then(|stem| stem.to_str())
+    .map(String::from)
+    .unwrap_or_else(|| String::from("plugin"));
+  let options = PluginOptions::new(&name, &args.file, &args.execute);
+  let mut repl = match Repl::start(options, &args.config) {
+    Ok(repl) => repl,
+    Err(error) => {
+      eprintln!("loading {} failed: {:?}", args.file, error);
+      return 1;
+    }
+  };
+  match repl.run(stdin().lock(), &mut stdout()) {
+    Ok(()) => 0,
+    Err(error) => {
+      eprintln!("{}", error);
+      1
+    }
+  }
+}
+
+fn main() {
+  let args: Vec<String> = std::env::args().skip(1).collect();
+  let code = match args.first().map(|command| command.as_str()) {
+    Some("check") => match parse_check_args(&args[1..]) {
+      Ok(check_args) => check(check_args),
+      Err(message) => {
+        eprintln!("{}\n\n{}", message, USAGE);
+        2
+      }
+    },
+    Some("repl") => match parse_repl_args(&args[1..]) {
+      Ok(repl_args) => repl(repl_args),
+      Err(message) => {
+        eprintln!("{}\n\n{}", message, USAGE);
+        2
+      }
+    },
+    Some("help") | Some("--help") | Some("-h") => {
+      println!("{}", USAGE);
+      0
+    }
+    _ => {
+      eprintln!("{}", USAGE);
+      2
+    }
+  };
+  exit(code);
+}
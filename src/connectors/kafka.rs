@@ -0,0 +1,182 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use log::error;
+use rdkafka::config::ClientConfig;
+use rdkafka::consumer::{BaseConsumer, CommitMode, Consumer};
+use rdkafka::error::{KafkaError, RDKafkaErrorCode};
+use rdkafka::message::{DeliveryResult, Message as _};
+use rdkafka::producer::{BaseProducer, BaseRecord, Producer, ProducerContext};
+use rdkafka::{ClientContext, Offset, TopicPartitionList};
+
+use crate::connectors::{Message, Sink, Source};
+use crate::plugin::PluginError;
+
+fn kafka_error(what: &str, error: KafkaError) -> PluginError {
+  error!("kafka {} failed", what);
+  error!("{}", error);
+  PluginError::ConnectorFailed
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct KafkaAck {
+  pub topic: String,
+  pub partition: i32,
+  pub offset: i64,
+}
+
+pub struct KafkaSource {
+  consumer: BaseConsumer,
+  max_batch: usize,
+}
+
+impl KafkaSource {
+  // joins `group` (see `connectors::consumer_group`), offsets are committed manually
+  pub fn new(brokers: &String, group: &String, topic: &String) -> Result<Self, PluginError> {
+    let consumer: BaseConsumer = ClientConfig::new()
+      .set("bootstrap.servers", brokers)
+      .set("group.id", group)
+      .set("enable.auto.commit", "false")
+      .set("auto.offset.reset", "earliest")
+      .create()
+      .map_err(|error| kafka_error("creating consumer", error))?;
+    consumer
+      .subscribe(&[topic.as_str()])
+      .map_err(|error| kafka_error("subscribing", error))?;
+    Ok(Self {
+      consumer,
+      max_batch: 100,
+    })
+  }
+
+  pub fn set_max_batch(&mut self, max_batch: usize) -> &mut Self {
+    self.max_batch = max_batch.max(1);
+    self
+  }
+}
+
+impl Source for KafkaSource {
+  type Ack = KafkaAck;
+
+  fn poll(&mut self, timeout: Duration) -> Result<Vec<Message<KafkaAck>>, PluginError> {
+    let mut messages = vec![];
+    let mut wait = timeout;
+    while messages.len() < self.max_batch {
+      let message = match self.consumer.poll(wait) {
+        Some(message) => message.map_err(|error| kafka_error("polling", error))?,
+        None => break,
+      };
+      // only the first message of a batch is waited for
+      wait = Duration::ZERO;
+      let key = message
+        .key()
+        .map(|key| String::from_utf8_lossy(key).into_owned())
+        .unwrap_or_default();
+      let payload = message
+        .payload()
+        .map(|payload| String::from_utf8_lossy(payload).into_owned())
+        .unwrap_or_default();
+      messages.push(Message {
+        key,
+        payload,
+        ack: KafkaAck {
+          topic: String::from(message.topic()),
+          partition: message.partition(),
+          offset: message.offset(),
+        },
+      });
+    }
+    Ok(messages)
+  }
+
+  fn commit(&mut self, messages: &[Message<KafkaAck>]) -> Result<(), PluginError> {
+    let mut offsets = TopicPartitionList::new();
+    for message in messages {
+      // the committed offset is the next message to consume
+      offsets
+        .add_partition_offset(
+          &message.ack.topic,
+          message.ack.partition,
+          Offset::Offset(message.ack.offset + 1),
+        )
+        .map_err(|error| kafka_error("committing", error))?;
+    }
+    self
+      .consumer
+      .commit(&offsets, CommitMode::Sync)
+      .map_err(|error| kafka_error("committing", error))
+  }
+}
+
+// counts failed deliveries, checked on flush
+#[derive(Clone, Default)]
+pub struct DeliveryContext {
+  failed: Arc<AtomicUsize>,
+}
+
+impl ClientContext for DeliveryContext {}
+
+impl ProducerContext for DeliveryContext {
+  type DeliveryOpaque = ();
+
+  fn delivery(&self, result: &DeliveryResult<'_>, _opaque: Self::DeliveryOpaque) {
+    if let Err((error, _)) = result {
+      error!("kafka delivery failed");
+      error!("{}", error);
+      self.failed.fetch_add(1, Ordering::Relaxed);
+    }
+  }
+}
+
+pub struct KafkaSink {
+  producer: BaseProducer<DeliveryContext>,
+  topic: String,
+  flush_timeout: Duration,
+}
+
+impl KafkaSink {
+  pub fn new(brokers: &String, topic: &String) -> Result<Self, PluginError> {
+    let producer = ClientConfig::new()
+      .set("bootstrap.servers", brokers)
+      .set("enable.idempotence", "true")
+      .set("acks", "all")
+      .create_with_context(DeliveryContext::default())
+      .map_err(|error| kafka_error("creating producer", error))?;
+    Ok(Self {
+      producer,
+      topic: topic.clone(),
+      flush_timeout: Duration::from_secs(30),
+    })
+  }
+}
+
+impl Sink for KafkaSink {
+  fn publish(&mut self, key: &String, payload: &String) -> Result<(), PluginError> {
+    let mut record = BaseRecord::to(&self.topic).key(key).payload(payload);
+    loop {
+      match self.producer.send(record) {
+        Ok(()) => return Ok(()),
+        // local queue is full, serve delivery reports and try again
+        Err((KafkaError::MessageProduction(RDKafkaErrorCode::QueueFull), returned)) => {
+          self.producer.poll(Duration::from_millis(100));
+          record = returned;
+        }
+        Err((error, _)) => return Err(kafka_error("publishing", error)),
+      }
+    }
+  }
+
+  fn flush(&mut self) -> Result<(), PluginError> {
+    self
+      .producer
+      .flush(self.flush_timeout)
+      .map_err(|error| kafka_error("flushing", error))?;
+    let failed = self.producer.context().failed.swap(0, Ordering::Relaxed);
+    if failed > 0 {
+      error!("kafka {} messages not delivered", failed);
+      return Err(PluginError::ConnectorFailed);
+    }
+    Ok(())
+  }
+}
@@ -0,0 +1,284 @@
+// streaming transforms: consume messages from a broker, run them through a
+// plugin and publish the results, enabled with feature `kafka` and/or `nats`
+//
+// delivery is at-least-once: the input offsets are only committed after the
+// results of the whole batch were confirmed by the output broker. after a crash
+// the uncommitted batch is consumed again, so results can be published twice
+
+#[cfg(feature = "kafka")]
+pub mod kafka;
+#[cfg(feature = "nats")]
+pub mod nats;
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+use log::{error, info, warn};
+
+use crate::plugin::shared::SharedPlugin;
+use crate::plugin::PluginError;
+
+// a consumed message, `ack` identifies it for the commit
+#[derive(Debug, Clone)]
+pub struct Message<A> {
+  pub key: String,
+  pub payload: String,
+  pub ack: A,
+}
+
+pub trait Source {
+  type Ack;
+
+  // waits up to `timeout` for the next batch, an empty batch is no error
+  fn poll(&mut self, timeout: Duration) -> Result<Vec<Message<Self::Ack>>, PluginError>;
+
+  // marks the messages as processed, they are not delivered again
+  fn commit(&mut self, messages: &[Message<Self::Ack>]) -> Result<(), PluginError>;
+}
+
+pub trait Sink {
+  fn publish(&mut self, key: &String, payload: &String) -> Result<(), PluginError>;
+
+  // returns once all published messages are confirmed by the broker
+  fn flush(&mut self) -> Result<(), PluginError>;
+}
+
+// what happens with a message the plugin fails on
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ErrorPolicy {
+  // log the error and commit the message without result
+  Skip,
+  // stop the runner without committing, the message is consumed again on restart
+  Stop,
+}
+
+pub struct StreamRunner<S: Source, K: Sink> {
+  plugin: SharedPlugin,
+  source: S,
+  sink: K,
+  poll_timeout: Duration,
+  error_policy: ErrorPolicy,
+}
+
+impl<S: Source, K: Sink> StreamRunner<S, K> {
+  pub fn new(plugin: SharedPlugin, source: S, sink: K) -> Self {
+    Self {
+      plugin,
+      source,
+      sink,
+      poll_timeout: Duration::from_millis(500),
+      error_policy: ErrorPolicy::Stop,
+    }
+  }
+
+  pub fn set_poll_timeout(&mut self, timeout: Duration) -> &mut Self {
+    self.poll_timeout = timeout;
+    self
+  }
+
+  pub fn set_error_policy(&mut self, policy: ErrorPolicy) -> &mut Self {
+    self.error_policy = policy;
+    self
+  }
+
+  // processes batches until `stop` is set or an error occurs
+  pub fn run(&mut self, stop: &AtomicBool) -> Result<(), PluginError> {
+    info!("WASM:{} stream runner started", self.plugin.module_name());
+    while !stop.load(Ordering::Relaxed) {
+      self.run_once()?;
+    }
+    info!("WASM:{} stream runner stopped", self.plugin.module_name());
+    Ok(())
+  }
+
+  // polls, transforms and publishes one batch, returns the number of messages
+  pub fn run_once(&mut self) -> Result<usize, PluginError> {
+    let messages = self.source.poll(self.poll_timeout)?;
+    if messages.is_empty() {
+      return Ok(0);
+    }
+
+    for message in messages.iter() {
+      match self.plugin.execute(&message.key, &message.payload) {
+        Ok(result) => self.sink.publish(&message.key, &result)?,
+        Err(error) => match self.error_policy {
+          ErrorPolicy::Skip => warn!(
+            "WASM:{} skipping message with key \"{}\": {:?}",
+            self.plugin.module_name(),
+            message.key,
+            error
+          ),
+          ErrorPolicy::Stop => {
+            error!(
+              "WASM:{} stopping on message with key \"{}\": {:?}",
+              self.plugin.module_name(),
+              message.key,
+              error
+            );
+            return Err(error);
+          }
+        },
+      }
+    }
+
+    self.sink.flush()?;
+    self.source.commit(&messages)?;
+    Ok(messages.len())
+  }
+}
+
+// consumer group / durable consumer name used for a plugin
+pub fn consumer_group(plugin: &SharedPlugin) -> String {
+  format!("wasmertest-{}", plugin.module_name())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::plugin::artifact::ArtifactHeader;
+  use crate::plugin::compiler::{compile_to_file, CompileOptions};
+  use crate::plugin::default::DefaultPlugin;
+  use crate::plugin::{Plugin, PluginOptions};
+
+  // batches are handed out in order, commits record the acks
+  struct MemorySource {
+    batches: Vec<Vec<Message<u32>>>,
+    committed: Vec<u32>,
+  }
+
+  impl Source for MemorySource {
+    type Ack = u32;
+
+    fn poll(&mut self, _timeout: Duration) -> Result<Vec<Message<u32>>, PluginError> {
+      if self.batches.is_empty() {
+        return Ok(vec![]);
+      }
+      Ok(self.batches.remove(0))
+    }
+
+    fn commit(&mut self, messages: &[Message<u32>]) -> Result<(), PluginError> {
+      self
+        .committed
+        .extend(messages.iter().map(|message| message.ack));
+      Ok(())
+    }
+  }
+
+  #[derive(Default)]
+  struct MemorySink {
+    published: Vec<String>,
+    flushes: usize,
+    fail_flush: bool,
+  }
+
+  impl Sink for MemorySink {
+    fn publish(&mut self, key: &String, _payload: &String) -> Result<(), PluginError> {
+      self.published.push(key.clone());
+      Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), PluginError> {
+      if self.fail_flush {
+        return Err(PluginError::ConnectorFailed);
+      }
+      self.flushes += 1;
+      Ok(())
+    }
+  }
+
+  fn tests(i: i32) -> i32 {
+    i + 1
+  }
+
+  fn tests2(i: i64) -> i64 {
+    i + 2
+  }
+
+  // keys longer than 16 bytes fail in the plugin
+  fn runner(
+    dir: &tempfile::TempDir,
+    keys: &[&str],
+    sink: MemorySink,
+  ) -> StreamRunner<MemorySource, MemorySink> {
+    let artifact = ArtifactHeader::host().artifact_file(dir.path(), "plugin");
+    compile_to_file(
+      &String::from("./assemblytest/build/optimized.wat"),
+      &artifact,
+      &CompileOptions::new(),
+    )
+    .unwrap();
+    let mut options = PluginOptions::new(
+      &String::from("connector_test"),
+      &artifact,
+      &String::from("transform"),
+    );
+    options.add_host_function("tests".into(), tests);
+    options.add_host_function("tests2".into(), tests2);
+    options.set_max_key_size(16);
+    let plugin = DefaultPlugin::create(options).unwrap();
+    plugin.init(&String::from("config")).unwrap();
+
+    let batch = keys
+      .iter()
+      .enumerate()
+      .map(|(ack, key)| Message {
+        key: String::from(*key),
+        payload: String::from("{}"),
+        ack: ack as u32,
+      })
+      .collect();
+    let source = MemorySource {
+      batches: vec![batch],
+      committed: vec![],
+    };
+    StreamRunner::new(SharedPlugin::new(plugin), source, sink)
+  }
+
+  #[test]
+  fn commits_after_flush() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut runner = runner(&dir, &["/a", "/b"], MemorySink::default());
+    assert_eq!(runner.run_once(), Ok(2));
+    assert_eq!(runner.sink.published, vec!["/a", "/b"]);
+    assert_eq!(runner.sink.flushes, 1);
+    assert_eq!(runner.source.committed, vec![0, 1]);
+    // nothing left to consume
+    assert_eq!(runner.run_once(), Ok(0));
+  }
+
+  #[test]
+  fn stop_does_not_commit() {
+    let dir = tempfile::tempdir().unwrap();
+    let keys = ["/a", "/a/key/which/is/too/long", "/c"];
+    let mut runner = runner(&dir, &keys, MemorySink::default());
+    assert!(matches!(
+      runner.run_once(),
+      Err(PluginError::PayloadTooLarge { .. })
+    ));
+    assert_eq!(runner.sink.flushes, 0);
+    assert!(runner.source.committed.is_empty());
+  }
+
+  #[test]
+  fn failed_flush_does_not_commit() {
+    let dir = tempfile::tempdir().unwrap();
+    let sink = MemorySink {
+      fail_flush: true,
+      ..MemorySink::default()
+    };
+    let mut runner = runner(&dir, &["/a", "/b"], sink);
+    assert_eq!(runner.run_once(), Err(PluginError::ConnectorFailed));
+    assert!(runner.source.committed.is_empty());
+  }
+
+  #[test]
+  fn skip_commits_failed_messages() {
+    let dir = tempfile::tempdir().unwrap();
+    let keys = ["/a", "/a/key/which/is/too/long", "/c"];
+    let mut runner = runner(&dir, &keys, MemorySink::default());
+    runner.set_error_policy(ErrorPolicy::Skip);
+    assert_eq!(runner.run_once(), Ok(3));
+    assert_eq!(runner.sink.published, vec!["/a", "/c"]);
+    assert_eq!(runner.source.committed, vec![0, 1, 2]);
+  }
+}
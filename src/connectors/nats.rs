@@ -0,0 +1,175 @@
+use std::time::Duration;
+
+use async_nats::jetstream::consumer::{pull, AckPolicy, Consumer};
+use async_nats::jetstream::{self, Context};
+use async_nats::HeaderMap;
+use futures_util::StreamExt;
+use log::error;
+use tokio::runtime::Runtime;
+
+use crate::connectors::{Message, Sink, Source};
+use crate::plugin::PluginError;
+
+// header carrying the message key, nats itself has no keys
+pub const KEY_HEADER: &str = "x-key";
+
+fn nats_error(what: &str, error: impl std::fmt::Display) -> PluginError {
+  error!("nats {} failed", what);
+  error!("{}", error);
+  PluginError::ConnectorFailed
+}
+
+// the async client is driven by a runtime owned by the connector, so the
+// runner stays a plain blocking loop like everything else in the crate
+fn runtime() -> Result<Runtime, PluginError> {
+  tokio::runtime::Builder::new_current_thread()
+    .enable_all()
+    .build()
+    .map_err(|error| nats_error("starting runtime", error))
+}
+
+pub struct NatsSource {
+  runtime: Runtime,
+  consumer: Consumer<pull::Config>,
+  max_batch: usize,
+}
+
+impl NatsSource {
+  // binds the durable pull consumer `durable` (see `connectors::consumer_group`)
+  // on `stream`, messages are acknowledged explicitly after processing
+  pub fn new(
+    server: &String,
+    stream: &String,
+    subject: &String,
+    durable: &String,
+  ) -> Result<Self, PluginError> {
+    let runtime = runtime()?;
+    let consumer = runtime.block_on(async {
+      let client = async_nats::connect(server.as_str())
+        .await
+        .map_err(|error| nats_error("connecting", error))?;
+      let stream = jetstream::new(client)
+        .get_stream(stream)
+        .await
+        .map_err(|error| nats_error("getting stream", error))?;
+      stream
+        .get_or_create_consumer(
+          durable,
+          pull::Config {
+            durable_name: Some(durable.clone()),
+            filter_subject: subject.clone(),
+            ack_policy: AckPolicy::Explicit,
+            ..Default::default()
+          },
+        )
+        .await
+        .map_err(|error| nats_error("creating consumer", error))
+    })?;
+    Ok(Self {
+      runtime,
+      consumer,
+      max_batch: 100,
+    })
+  }
+
+  pub fn set_max_batch(&mut self, max_batch: usize) -> &mut Self {
+    self.max_batch = max_batch.max(1);
+    self
+  }
+}
+
+impl Source for NatsSource {
+  type Ack = jetstream::Message;
+
+  fn poll(&mut self, timeout: Duration) -> Result<Vec<Message<jetstream::Message>>, PluginError> {
+    let consumer = &self.consumer;
+    let max_batch = self.max_batch;
+    self.runtime.block_on(async {
+      let mut batch = consumer
+        .batch()
+        .max_messages(max_batch)
+        .expires(timeout)
+        .messages()
+        .await
+        .map_err(|error| nats_error("polling", error))?;
+
+      let mut messages = vec![];
+      while let Some(message) = batch.next().await {
+        let message = message.map_err(|error| nats_error("polling", error))?;
+        let key = match message.headers.as_ref().and_then(|h| h.get(KEY_HEADER)) {
+          Some(key) => String::from(key.as_str()),
+          None => message.subject.to_string(),
+        };
+        messages.push(Message {
+          key,
+          payload: String::from_utf8_lossy(&message.payload).into_owned(),
+          ack: message,
+        });
+      }
+      Ok(messages)
+    })
+  }
+
+  fn commit(&mut self, messages: &[Message<jetstream::Message>]) -> Result<(), PluginError> {
+    self.runtime.block_on(async {
+      for message in messages {
+        message
+          .ack
+          .double_ack()
+          .await
+          .map_err(|error| nats_error("acknowledging", error))?;
+      }
+      Ok(())
+    })
+  }
+}
+
+pub struct NatsSink {
+  runtime: Runtime,
+  context: Context,
+  subject: String,
+  pending: Vec<jetstream::context::PublishAckFuture>,
+}
+
+impl NatsSink {
+  // `subject` must be bound to a stream, publishes are confirmed by jetstream
+  pub fn new(server: &String, subject: &String) -> Result<Self, PluginError> {
+    let runtime = runtime()?;
+    let client = runtime
+      .block_on(async_nats::connect(server.as_str()))
+      .map_err(|error| nats_error("connecting", error))?;
+    Ok(Self {
+      runtime,
+      context: jetstream::new(client),
+      subject: subject.clone(),
+      pending: vec![],
+    })
+  }
+}
+
+impl Sink for NatsSink {
+  fn publish(&mut self, key: &String, payload: &String) -> Result<(), PluginError> {
+    let mut headers = HeaderMap::new();
+    headers.insert(KEY_HEADER, key.as_str());
+    let ack = self
+      .runtime
+      .block_on(self.context.publish_with_headers(
+        self.subject.clone(),
+        headers,
+        payload.clone().into(),
+      ))
+      .map_err(|error| nats_error("publishing", error))?;
+    self.pending.push(ack);
+    Ok(())
+  }
+
+  fn flush(&mut self) -> Result<(), PluginError> {
+    let pending = std::mem::take(&mut self.pending);
+    self.runtime.block_on(async {
+      for ack in pending {
+        ack.await.map_err(|error| nats_error("publishing", error))?;
+      }
+      Ok(())
+    })
+  }
+}
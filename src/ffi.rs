@@ -0,0 +1,242 @@
+// c interface for embedding the plugin host, enabled with feature `ffi`
+//
+// build the shared library with
+//   cargo rustc --release --lib --features ffi --crate-type cdylib
+// the header `include/wasmertest.h` is generated by `build.rs` with cbindgen
+//
+// all functions return `PLUGIN_OK` or a negative error code, the message of
+// the last error on the calling thread is available via `plugin_last_error`.
+// a plugin may be used from several threads, calls are serialized
+// plugins importing host functions of the `custom` namespace can not be
+// loaded, there is no way to register them from c yet
+
+use std::cell::RefCell;
+use std::ffi::{CStr, CString};
+use std::os::raw::c_char;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::ptr;
+
+use log::error;
+
+use crate::plugin::default::DefaultPlugin;
+use crate::plugin::shared::SharedPlugin;
+use crate::plugin::{Plugin, PluginError, PluginOptions};
+
+pub const PLUGIN_OK: i32 = 0;
+// null pointer or string which is not utf-8
+pub const PLUGIN_ERROR_INVALID_ARGUMENT: i32 = -1;
+// artifact missing, compiled for another host or instantiating failed
+pub const PLUGIN_ERROR_LOADING: i32 = -2;
+pub const PLUGIN_ERROR_INIT: i32 = -3;
+// the guest trapped, exited or returned an invalid result
+pub const PLUGIN_ERROR_EXECUTE: i32 = -4;
+// the guest reported an error with `raise_error`
+pub const PLUGIN_ERROR_GUEST: i32 = -5;
+// key, payload or result exceeds a size limit
+pub const PLUGIN_ERROR_TOO_LARGE: i32 = -6;
+pub const PLUGIN_ERROR_RATE_LIMITED: i32 = -7;
+// a host side bug, the plugin should be destroyed
+pub const PLUGIN_ERROR_PANIC: i32 = -8;
+
+// opaque handle returned by `plugin_create`
+pub struct WasmPlugin {
+  plugin: SharedPlugin,
+}
+
+thread_local! {
+  static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+fn set_last_error(message: String) {
+  let message = CString::new(message.replace('\0', " ")).unwrap_or_default();
+  LAST_ERROR.with(|last| *last.borrow_mut() = Some(message));
+}
+
+// code of a failed execute call
+fn error_code(error: &PluginError) -> i32 {
+  match error {
+    PluginError::GuestError(_) => PLUGIN_ERROR_GUEST,
+    PluginError::PayloadTooLarge { .. } => PLUGIN_ERROR_TOO_LARGE,
+    PluginError::RateLimited { .. } => PLUGIN_ERROR_RATE_LIMITED,
+    _ => PLUGIN_ERROR_EXECUTE,
+  }
+}
+
+fn fail(error: PluginError, code: i32) -> i32 {
+  set_last_error(format!("{:?}", error));
+  code
+}
+
+unsafe fn read_str(name: &str, value: *const c_char) -> Result<String, i32> {
+  if value.is_null() {
+    set_last_error(format!("{} is null", name));
+    return Err(PLUGIN_ERROR_INVALID_ARGUMENT);
+  }
+  match CStr::from_ptr(value).to_str() {
+    Ok(value) => Ok(String::from(value)),
+    Err(_) => {
+      set_last_error(format!("{} is not utf-8", name));
+      Err(PLUGIN_ERROR_INVALID_ARGUMENT)
+    }
+  }
+}
+
+// a panic must not unwind into the calling c code
+fn guarded(name: &str, f: impl FnOnce() -> i32) -> i32 {
+  match catch_unwind(AssertUnwindSafe(f)) {
+    Ok(code) => code,
+    Err(_) => {
+      error!("{} panicked", name);
+      set_last_error(format!("{} panicked", name));
+      PLUGIN_ERROR_PANIC
+    }
+  }
+}
+
+/// creates a plugin from a compiled artifact, `*out` is set on success and
+/// released with `plugin_destroy`
+///
+/// # Safety
+/// the strings must be null terminated, `out` must be valid for writes
+#[no_mangle]
+pub unsafe extern "C" fn plugin_create(
+  name: *const c_char,
+  file: *const c_char,
+  execute_function: *const c_char,
+  out: *mut *mut WasmPlugin,
+) -> i32 {
+  guarded("plugin_create", || {
+    if out.is_null() {
+      set_last_error(String::from("out is null"));
+      return PLUGIN_ERROR_INVALID_ARGUMENT;
+    }
+    *out = ptr::null_mut();
+    let (name, file, execute_function) = match (
+      read_str("name", name),
+      read_str("file", file),
+      read_str("execute_function", execute_function),
+    ) {
+      (Ok(name), Ok(file), Ok(execute_function)) => (name, file, execute_function),
+      (Err(code), _, _) | (_, Err(code), _) | (_, _, Err(code)) => return code,
+    };
+    let options = PluginOptions::new(&name, &file, &execute_function);
+    match DefaultPlugin::create(options) {
+      Ok(plugin) => {
+        let handle = WasmPlugin {
+          plugin: SharedPlugin::new(plugin),
+        };
+        *out = Box::into_raw(Box::new(handle));
+        PLUGIN_OK
+      }
+      Err(error) => fail(error, PLUGIN_ERROR_LOADING),
+    }
+  })
+}
+
+/// calls the init function of the guest with the config
+///
+/// # Safety
+/// `plugin` must come from `plugin_create`, `config` must be null terminated
+#[no_mangle]
+pub unsafe extern "C" fn plugin_init(plugin: *const WasmPlugin, config: *const c_char) -> i32 {
+  guarded("plugin_init", || {
+    let plugin = match plugin.as_ref() {
+      Some(plugin) => plugin,
+      None => {
+        set_last_error(String::from("plugin is null"));
+        return PLUGIN_ERROR_INVALID_ARGUMENT;
+      }
+    };
+    let config = match read_str("config", config) {
+      Ok(config) => config,
+      Err(code) => return code,
+    };
+    match plugin.plugin.init(&config) {
+      Ok(()) => PLUGIN_OK,
+      Err(error) => fail(error, PLUGIN_ERROR_INIT),
+    }
+  })
+}
+
+/// executes the plugin, `*result` is set to a null terminated string on
+/// success which is released with `plugin_free_string`
+///
+/// # Safety
+/// `plugin` must come from `plugin_create`, `key` and `payload` must be null
+/// terminated, `result` must be valid for writes
+#[no_mangle]
+pub unsafe extern "C" fn plugin_execute(
+  plugin: *const WasmPlugin,
+  key: *const c_char,
+  payload: *const c_char,
+  result: *mut *mut c_char,
+) -> i32 {
+  guarded("plugin_execute", || {
+    let plugin = match plugin.as_ref() {
+      Some(plugin) => plugin,
+      None => {
+        set_last_error(String::from("plugin is null"));
+        return PLUGIN_ERROR_INVALID_ARGUMENT;
+      }
+    };
+    if result.is_null() {
+      set_last_error(String::from("result is null"));
+      return PLUGIN_ERROR_INVALID_ARGUMENT;
+    }
+    *result = ptr::null_mut();
+    let (key, payload) = match (read_str("key", key), read_str("payload", payload)) {
+      (Ok(key), Ok(payload)) => (key, payload),
+      (Err(code), _) | (_, Err(code)) => return code,
+    };
+    let output = match plugin.plugin.execute(&key, &payload) {
+      Ok(output) => output,
+      Err(error) => {
+        let code = error_code(&error);
+        return fail(error, code);
+      }
+    };
+    match CString::new(output) {
+      Ok(output) => {
+        *result = output.into_raw();
+        PLUGIN_OK
+      }
+      Err(_) => {
+        set_last_error(String::from("result contains a null byte"));
+        PLUGIN_ERROR_EXECUTE
+      }
+    }
+  })
+}
+
+/// releases a result of `plugin_execute`, null is ignored
+///
+/// # Safety
+/// `value` must come from `plugin_execute` and must not be used afterwards
+#[no_mangle]
+pub unsafe extern "C" fn plugin_free_string(value: *mut c_char) {
+  if !value.is_null() {
+    drop(CString::from_raw(value));
+  }
+}
+
+/// releases the plugin, null is ignored
+///
+/// # Safety
+/// `plugin` must come from `plugin_create`, no call may run on it and it must
+/// not be used afterwards
+#[no_mangle]
+pub unsafe extern "C" fn plugin_destroy(plugin: *mut WasmPlugin) {
+  if !plugin.is_null() {
+    drop(Box::from_raw(plugin));
+  }
+}
+
+/// message of the last failed call on this thread, null if there was none
+/// the pointer is valid until the next failing call on this thread
+#[no_mangle]
+pub extern "C" fn plugin_last_error() -> *const c_char {
+  LAST_ERROR.with(|last| match last.borrow().as_ref() {
+    Some(message) => message.as_ptr(),
+    None => ptr::null(),
+  })
+}
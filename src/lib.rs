@@ -0,0 +1,10 @@
+pub mod plugin;
+
+#[cfg(feature = "logging")]
+pub mod logging;
+#[cfg(any(feature = "kafka", feature = "nats"))]
+pub mod connectors;
+#[cfg(feature = "server")]
+pub mod server;
+#[cfg(feature = "ffi")]
+pub mod ffi;
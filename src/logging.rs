@@ -0,0 +1,178 @@
+use std::io::Write;
+
+use flexi_logger::{
+  Cleanup, Criterion, DeferredNow, Duplicate, FileSpec, FlexiLoggerError, FormatFunction, Logger,
+  LoggerHandle, Naming, Record,
+};
+
+// output format of the log lines
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LogFormat {
+  Default,
+  Detailed,
+  Colored,
+  ColoredDetailed,
+  // one json object per line: {"ts":<unix millis>,"level":"INFO","target":"...","message":"..."}
+  Json,
+}
+
+#[derive(Debug, Clone)]
+struct FileOutput {
+  directory: String,
+  basename: String,
+  rotate_size: Option<u64>,
+  keep_files: usize,
+}
+
+// the library itself only logs through the `log` facade
+// this builder is a convenience for binaries which are fine with flexi_logger,
+// embedders can disable the `logging` feature and install any other backend
+#[derive(Debug, Clone)]
+pub struct LoggingBuilder {
+  level: String,
+  modules: Vec<(String, String)>,
+  format: LogFormat,
+  file: Option<FileOutput>,
+  duplicate_to_stderr: bool,
+}
+
+impl Default for LoggingBuilder {
+  fn default() -> Self {
+    Self {
+      level: String::from("info"),
+      modules: vec![],
+      format: LogFormat::ColoredDetailed,
+      file: None,
+      duplicate_to_stderr: false,
+    }
+  }
+}
+
+impl LoggingBuilder {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  pub fn set_level(&mut self, level: &String) -> &mut Self {
+    self.level = level.clone();
+    self
+  }
+
+  // eg `set_module_level("wasmer_wasi::syscalls", "off")`
+  pub fn set_module_level(&mut self, module: &String, level: &String) -> &mut Self {
+    self.modules.push((module.clone(), level.clone()));
+    self
+  }
+
+  // level for the guest output of one plugin, logged with target `wasm::<plugin>`
+  pub fn set_plugin_level(&mut self, plugin_name: &String, level: &String) -> &mut Self {
+    self.set_module_level(&format!("wasm::{}", plugin_name), level)
+  }
+
+  pub fn set_format(&mut self, format: LogFormat) -> &mut Self {
+    self.format = format;
+    self
+  }
+
+  // writes the log to `<directory>/<basename>_*.log` instead of stderr
+  pub fn log_to_file(&mut self, directory: &String, basename: &String) -> &mut Self {
+    self.file = Some(FileOutput {
+      directory: directory.clone(),
+      basename: basename.clone(),
+      rotate_size: None,
+      keep_files: 0,
+    });
+    self
+  }
+
+  // rotates the log file when it exceeds `size` bytes and keeps the last `keep_files` files
+  // only has an effect together with `log_to_file`
+  pub fn rotate(&mut self, size: u64, keep_files: usize) -> &mut Self {
+    if let Some(file) = self.file.as_mut() {
+      file.rotate_size = Some(size);
+      file.keep_files = keep_files;
+    }
+    self
+  }
+
+  // when logging to file, also write all lines to stderr
+  pub fn duplicate_to_stderr(&mut self, duplicate: bool) -> &mut Self {
+    self.duplicate_to_stderr = duplicate;
+    self
+  }
+
+  // log specification as understood by flexi_logger, eg `trace, wasm::enrich=warn`
+  pub fn spec(&self) -> String {
+    let mut spec = self.level.clone();
+    for (module, level) in self.modules.iter() {
+      spec.push_str(&format!(", {}={}", module, level));
+    }
+    spec
+  }
+
+  // the env variable `RUST_LOG` overrides the configured levels
+  // the returned handle must be kept alive as long as logging is needed
+  pub fn start(&self) -> Result<LoggerHandle, FlexiLoggerError> {
+    let format: FormatFunction = match self.format {
+      LogFormat::Default => flexi_logger::default_format,
+      LogFormat::Detailed => flexi_logger::detailed_format,
+      LogFormat::Colored => flexi_logger::colored_default_format,
+      LogFormat::ColoredDetailed => flexi_logger::colored_detailed_format,
+      LogFormat::Json => json_format,
+    };
+    let mut logger = Logger::try_with_env_or_str(self.spec())?.format(format);
+
+    if let Some(file) = &self.file {
+      logger = logger.log_to_file(
+        FileSpec::default()
+          .directory(file.directory.clone())
+          .basename(file.basename.clone()),
+      );
+      if let Some(size) = file.rotate_size {
+        logger = logger.rotate(
+          Criterion::Size(size),
+          Naming::Numbers,
+          Cleanup::KeepLogFiles(file.keep_files),
+        );
+      }
+      if self.duplicate_to_stderr {
+        logger = logger.duplicate_to_stderr(Duplicate::All);
+      }
+    }
+
+    logger.start()
+  }
+}
+
+fn json_format(
+  w: &mut dyn Write,
+  now: &mut DeferredNow,
+  record: &Record,
+) -> Result<(), std::io::Error> {
+  write!(
+    w,
+    "{{\"ts\":{},\"level\":\"{}\",\"target\":{},\"message\":{}}}",
+    now.now().unix_timestamp_nanos() / 1_000_000,
+    record.level(),
+    json_string(record.target()),
+    json_string(&record.args().to_string())
+  )
+}
+
+fn json_string(value: &str) -> String {
+  let mut result = String::with_capacity(value.len() + 2);
+  result.push('"');
+  for c in value.chars() {
+    match c {
+      '"' => result.push_str("\\\""),
+      '\\' => result.push_str("\\\\"),
+      '\n' => result.push_str("\\n"),
+      '\r' => result.push_str("\\r"),
+      '\t' => result.push_str("\\t"),
+      c if (c as u32) < 0x20 => result.push_str(&format!("\\u{:04x}", c as u32)),
+      c => result.push(c),
+    }
+  }
+  result.push('"');
+  result
+}
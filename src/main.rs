@@ -1,37 +1,32 @@
-use log::{debug, error, info};
-use wasmer::*;
+use std::path::Path;
 
-mod plugin;
+use log::{error, info};
 
-use plugin::default::DefaultPlugin;
-use plugin::{Plugin, PluginOptions};
+use wasmertest::logging::{LogFormat, LoggingBuilder};
+use wasmertest::plugin::artifact::ArtifactHeader;
+use wasmertest::plugin::compiler::{compile_to_file, CompileOptions};
+use wasmertest::plugin::default::DefaultPlugin;
+use wasmertest::plugin::{Plugin, PluginOptions};
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
-  let log_modules = format!(
-    "{}{}",
-    "trace, wasmer_wasi::syscalls=off, wasmer_wasi::state=off", ""
-  );
-  let logger = flexi_logger::Logger::try_with_env_or_str(log_modules)
-    .unwrap()
-    .format(flexi_logger::colored_detailed_format)
-    .duplicate_to_stderr(flexi_logger::Duplicate::All)
-    .print_message();
-  logger.start().unwrap();
+  let _logger = LoggingBuilder::new()
+    .set_level(&String::from("trace"))
+    .set_module_level(&String::from("wasmer_wasi::syscalls"), &String::from("off"))
+    .set_module_level(&String::from("wasmer_wasi::state"), &String::from("off"))
+    .set_format(LogFormat::ColoredDetailed)
+    .start()
+    .unwrap();
 
-  // we use ahead-of-time compile .wasm to .so
+  // we use ahead-of-time compile .wasm to .so (.dylib on macos, .dll on windows)
   // in real world compile should be done only when wasm has changed
   // eg in build pipeline, on docker compose ....
-  {
-    let compiler_exp = LLVM::new();
-    let engine_exp = Universal::new(compiler_exp).engine();
-    let store_exp = Store::new(&engine_exp);
-
-    debug!("Compiling module");
-    let module_exp = Module::from_file(&store_exp, "./assemblytest/build/optimized.wasm").unwrap();
-
-    debug!("serialize compiled module to file");
-    module_exp.serialize_to_file("./optimized.so").unwrap();
-  };
+  let plugin_file_name = ArtifactHeader::host().artifact_file(Path::new("."), "optimized");
+  compile_to_file(
+    &String::from("./assemblytest/build/optimized.wasm"),
+    &plugin_file_name,
+    &CompileOptions::new(),
+  )
+  .unwrap();
 
   // two simple host function we will call in our webassembly plugin
   fn tests(i: i32) -> i32 {
@@ -46,7 +41,6 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
 
   // load compiled webassembly .so file
   let plugin_name = String::from("test_plugin");
-  let plugin_file_name = String::from("./optimized.so");
   let plugin_function_name = String::from("transform");
 
   let mut options = PluginOptions::new(&plugin_name, &plugin_file_name, &plugin_function_name);
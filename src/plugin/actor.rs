@@ -0,0 +1,232 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use log::{error, info, warn};
+
+use crate::plugin::default::DefaultPlugin;
+use crate::plugin::priority::{Priority, PriorityQueue, Pushed};
+use crate::plugin::{Plugin, PluginError, PluginOptions};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExecuteMsg {
+  pub key: String,
+  pub payload: String,
+  pub trace_id: Option<String>,
+  pub caller: Option<String>,
+  // `None` uses the priority of the plugin, see `PluginOptions::set_priority`
+  pub priority: Option<Priority>,
+}
+
+impl ExecuteMsg {
+  pub fn new(key: &String, payload: &String) -> Self {
+    Self {
+      key: key.clone(),
+      payload: payload.clone(),
+      trace_id: None,
+      caller: None,
+      priority: None,
+    }
+  }
+
+  pub fn with_trace_id(mut self, trace_id: &String) -> Self {
+    self.trace_id = Some(trace_id.clone());
+    self
+  }
+
+  pub fn with_caller(mut self, caller: &String) -> Self {
+    self.caller = Some(caller.clone());
+    self
+  }
+
+  pub fn with_priority(mut self, priority: Priority) -> Self {
+    self.priority = Some(priority);
+    self
+  }
+}
+
+type Job = (ExecuteMsg, Sender<Result<String, PluginError>>);
+
+struct Worker {
+  // shared with the worker thread, which only holds the lock while taking the
+  // next job, so the queue of a wedged thread can still be drained
+  queue: Arc<PriorityQueue<Job>>,
+  // start of the call currently running on the worker thread
+  busy_since: Arc<Mutex<Option<Instant>>>,
+  queued: Arc<AtomicUsize>,
+}
+
+// owns a plugin on a dedicated thread, calls are queued as messages and run
+// strictly one after another, interactive calls before batch calls, see
+// `SchedulingConfig`
+//
+// a guest stuck in an endless loop can not be interrupted, `restart` abandons
+// the wedged thread and continues with a fresh instance on a new thread.
+// messages still queued for the old thread are answered with `ActorStopped`
+pub struct PluginActor {
+  options: PluginOptions,
+  init_config: String,
+  worker: Mutex<Worker>,
+}
+
+impl PluginActor {
+  // creates and initializes the plugin on the worker thread
+  pub fn spawn(options: PluginOptions, init_config: &String) -> Result<Self, PluginError> {
+    let worker = start_worker(&options, init_config)?;
+    Ok(Self {
+      options,
+      init_config: init_config.clone(),
+      worker: Mutex::new(worker),
+    })
+  }
+
+  // the returned receiver works as oneshot channel for the result
+  pub fn send(&self, msg: ExecuteMsg) -> Receiver<Result<String, PluginError>> {
+    let (result_sender, result_receiver) = channel();
+    let priority = msg.priority.unwrap_or(self.options.priority);
+    let worker = self.worker.lock().unwrap();
+    worker.queued.fetch_add(1, Ordering::Relaxed);
+    match worker.queue.push((msg, result_sender), priority) {
+      Pushed::Queued => (),
+      Pushed::Preempted((msg, result_sender)) => {
+        worker.queued.fetch_sub(1, Ordering::Relaxed);
+        warn!(
+          "WASM:{} queued batch call for key \"{}\" preempted",
+          self.options.module_name, msg.key
+        );
+        let _ = result_sender.send(Err(PluginError::Preempted));
+      }
+      Pushed::Rejected((_, result_sender)) => {
+        worker.queued.fetch_sub(1, Ordering::Relaxed);
+        let _ = result_sender.send(Err(PluginError::QueueFull));
+      }
+      Pushed::Closed((_, result_sender)) => {
+        worker.queued.fetch_sub(1, Ordering::Relaxed);
+        let _ = result_sender.send(Err(PluginError::ActorStopped));
+      }
+    }
+    result_receiver
+  }
+
+  pub fn execute(&self, key: &String, payload: &String) -> Result<String, PluginError> {
+    match self.send(ExecuteMsg::new(key, payload)).recv() {
+      Ok(result) => result,
+      Err(_) => Err(PluginError::ActorStopped),
+    }
+  }
+
+  // messages waiting for or being executed
+  pub fn queued(&self) -> usize {
+    self.worker.lock().unwrap().queued.load(Ordering::Relaxed)
+  }
+
+  // true if the running call takes longer than `max_call_duration`
+  pub fn is_wedged(&self, max_call_duration: Duration) -> bool {
+    let worker = self.worker.lock().unwrap();
+    let busy_since = *worker.busy_since.lock().unwrap();
+    match busy_since {
+      Some(start) => start.elapsed() > max_call_duration,
+      None => false,
+    }
+  }
+
+  // replaces the worker thread with a new one running a fresh instance
+  pub fn restart(&self) -> Result<(), PluginError> {
+    warn!("WASM:{} restarting plugin actor", self.options.module_name);
+    let worker = start_worker(&self.options, &self.init_config)?;
+    let old = std::mem::replace(&mut *self.worker.lock().unwrap(), worker);
+    // a wedged thread never takes these, it is detached and left behind
+    old.queue.close();
+    for (_, result_sender) in old.queue.drain() {
+      let _ = result_sender.send(Err(PluginError::ActorStopped));
+    }
+    Ok(())
+  }
+}
+
+impl Drop for PluginActor {
+  fn drop(&mut self) {
+    // queued calls are still executed, the worker stops afterwards
+    self.worker.lock().unwrap().queue.close();
+  }
+}
+
+fn start_worker(options: &PluginOptions, init_config: &String) -> Result<Worker, PluginError> {
+  let queue = Arc::new(PriorityQueue::new(options.scheduling));
+  let (ready_sender, ready_receiver) = channel::<Result<(), PluginError>>();
+  let busy_since = Arc::new(Mutex::new(None));
+  let queued = Arc::new(AtomicUsize::new(0));
+
+  let options = options.clone();
+  let init_config = init_config.clone();
+  let thread_queue = queue.clone();
+  let thread_busy_since = busy_since.clone();
+  let thread_queued = queued.clone();
+  let module_name = options.module_name.clone();
+  let spawned = thread::Builder::new()
+    .name(format!("wasm-{}", module_name))
+    .spawn(move || {
+      if let Err(error) = options
+        .thread_config
+        .apply_to_current_thread(&options.module_name)
+      {
+        let _ = ready_sender.send(Err(error));
+        return;
+      }
+      let plugin = match DefaultPlugin::create(options).and_then(|plugin| {
+        plugin.init(&init_config)?;
+        Ok(plugin)
+      }) {
+        Ok(plugin) => {
+          let _ = ready_sender.send(Ok(()));
+          plugin
+        }
+        Err(error) => {
+          let _ = ready_sender.send(Err(error));
+          return;
+        }
+      };
+      run_worker(&plugin, &thread_queue, &thread_busy_since, &thread_queued);
+    });
+  if let Err(error) = spawned {
+    error!("WASM:{} spawning actor thread failed", module_name);
+    error!("{}", error);
+    return Err(PluginError::InstanceInitFailed);
+  }
+
+  match ready_receiver.recv() {
+    Ok(Ok(())) => {
+      info!("WASM:{} plugin actor started", module_name);
+      Ok(Worker {
+        queue,
+        busy_since,
+        queued,
+      })
+    }
+    Ok(Err(error)) => Err(error),
+    Err(_) => Err(PluginError::InstanceInitFailed),
+  }
+}
+
+fn run_worker(
+  plugin: &DefaultPlugin,
+  queue: &PriorityQueue<Job>,
+  busy_since: &Mutex<Option<Instant>>,
+  queued: &AtomicUsize,
+) {
+  while let Some((msg, result_sender)) = queue.pop() {
+    *busy_since.lock().unwrap() = Some(Instant::now());
+    let result = plugin.execute_with(
+      msg.trace_id.as_ref(),
+      msg.caller.as_ref(),
+      &msg.key,
+      &msg.payload,
+    );
+    *busy_since.lock().unwrap() = None;
+    queued.fetch_sub(1, Ordering::Relaxed);
+    // the caller may have given up waiting
+    let _ = result_sender.send(result);
+  }
+}
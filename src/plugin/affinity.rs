@@ -0,0 +1,126 @@
+#[cfg(not(target_os = "linux"))]
+use log::warn;
+use log::{debug, error};
+
+use crate::plugin::PluginError;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ThreadPriority {
+  // nice value of the thread, -20 (highest) to 19 (lowest)
+  Nice(i32),
+  // `SCHED_FIFO` with the priority 1 (lowest) to 99 (highest), needs
+  // `CAP_SYS_NICE` or a matching `RLIMIT_RTPRIO`
+  RealTime(i32),
+}
+
+// cores and priority of the threads running a plugin, set with
+// `PluginOptions::set_cpu_affinity` and `PluginOptions::set_thread_priority`
+//
+// applied to the worker thread of `PluginActor`, own executors call
+// `apply_to_current_thread` on their threads. only supported on linux, other
+// platforms log a warning and keep the defaults
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ThreadConfig {
+  // empty runs on all cores
+  pub cpus: Vec<usize>,
+  pub priority: Option<ThreadPriority>,
+}
+
+impl ThreadConfig {
+  pub fn is_default(&self) -> bool {
+    self.cpus.is_empty() && self.priority.is_none()
+  }
+
+  pub fn apply_to_current_thread(&self, module_name: &String) -> Result<(), PluginError> {
+    if self.is_default() {
+      return Ok(());
+    }
+    if !self.cpus.is_empty() {
+      if let Err(error) = set_affinity(&self.cpus) {
+        error!(
+          "WASM:{} pinning thread to cpus {:?} failed",
+          module_name, self.cpus
+        );
+        error!("{}", error);
+        return Err(PluginError::ThreadConfigFailed);
+      }
+    }
+    if let Some(priority) = self.priority {
+      if let Err(error) = set_priority(priority) {
+        error!(
+          "WASM:{} setting thread priority {:?} failed",
+          module_name, priority
+        );
+        error!("{}", error);
+        return Err(PluginError::ThreadConfigFailed);
+      }
+    }
+    debug!("WASM:{} thread config applied: {:?}", module_name, self);
+    Ok(())
+  }
+}
+
+#[cfg(target_os = "linux")]
+fn set_affinity(cpus: &[usize]) -> std::io::Result<()> {
+  unsafe {
+    let mut set: libc::cpu_set_t = std::mem::zeroed();
+    libc::CPU_ZERO(&mut set);
+    for cpu in cpus {
+      if *cpu >= libc::CPU_SETSIZE as usize {
+        return Err(std::io::Error::new(
+          std::io::ErrorKind::InvalidInput,
+          format!("cpu {} out of range", cpu),
+        ));
+      }
+      libc::CPU_SET(*cpu, &mut set);
+    }
+    // pid 0 is the calling thread
+    if libc::sched_setaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &set) != 0 {
+      return Err(std::io::Error::last_os_error());
+    }
+  }
+  Ok(())
+}
+
+#[cfg(target_os = "linux")]
+fn set_priority(priority: ThreadPriority) -> std::io::Result<()> {
+  unsafe {
+    let result = match priority {
+      // on linux the nice value is per thread
+      ThreadPriority::Nice(nice) => {
+        libc::setpriority(libc::PRIO_PROCESS as _, libc::gettid() as libc::id_t, nice)
+      }
+      ThreadPriority::RealTime(priority) => {
+        let param = libc::sched_param {
+          sched_priority: priority,
+        };
+        match libc::pthread_setschedparam(libc::pthread_self(), libc::SCHED_FIFO, &param) {
+          0 => 0,
+          code => return Err(std::io::Error::from_raw_os_error(code)),
+        }
+      }
+    };
+    if result != 0 {
+      return Err(std::io::Error::last_os_error());
+    }
+  }
+  Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn set_affinity(cpus: &[usize]) -> std::io::Result<()> {
+  warn!(
+    "cpu affinity {:?} is only supported on linux - ignored",
+    cpus
+  );
+  Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn set_priority(priority: ThreadPriority) -> std::io::Result<()> {
+  warn!(
+    "thread priority {:?} is only supported on linux - ignored",
+    priority
+  );
+  Ok(())
+}
@@ -0,0 +1,98 @@
+use std::sync::{Arc, Mutex};
+
+// AssemblyScript objects are 16 byte aligned, which suits all other abis too
+const ALIGNMENT: u32 = 16;
+
+#[derive(Debug, Default)]
+struct ArenaState {
+  // start of the region, reserved from the guest allocator on first use
+  base: Option<u32>,
+  offset: u32,
+  // only set during an execute call, see `begin`
+  active: bool,
+  high_water: u32,
+}
+
+// bump allocator for the key and payload of execute calls, set with
+// `PluginOptions::set_call_arena`
+//
+// one block of `size` bytes is allocated (and pinned) from the guest once,
+// the strings of a call are placed one after the other in it and the whole
+// block is reset after the call. this saves the malloc, pin and unpin calls
+// per string and keeps short lived strings away from the guest gc
+//
+// the guest must not keep references to key or payload after the call
+// returned, their memory is reused by the next call. strings which do not fit
+// are allocated by the guest as usual
+#[derive(Debug, Clone)]
+pub struct CallArena {
+  size: u32,
+  state: Arc<Mutex<ArenaState>>,
+}
+
+impl CallArena {
+  pub fn new(size: u32) -> Self {
+    Self {
+      size,
+      state: Arc::new(Mutex::new(ArenaState::default())),
+    }
+  }
+
+  pub fn capacity(&self) -> u32 {
+    self.size
+  }
+
+  pub fn is_reserved(&self) -> bool {
+    self.state.lock().unwrap().base.is_some()
+  }
+
+  // most bytes used by a single call so far, eg to size the arena
+  pub fn high_water(&self) -> u32 {
+    self.state.lock().unwrap().high_water
+  }
+
+  pub(crate) fn set_region(&self, base: u32) {
+    self.state.lock().unwrap().base = Some(base);
+  }
+
+  pub(crate) fn begin(&self) {
+    self.state.lock().unwrap().active = true;
+  }
+
+  // resets the arena, all strings of the call are released at once
+  pub(crate) fn finish(&self) {
+    let mut state = self.state.lock().unwrap();
+    state.active = false;
+    state.offset = 0;
+  }
+
+  pub(crate) fn is_active(&self) -> bool {
+    self.state.lock().unwrap().active
+  }
+
+  // pointer to `length` bytes preceded by `header` bytes for the length or
+  // object header, `None` if the arena is full, not reserved or not active
+  pub(crate) fn alloc(&self, header: u32, length: u32) -> Option<u32> {
+    let mut state = self.state.lock().unwrap();
+    let base = match (state.active, state.base) {
+      (true, Some(base)) => base,
+      _ => return None,
+    };
+    let start = base.checked_add(state.offset)?.checked_add(header)?;
+    let start = start.checked_add(ALIGNMENT - 1)? / ALIGNMENT * ALIGNMENT;
+    let end = start.checked_add(length)?;
+    if end - base > self.size {
+      return None;
+    }
+    state.offset = end - base;
+    state.high_water = state.high_water.max(state.offset);
+    Some(start)
+  }
+
+  pub(crate) fn contains(&self, ptr: u32) -> bool {
+    match self.state.lock().unwrap().base {
+      Some(base) => ptr >= base && ptr < base.saturating_add(self.size),
+      None => false,
+    }
+  }
+}
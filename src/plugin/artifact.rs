@@ -0,0 +1,166 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use sha2::{Digest, Sha256};
+use wasmer::{Target, VERSION};
+
+// extension usable for artifacts of every target, eg for artifacts shipped to
+// mixed fleets
+pub const NEUTRAL_ARTIFACT_EXTENSION: &str = "wmod";
+
+// extension of native artifacts for a target triple, without leading dot
+// the loader does not care, it mirrors the library suffix of the platform so
+// artifacts of several targets can live in one directory
+pub fn artifact_extension(target_triple: &str) -> &'static str {
+  if target_triple.contains("windows") {
+    "dll"
+  } else if target_triple.contains("apple") || target_triple.contains("darwin") {
+    "dylib"
+  } else {
+    "so"
+  }
+}
+
+// artifact path as string for `PluginOptions` and `compile_to_file`
+// drops the `\\?\` prefix `fs::canonicalize` adds on windows and uses the
+// separator of the platform throughout
+pub fn normalize_path(path: &Path) -> String {
+  let path = path.to_string_lossy();
+  let path = match path.strip_prefix(r"\\?\") {
+    Some(stripped) if !stripped.starts_with(r"UNC\") => stripped,
+    _ => &path,
+  };
+  if cfg!(windows) {
+    path.replace('/', "\\")
+  } else {
+    String::from(path)
+  }
+}
+
+// hex encoded sha-256 of an artifact or wasm file, identifies the module
+// independent of file name and modification time
+pub fn content_hash(bytes: &[u8]) -> String {
+  Sha256::digest(bytes)
+    .iter()
+    .map(|byte| format!("{:02x}", byte))
+    .collect()
+}
+
+// describes the machine a serialized module was compiled for
+// it is stored next to the artifact as `<artifact>.header` and checked before
+// the artifact is deserialized, as loading native code for another target is UB
+#[derive(Debug, Clone, PartialEq)]
+pub struct ArtifactHeader {
+  pub target_triple: String,
+  pub cpu_features: Vec<String>,
+  pub wasmer_version: String,
+  // wasm features enabled when compiling, `None` for artifacts of older versions
+  pub wasm_features: Option<Vec<String>>,
+}
+
+impl ArtifactHeader {
+  pub fn for_target(target: &Target) -> Self {
+    let mut cpu_features: Vec<String> = target
+      .cpu_features()
+      .iter()
+      .map(|feature| feature.to_string())
+      .collect();
+    cpu_features.sort();
+
+    Self {
+      target_triple: target.triple().to_string(),
+      cpu_features,
+      wasmer_version: String::from(VERSION),
+      wasm_features: None,
+    }
+  }
+
+  pub fn host() -> Self {
+    Self::for_target(&Target::default())
+  }
+
+  // eg "so" on linux, "dylib" on macos and "dll" on windows
+  pub fn extension(&self) -> &'static str {
+    artifact_extension(&self.target_triple)
+  }
+
+  // `<dir>/<name>.<extension>` for the target of this header
+  pub fn artifact_file(&self, dir: &Path, name: &str) -> String {
+    normalize_path(&dir.join(format!("{}.{}", name, self.extension())))
+  }
+
+  pub fn header_file(artifact_file: &String) -> String {
+    format!("{}.header", artifact_file)
+  }
+
+  pub fn write_to_file(&self, artifact_file: &String) -> io::Result<()> {
+    let mut content = format!(
+      "target_triple={}\ncpu_features={}\nwasmer_version={}\n",
+      self.target_triple,
+      self.cpu_features.join(","),
+      self.wasmer_version
+    );
+    if let Some(features) = &self.wasm_features {
+      content.push_str(&format!("wasm_features={}\n", features.join(",")));
+    }
+    fs::write(Self::header_file(artifact_file), content)
+  }
+
+  pub fn read_from_file(artifact_file: &String) -> io::Result<Self> {
+    let content = fs::read_to_string(Self::header_file(artifact_file))?;
+
+    let mut header = Self {
+      target_triple: String::new(),
+      cpu_features: vec![],
+      wasmer_version: String::new(),
+      wasm_features: None,
+    };
+    for line in content.lines() {
+      match line.split_once('=') {
+        Some(("target_triple", value)) => header.target_triple = String::from(value.trim()),
+        Some(("cpu_features", value)) => header.cpu_features = split_list(value),
+        Some(("wasmer_version", value)) => header.wasmer_version = String::from(value.trim()),
+        Some(("wasm_features", value)) => header.wasm_features = Some(split_list(value)),
+        _ => (),
+      }
+    }
+    Ok(header)
+  }
+
+  // checks if an artifact with this header can be executed on the given host
+  pub fn validate(&self, host: &ArtifactHeader) -> Result<(), String> {
+    if self.target_triple != host.target_triple {
+      return Err(format!(
+        "artifact compiled for target \"{}\" but host is \"{}\"",
+        self.target_triple, host.target_triple
+      ));
+    }
+    if self.wasmer_version != host.wasmer_version {
+      return Err(format!(
+        "artifact compiled with wasmer {} but host uses wasmer {}",
+        self.wasmer_version, host.wasmer_version
+      ));
+    }
+    let missing: Vec<&String> = self
+      .cpu_features
+      .iter()
+      .filter(|feature| !host.cpu_features.contains(feature))
+      .collect();
+    if !missing.is_empty() {
+      return Err(format!(
+        "artifact requires cpu features not available on host: {:?}",
+        missing
+      ));
+    }
+    Ok(())
+  }
+}
+
+fn split_list(value: &str) -> Vec<String> {
+  value
+    .split(',')
+    .map(|item| String::from(item.trim()))
+    .filter(|item| !item.is_empty())
+    .collect()
+}
@@ -0,0 +1,157 @@
+use std::fmt;
+use std::fs::OpenOptions;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use log::error;
+use wasmer::Val;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AuditDirection {
+  // the host calls an export of the guest, eg execute, malloc or the gc
+  HostToGuest,
+  // the guest calls a host function
+  GuestToHost,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AuditRecord {
+  // milliseconds since the unix epoch when the call started
+  pub timestamp_ms: u64,
+  pub module_name: String,
+  // `None` outside of an execute call, eg for init
+  pub call_id: Option<u64>,
+  pub trace_id: Option<String>,
+  pub direction: AuditDirection,
+  pub function: String,
+  // parameter summary, sizes instead of contents for strings
+  pub args: String,
+  pub duration: Duration,
+  pub error: Option<String>,
+}
+
+impl AuditRecord {
+  // one json object per line
+  pub fn to_json_line(&self) -> String {
+    let optional = |value: Option<String>| match value {
+      Some(value) => value,
+      None => String::from("null"),
+    };
+    format!(
+      "{{\"timestamp_ms\":{},\"module\":{},\"call_id\":{},\"trace_id\":{},\"direction\":\"{}\",\"function\":{},\"args\":{},\"duration_us\":{},\"error\":{}}}",
+      self.timestamp_ms,
+      json_string(&self.module_name),
+      optional(self.call_id.map(|id| id.to_string())),
+      optional(self.trace_id.as_ref().map(|id| json_string(id))),
+      match self.direction {
+        AuditDirection::HostToGuest => "host_to_guest",
+        AuditDirection::GuestToHost => "guest_to_host",
+      },
+      json_string(&self.function),
+      json_string(&self.args),
+      self.duration.as_micros(),
+      optional(self.error.as_ref().map(|error| json_string(error))),
+    )
+  }
+}
+
+pub(crate) fn json_string(value: &str) -> String {
+  let mut escaped = String::with_capacity(value.len() + 2);
+  escaped.push('"');
+  for c in value.chars() {
+    match c {
+      '"' => escaped.push_str("\\\""),
+      '\\' => escaped.push_str("\\\\"),
+      '\n' => escaped.push_str("\\n"),
+      '\r' => escaped.push_str("\\r"),
+      '\t' => escaped.push_str("\\t"),
+      c if (c as u32) < 0x20 => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+      c => escaped.push(c),
+    }
+  }
+  escaped.push('"');
+  escaped
+}
+
+pub type AuditCallback = dyn Fn(&AuditRecord) + Send + Sync;
+
+enum AuditSink {
+  Writer(Mutex<Box<dyn Write + Send>>),
+  Callback(Arc<AuditCallback>),
+}
+
+// records every call crossing the guest/host boundary, set with
+// `PluginOptions::set_audit_log`
+// guest calls to host functions are only seen for dynamic host functions
+// (`add_dynamic_host_function`), native ones can not be wrapped
+pub struct AuditLog {
+  sink: AuditSink,
+}
+
+impl fmt::Debug for AuditLog {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.debug_struct("AuditLog").finish()
+  }
+}
+
+impl AuditLog {
+  // writes json lines, every record is flushed before the call continues
+  pub fn new(writer: Box<dyn Write + Send>) -> Self {
+    Self {
+      sink: AuditSink::Writer(Mutex::new(writer)),
+    }
+  }
+
+  // opens the file in append mode, existing records are never rewritten
+  pub fn to_file(path: &Path) -> std::io::Result<Self> {
+    let file = OpenOptions::new().create(true).append(true).open(path)?;
+    Ok(Self::new(Box::new(BufWriter::new(file))))
+  }
+
+  pub fn callback(callback: impl Fn(&AuditRecord) + Send + Sync + 'static) -> Self {
+    Self {
+      sink: AuditSink::Callback(Arc::new(callback)),
+    }
+  }
+
+  pub fn record(&self, record: &AuditRecord) {
+    match &self.sink {
+      AuditSink::Writer(writer) => {
+        let mut writer = writer.lock().unwrap();
+        let written = writeln!(writer, "{}", record.to_json_line()).and_then(|_| writer.flush());
+        if let Err(error) = written {
+          error!(
+            "WASM:{} writing audit record for {} failed",
+            record.module_name, record.function
+          );
+          error!("{}", error);
+        }
+      }
+      AuditSink::Callback(callback) => callback(record),
+    }
+  }
+}
+
+pub fn now_ms() -> u64 {
+  SystemTime::now()
+    .duration_since(UNIX_EPOCH)
+    .map(|elapsed| elapsed.as_millis() as u64)
+    .unwrap_or(0)
+}
+
+// `i32:1, i64:2`
+pub fn summarize_values(values: &[Val]) -> String {
+  values
+    .iter()
+    .map(|value| match value {
+      Val::I32(v) => format!("i32:{}", v),
+      Val::I64(v) => format!("i64:{}", v),
+      Val::F32(v) => format!("f32:{}", v),
+      Val::F64(v) => format!("f64:{}", v),
+      _ => String::from("ref"),
+    })
+    .collect::<Vec<String>>()
+    .join(", ")
+}
@@ -0,0 +1,148 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use log::trace;
+
+use crate::plugin::metrics::{Counter, Metrics};
+use crate::plugin::middleware::{CallContext, Middleware, Next};
+use crate::plugin::PluginError;
+
+struct CacheEntry {
+  module_name: String,
+  key: String,
+  payload: String,
+  result: String,
+  created: Instant,
+  last_used: u64,
+}
+
+impl CacheEntry {
+  fn matches(&self, ctx: &CallContext) -> bool {
+    self.module_name == ctx.module_name && self.key == ctx.key && self.payload == ctx.payload
+  }
+}
+
+#[derive(Default)]
+struct CacheState {
+  entries: HashMap<u64, CacheEntry>,
+  tick: u64,
+}
+
+// LRU cache for results of idempotent transforms
+// only successful results are cached - errors always hit the guest again
+pub struct CacheMiddleware {
+  capacity: usize,
+  ttl: Option<Duration>,
+  state: Mutex<CacheState>,
+  hits: Arc<Counter>,
+  misses: Arc<Counter>,
+}
+
+impl CacheMiddleware {
+  pub fn new(capacity: usize, ttl: Option<Duration>) -> Self {
+    Self::with_metrics(capacity, ttl, &Metrics::new())
+  }
+
+  // hit and miss counters are registered as `cache_hits` and `cache_misses`
+  pub fn with_metrics(capacity: usize, ttl: Option<Duration>, metrics: &Metrics) -> Self {
+    Self {
+      capacity,
+      ttl,
+      state: Mutex::new(CacheState::default()),
+      hits: metrics.counter(&String::from("cache_hits")),
+      misses: metrics.counter(&String::from("cache_misses")),
+    }
+  }
+
+  pub fn hits(&self) -> u64 {
+    self.hits.get()
+  }
+
+  pub fn misses(&self) -> u64 {
+    self.misses.get()
+  }
+
+  pub fn clear(&self) {
+    self.state.lock().unwrap().entries.clear();
+  }
+
+  fn hash(ctx: &CallContext) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    ctx.module_name.hash(&mut hasher);
+    ctx.key.hash(&mut hasher);
+    ctx.payload.hash(&mut hasher);
+    hasher.finish()
+  }
+
+  fn lookup(&self, hash: u64, ctx: &CallContext) -> Option<String> {
+    let mut state = self.state.lock().unwrap();
+    state.tick += 1;
+    let tick = state.tick;
+
+    let expired = match state.entries.get_mut(&hash) {
+      Some(entry) if entry.matches(ctx) => match self.ttl {
+        Some(ttl) if entry.created.elapsed() > ttl => true,
+        _ => {
+          entry.last_used = tick;
+          return Some(entry.result.clone());
+        }
+      },
+      _ => false,
+    };
+    if expired {
+      state.entries.remove(&hash);
+    }
+    None
+  }
+
+  fn store(&self, hash: u64, ctx: &CallContext, result: &String) {
+    if self.capacity == 0 {
+      return;
+    }
+    let mut state = self.state.lock().unwrap();
+    state.tick += 1;
+    let tick = state.tick;
+
+    if !state.entries.contains_key(&hash) && state.entries.len() >= self.capacity {
+      let oldest = state
+        .entries
+        .iter()
+        .min_by_key(|(_, entry)| entry.last_used)
+        .map(|(hash, _)| *hash);
+      if let Some(oldest) = oldest {
+        state.entries.remove(&oldest);
+      }
+    }
+
+    state.entries.insert(
+      hash,
+      CacheEntry {
+        module_name: ctx.module_name.clone(),
+        key: ctx.key.clone(),
+        payload: ctx.payload.clone(),
+        result: result.clone(),
+        created: Instant::now(),
+        last_used: tick,
+      },
+    );
+  }
+}
+
+impl Middleware for CacheMiddleware {
+  fn handle(&self, ctx: &CallContext, next: Next) -> Result<String, PluginError> {
+    let hash = Self::hash(ctx);
+    if let Some(result) = self.lookup(hash, ctx) {
+      trace!("WASM:{} cache hit for \"{}\"", ctx.module_name, ctx.key);
+      self.hits.increment();
+      return Ok(result);
+    }
+    self.misses.increment();
+
+    let result = next.run(ctx)?;
+    self.store(hash, ctx, &result);
+    Ok(result)
+  }
+}
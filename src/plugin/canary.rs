@@ -0,0 +1,116 @@
+use std::sync::mpsc::{sync_channel, SyncSender, TrySendError};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use log::{error, warn};
+use semver::Version;
+
+use crate::plugin::events::GuestEvent;
+use crate::plugin::metrics::Metrics;
+use crate::plugin::shared::SharedPlugin;
+use crate::plugin::PluginError;
+
+// comparisons waiting for the canary thread, further ones are dropped
+const CANARY_QUEUE_SIZE: usize = 64;
+// comparisons waiting longer are skipped, eg while the candidate is stuck
+const CANARY_TIMEOUT: Duration = Duration::from_secs(5);
+
+pub(crate) struct CanaryJob {
+  pub(crate) name: String,
+  pub(crate) version: Version,
+  // caller of `execute_as`, the candidate runs as the same caller
+  pub(crate) caller: Option<String>,
+  pub(crate) key: String,
+  pub(crate) payload: String,
+  pub(crate) expected: Result<String, PluginError>,
+  pub(crate) candidate: SharedPlugin,
+}
+
+// runs canary candidates on a thread of the manager, so the caller only waits
+// for the active version. the thread is started with the first comparison and
+// ends when the manager is dropped
+#[derive(Default)]
+pub(crate) struct CanaryRunner {
+  sender: Mutex<Option<SyncSender<(Instant, CanaryJob)>>>,
+}
+
+impl CanaryRunner {
+  pub(crate) fn submit(&self, metrics: &Metrics, job: CanaryJob) {
+    let mut sender = self.sender.lock().unwrap();
+    if sender.is_none() {
+      *sender = self.start(metrics);
+    }
+    let name = job.name.clone();
+    let sent = match sender.as_ref() {
+      Some(sender) => sender.try_send((Instant::now(), job)),
+      None => return,
+    };
+    match sent {
+      Ok(()) => {}
+      Err(TrySendError::Full(_)) => {
+        metrics
+          .counter(&format!("{}.canary_dropped", name))
+          .increment();
+      }
+      Err(TrySendError::Disconnected(_)) => *sender = None,
+    }
+  }
+
+  fn start(&self, metrics: &Metrics) -> Option<SyncSender<(Instant, CanaryJob)>> {
+    let (sender, receiver) = sync_channel::<(Instant, CanaryJob)>(CANARY_QUEUE_SIZE);
+    let metrics = metrics.clone();
+    let started = thread::Builder::new()
+      .name(String::from("canary"))
+      .spawn(move || {
+        while let Ok((queued, job)) = receiver.recv() {
+          if queued.elapsed() > CANARY_TIMEOUT {
+            metrics
+              .counter(&format!("{}.canary_timeouts", job.name))
+              .increment();
+            continue;
+          }
+          compare(&metrics, job);
+        }
+      });
+    match started {
+      Ok(_) => Some(sender),
+      Err(error) => {
+        error!("starting canary thread failed");
+        error!("{}", error);
+        None
+      }
+    }
+  }
+}
+
+fn compare(metrics: &Metrics, job: CanaryJob) {
+  let actual = match &job.caller {
+    Some(caller) => job.candidate.execute_as(caller, &job.key, &job.payload),
+    None => job.candidate.execute(&job.key, &job.payload),
+  };
+  metrics
+    .counter(&format!("{}.canary_calls", job.name))
+    .increment();
+  if job.expected == actual {
+    return;
+  }
+  metrics
+    .counter(&format!("{}.canary_mismatches", job.name))
+    .increment();
+  warn!(
+    "WASM:{}@{} canary mismatch for key \"{}\": expected {:?} got {:?}",
+    job.name, job.version, job.key, job.expected, actual
+  );
+  job
+    .candidate
+    .options()
+    .events
+    .emit(GuestEvent::CanaryMismatch {
+      module_name: job.name.clone(),
+      version: job.version.to_string(),
+      key: job.key.clone(),
+      expected: job.expected.clone(),
+      actual,
+    });
+}
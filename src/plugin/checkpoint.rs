@@ -0,0 +1,208 @@
+use std::fs;
+use std::io::{Read, Write};
+use std::path::Path;
+
+use log::{error, info};
+use wasmer::{Extern, Instance, Memory, Mutability, Pages};
+
+use crate::plugin::artifact::content_hash;
+use crate::plugin::globals::hidden_mutable_globals;
+use crate::plugin::recorder::{encode_string, encode_values, Decoder, HostValue};
+use crate::plugin::PluginError;
+
+const CHECKPOINT_MAGIC: &[u8; 4] = b"ASC1";
+
+// snapshot of the guest state: linear memory and mutable globals
+//
+// globals which are not exported (eg the allocator state of the AssemblyScript
+// runtime) are only reachable in artifacts of `compile_to_file` with
+// `CompileOptions::enable_state_globals`, see `globals`. modules hiding mutable globals are rejected, restoring
+// memory without them corrupts the heap. the wasi state (open files) is not
+// captured, a guest restored from a checkpoint must keep its state in memory
+#[derive(Debug, Clone, PartialEq)]
+pub struct Checkpoint {
+  // sha-256 of the artifact the checkpoint was taken from
+  pub artifact: String,
+  // `init` config the state was built from
+  pub config: String,
+  pub memory: Vec<u8>,
+  pub globals: Vec<(String, HostValue)>,
+}
+
+impl Checkpoint {
+  // `fingerprint` of the artifact the instance was created from, see
+  // `artifact_fingerprint`
+  pub fn capture(
+    module_name: &String,
+    fingerprint: &String,
+    config: &String,
+    instance: &Instance,
+    memory: &Memory,
+  ) -> Result<Self, PluginError> {
+    check_globals_reachable(module_name, instance)?;
+    let memory = memory.view::<u8>().iter().map(|cell| cell.get()).collect();
+    let mut globals = vec![];
+    for (name, export) in instance.exports.iter() {
+      if let Extern::Global(global) = export {
+        if global.ty().mutability != Mutability::Var {
+          continue;
+        }
+        if let Some(value) = HostValue::from_val(&global.get()) {
+          globals.push((name.clone(), value));
+        }
+      }
+    }
+    Ok(Self {
+      artifact: fingerprint.clone(),
+      config: config.clone(),
+      memory,
+      globals,
+    })
+  }
+
+  pub fn apply(
+    &self,
+    module_name: &String,
+    fingerprint: &String,
+    instance: &Instance,
+    memory: &Memory,
+  ) -> Result<(), PluginError> {
+    if &self.artifact != fingerprint {
+      error!(
+        "WASM:{} checkpoint was taken from another artifact",
+        module_name
+      );
+      return Err(PluginError::InvalidCheckpoint);
+    }
+    check_globals_reachable(module_name, instance)?;
+
+    let page_size = wasmer::WASM_PAGE_SIZE as u64;
+    let pages = (self.memory.len() as u64).div_ceil(page_size);
+    let current = memory.size().0 as u64;
+    if pages > current {
+      if let Err(error) = memory.grow(Pages((pages - current) as u32)) {
+        error!("WASM:{} growing memory for checkpoint failed", module_name);
+        error!("{}", error);
+        return Err(PluginError::MemoryLimitExceeded);
+      }
+    }
+    let view = memory.view::<u8>();
+    for (index, cell) in view.iter().enumerate() {
+      cell.set(self.memory.get(index).copied().unwrap_or(0));
+    }
+
+    for (name, value) in self.globals.iter() {
+      let global = match instance.exports.get_global(name) {
+        Ok(global) => global,
+        Err(_) => {
+          error!(
+            "WASM:{} checkpoint global {} not exported",
+            module_name, name
+          );
+          return Err(PluginError::InvalidCheckpoint);
+        }
+      };
+      if let Err(error) = global.set(value.to_val()) {
+        error!("WASM:{} restoring global {} failed", module_name, name);
+        error!("{}", error);
+        return Err(PluginError::InvalidCheckpoint);
+      }
+    }
+    Ok(())
+  }
+
+  pub fn write_to_file(&self, file: &Path) -> Result<(), PluginError> {
+    let mut out = CHECKPOINT_MAGIC.to_vec();
+    encode_string(&mut out, &self.artifact);
+    encode_string(&mut out, &self.config);
+    out.extend((self.memory.len() as u64).to_le_bytes());
+    out.extend(&self.memory);
+    out.extend((self.globals.len() as u32).to_le_bytes());
+    for (name, _) in self.globals.iter() {
+      encode_string(&mut out, name);
+    }
+    let values: Vec<HostValue> = self.globals.iter().map(|(_, value)| *value).collect();
+    encode_values(&mut out, &values);
+
+    // written next to the target and renamed, so a crash never leaves half a checkpoint
+    let tmp_file = file.with_extension("tmp");
+    let written = fs::File::create(&tmp_file)
+      .and_then(|mut f| f.write_all(&out).and_then(|_| f.sync_all()))
+      .and_then(|_| fs::rename(&tmp_file, file));
+    if let Err(error) = written {
+      error!("writing checkpoint {:?} failed", file);
+      error!("{}", error);
+      return Err(PluginError::InvalidCheckpoint);
+    }
+    info!("checkpoint {:?} written", file);
+    Ok(())
+  }
+
+  pub fn read_from_file(file: &Path) -> Result<Self, PluginError> {
+    let mut bytes = vec![];
+    if let Err(error) = fs::File::open(file).and_then(|mut f| f.read_to_end(&mut bytes)) {
+      error!("reading checkpoint {:?} failed", file);
+      error!("{}", error);
+      return Err(PluginError::InvalidCheckpoint);
+    }
+    match parse(&bytes) {
+      Some(checkpoint) => Ok(checkpoint),
+      None => {
+        error!("invalid checkpoint {:?}", file);
+        Err(PluginError::InvalidCheckpoint)
+      }
+    }
+  }
+}
+
+// content hash of the compiled artifact, size and modification time change
+// with every copy or rebuild and may match for different content
+pub fn artifact_fingerprint(artifact_file: &String) -> Result<String, PluginError> {
+  match fs::read(artifact_file) {
+    Ok(bytes) => Ok(content_hash(&bytes)),
+    Err(error) => {
+      error!(
+        "reading artifact \"{}\" for checkpoint failed",
+        artifact_file
+      );
+      error!("{}", error);
+      Err(PluginError::InvalidCheckpoint)
+    }
+  }
+}
+
+fn check_globals_reachable(module_name: &String, instance: &Instance) -> Result<(), PluginError> {
+  let hidden = hidden_mutable_globals(instance.module());
+  if hidden > 0 {
+    error!(
+      "WASM:{} has {} mutable globals which are not exported, recompile it with `CompileOptions::enable_state_globals`",
+      module_name, hidden
+    );
+    return Err(PluginError::InvalidCheckpoint);
+  }
+  Ok(())
+}
+
+fn parse(bytes: &[u8]) -> Option<Checkpoint> {
+  let mut decoder = Decoder {
+    bytes: bytes.strip_prefix(CHECKPOINT_MAGIC)?,
+  };
+  let artifact = decoder.string()?;
+  let config = decoder.string()?;
+  let length = usize::try_from(decoder.u64()?).ok()?;
+  let memory = decoder.take(length)?.to_vec();
+  let mut names = vec![];
+  for _ in 0..decoder.u32()? {
+    names.push(decoder.string()?);
+  }
+  let values = decoder.values()?;
+  if values.len() != names.len() {
+    return None;
+  }
+  Some(Checkpoint {
+    artifact,
+    config,
+    memory,
+    globals: names.into_iter().zip(values).collect(),
+  })
+}
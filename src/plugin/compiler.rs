@@ -0,0 +1,252 @@
+use std::fs;
+use std::str::FromStr;
+
+use enumset::EnumSet;
+use log::{debug, error};
+use rayon::prelude::*;
+use rayon::ThreadPoolBuilder;
+use wasmer::{
+  wat2wasm, CpuFeature, Module, Store, Target, Triple, Universal, UniversalEngine, LLVM,
+};
+
+use crate::plugin::artifact::ArtifactHeader;
+use crate::plugin::features::WasmFeatures;
+use crate::plugin::globals::export_state_globals;
+use crate::plugin::PluginError;
+
+// options for the ahead-of-time compile step
+// by default the artifact is compiled for the current host
+#[derive(Debug, Clone, Default)]
+pub struct CompileOptions {
+  target_triple: Option<String>,
+  cpu_features: Option<Vec<String>>,
+  wasm_features: WasmFeatures,
+  state_globals: bool,
+}
+
+impl CompileOptions {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  // eg "aarch64-unknown-linux-gnu" to compile on x86_64 ci for arm edge devices
+  pub fn set_target_triple(&mut self, triple: &String) -> &mut Self {
+    self.target_triple = Some(triple.clone());
+    self
+  }
+
+  // eg "sse2", "avx2" - if none are added the host features are used for host
+  // targets and no extra features for foreign targets
+  pub fn add_cpu_feature(&mut self, feature: &String) -> &mut Self {
+    self
+      .cpu_features
+      .get_or_insert_with(Vec::new)
+      .push(feature.clone());
+    self
+  }
+
+  // wasm proposals the modules may use, see `WasmFeatures`
+  pub fn set_wasm_features(&mut self, features: WasmFeatures) -> &mut Self {
+    self.wasm_features = features;
+    self
+  }
+
+  // exports the hidden mutable globals of the module, see `globals`, needed
+  // for `Checkpoint` and `WarmTemplate`
+  pub fn enable_state_globals(&mut self) -> &mut Self {
+    self.state_globals = true;
+    self
+  }
+
+  pub fn wasm_features(&self) -> &WasmFeatures {
+    &self.wasm_features
+  }
+
+  pub fn target(&self) -> Result<Target, PluginError> {
+    let triple = match &self.target_triple {
+      Some(name) => match Triple::from_str(name) {
+        Ok(triple) => triple,
+        Err(error) => {
+          error!("invalid target triple \"{}\": {}", name, error);
+          return Err(PluginError::CompileFailed);
+        }
+      },
+      None => Triple::host(),
+    };
+
+    let cpu_features = match &self.cpu_features {
+      Some(names) => {
+        let mut features = EnumSet::new();
+        for name in names {
+          match CpuFeature::from_str(name) {
+            Ok(feature) => {
+              features.insert(feature);
+            }
+            Err(error) => {
+              error!("invalid cpu feature \"{}\": {}", name, error);
+              return Err(PluginError::CompileFailed);
+            }
+          }
+        }
+        features
+      }
+      None if triple == Triple::host() => CpuFeature::for_host(),
+      None => EnumSet::new(),
+    };
+
+    Ok(Target::new(triple, cpu_features))
+  }
+
+  fn header(&self, target: &Target) -> ArtifactHeader {
+    let mut header = ArtifactHeader::for_target(target);
+    header.wasm_features = Some(self.wasm_features.names());
+    header
+  }
+
+  fn engine(&self, target: Target) -> UniversalEngine {
+    Universal::new(LLVM::new())
+      .target(target)
+      .features(self.wasm_features.to_wasmer())
+      .engine()
+  }
+}
+
+#[derive(Debug, Clone)]
+pub struct CompileJob {
+  pub wasm_file: String,
+  pub artifact_file: String,
+}
+
+impl CompileJob {
+  pub fn new(wasm_file: &String, artifact_file: &String) -> Self {
+    Self {
+      wasm_file: wasm_file.clone(),
+      artifact_file: artifact_file.clone(),
+    }
+  }
+}
+
+// compiles the wasm file to a native artifact and writes the matching artifact header
+pub fn compile_to_file(
+  wasm_file: &String,
+  artifact_file: &String,
+  options: &CompileOptions,
+) -> Result<ArtifactHeader, PluginError> {
+  let target = options.target()?;
+  let header = options.header(&target);
+
+  let engine = options.engine(target);
+  let store = Store::new(&engine);
+
+  compile_with_store(
+    &store,
+    &header,
+    &CompileJob::new(wasm_file, artifact_file),
+    options.state_globals,
+  )?;
+  Ok(header)
+}
+
+// compiles a set of wasm files in parallel, all jobs share one engine
+// `threads` of 0 uses one thread per cpu
+// results are returned in the same order as the jobs
+pub fn compile_all(
+  jobs: &[CompileJob],
+  options: &CompileOptions,
+  threads: usize,
+) -> Vec<Result<ArtifactHeader, PluginError>> {
+  let target = match options.target() {
+    Ok(target) => target,
+    Err(error) => return jobs.iter().map(|_| Err(error.clone())).collect(),
+  };
+  let header = options.header(&target);
+
+  let engine = options.engine(target);
+  let store = Store::new(&engine);
+
+  let pool = match ThreadPoolBuilder::new().num_threads(threads).build() {
+    Ok(pool) => pool,
+    Err(error) => {
+      error!("creating compile thread pool failed");
+      error!("{}", error);
+      return jobs
+        .iter()
+        .map(|_| Err(PluginError::CompileFailed))
+        .collect();
+    }
+  };
+
+  pool.install(|| {
+    jobs
+      .par_iter()
+      .map(|job| {
+        compile_with_store(&store, &header, job, options.state_globals).map(|_| header.clone())
+      })
+      .collect()
+  })
+}
+
+fn compile_with_store(
+  store: &Store,
+  header: &ArtifactHeader,
+  job: &CompileJob,
+  state_globals: bool,
+) -> Result<(), PluginError> {
+  debug!(
+    "compiling module \"{}\" for {}",
+    job.wasm_file, header.target_triple
+  );
+  let bytes = match fs::read(&job.wasm_file) {
+    Ok(bytes) => bytes,
+    Err(error) => {
+      error!("reading module \"{}\" failed", job.wasm_file);
+      error!("{}", error);
+      return Err(PluginError::CompileFailed);
+    }
+  };
+  let wasm = match wat2wasm(&bytes) {
+    Ok(wasm) => wasm.to_vec(),
+    Err(error) => {
+      error!("parsing module \"{}\" failed", job.wasm_file);
+      error!("{}", error);
+      return Err(PluginError::CompileFailed);
+    }
+  };
+  // hidden runtime state must be reachable for checkpoints and warm templates
+  let wasm = if state_globals {
+    match export_state_globals(&wasm) {
+      Some(wasm) => wasm,
+      None => {
+        error!("exporting state globals of \"{}\" failed", job.wasm_file);
+        return Err(PluginError::CompileFailed);
+      }
+    }
+  } else {
+    wasm
+  };
+  let module = match Module::new(store, wasm) {
+    Ok(m) => m,
+    Err(error) => {
+      error!("compiling module \"{}\" failed", job.wasm_file);
+      error!("{}", error);
+      return Err(PluginError::CompileFailed);
+    }
+  };
+
+  debug!("serialize compiled module to \"{}\"", job.artifact_file);
+  if let Err(error) = module.serialize_to_file(&job.artifact_file) {
+    error!("serializing module to \"{}\" failed", job.artifact_file);
+    error!("{}", error);
+    return Err(PluginError::CompileFailed);
+  }
+  if let Err(error) = header.write_to_file(&job.artifact_file) {
+    error!(
+      "writing artifact header for \"{}\" failed",
+      job.artifact_file
+    );
+    error!("{}", error);
+    return Err(PluginError::CompileFailed);
+  }
+
+  Ok(())
+}
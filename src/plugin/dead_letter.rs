@@ -0,0 +1,190 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use log::error;
+
+use crate::plugin::audit::{json_string, now_ms};
+use crate::plugin::middleware::CallContext;
+use crate::plugin::sections::{parse_json, JsonValue};
+use crate::plugin::PluginError;
+
+// input of a failed execute call, written as one json file per call
+#[derive(Debug, Clone, PartialEq)]
+pub struct DeadLetter {
+  pub timestamp_ms: u64,
+  pub module_name: String,
+  pub trace_id: String,
+  pub key: String,
+  pub payload: String,
+  // `PluginError` in debug format, eg `GuestExited(1)`
+  pub error: String,
+  // guest stderr of the failed call
+  pub stderr: String,
+}
+
+impl DeadLetter {
+  pub fn to_json(&self) -> String {
+    format!(
+      "{{\"timestamp_ms\":{},\"module\":{},\"trace_id\":{},\"key\":{},\"payload\":{},\"error\":{},\"stderr\":{}}}",
+      self.timestamp_ms,
+      json_string(&self.module_name),
+      json_string(&self.trace_id),
+      json_string(&self.key),
+      json_string(&self.payload),
+      json_string(&self.error),
+      json_string(&self.stderr),
+    )
+  }
+
+  pub fn from_json(content: &str) -> Result<Self, String> {
+    let value = parse_json(content)?;
+    let field = |name: &str| match value.get(name).and_then(|v| v.as_str()) {
+      Some(v) => Ok(String::from(v)),
+      None => Err(format!("missing field \"{}\"", name)),
+    };
+    let timestamp_ms = match value.get("timestamp_ms") {
+      Some(JsonValue::Number(n)) if *n >= 0.0 => *n as u64,
+      _ => return Err(String::from("missing field \"timestamp_ms\"")),
+    };
+    Ok(Self {
+      timestamp_ms,
+      module_name: field("module")?,
+      trace_id: field("trace_id")?,
+      key: field("key")?,
+      payload: field("payload")?,
+      error: field("error")?,
+      stderr: field("stderr")?,
+    })
+  }
+}
+
+pub type DeadLetterCallback = dyn Fn(&DeadLetter) + Send + Sync;
+
+enum DeadLetterSink {
+  Directory(PathBuf),
+  Callback(Arc<DeadLetterCallback>),
+}
+
+// keeps the inputs of failed execute calls so they can be replayed once the
+// plugin is fixed, set with `PluginOptions::set_dead_letters`
+// calls answered by a fallback are captured as well
+pub struct DeadLetterQueue {
+  sink: DeadLetterSink,
+  // guest stderr of the running calls by call id
+  stderr: Mutex<HashMap<u64, String>>,
+}
+
+impl fmt::Debug for DeadLetterQueue {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.debug_struct("DeadLetterQueue").finish()
+  }
+}
+
+impl DeadLetterQueue {
+  // one `<timestamp>-<module>-<call id>.json` file per failed call
+  pub fn to_dir(path: &Path) -> std::io::Result<Self> {
+    fs::create_dir_all(path)?;
+    Ok(Self::with_sink(DeadLetterSink::Directory(
+      path.to_path_buf(),
+    )))
+  }
+
+  pub fn callback(callback: impl Fn(&DeadLetter) + Send + Sync + 'static) -> Self {
+    Self::with_sink(DeadLetterSink::Callback(Arc::new(callback)))
+  }
+
+  fn with_sink(sink: DeadLetterSink) -> Self {
+    Self {
+      sink,
+      stderr: Mutex::new(HashMap::new()),
+    }
+  }
+
+  pub(crate) fn capture_stderr(&self, call_id: u64, out: &String) {
+    self
+      .stderr
+      .lock()
+      .unwrap()
+      .entry(call_id)
+      .or_default()
+      .push_str(out);
+  }
+
+  // called once per execute call with its result
+  pub(crate) fn finish(&self, ctx: &CallContext, result: &Result<String, PluginError>) {
+    let stderr = self.stderr.lock().unwrap().remove(&ctx.call_id);
+    let error = match result {
+      Ok(_) => return,
+      Err(error) => error,
+    };
+    let letter = DeadLetter {
+      timestamp_ms: now_ms(),
+      module_name: ctx.module_name.clone(),
+      trace_id: ctx.trace_id.clone(),
+      key: ctx.key.clone(),
+      payload: ctx.payload.clone(),
+      error: format!("{:?}", error),
+      stderr: stderr.unwrap_or_default(),
+    };
+    match &self.sink {
+      DeadLetterSink::Directory(dir) => {
+        let file = dir.join(format!(
+          "{}-{}-{}.json",
+          letter.timestamp_ms, letter.module_name, ctx.call_id
+        ));
+        if let Err(error) = write_file(&file, &letter.to_json()) {
+          error!(
+            "WASM:{} writing dead letter {:?} failed",
+            letter.module_name, file
+          );
+          error!("{}", error);
+        }
+      }
+      DeadLetterSink::Callback(callback) => callback(&letter),
+    }
+  }
+}
+
+// written next to the target and renamed, readers never see half a file
+fn write_file(file: &Path, content: &String) -> std::io::Result<()> {
+  let tmp = file.with_extension("json.tmp");
+  let mut out = fs::File::create(&tmp)?;
+  out.write_all(content.as_bytes())?;
+  out.sync_all()?;
+  fs::rename(&tmp, file)
+}
+
+// the dead letters of a directory, oldest first, for replaying them
+pub fn read_dir(path: &Path) -> Result<Vec<(PathBuf, DeadLetter)>, PluginError> {
+  let entries = match fs::read_dir(path) {
+    Ok(entries) => entries,
+    Err(error) => {
+      error!("reading dead letters {:?} failed", path);
+      error!("{}", error);
+      return Err(PluginError::InvalidDeadLetter);
+    }
+  };
+  let mut letters = vec![];
+  for entry in entries.flatten() {
+    let file = entry.path();
+    if file.extension().map(|ext| ext != "json").unwrap_or(true) {
+      continue;
+    }
+    let letter = fs::read_to_string(&file)
+      .map_err(|error| error.to_string())
+      .and_then(|content| DeadLetter::from_json(&content));
+    match letter {
+      Ok(letter) => letters.push((file, letter)),
+      Err(message) => {
+        error!("invalid dead letter {:?}: {}", file, message);
+        return Err(PluginError::InvalidDeadLetter);
+      }
+    }
+  }
+  letters.sort_by_key(|(_, letter)| letter.timestamp_ms);
+  Ok(letters)
+}
@@ -1,16 +1,67 @@
-use log::{debug, error, info};
-use wasmer::{Instance, Module, NativeFunc};
+use std::cell::Cell;
+use std::marker::PhantomData;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use log::{debug, error, info, warn};
+use rayon::prelude::*;
+use rayon::ThreadPoolBuilder;
+use wasmer::{Function, Instance, Module, NativeFunc};
 use wasmer_wasi::{Pipe, WasiEnv, WasiState};
 
-use crate::plugin::{helper_get_function, Plugin, PluginError, PluginOptions, WasmerStringPtr};
+use crate::plugin::arena::CallArena;
+use crate::plugin::artifact::ArtifactHeader;
+use crate::plugin::errors::{
+  last_host_error, raise_error, ErrorEnv, LAST_HOST_ERROR_FUNCTION, RAISE_ERROR_FUNCTION,
+};
+use crate::plugin::events::{EventKind, GuestEvent, LifecycleState};
+use crate::plugin::function_table::FunctionTable;
+use crate::plugin::heap::{read_heap_stats, GcCounter, HeapStats};
+use crate::plugin::host::{get_trace_id, TraceEnv};
+use crate::plugin::limits::check_size;
+use crate::plugin::middleware::CallContext;
+use crate::plugin::probe::{probe_exports, Allocator, ExportConvention};
+use crate::plugin::recorder::wrap_host_function;
+use crate::plugin::report::{CallReport, ReportCapture};
+use crate::plugin::scratch::ScratchDir;
+use crate::plugin::sections::ModuleSections;
+use crate::plugin::string_abi::{ResultAbi, StringAbi};
+use crate::plugin::syscall::apply_syscall_policy;
+use crate::plugin::wasi::{apply_wasi_stubs, wasi_import_object};
+use crate::plugin::{
+  helper_get_function, Plugin, PluginError, PluginOptions, ReallocFn, WasmerStringPtr,
+};
 
+// execute export resolved for the configured `ResultAbi`
+#[derive(Clone)]
+enum ExecuteFn {
+  LengthHeader(NativeFunc<(WasmerStringPtr, WasmerStringPtr), WasmerStringPtr>),
+  PointerLength(NativeFunc<(WasmerStringPtr, WasmerStringPtr), (u32, u32)>),
+  OutBuffer(
+    NativeFunc<(WasmerStringPtr, WasmerStringPtr, WasmerStringPtr, u32), i32>,
+    u32,
+  ),
+}
+
+// a plugin must only run one guest call at a time, so it is not `Sync`
+// use `SharedPlugin` to call it from several threads
 #[derive(Clone)]
 pub struct DefaultPlugin {
   options: PluginOptions,
   instance: Instance,
   environment: WasiEnv,
-  execute_fn: NativeFunc<(WasmerStringPtr, WasmerStringPtr), WasmerStringPtr>,
-  malloc_fn: NativeFunc<u32, WasmerStringPtr>,
+  execute_fn: ExecuteFn,
+  malloc_fn: Option<NativeFunc<u32, WasmerStringPtr>>,
+  realloc_fn: Option<ReallocFn>,
+  functions: FunctionTable,
+  arena: Option<CallArena>,
+  exports: ExportConvention,
+  exit_code: Arc<Mutex<Option<u32>>>,
+  gc: GcCounter,
+  report: ReportCapture,
+  scratch_dir: Option<Arc<ScratchDir>>,
+  not_sync: PhantomData<Cell<()>>,
 }
 
 impl Plugin for DefaultPlugin {
@@ -20,137 +71,629 @@ impl Plugin for DefaultPlugin {
   fn get_instance(&self) -> &Instance {
     &self.instance
   }
-  fn get_malloc_fn(&self) -> &NativeFunc<u32, WasmerStringPtr> {
-    &self.malloc_fn
+  fn get_malloc_fn(&self) -> Option<&NativeFunc<u32, WasmerStringPtr>> {
+    self.malloc_fn.as_ref()
   }
   fn get_options(&self) -> &PluginOptions {
     &self.options
   }
+  fn last_exit_code(&self) -> Option<u32> {
+    *self.exit_code.lock().unwrap()
+  }
+  fn record_exit_code(&self, code: u32) {
+    *self.exit_code.lock().unwrap() = Some(code);
+  }
+
+  fn get_realloc_fn(&self) -> Option<&ReallocFn> {
+    self.realloc_fn.as_ref()
+  }
+
+  fn get_function_table(&self) -> Option<&FunctionTable> {
+    Some(&self.functions)
+  }
 
-  fn create(options: PluginOptions) -> Result<Self, PluginError> {
+  fn get_call_arena(&self) -> Option<&CallArena> {
+    self.arena.as_ref()
+  }
+
+  fn get_report_capture(&self) -> Option<&ReportCapture> {
+    Some(&self.report)
+  }
+
+  fn create(mut options: PluginOptions) -> Result<Self, PluginError> {
     info!(
       "WASM:{} start create wasm plugin from \"{}\"",
       &options.module_name, options.file
     );
 
-    debug!("WASM:{} loading module file", options.module_name);
-    let module = unsafe {
-      match Module::deserialize_from_file(&options.store, &options.file) {
-        Ok(m) => {
-          debug!("WASM:{} loading done", options.module_name);
-          m
-        }
-        Err(error) => {
-          error!("WASM:{} loading module failed", options.module_name);
-          error!("{}", error);
-          return Err(PluginError::LoadingError);
-        }
-      }
-    };
+    let (instance, environment, scratch_dir) = instantiate(&mut options)?;
+    let exports = probe_exports(&instance, &mut options);
 
-    let wasi_env_create = WasiState::new(&options.module_name)
-      .stdin(Box::new(Pipe::new()))
-      .stdout(Box::new(Pipe::new()))
-      .stderr(Box::new(Pipe::new()))
-      .envs(options.envs.clone())
-      .args(options.args.clone())
-      .finalize();
-
-    let mut environment = match wasi_env_create {
-      Ok(env) => {
-        debug!("WASM:{} wasi environment ok", options.module_name);
-        env
-      }
-      Err(error) => {
-        error!(
-          "WASM:{} create wasi environment failed",
-          options.module_name
-        );
-        error!("{}", error);
-        return Err(PluginError::InitWasiEnvFailed);
+    let name = &options.execute_function_name;
+    let execute_fn = match options.result_abi(name) {
+      ResultAbi::LengthHeader => {
+        ExecuteFn::LengthHeader(helper_get_function(&instance, &options, name)?)
       }
-    };
-    let mut import_object = match environment.import_object(&module) {
-      Ok(o) => {
-        debug!("WASM:{} wasi import object ok", options.module_name);
-        o
+      ResultAbi::PointerLength => {
+        ExecuteFn::PointerLength(helper_get_function(&instance, &options, name)?)
       }
-      Err(error) => {
-        error!("WASM:{} wasi import object failed", options.module_name);
-        error!("{}", error);
-        return Err(PluginError::WasiImportObjectFailed);
+      ResultAbi::OutBuffer { capacity } => {
+        ExecuteFn::OutBuffer(helper_get_function(&instance, &options, name)?, capacity)
       }
     };
 
-    debug!("WASM:{} init custom environment", options.module_name);
-
-    import_object.register("custom", options.custom_exports.clone());
-
-    debug!("WASM:{} create new instance", options.module_name);
-    let instance = match Instance::new(&module, &import_object) {
-      Ok(i) => {
-        debug!("WASM:{} instance created", options.module_name);
-        i
+    // without malloc export (eg an unmodified `--exportRuntime` build) and for
+    // utf-16 strings, allocations go through the AssemblyScript runtime `__new`
+    // length prefixed strings need malloc or the component model realloc
+    let mut realloc_fn = None;
+    let malloc_fn = match &exports.allocator {
+      Allocator::Malloc(name) => Some(helper_get_function::<u32, WasmerStringPtr>(
+        &instance, &options, name,
+      )?),
+      Allocator::RuntimeNew(name) => {
+        if options.string_abi == StringAbi::Utf8ArrayBuffer {
+          debug!(
+            "WASM:{} no {} export - using {}",
+            options.module_name, options.allocate_utf8array_function_name, name
+          );
+          helper_get_function::<(u32, u32), WasmerStringPtr>(&instance, &options, name)?;
+        }
+        None
       }
-      Err(error) => {
-        error!("WASM:{} create instance failed", options.module_name);
-        error!("{}", error);
-        return Err(PluginError::InstanceInitFailed);
+      Allocator::CanonicalRealloc(name) => {
+        realloc_fn = Some(
+          helper_get_function::<(u32, u32, u32, u32), WasmerStringPtr>(&instance, &options, name)?,
+        );
+        None
       }
     };
 
-    let execute_fn = helper_get_function::<(WasmerStringPtr, WasmerStringPtr), WasmerStringPtr>(
-      &instance,
-      &options,
-      &options.execute_function_name,
-    )?;
+    let functions = FunctionTable::for_plugin(&instance, &options);
+    let arena = options.call_arena_size.map(CallArena::new);
 
-    let malloc_fn = helper_get_function::<u32, WasmerStringPtr>(
-      &instance,
-      &options,
-      &options.allocate_utf8array_function_name,
-    )?;
-
-    Ok(Self {
+    let plugin = Self {
       options,
       instance,
       environment,
       execute_fn,
       malloc_fn,
-    })
+      realloc_fn,
+      functions,
+      arena,
+      exports,
+      exit_code: Arc::new(Mutex::new(None)),
+      gc: GcCounter::default(),
+      report: ReportCapture::default(),
+      scratch_dir,
+      not_sync: PhantomData,
+    };
+    plugin.emit_lifecycle(LifecycleState::Created);
+    Ok(plugin)
   }
 }
 
 impl DefaultPlugin {
-  pub fn execute(&self, key: &String, payload: &String) -> Result<String, PluginError> {
-    let key_ptr = self.allocate_string(key);
-    let payload_ptr = self.allocate_string(payload);
-
-    let result = match self.execute_fn.call(key_ptr, payload_ptr) {
-      Ok(result_ptr) => {
-        match self.read_from_stdout() {
-          Some(out) => info!(
-            "WASM:{}:{} \"{}\"",
-            self.options.module_name, self.options.execute_function_name, out
-          ),
-          None => (),
-        };
-        Ok(self.get_string(result_ptr))
+  // exports and conventions detected on create, including the fallbacks used
+  // for missing exports, eg `println!("{}", plugin.describe())`
+  pub fn describe(&self) -> &ExportConvention {
+    &self.exports
+  }
+
+  // memory size, gc runs and what the guest reports about its heap, see
+  // `HeapStats`, eg to find leaks which make `__collect` slow
+  // calls the guest, must not be used while another call is running
+  pub fn heap_stats(&self) -> Result<HeapStats, PluginError> {
+    read_heap_stats(
+      &self.options.module_name,
+      &self.instance,
+      self.get_memory(),
+      &self.gc,
+    )
+  }
+
+  // host path of the scratch dir, see `PluginOptions::enable_scratch_dir`
+  pub fn scratch_dir(&self) -> Option<&Path> {
+    self.scratch_dir.as_ref().map(|dir| dir.path())
+  }
+
+  pub fn clear_scratch_dir(&self) -> Result<(), PluginError> {
+    match &self.scratch_dir {
+      Some(dir) => dir.clear().map_err(|error| {
+        error!(
+          "WASM:{} clearing scratch dir failed",
+          self.options.module_name
+        );
+        error!("{}", error);
+        PluginError::RuntimeError
+      }),
+      None => Ok(()),
+    }
+  }
+
+  // deserializes and instantiates a set of plugins in parallel
+  // `threads` of 0 uses one thread per cpu
+  // results are returned in the same order as the options
+  pub fn create_all(
+    options: Vec<PluginOptions>,
+    threads: usize,
+  ) -> Vec<Result<DefaultPlugin, PluginError>> {
+    let pool = match ThreadPoolBuilder::new().num_threads(threads).build() {
+      Ok(pool) => pool,
+      Err(error) => {
+        error!("creating plugin loader thread pool failed");
+        error!("{}", error);
+        return options
+          .iter()
+          .map(|_| Err(PluginError::LoadingError))
+          .collect();
       }
-      Err(error) => Err(self.log_and_transform_error(error, &self.options.execute_function_name)),
     };
 
+    pool.install(|| options.into_par_iter().map(DefaultPlugin::create).collect())
+  }
+
+  pub fn execute(&self, key: &String, payload: &String) -> Result<String, PluginError> {
+    let ctx = self.new_context(key, payload);
+    self.run_execute(&ctx)
+  }
+
+  // same as `execute` with a correlation id which is available to the guest via
+  // the `get_trace_id` host function and part of all host side log lines of the call
+  pub fn execute_traced(
+    &self,
+    trace_id: &String,
+    key: &String,
+    payload: &String,
+  ) -> Result<String, PluginError> {
+    let ctx = self.new_context(key, payload).with_trace_id(trace_id);
+    self.run_execute(&ctx)
+  }
+
+  // same as `execute` and returns the diagnostics of the call as well, eg to
+  // attach them to own telemetry
+  pub fn execute_with_report(
+    &self,
+    key: &String,
+    payload: &String,
+  ) -> (Result<String, PluginError>, CallReport) {
+    let ctx = self.new_context(key, payload);
+    self.report.begin(&ctx);
+    let start = Instant::now();
+    let result = self.run_execute(&ctx);
+    (result, self.report.finish(start.elapsed()))
+  }
+
+  // same as `execute` but the call is accounted to given caller for per caller rate limits
+  pub fn execute_as(
+    &self,
+    caller: &String,
+    key: &String,
+    payload: &String,
+  ) -> Result<String, PluginError> {
+    let ctx = self.new_context(key, payload).with_caller(caller);
+    self.run_execute(&ctx)
+  }
+
+  // `execute` with an optional trace id and caller, both end up in the context
+  // of the call, eg for messages of a `PluginActor`
+  pub(crate) fn execute_with(
+    &self,
+    trace_id: Option<&String>,
+    caller: Option<&String>,
+    key: &String,
+    payload: &String,
+  ) -> Result<String, PluginError> {
+    let mut ctx = self.new_context(key, payload);
+    if let Some(trace_id) = trace_id {
+      ctx = ctx.with_trace_id(trace_id);
+    }
+    if let Some(caller) = caller {
+      ctx = ctx.with_caller(caller);
+    }
+    self.run_execute(&ctx)
+  }
+
+  fn new_context(&self, key: &String, payload: &String) -> CallContext {
+    let ctx = CallContext::new(&self.options.module_name, key, payload);
+    match self.options.call_timeout {
+      Some(timeout) => ctx.with_timeout(timeout),
+      None => ctx,
+    }
+  }
+
+  fn run_execute(&self, ctx: &CallContext) -> Result<String, PluginError> {
+    if let Some(limiter) = &self.options.rate_limiter {
+      limiter.acquire(&self.options.module_name)?;
+    }
+    if let (Some(limiter), Some(caller)) = (&self.options.caller_rate_limiter, &ctx.caller) {
+      limiter.acquire(caller)?;
+    }
+
+    let result = self.options.middlewares.run(ctx, &|ctx| {
+      self.options.host_env.enter(ctx);
+      if let Some(arena) = &self.arena {
+        arena.begin();
+      }
+      self.options.error_slots.clear();
+      let result = self.call_execute(ctx);
+      if let Some(arena) = &self.arena {
+        arena.finish();
+      }
+      // a raised error wins over the result and over a trap following it
+      let result = match self.options.error_slots.take_guest_error() {
+        Some(guest_error) => {
+          warn!(
+            "WASM:{} {} raised error {}: {}",
+            self.options.module_name,
+            self.options.execute_function_name,
+            guest_error.code,
+            guest_error.message
+          );
+          Err(PluginError::GuestError(guest_error))
+        }
+        None => result,
+      };
+      self.options.host_env.leave();
+      result
+    });
+    if let Some(dead_letters) = &self.options.dead_letters {
+      dead_letters.finish(ctx, &result);
+    }
+    match &self.options.fallback {
+      Some(fallback) => fallback.resolve(ctx, result),
+      None => result,
+    }
+  }
+
+  fn call_execute(&self, ctx: &CallContext) -> Result<String, PluginError> {
+    let key = &self.options.guest_key(ctx);
+    let payload = &ctx.payload;
+    let limits = &self.options.size_limits;
+    check_size(&self.options.module_name, "key", key.len(), limits.key)?;
+    check_size(
+      &self.options.module_name,
+      "payload",
+      payload.len(),
+      limits.payload,
+    )?;
+
+    // pinned strings are released on every path, a failed allocation must not
+    // leave the key pinned
+    let key_ptr = self.allocate_string(key)?;
+    let payload_ptr = match self.allocate_string(payload) {
+      Ok(ptr) => ptr,
+      Err(error) => {
+        self.release_string(key_ptr)?;
+        return Err(error);
+      }
+    };
+    let args = || format!("key {} bytes, payload {} bytes", key.len(), payload.len());
+    let result = self.call_execute_fn(key_ptr, payload_ptr, args);
+    let key_released = self.release_string(key_ptr);
+    let payload_released = self.release_string(payload_ptr);
+    key_released?;
+    payload_released?;
     self.call_garbage_collector()?;
+    self.check_memory_limit()?;
+    if let Some(scratch_dir) = &self.scratch_dir {
+      scratch_dir.check_quota(&self.options.module_name)?;
+    }
 
     return result;
   }
 
+  // calls the execute export of the guest with the allocated key and payload
+  fn call_execute_fn(
+    &self,
+    key_ptr: WasmerStringPtr,
+    payload_ptr: WasmerStringPtr,
+    args: impl FnOnce() -> String,
+  ) -> Result<String, PluginError> {
+    let name = &self.options.execute_function_name;
+    match &self.execute_fn {
+      ExecuteFn::LengthHeader(execute) => {
+        match self.guest_call(name, args, || execute.call(key_ptr, payload_ptr)) {
+          Ok(result_ptr) => self.read_result(result_ptr, None),
+          Err(error) => Err(self.log_and_transform_error(error, name)),
+        }
+      }
+      ExecuteFn::PointerLength(execute) => {
+        match self.guest_call(name, args, || execute.call(key_ptr, payload_ptr)) {
+          Ok((result_ptr, length)) => {
+            self.read_result(WasmerStringPtr::new(result_ptr), Some(length))
+          }
+          Err(error) => Err(self.log_and_transform_error(error, name)),
+        }
+      }
+      ExecuteFn::OutBuffer(execute, capacity) => {
+        // the buffer is a zeroed string, its header holds the capacity in bytes
+        let out_ptr = self.allocate_string(&"\0".repeat(*capacity as usize))?;
+        let out_capacity = match self.get_string_length(out_ptr) {
+          Ok(capacity) => capacity,
+          Err(error) => {
+            self.release_string(out_ptr)?;
+            return Err(error);
+          }
+        };
+        let call = || execute.call(key_ptr, payload_ptr, out_ptr, out_capacity);
+        let result = match self.guest_call(name, args, call) {
+          Ok(length) if length < 0 => {
+            self.log_guest_output(name);
+            error!(
+              "WASM:{} {} signaled error {}",
+              self.options.module_name, name, length
+            );
+            Err(PluginError::RuntimeError)
+          }
+          Ok(length) if length as u32 > out_capacity => {
+            self.log_guest_output(name);
+            error!(
+              "WASM:{} result of {} bytes does not fit into out buffer of {} bytes",
+              self.options.module_name, length, out_capacity
+            );
+            Err(PluginError::PayloadTooLarge {
+              size: length as usize,
+              limit: out_capacity as usize,
+            })
+          }
+          Ok(length) => self.read_result(out_ptr, Some(length as u32)),
+          Err(error) => Err(self.log_and_transform_error(error, name)),
+        };
+        self.release_string(out_ptr)?;
+        result
+      }
+    }
+  }
+
+  // `length` is `None` for results with length header, only those are released
+  fn read_result(&self, ptr: WasmerStringPtr, length: Option<u32>) -> Result<String, PluginError> {
+    self.log_guest_output(&self.options.execute_function_name);
+    let (length, release) = match length {
+      Some(length) => (length, false),
+      None => (self.get_string_length(ptr)?, true),
+    };
+    check_size(
+      &self.options.module_name,
+      "result",
+      length as usize,
+      self.options.size_limits.result,
+    )?;
+    let result = self.get_string_with_length(ptr, length)?;
+    if release {
+      self.release_result(ptr)?;
+    }
+    Ok(result)
+  }
+
   fn call_garbage_collector(&self) -> Result<(), PluginError> {
-    let garbage_collector = self.get_function::<(), ()>(&String::from("__collect"))?;
+    let name = match &self.options.collect_function_name {
+      Some(name) => name,
+      None => return Ok(()),
+    };
+    let garbage_collector = self.get_function::<(), ()>(name)?;
+
+    let start = Instant::now();
+    match self.guest_call(name, String::new, || garbage_collector.call()) {
+      Ok(_result) => {
+        self.gc.record(start.elapsed());
+        self.report.record_gc(start.elapsed());
+        if self
+          .options
+          .events
+          .has_subscribers(EventKind::GarbageCollection)
+        {
+          self.options.events.emit(GuestEvent::GarbageCollection {
+            module_name: self.options.module_name.clone(),
+            trace_id: self.current_trace_id(),
+            duration: start.elapsed(),
+          });
+        }
+        Ok(())
+      }
+      Err(error) => Err(self.log_and_transform_error(error, name)),
+    }
+  }
+}
 
-    match garbage_collector.call() {
-      Ok(_result) => Ok(()),
-      Err(error) => Err(self.log_and_transform_error(error, &String::from("__collect"))),
+// loads the artifact and creates the instance with the wasi environment and
+// host functions configured by the options, shared by all plugin kinds
+// the custom sections of the module are added to the metadata of the options
+pub(crate) fn instantiate(
+  options: &mut PluginOptions,
+) -> Result<(Instance, WasiEnv, Option<Arc<ScratchDir>>), PluginError> {
+  let header = match ArtifactHeader::read_from_file(&options.file) {
+    Ok(header) => header,
+    Err(error) => {
+      error!(
+        "WASM:{} reading artifact header \"{}\" failed",
+        options.module_name,
+        ArtifactHeader::header_file(&options.file)
+      );
+      error!("{}", error);
+      return Err(PluginError::IncompatibleArtifact(String::from(
+        "missing or unreadable artifact header",
+      )));
+    }
+  };
+  if let Err(message) = header.validate(&ArtifactHeader::host()) {
+    error!(
+      "WASM:{} incompatible artifact: {}",
+      options.module_name, message
+    );
+    return Err(PluginError::IncompatibleArtifact(message));
+  }
+  if let Some(features) = &header.wasm_features {
+    let disallowed = options.wasm_features.disallowed(features);
+    if !disallowed.is_empty() {
+      let message = format!(
+        "artifact compiled with disabled wasm features {:?}",
+        disallowed
+      );
+      error!(
+        "WASM:{} incompatible artifact: {}",
+        options.module_name, message
+      );
+      return Err(PluginError::IncompatibleArtifact(message));
     }
   }
+
+  for name in options.metadata.required_host_functions.iter() {
+    let dynamic = options
+      .dynamic_host_functions
+      .iter()
+      .any(|function| &function.name == name);
+    if !options.custom_exports.contains(name.as_str()) && !dynamic {
+      error!(
+        "WASM:{} required host function \"{}\" is not registered",
+        options.module_name, name
+      );
+      return Err(PluginError::HostFunctionMissing);
+    }
+  }
+
+  debug!("WASM:{} loading module file", options.module_name);
+  let module = match &options.module_cache {
+    Some(cache) => cache.load(&options.module_name, &options.file)?,
+    None => unsafe {
+      match Module::deserialize_from_file(&options.store, &options.file) {
+        Ok(m) => {
+          debug!("WASM:{} loading done", options.module_name);
+          m
+        }
+        Err(error) => {
+          error!("WASM:{} loading module failed", options.module_name);
+          error!("{}", error);
+          return Err(PluginError::LoadingError);
+        }
+      }
+    },
+  };
+  options.metadata.sections = ModuleSections::read(&options.module_name, &module);
+
+  let scratch_dir = match &options.scratch_dir {
+    Some(config) => Some(Arc::new(ScratchDir::create(&options.module_name, config)?)),
+    None => None,
+  };
+
+  let mut wasi_state = WasiState::new(&options.module_name);
+  wasi_state
+    .stdin(Box::new(Pipe::new()))
+    .stdout(Box::new(Pipe::new()))
+    .stderr(Box::new(Pipe::new()))
+    .envs(options.envs.clone())
+    .args(options.args.clone());
+  if let Some(scratch_dir) = &scratch_dir {
+    let preopen = wasi_state.preopen(|p| {
+      p.directory(scratch_dir.path())
+        .alias(scratch_dir.alias())
+        .read(true)
+        .write(true)
+        .create(true)
+    });
+    if let Err(error) = preopen {
+      error!("WASM:{} preopen scratch dir failed", options.module_name);
+      error!("{}", error);
+      return Err(PluginError::InitWasiEnvFailed);
+    }
+  }
+  if let Some(memory_fs) = &options.memory_fs {
+    if scratch_dir.is_some() {
+      error!(
+        "WASM:{} memory fs can not be combined with a scratch dir",
+        options.module_name
+      );
+      return Err(PluginError::InitWasiEnvFailed);
+    }
+    let fs = match memory_fs.build() {
+      Ok(fs) => fs,
+      Err(message) => {
+        error!(
+          "WASM:{} building memory fs failed: {}",
+          options.module_name, message
+        );
+        return Err(PluginError::InitWasiEnvFailed);
+      }
+    };
+    wasi_state.set_fs(Box::new(fs));
+    let preopen = wasi_state.preopen(|p| p.directory(memory_fs.mount()).read(true));
+    if let Err(error) = preopen {
+      error!("WASM:{} preopen memory fs failed", options.module_name);
+      error!("{}", error);
+      return Err(PluginError::InitWasiEnvFailed);
+    }
+  }
+  let wasi_env_create = wasi_state.finalize();
+
+  let environment = match wasi_env_create {
+    Ok(env) => {
+      debug!("WASM:{} wasi environment ok", options.module_name);
+      env
+    }
+    Err(error) => {
+      error!(
+        "WASM:{} create wasi environment failed",
+        options.module_name
+      );
+      error!("{}", error);
+      return Err(PluginError::InitWasiEnvFailed);
+    }
+  };
+  let mut import_object = wasi_import_object(&options.module_name, &environment, &module)?;
+  debug!("WASM:{} wasi import object ok", options.module_name);
+  // explicit stubs take precedence over the ones enforcing the network policy
+  let mut wasi_stubs = options.wasi_stubs.clone();
+  wasi_stubs.extend(options.network_policy.wasi_stubs());
+  apply_wasi_stubs(
+    &options.module_name,
+    &mut import_object,
+    &module,
+    &wasi_stubs,
+  );
+  if let Some(policy) = &options.syscall_policy {
+    apply_syscall_policy(
+      &options.module_name,
+      &mut import_object,
+      &module,
+      policy,
+      &options.host_env,
+      &options.audit_log,
+    );
+  }
+
+  debug!("WASM:{} init custom environment", options.module_name);
+
+  let mut custom_exports = options.custom_exports.clone();
+  let trace_env = TraceEnv::new(&options.host_env, options);
+  for function in options.dynamic_host_functions.iter() {
+    custom_exports.insert(function.name.clone(), wrap_host_function(options, function));
+  }
+  custom_exports.insert(
+    "get_trace_id",
+    Function::new_native_with_env(&options.store, trace_env, get_trace_id),
+  );
+  let error_env = ErrorEnv::new(&options.error_slots, options);
+  custom_exports.insert(
+    LAST_HOST_ERROR_FUNCTION,
+    Function::new_native_with_env(&options.store, error_env.clone(), last_host_error),
+  );
+  custom_exports.insert(
+    RAISE_ERROR_FUNCTION,
+    Function::new_native_with_env(&options.store, error_env, raise_error),
+  );
+  import_object.register("custom", custom_exports);
+
+  debug!("WASM:{} create new instance", options.module_name);
+  let instance = match Instance::new(&module, &import_object) {
+    Ok(i) => {
+      debug!("WASM:{} instance created", options.module_name);
+      i
+    }
+    Err(error) => {
+      error!("WASM:{} create instance failed", options.module_name);
+      error!("{}", error);
+      return Err(PluginError::InstanceInitFailed);
+    }
+  };
+
+  Ok((instance, environment, scratch_dir))
 }
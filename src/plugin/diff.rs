@@ -0,0 +1,124 @@
+use std::time::{Duration, Instant};
+
+use crate::plugin::default::DefaultPlugin;
+use crate::plugin::recorder::{to_outcome, JournalEntry, Outcome};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Divergence {
+  // both calls succeeded with different results
+  OutputMismatch,
+  // only one call failed or both failed with different errors
+  ErrorDivergence,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DiffCase {
+  pub key: String,
+  pub payload: String,
+  pub outcome_a: Outcome,
+  pub outcome_b: Outcome,
+  pub duration_a: Duration,
+  pub duration_b: Duration,
+  pub divergence: Option<Divergence>,
+}
+
+impl DiffCase {
+  // positive if plugin b was slower
+  pub fn latency_delta_us(&self) -> i128 {
+    self.duration_b.as_micros() as i128 - self.duration_a.as_micros() as i128
+  }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DiffReport {
+  pub cases: Vec<DiffCase>,
+}
+
+impl DiffReport {
+  pub fn total(&self) -> usize {
+    self.cases.len()
+  }
+
+  pub fn output_mismatches(&self) -> Vec<&DiffCase> {
+    self.diverged(Divergence::OutputMismatch)
+  }
+
+  pub fn error_divergences(&self) -> Vec<&DiffCase> {
+    self.diverged(Divergence::ErrorDivergence)
+  }
+
+  pub fn is_equivalent(&self) -> bool {
+    self.cases.iter().all(|case| case.divergence.is_none())
+  }
+
+  pub fn total_duration_a(&self) -> Duration {
+    self.cases.iter().map(|case| case.duration_a).sum()
+  }
+
+  pub fn total_duration_b(&self) -> Duration {
+    self.cases.iter().map(|case| case.duration_b).sum()
+  }
+
+  // mean latency change per call in microseconds, positive if plugin b was slower
+  pub fn mean_latency_delta_us(&self) -> f64 {
+    if self.cases.is_empty() {
+      return 0.0;
+    }
+    let sum: i128 = self.cases.iter().map(|case| case.latency_delta_us()).sum();
+    sum as f64 / self.cases.len() as f64
+  }
+
+  fn diverged(&self, divergence: Divergence) -> Vec<&DiffCase> {
+    self
+      .cases
+      .iter()
+      .filter(|case| case.divergence == Some(divergence))
+      .collect()
+  }
+}
+
+// runs every key/payload pair of the corpus against both plugins, eg two builds
+// of the same guest produced by different compiler versions
+pub fn compare(
+  plugin_a: &DefaultPlugin,
+  plugin_b: &DefaultPlugin,
+  corpus: &[(String, String)],
+) -> DiffReport {
+  let cases = corpus
+    .iter()
+    .map(|(key, payload)| {
+      let (outcome_a, duration_a) = timed_execute(plugin_a, key, payload);
+      let (outcome_b, duration_b) = timed_execute(plugin_b, key, payload);
+      let divergence = match (&outcome_a, &outcome_b) {
+        (Ok(a), Ok(b)) if a == b => None,
+        (Ok(_), Ok(_)) => Some(Divergence::OutputMismatch),
+        (Err(a), Err(b)) if a == b => None,
+        _ => Some(Divergence::ErrorDivergence),
+      };
+      DiffCase {
+        key: key.clone(),
+        payload: payload.clone(),
+        outcome_a,
+        outcome_b,
+        duration_a,
+        duration_b,
+        divergence,
+      }
+    })
+    .collect();
+  DiffReport { cases }
+}
+
+// key/payload pairs of a recorded journal, to diff against production traffic
+pub fn corpus_from_journal(journal: &[JournalEntry]) -> Vec<(String, String)> {
+  journal
+    .iter()
+    .map(|entry| (entry.key.clone(), entry.payload.clone()))
+    .collect()
+}
+
+fn timed_execute(plugin: &DefaultPlugin, key: &String, payload: &String) -> (Outcome, Duration) {
+  let start = Instant::now();
+  let outcome = to_outcome(&plugin.execute(key, payload));
+  (outcome, start.elapsed())
+}
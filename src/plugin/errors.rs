@@ -0,0 +1,191 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::thread::{self, ThreadId};
+
+use log::{error, warn};
+use wasmer::{HostEnvInitError, Instance, WasmerEnv};
+
+use crate::plugin::host::{GuestStrings, HostEnv};
+use crate::plugin::middleware::CallContext;
+use crate::plugin::string_abi::string_length;
+use crate::plugin::{PluginOptions, WasmerStringPtr};
+
+// errors across the host/guest boundary without trapping
+//
+// host functions added with `PluginOptions::add_host_function_with_errors`
+// return a negative status code on failure, the guest reads the message with
+// `custom.last_host_error()` (0 if there is none), a string in the string abi
+// of the plugin, eg an ArrayBuffer for AssemblyScript
+//
+// guests report a typed error with `custom.raise_error(code: i32, message)`
+// before returning from the execute function, the call then fails with
+// `PluginError::GuestError` instead of returning the result
+pub const LAST_HOST_ERROR_FUNCTION: &str = "last_host_error";
+pub const RAISE_ERROR_FUNCTION: &str = "raise_error";
+
+// failure of a host function, the code is handed to the guest
+#[derive(Debug, Clone, PartialEq)]
+pub struct HostError {
+  // always negative, non negative status codes are successful results
+  pub code: i32,
+  pub message: String,
+}
+
+impl HostError {
+  // codes >= 0 are turned into -1
+  pub fn new(code: i32, message: &str) -> Self {
+    Self {
+      code: if code < 0 { code } else { -1 },
+      message: String::from(message),
+    }
+  }
+}
+
+pub type HostResult<T> = Result<T, HostError>;
+
+// typed error raised by the guest, see `PluginError::GuestError`
+#[derive(Debug, Clone, PartialEq, PartialOrd)]
+pub struct GuestError {
+  pub code: i32,
+  pub message: String,
+}
+
+// last error per thread, a guest call and the host functions it calls always
+// run on the same thread, so plugins sharing the options do not see each
+// others errors
+#[derive(Debug, Clone, Default)]
+pub struct ErrorSlots {
+  host: Arc<Mutex<HashMap<ThreadId, HostError>>>,
+  guest: Arc<Mutex<HashMap<ThreadId, GuestError>>>,
+}
+
+impl ErrorSlots {
+  pub(crate) fn set_host_error(&self, error: HostError) {
+    self
+      .host
+      .lock()
+      .unwrap()
+      .insert(thread::current().id(), error);
+  }
+
+  pub(crate) fn take_host_error(&self) -> Option<HostError> {
+    self.host.lock().unwrap().remove(&thread::current().id())
+  }
+
+  pub(crate) fn set_guest_error(&self, error: GuestError) {
+    self
+      .guest
+      .lock()
+      .unwrap()
+      .insert(thread::current().id(), error);
+  }
+
+  pub(crate) fn take_guest_error(&self) -> Option<GuestError> {
+    self.guest.lock().unwrap().remove(&thread::current().id())
+  }
+
+  // drops what is left of a previous call
+  pub(crate) fn clear(&self) {
+    self.take_host_error();
+    self.take_guest_error();
+  }
+}
+
+// environment of host functions added with `PluginOptions::add_host_function_with_errors`
+// eg `fn lookup(env: &HostErrorEnv, id: i32) -> i32 { env.status(find(id)) }`
+#[derive(Clone)]
+pub struct HostErrorEnv {
+  host_env: HostEnv,
+  slots: ErrorSlots,
+}
+
+impl HostErrorEnv {
+  pub(crate) fn new(host_env: &HostEnv, slots: &ErrorSlots) -> Self {
+    Self {
+      host_env: host_env.clone(),
+      slots: slots.clone(),
+    }
+  }
+
+  pub fn call_context(&self) -> Option<CallContext> {
+    self.host_env.call_context()
+  }
+
+  // the value for `Ok`, the negative code for `Err` with the message kept for
+  // `last_host_error`
+  pub fn status(&self, result: HostResult<i32>) -> i32 {
+    match result {
+      Ok(value) => value,
+      Err(error) => self.fail(error),
+    }
+  }
+
+  pub fn fail(&self, error: HostError) -> i32 {
+    let code = error.code;
+    self.slots.set_host_error(error);
+    code
+  }
+}
+
+impl WasmerEnv for HostErrorEnv {}
+
+// environment of the built-in `last_host_error` and `raise_error`
+#[derive(Clone)]
+pub struct ErrorEnv {
+  slots: ErrorSlots,
+  strings: GuestStrings,
+}
+
+impl ErrorEnv {
+  pub fn new(slots: &ErrorSlots, options: &PluginOptions) -> Self {
+    Self {
+      slots: slots.clone(),
+      strings: GuestStrings::new(options),
+    }
+  }
+}
+
+impl WasmerEnv for ErrorEnv {
+  fn init_with_instance(&mut self, instance: &Instance) -> Result<(), HostEnvInitError> {
+    self.strings.init_with_instance(instance)
+  }
+}
+
+// `custom.last_host_error()` - message of the last failed host function call
+// in the string abi of the plugin, 0 if there is none. the error is cleared by
+// reading it
+pub fn last_host_error(env: &ErrorEnv) -> WasmerStringPtr {
+  let error = match env.slots.take_host_error() {
+    Some(error) => error,
+    None => return WasmerStringPtr::new(0),
+  };
+  env.strings.write(&error.message)
+}
+
+// `custom.raise_error(code: i32, message)` - message in the string abi of the
+// plugin, the execute call in progress fails with this error
+pub fn raise_error(env: &ErrorEnv, code: i32, message: WasmerStringPtr) {
+  let memory = match env.strings.memory() {
+    Some(memory) => memory,
+    None => {
+      error!("raise_error called before instance was initialized");
+      return;
+    }
+  };
+  let view = memory.view::<u32>();
+  let read_header = |index: usize| view.get(index).map(|cell| cell.get());
+  let message = match string_length(message.offset(), read_header, memory.data_size()) {
+    Ok(length) => match message.deref(memory, 0, length) {
+      Some(bytes) => {
+        let bytes: Vec<u8> = bytes.iter().map(|byte| byte.get()).collect();
+        env.strings.string_abi.decode(&bytes)
+      }
+      None => String::new(),
+    },
+    Err(reason) => {
+      warn!("raise_error with invalid message pointer: {}", reason);
+      String::new()
+    }
+  };
+  env.slots.set_guest_error(GuestError { code, message });
+}
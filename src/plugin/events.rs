@@ -0,0 +1,130 @@
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use log::Level;
+
+use crate::plugin::PluginError;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EventKind {
+  Log,
+  Error,
+  GarbageCollection,
+  Lifecycle,
+  Canary,
+  All,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GuestStream {
+  Stdout,
+  Stderr,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum LifecycleState {
+  Created,
+  Started,
+  Initialized,
+  Exited(u32),
+}
+
+// something that happened in or around a guest, delivered to subscribers
+// trace id is set if the event belongs to an execute call
+#[derive(Debug, Clone, PartialEq)]
+pub enum GuestEvent {
+  Log {
+    module_name: String,
+    trace_id: Option<String>,
+    stream: GuestStream,
+    level: Level,
+    message: String,
+  },
+  Error {
+    module_name: String,
+    trace_id: Option<String>,
+    function: String,
+    error: PluginError,
+  },
+  GarbageCollection {
+    module_name: String,
+    trace_id: Option<String>,
+    duration: Duration,
+  },
+  Lifecycle {
+    module_name: String,
+    state: LifecycleState,
+  },
+  // the instance was replaced by a new one, eg by the watchdog
+  PluginRestarted {
+    module_name: String,
+    reason: String,
+  },
+  // the canary candidate answered a sampled call differently than the active
+  // version, emitted on the candidate, see `PluginManager::set_canary`
+  CanaryMismatch {
+    module_name: String,
+    version: String,
+    key: String,
+    expected: Result<String, PluginError>,
+    actual: Result<String, PluginError>,
+  },
+}
+
+impl GuestEvent {
+  pub fn kind(&self) -> EventKind {
+    match self {
+      GuestEvent::Log { .. } => EventKind::Log,
+      GuestEvent::Error { .. } => EventKind::Error,
+      GuestEvent::GarbageCollection { .. } => EventKind::GarbageCollection,
+      GuestEvent::Lifecycle { .. } | GuestEvent::PluginRestarted { .. } => EventKind::Lifecycle,
+      GuestEvent::CanaryMismatch { .. } => EventKind::Canary,
+    }
+  }
+}
+
+type Subscribers = Arc<Mutex<Vec<(EventKind, Sender<GuestEvent>)>>>;
+
+// fan out of guest events to channels, shared by all clones of a plugin
+// subscribers whose receiver was dropped are removed on the next event
+#[derive(Debug, Clone, Default)]
+pub struct EventBus {
+  subscribers: Subscribers,
+}
+
+impl EventBus {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  pub fn subscribe(&self, kind: EventKind) -> Receiver<GuestEvent> {
+    let (sender, receiver) = channel();
+    self.subscribers.lock().unwrap().push((kind, sender));
+    receiver
+  }
+
+  // lets callers skip building events nobody listens to
+  pub fn has_subscribers(&self, kind: EventKind) -> bool {
+    self
+      .subscribers
+      .lock()
+      .unwrap()
+      .iter()
+      .any(|(subscribed, _)| *subscribed == kind || *subscribed == EventKind::All)
+  }
+
+  pub fn emit(&self, event: GuestEvent) {
+    let kind = event.kind();
+    self
+      .subscribers
+      .lock()
+      .unwrap()
+      .retain(|(subscribed, sender)| {
+        if *subscribed != kind && *subscribed != EventKind::All {
+          return true;
+        }
+        sender.send(event.clone()).is_ok()
+      });
+  }
+}
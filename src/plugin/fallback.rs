@@ -0,0 +1,73 @@
+use std::fmt;
+use std::time::Duration;
+
+use log::{error, warn};
+
+use crate::plugin::middleware::CallContext;
+use crate::plugin::shared::SharedPlugin;
+use crate::plugin::PluginError;
+
+// what `execute` answers instead of an error, set with `PluginOptions::set_fallback`
+//
+// used when the call fails or returns after the deadline of
+// `PluginOptions::set_call_timeout`, the guest call itself is not interrupted
+#[derive(Clone)]
+pub enum FallbackStrategy {
+  // answers with a fixed string
+  Default(String),
+  // executes the call with the same key, payload and trace id on another plugin
+  Plugin(Box<SharedPlugin>),
+  // returns the payload unchanged
+  PassThrough,
+}
+
+impl fmt::Debug for FallbackStrategy {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      FallbackStrategy::Default(value) => f.debug_tuple("Default").field(value).finish(),
+      FallbackStrategy::Plugin(plugin) => {
+        f.debug_tuple("Plugin").field(plugin.module_name()).finish()
+      }
+      FallbackStrategy::PassThrough => write!(f, "PassThrough"),
+    }
+  }
+}
+
+impl FallbackStrategy {
+  // the result of the call or the fallback for it
+  // if the fallback plugin fails too, the original result is returned
+  pub fn resolve(
+    &self,
+    ctx: &CallContext,
+    result: Result<String, PluginError>,
+  ) -> Result<String, PluginError> {
+    let reason = match &result {
+      Err(error) => format!("{:?}", error),
+      Ok(_) if ctx.remaining() == Some(Duration::ZERO) => String::from("deadline exceeded"),
+      Ok(_) => return result,
+    };
+    warn!(
+      "WASM:{} execute failed ({}) - using fallback {:?}",
+      ctx.module_name, reason, self
+    );
+
+    match self {
+      FallbackStrategy::Default(value) => Ok(value.clone()),
+      FallbackStrategy::PassThrough => Ok(ctx.payload.clone()),
+      FallbackStrategy::Plugin(plugin) => {
+        match plugin.execute_traced(&ctx.trace_id, &ctx.key, &ctx.payload) {
+          Ok(fallback) => Ok(fallback),
+          Err(error) => {
+            error!(
+              "WASM:{} fallback plugin {} failed",
+              ctx.module_name,
+              plugin.module_name()
+            );
+            error!("{:?}", error);
+            result
+          }
+        }
+      }
+    }
+  }
+}
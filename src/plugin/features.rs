@@ -0,0 +1,112 @@
+use wasmer::Features;
+
+// wasm proposals a module may use, set on `CompileOptions` for the compile
+// step and on `PluginOptions` for the artifacts a plugin may load
+//
+// the defaults are those of wasmer: simd, bulk memory, reference types and
+// multi value are enabled, threads are not. modules using a disabled feature
+// fail to compile, artifacts compiled with features a plugin does not allow
+// fail to load with `PluginError::IncompatibleArtifact`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct WasmFeatures {
+  pub simd: bool,
+  pub bulk_memory: bool,
+  pub reference_types: bool,
+  pub threads: bool,
+  pub multi_value: bool,
+}
+
+impl Default for WasmFeatures {
+  fn default() -> Self {
+    Self {
+      simd: true,
+      bulk_memory: true,
+      reference_types: true,
+      threads: false,
+      multi_value: true,
+    }
+  }
+}
+
+impl WasmFeatures {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  // mvp only, eg for untrusted code
+  pub fn none() -> Self {
+    Self {
+      simd: false,
+      bulk_memory: false,
+      reference_types: false,
+      threads: false,
+      multi_value: false,
+    }
+  }
+
+  pub fn set_simd(&mut self, enable: bool) -> &mut Self {
+    self.simd = enable;
+    self
+  }
+
+  pub fn set_bulk_memory(&mut self, enable: bool) -> &mut Self {
+    self.bulk_memory = enable;
+    self
+  }
+
+  // reference types build on bulk memory, enabling them enables bulk memory
+  pub fn set_reference_types(&mut self, enable: bool) -> &mut Self {
+    self.reference_types = enable;
+    if enable {
+      self.bulk_memory = true;
+    }
+    self
+  }
+
+  pub fn set_threads(&mut self, enable: bool) -> &mut Self {
+    self.threads = enable;
+    self
+  }
+
+  pub fn set_multi_value(&mut self, enable: bool) -> &mut Self {
+    self.multi_value = enable;
+    self
+  }
+
+  pub fn to_wasmer(&self) -> Features {
+    let mut features = Features::new();
+    features
+      .simd(self.simd)
+      .bulk_memory(self.bulk_memory)
+      .reference_types(self.reference_types)
+      .threads(self.threads)
+      .multi_value(self.multi_value);
+    features
+  }
+
+  // enabled features by name as written to the artifact header
+  pub fn names(&self) -> Vec<String> {
+    let features = [
+      ("bulk_memory", self.bulk_memory),
+      ("multi_value", self.multi_value),
+      ("reference_types", self.reference_types),
+      ("simd", self.simd),
+      ("threads", self.threads),
+    ];
+    features
+      .iter()
+      .filter(|(_, enabled)| *enabled)
+      .map(|(name, _)| String::from(*name))
+      .collect()
+  }
+
+  // names of `features` which are not enabled here
+  pub fn disallowed(&self, features: &[String]) -> Vec<String> {
+    let allowed = self.names();
+    features
+      .iter()
+      .filter(|feature| !allowed.contains(feature))
+      .cloned()
+      .collect()
+  }
+}
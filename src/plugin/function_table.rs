@@ -0,0 +1,102 @@
+use std::any::Any;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use log::debug;
+use wasmer::{Instance, NativeFunc, WasmTypeList};
+
+use crate::plugin::{PluginOptions, WasmerStringPtr};
+
+// guest export resolved and type checked once, holds a `NativeFunc<T, O>`
+#[derive(Clone)]
+pub struct CachedFunc {
+  native: Arc<dyn Any + Send + Sync>,
+}
+
+impl CachedFunc {
+  pub fn new<T, O>(native: NativeFunc<T, O>) -> Self
+  where
+    T: WasmTypeList + Sync + 'static,
+    O: WasmTypeList + Sync + 'static,
+  {
+    Self {
+      native: Arc::new(native),
+    }
+  }
+
+  // `None` if the export was cached with another signature
+  pub fn get<T, O>(&self) -> Option<NativeFunc<T, O>>
+  where
+    T: WasmTypeList + 'static,
+    O: WasmTypeList + 'static,
+  {
+    self.native.downcast_ref::<NativeFunc<T, O>>().cloned()
+  }
+}
+
+// exports of a plugin resolved when the plugin is created, so the guest calls
+// (start, init, gc, pin, unpin, free, ...) skip the export lookup and the
+// signature check. `Plugin::get_function` falls back to the instance for
+// exports which are not in the table
+#[derive(Clone, Default)]
+pub struct FunctionTable {
+  functions: HashMap<String, CachedFunc>,
+}
+
+impl FunctionTable {
+  // caches the exports used by the `Plugin` call paths which the guest provides
+  // missing exports are left out, they fail or are skipped on use as before
+  pub fn for_plugin(instance: &Instance, options: &PluginOptions) -> Self {
+    let mut table = Self::default();
+    table.cache::<(), ()>(instance, &options.start_function_name);
+    table.cache::<WasmerStringPtr, ()>(instance, &options.init_function_name);
+    table.cache::<(u32, u32), WasmerStringPtr>(instance, &options.new_function_name);
+    table.cache::<WasmerStringPtr, WasmerStringPtr>(instance, &options.pin_function_name);
+    table.cache::<WasmerStringPtr, ()>(instance, &options.unpin_function_name);
+    table.cache::<u32, ()>(instance, &options.free_function_name);
+    if let Some(name) = &options.collect_function_name {
+      table.cache::<(), ()>(instance, name);
+    }
+    if let Some(name) = &options.loop_function_name {
+      table.cache::<(), ()>(instance, name);
+    }
+    debug!(
+      "WASM:{} function table: {} exports cached",
+      options.module_name,
+      table.functions.len()
+    );
+    table
+  }
+
+  // adds the export if it exists with the given signature
+  pub fn cache<T, O>(&mut self, instance: &Instance, name: &String)
+  where
+    T: WasmTypeList + Sync + 'static,
+    O: WasmTypeList + Sync + 'static,
+  {
+    // a signature missmatch is reported by `helper_get_function` on use
+    if let Ok(native) = instance.exports.get_native_function::<T, O>(name) {
+      self.insert(name, native);
+    }
+  }
+
+  pub fn insert<T, O>(&mut self, name: &String, native: NativeFunc<T, O>)
+  where
+    T: WasmTypeList + Sync + 'static,
+    O: WasmTypeList + Sync + 'static,
+  {
+    self.functions.insert(name.clone(), CachedFunc::new(native));
+  }
+
+  pub fn get<T, O>(&self, name: &String) -> Option<NativeFunc<T, O>>
+  where
+    T: WasmTypeList + 'static,
+    O: WasmTypeList + 'static,
+  {
+    self.functions.get(name).and_then(|function| function.get())
+  }
+
+  pub fn contains(&self, name: &String) -> bool {
+    self.functions.contains_key(name)
+  }
+}
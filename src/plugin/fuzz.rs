@@ -0,0 +1,53 @@
+// entry points for cargo-fuzz, see `fuzz/` - only built with feature `fuzzing`
+// they run the same parsing code as the plugin on arbitrary input and must
+// never panic, whatever the input looks like
+
+use std::io::Cursor;
+
+use crate::plugin::default::DefaultPlugin;
+use crate::plugin::manifest::PluginManifest;
+use crate::plugin::memfs::MemoryFs;
+use crate::plugin::recorder::read_journal_from;
+use crate::plugin::sections::{parse_json, parse_module_name, parse_producers};
+use crate::plugin::string_abi::{string_length, StringAbi};
+
+// reads a string at `ptr` from a fake guest memory
+pub fn fuzz_get_string(memory_bytes: &[u8], ptr: u32, abi: StringAbi) -> Option<String> {
+  let read_header = |index: usize| {
+    let start = index.checked_mul(4)?;
+    let bytes = memory_bytes.get(start..start.checked_add(4)?)?;
+    Some(u32::from_le_bytes(bytes.try_into().ok()?))
+  };
+  let length = string_length(ptr, read_header, memory_bytes.len() as u64).ok()?;
+  let bytes = memory_bytes.get(ptr as usize..ptr as usize + length as usize)?;
+  Some(abi.decode(bytes))
+}
+
+// splits the input in key and payload at the first zero byte
+pub fn fuzz_execute(plugin: &DefaultPlugin, data: &[u8]) {
+  let (key, payload) = match data.iter().position(|b| *b == 0) {
+    Some(index) => (&data[..index], &data[index + 1..]),
+    None => (data, &[][..]),
+  };
+  let key = String::from_utf8_lossy(key).into_owned();
+  let payload = String::from_utf8_lossy(payload).into_owned();
+  let _ = plugin.execute(&key, &payload);
+}
+
+pub fn fuzz_manifest(data: &[u8]) {
+  let _ = PluginManifest::parse(&String::from_utf8_lossy(data).into_owned());
+}
+
+pub fn fuzz_journal(data: &[u8]) {
+  let _ = read_journal_from(&mut Cursor::new(data));
+}
+
+pub fn fuzz_tar(data: &[u8]) {
+  let _ = MemoryFs::from_tar(&String::from("/data"), data);
+}
+
+pub fn fuzz_sections(data: &[u8]) {
+  let _ = parse_module_name(data);
+  let _ = parse_producers(data);
+  let _ = parse_json(&String::from_utf8_lossy(data));
+}
@@ -0,0 +1,89 @@
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::plugin::PluginError;
+
+// what happens to calls arriving while a plugin is paused, see `PluginManager::pause`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PauseMode {
+  // fail at once with `PluginError::PluginPaused`
+  Reject,
+  // wait for `resume`, calls still waiting after `timeout` fail with `PluginError::PluginPaused`
+  Queue { timeout: Duration },
+}
+
+#[derive(Debug, Default)]
+struct GateState {
+  paused: Option<PauseMode>,
+  in_flight: usize,
+}
+
+// counts the running calls of one plugin name and holds back new ones while paused
+#[derive(Debug, Default)]
+pub(crate) struct CallGate {
+  state: Mutex<GateState>,
+  changed: Condvar,
+}
+
+// a running call, leaving the gate when dropped
+pub(crate) struct GatePass {
+  gate: Arc<CallGate>,
+}
+
+impl Drop for GatePass {
+  fn drop(&mut self) {
+    let mut state = self.gate.state.lock().unwrap();
+    state.in_flight -= 1;
+    self.gate.changed.notify_all();
+  }
+}
+
+impl CallGate {
+  pub(crate) fn enter(gate: &Arc<CallGate>) -> Result<GatePass, PluginError> {
+    let mut state = gate.state.lock().unwrap();
+    let mut deadline = None;
+    while let Some(mode) = state.paused {
+      let timeout = match mode {
+        PauseMode::Reject => return Err(PluginError::PluginPaused),
+        PauseMode::Queue { timeout } => timeout,
+      };
+      let deadline = *deadline.get_or_insert_with(|| Instant::now() + timeout);
+      let remaining = deadline.saturating_duration_since(Instant::now());
+      if remaining.is_zero() {
+        return Err(PluginError::PluginPaused);
+      }
+      state = gate.changed.wait_timeout(state, remaining).unwrap().0;
+    }
+    state.in_flight += 1;
+    Ok(GatePass { gate: gate.clone() })
+  }
+
+  // new calls are held back from now on, waits until the running calls have
+  // finished or `drain_timeout` passed
+  pub(crate) fn pause(&self, mode: PauseMode, drain_timeout: Duration) -> Result<(), usize> {
+    let mut state = self.state.lock().unwrap();
+    state.paused = Some(mode);
+    let deadline = Instant::now() + drain_timeout;
+    while state.in_flight > 0 {
+      let remaining = deadline.saturating_duration_since(Instant::now());
+      if remaining.is_zero() {
+        return Err(state.in_flight);
+      }
+      state = self.changed.wait_timeout(state, remaining).unwrap().0;
+    }
+    Ok(())
+  }
+
+  pub(crate) fn resume(&self) {
+    self.state.lock().unwrap().paused = None;
+    self.changed.notify_all();
+  }
+
+  pub(crate) fn is_paused(&self) -> bool {
+    self.state.lock().unwrap().paused.is_some()
+  }
+
+  pub(crate) fn in_flight(&self) -> usize {
+    self.state.lock().unwrap().in_flight
+  }
+}
@@ -0,0 +1,272 @@
+use std::collections::HashSet;
+
+use wasmer::{ExportIndex, Module, Mutability};
+
+// mutable globals the guest does not export, eg the allocator and gc state of
+// the AssemblyScript runtime (`~lib/rt/tlsf/ROOT`, `~lib/rt/tcms/*`), are part
+// of the guest state but can not be read or written through an instance
+//
+// `compile_to_file` with `CompileOptions::enable_state_globals` exports them
+// as `__state_global_<index>`, so `Checkpoint` and `WarmTemplate` capture them
+// like any exported global. other artifacts still hide them and can not be
+// checkpointed
+pub const STATE_GLOBAL_PREFIX: &str = "__state_global_";
+
+const SECTION_IMPORT: u8 = 2;
+const SECTION_GLOBAL: u8 = 6;
+const SECTION_EXPORT: u8 = 7;
+const EXTERNAL_GLOBAL: u8 = 3;
+
+// number of local mutable globals which are not exported
+pub fn hidden_mutable_globals(module: &Module) -> usize {
+  let info = module.info();
+  let exported: HashSet<_> = info
+    .exports
+    .values()
+    .filter_map(|export| match export {
+      ExportIndex::Global(index) => Some(*index),
+      _ => None,
+    })
+    .collect();
+  info
+    .globals
+    .iter()
+    .filter(|(index, ty)| {
+      ty.mutability == Mutability::Var
+        && !info.is_imported_global(*index)
+        && !exported.contains(index)
+    })
+    .count()
+}
+
+// the wasm binary with an export for every local mutable global which is not
+// exported yet, `None` if the binary can not be parsed
+pub fn export_state_globals(wasm: &[u8]) -> Option<Vec<u8>> {
+  let mut reader = Reader {
+    bytes: wasm.strip_prefix(b"\0asm")?,
+  };
+  let version = reader.take(4)?;
+
+  let mut sections: Vec<(u8, &[u8])> = vec![];
+  while !reader.bytes.is_empty() {
+    let id = reader.byte()?;
+    let size = reader.leb()? as usize;
+    sections.push((id, reader.take(size)?));
+  }
+
+  let mut imported_globals = 0;
+  let mut mutable = vec![];
+  let mut exports: Vec<&[u8]> = vec![];
+  let mut exported_globals = HashSet::new();
+  let mut export_names = HashSet::new();
+  for (id, content) in sections.iter() {
+    let mut reader = Reader { bytes: content };
+    match *id {
+      SECTION_IMPORT => {
+        for _ in 0..reader.leb()? {
+          reader.name()?;
+          reader.name()?;
+          if reader.import_desc()? == EXTERNAL_GLOBAL {
+            imported_globals += 1;
+          }
+        }
+      }
+      SECTION_GLOBAL => {
+        for index in 0..reader.leb()? {
+          reader.byte()?;
+          if reader.byte()? == 1 {
+            mutable.push(imported_globals + index);
+          }
+          reader.skip_const_expr()?;
+        }
+      }
+      SECTION_EXPORT => {
+        for _ in 0..reader.leb()? {
+          let start = reader.bytes;
+          let name = reader.name()?;
+          let kind = reader.byte()?;
+          let index = reader.leb()?;
+          if kind == EXTERNAL_GLOBAL {
+            exported_globals.insert(index);
+          }
+          export_names.insert(name);
+          exports.push(&start[..start.len() - reader.bytes.len()]);
+        }
+      }
+      _ => {}
+    }
+  }
+
+  let mut added = vec![];
+  for index in mutable {
+    let name = format!("{}{}", STATE_GLOBAL_PREFIX, index);
+    if exported_globals.contains(&index) || export_names.contains(&name) {
+      continue;
+    }
+    let mut export = vec![];
+    write_leb(&mut export, name.len() as u32);
+    export.extend(name.as_bytes());
+    export.push(EXTERNAL_GLOBAL);
+    write_leb(&mut export, index);
+    added.push(export);
+  }
+  if added.is_empty() {
+    return Some(wasm.to_vec());
+  }
+
+  let mut export_section = vec![];
+  write_leb(&mut export_section, (exports.len() + added.len()) as u32);
+  for export in exports.iter() {
+    export_section.extend(*export);
+  }
+  for export in added.iter() {
+    export_section.extend(export);
+  }
+
+  // the export section follows the global section, start, element, data
+  // count, code and data sections come after it
+  let insert_at = sections
+    .iter()
+    .position(|(id, _)| *id == SECTION_EXPORT || matches!(*id, 8..=12))
+    .unwrap_or(sections.len());
+  let mut out = b"\0asm".to_vec();
+  out.extend(version);
+  for (position, (id, content)) in sections.iter().enumerate() {
+    if position == insert_at {
+      write_section(&mut out, SECTION_EXPORT, &export_section);
+    }
+    if *id != SECTION_EXPORT {
+      write_section(&mut out, *id, content);
+    }
+  }
+  if insert_at == sections.len() {
+    write_section(&mut out, SECTION_EXPORT, &export_section);
+  }
+  Some(out)
+}
+
+fn write_leb(out: &mut Vec<u8>, mut value: u32) {
+  loop {
+    let byte = (value & 0x7f) as u8;
+    value >>= 7;
+    if value == 0 {
+      out.push(byte);
+      return;
+    }
+    out.push(byte | 0x80);
+  }
+}
+
+fn write_section(out: &mut Vec<u8>, id: u8, content: &[u8]) {
+  out.push(id);
+  write_leb(out, content.len() as u32);
+  out.extend(content);
+}
+
+struct Reader<'a> {
+  bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+  fn byte(&mut self) -> Option<u8> {
+    let (byte, rest) = self.bytes.split_first()?;
+    self.bytes = rest;
+    Some(*byte)
+  }
+
+  fn leb(&mut self) -> Option<u32> {
+    let mut result: u32 = 0;
+    for shift in (0..35).step_by(7) {
+      let byte = self.byte()?;
+      result |= ((byte & 0x7f) as u32).checked_shl(shift)?;
+      if byte & 0x80 == 0 {
+        return Some(result);
+      }
+    }
+    None
+  }
+
+  // signed and 64 bit immediates are only skipped
+  fn skip_leb(&mut self) -> Option<()> {
+    for _ in 0..10 {
+      if self.byte()? & 0x80 == 0 {
+        return Some(());
+      }
+    }
+    None
+  }
+
+  fn take(&mut self, length: usize) -> Option<&'a [u8]> {
+    if self.bytes.len() < length {
+      return None;
+    }
+    let (taken, rest) = self.bytes.split_at(length);
+    self.bytes = rest;
+    Some(taken)
+  }
+
+  fn name(&mut self) -> Option<String> {
+    let length = self.leb()? as usize;
+    let bytes = self.take(length)?;
+    String::from_utf8(bytes.to_vec()).ok()
+  }
+
+  fn limits(&mut self) -> Option<()> {
+    let flags = self.byte()?;
+    self.skip_leb()?;
+    if flags & 1 == 1 {
+      self.skip_leb()?;
+    }
+    Some(())
+  }
+
+  // skips an import description, returns its kind
+  fn import_desc(&mut self) -> Option<u8> {
+    let kind = self.byte()?;
+    match kind {
+      0 => self.skip_leb()?,
+      1 => {
+        self.byte()?;
+        self.limits()?
+      }
+      2 => self.limits()?,
+      EXTERNAL_GLOBAL => {
+        self.take(2)?;
+      }
+      4 => {
+        self.byte()?;
+        self.skip_leb()?
+      }
+      _ => return None,
+    }
+    Some(kind)
+  }
+
+  // constant expression of a global initializer, up to and including `end`
+  fn skip_const_expr(&mut self) -> Option<()> {
+    loop {
+      match self.byte()? {
+        0x0b => return Some(()),
+        // i32.const, i64.const, global.get, ref.func
+        0x41 | 0x42 | 0x23 | 0xd2 => self.skip_leb()?,
+        0x43 => {
+          self.take(4)?;
+        }
+        0x44 => {
+          self.take(8)?;
+        }
+        // ref.null
+        0xd0 => {
+          self.byte()?;
+        }
+        // v128.const
+        0xfd if self.leb()? == 12 => {
+          self.take(16)?;
+        }
+        // extended constant expressions, add, sub and mul
+        0x6a | 0x6b | 0x6c | 0x7c | 0x7d | 0x7e => {}
+        _ => return None,
+      }
+    }
+  }
+}
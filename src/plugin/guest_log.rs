@@ -0,0 +1,120 @@
+use log::{log, Level};
+
+// how lines written by the guest to stdout/stderr are interpreted
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GuestLogFormat {
+  // every line is logged with the default level of its stream
+  Plain,
+  // lines starting with `ERROR:`, `WARN:`, `INFO:`, `DEBUG:` or `TRACE:` are
+  // logged with that level, other lines with the default level of the stream
+  Prefixed,
+  // json lines like `{"level":"warn","message":"..."}`, `msg` is accepted as well
+  // lines which are not json fall back to the default level of the stream
+  Json,
+}
+
+// guest output is logged with target `wasm::<plugin name>` so it can be
+// filtered independent of the host logs
+#[derive(Debug, Clone)]
+pub struct GuestLogConfig {
+  pub format: GuestLogFormat,
+  pub stdout_level: Level,
+  pub stderr_level: Level,
+}
+
+impl Default for GuestLogConfig {
+  fn default() -> Self {
+    Self {
+      format: GuestLogFormat::Plain,
+      stdout_level: Level::Info,
+      stderr_level: Level::Error,
+    }
+  }
+}
+
+impl GuestLogConfig {
+  pub fn target(module_name: &String) -> String {
+    format!("wasm::{}", module_name)
+  }
+
+  // logs the output and returns the parsed lines
+  pub fn emit(
+    &self,
+    module_name: &String,
+    prefix: &String,
+    output: &String,
+    default: Level,
+  ) -> Vec<(Level, String)> {
+    let target = Self::target(module_name);
+    let mut lines = vec![];
+    for line in output.lines() {
+      let line = line.trim();
+      if line.is_empty() {
+        continue;
+      }
+      let (level, message) = parse_line(line, self.format, default);
+      log!(target: &target, level, "{} {}", prefix, message);
+      lines.push((level, message));
+    }
+    lines
+  }
+}
+
+pub fn parse_line(line: &str, format: GuestLogFormat, default: Level) -> (Level, String) {
+  match format {
+    GuestLogFormat::Plain => (default, String::from(line)),
+    GuestLogFormat::Prefixed => match line.split_once(':') {
+      Some((prefix, message)) => match parse_level(prefix.trim()) {
+        Some(level) => (level, String::from(message.trim())),
+        None => (default, String::from(line)),
+      },
+      None => (default, String::from(line)),
+    },
+    GuestLogFormat::Json => {
+      if !line.starts_with('{') {
+        return (default, String::from(line));
+      }
+      let level = json_string_field(line, "level")
+        .and_then(|level| parse_level(&level))
+        .unwrap_or(default);
+      let message = json_string_field(line, "message")
+        .or_else(|| json_string_field(line, "msg"))
+        .unwrap_or_else(|| String::from(line));
+      (level, message)
+    }
+  }
+}
+
+fn parse_level(name: &str) -> Option<Level> {
+  match name.to_ascii_lowercase().as_str() {
+    "error" | "fatal" => Some(Level::Error),
+    "warn" | "warning" => Some(Level::Warn),
+    "info" => Some(Level::Info),
+    "debug" => Some(Level::Debug),
+    "trace" => Some(Level::Trace),
+    _ => None,
+  }
+}
+
+// minimal lookup of a top level string field, enough for flat log records
+fn json_string_field(line: &str, field: &str) -> Option<String> {
+  let key = format!("\"{}\"", field);
+  let rest = &line[line.find(&key)? + key.len()..];
+  let rest = rest.trim_start().strip_prefix(':')?.trim_start();
+  let rest = rest.strip_prefix('"')?;
+
+  let mut value = String::new();
+  let mut chars = rest.chars();
+  while let Some(c) = chars.next() {
+    match c {
+      '"' => return Some(value),
+      '\\' => match chars.next()? {
+        'n' => value.push('\n'),
+        't' => value.push('\t'),
+        other => value.push(other),
+      },
+      _ => value.push(c),
+    }
+  }
+  None
+}
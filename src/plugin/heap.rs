@@ -0,0 +1,135 @@
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use log::{debug, error};
+use wasmer::{Instance, Memory, NativeFunc, Value};
+
+use crate::plugin::PluginError;
+
+// optional guest export `memory_stats() -> u32` returning a pointer to three
+// little endian u64: live objects, heap bytes in use and gc runs of the guest
+pub const MEMORY_STATS_FUNCTION: &str = "memory_stats";
+// global of the AssemblyScript runtime pointing to its type table
+pub const RTTI_BASE_GLOBAL: &str = "__rtti_base";
+
+// heap of a plugin, see `DefaultPlugin::heap_stats`
+//
+// the AssemblyScript runtime does not export the state of its collector, the
+// guest fields are only set for guests exporting `memory_stats`, eg
+// `export function memory_stats(): usize` filling a static `StaticArray<u64>`
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct HeapStats {
+  // size of the linear memory, it never shrinks
+  pub memory_bytes: u64,
+  // classes registered in the runtime type table (`__rtti_base`)
+  pub rtti_classes: Option<u32>,
+  // `__collect` calls by the host since create and the time spent in them
+  pub collections: u64,
+  pub collect_time: Duration,
+  // reported by `memory_stats`
+  pub live_objects: Option<u64>,
+  pub heap_used_bytes: Option<u64>,
+  pub guest_collections: Option<u64>,
+}
+
+impl HeapStats {
+  pub fn average_collect_time(&self) -> Option<Duration> {
+    match self.collections {
+      0 => None,
+      count => Some(self.collect_time / count as u32),
+    }
+  }
+}
+
+// gc runs of one instance, shared by its clones
+#[derive(Debug, Clone, Default)]
+pub(crate) struct GcCounter {
+  runs: Arc<Mutex<(u64, Duration)>>,
+}
+
+impl GcCounter {
+  pub(crate) fn record(&self, duration: Duration) {
+    let mut runs = self.runs.lock().unwrap();
+    runs.0 += 1;
+    runs.1 += duration;
+  }
+
+  pub(crate) fn get(&self) -> (u64, Duration) {
+    *self.runs.lock().unwrap()
+  }
+}
+
+pub(crate) fn read_heap_stats(
+  module_name: &String,
+  instance: &Instance,
+  memory: &Memory,
+  gc: &GcCounter,
+) -> Result<HeapStats, PluginError> {
+  let (collections, collect_time) = gc.get();
+  let mut stats = HeapStats {
+    memory_bytes: memory.data_size(),
+    rtti_classes: None,
+    collections,
+    collect_time,
+    live_objects: None,
+    heap_used_bytes: None,
+    guest_collections: None,
+  };
+
+  // the first u32 of the type table is the number of entries
+  if let Ok(global) = instance.exports.get_global(RTTI_BASE_GLOBAL) {
+    if let Value::I32(base) = global.get() {
+      stats.rtti_classes = Some(read_u32(module_name, memory, base as u32)?);
+    }
+  }
+
+  let memory_stats: NativeFunc<(), u32> =
+    match instance.exports.get_native_function(MEMORY_STATS_FUNCTION) {
+      Ok(function) => function,
+      Err(_) => {
+        debug!("WASM:{} no {} export", module_name, MEMORY_STATS_FUNCTION);
+        return Ok(stats);
+      }
+    };
+  let ptr = match memory_stats.call() {
+    Ok(ptr) => ptr,
+    Err(error) => {
+      error!("WASM:{} {} failed", module_name, MEMORY_STATS_FUNCTION);
+      error!("{}", error);
+      return Err(PluginError::RuntimeError);
+    }
+  };
+  stats.live_objects = Some(read_u64(module_name, memory, ptr)?);
+  stats.heap_used_bytes = Some(read_u64(module_name, memory, ptr + 8)?);
+  stats.guest_collections = Some(read_u64(module_name, memory, ptr + 16)?);
+  Ok(stats)
+}
+
+fn read_bytes<const N: usize>(
+  module_name: &String,
+  memory: &Memory,
+  ptr: u32,
+) -> Result<[u8; N], PluginError> {
+  let view = memory.view::<u8>();
+  let start = ptr as usize;
+  if start + N > view.len() {
+    error!(
+      "WASM:{} heap stats pointer {} out of bounds",
+      module_name, ptr
+    );
+    return Err(PluginError::InvalidPointer);
+  }
+  let mut bytes = [0u8; N];
+  for (i, cell) in view[start..start + N].iter().enumerate() {
+    bytes[i] = cell.get();
+  }
+  Ok(bytes)
+}
+
+fn read_u32(module_name: &String, memory: &Memory, ptr: u32) -> Result<u32, PluginError> {
+  Ok(u32::from_le_bytes(read_bytes(module_name, memory, ptr)?))
+}
+
+fn read_u64(module_name: &String, memory: &Memory, ptr: u32) -> Result<u64, PluginError> {
+  Ok(u64::from_le_bytes(read_bytes(module_name, memory, ptr)?))
+}
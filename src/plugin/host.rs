@@ -0,0 +1,233 @@
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use std::thread::{self, ThreadId};
+
+use log::error;
+use wasmer::{HostEnvInitError, Instance, LazyInit, Memory, NativeFunc, WasmerEnv};
+
+use crate::plugin::middleware::CallContext;
+use crate::plugin::probe::REALLOC_CANDIDATES;
+use crate::plugin::string_abi::StringAbi;
+use crate::plugin::{PluginOptions, ReallocFn, WasmerStringPtr};
+
+// environment handed to host functions registered with
+// `PluginOptions::add_host_function_with_context`
+// it gives access to the context of the guest call currently running
+//
+// the host functions are shared by all instances created from clones of the
+// options (`SharedPlugin::spawn`, warm templates, actor restarts), so the
+// context is kept per thread like `ErrorSlots`: a guest call and the host
+// functions it calls always run on the same thread
+#[derive(Debug, Clone, Default)]
+pub struct HostEnv {
+  current: Arc<RwLock<HashMap<ThreadId, CallContext>>>,
+}
+
+impl HostEnv {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  // context of the execute call in progress on this thread, `None` while no
+  // call is running, eg during `_start` and `init`
+  pub fn call_context(&self) -> Option<CallContext> {
+    self
+      .current
+      .read()
+      .unwrap()
+      .get(&thread::current().id())
+      .cloned()
+  }
+
+  pub(crate) fn enter(&self, ctx: &CallContext) {
+    self
+      .current
+      .write()
+      .unwrap()
+      .insert(thread::current().id(), ctx.clone());
+  }
+
+  pub(crate) fn leave(&self) {
+    self
+      .current
+      .write()
+      .unwrap()
+      .remove(&thread::current().id());
+  }
+}
+
+impl WasmerEnv for HostEnv {}
+
+// environment of the built-in `get_trace_id` host function
+#[derive(Clone)]
+pub struct TraceEnv {
+  host_env: HostEnv,
+  strings: GuestStrings,
+}
+
+impl TraceEnv {
+  pub fn new(host_env: &HostEnv, options: &PluginOptions) -> Self {
+    Self {
+      host_env: host_env.clone(),
+      strings: GuestStrings::new(options),
+    }
+  }
+}
+
+impl WasmerEnv for TraceEnv {
+  fn init_with_instance(&mut self, instance: &Instance) -> Result<(), HostEnvInitError> {
+    self.strings.init_with_instance(instance)
+  }
+}
+
+// host function `get_trace_id()` in the `custom` namespace
+// returns the trace id of the running execute call in the string abi of the plugin
+pub fn get_trace_id(env: &TraceEnv) -> WasmerStringPtr {
+  let trace_id = match env.host_env.call_context() {
+    Some(ctx) => ctx.trace_id,
+    None => String::new(),
+  };
+  env.strings.write(&trace_id)
+}
+
+// strings handed to the guest by built-in host functions, allocated and
+// encoded in the string abi of the plugin like the key and payload of a call
+//
+// memory and allocate functions are resolved as weak references once the
+// instance exists, to not keep the instance alive through its own imports
+#[derive(Clone)]
+pub(crate) struct GuestStrings {
+  pub(crate) string_abi: StringAbi,
+  memory_name: String,
+  allocate_function_name: String,
+  new_function_name: String,
+  array_buffer_class_id: u32,
+  string_class_id: u32,
+  memory: LazyInit<Memory>,
+  malloc: LazyInit<NativeFunc<u32, WasmerStringPtr>>,
+  new: LazyInit<NativeFunc<(u32, u32), WasmerStringPtr>>,
+  realloc: LazyInit<ReallocFn>,
+}
+
+impl GuestStrings {
+  pub(crate) fn new(options: &PluginOptions) -> Self {
+    Self {
+      string_abi: options.string_abi,
+      memory_name: options.memory_name.clone(),
+      allocate_function_name: options.allocate_utf8array_function_name.clone(),
+      new_function_name: options.new_function_name.clone(),
+      array_buffer_class_id: options.array_buffer_class_id,
+      string_class_id: options.string_class_id,
+      memory: LazyInit::new(),
+      malloc: LazyInit::new(),
+      new: LazyInit::new(),
+      realloc: LazyInit::new(),
+    }
+  }
+
+  // guests without allocator exports can not receive strings, but must still
+  // be instantiable
+  pub(crate) fn init_with_instance(&mut self, instance: &Instance) -> Result<(), HostEnvInitError> {
+    let memory: Memory = instance.exports.get_with_generics_weak(&self.memory_name)?;
+    self.memory.initialize(memory);
+    let exports = &instance.exports;
+    if let Ok(malloc) = exports.get_with_generics_weak(&self.allocate_function_name) {
+      self.malloc.initialize(malloc);
+    }
+    if let Ok(new) = exports.get_with_generics_weak(&self.new_function_name) {
+      self.new.initialize(new);
+    }
+    for name in REALLOC_CANDIDATES {
+      if let Ok(realloc) = exports.get_with_generics_weak(name) {
+        self.realloc.initialize(realloc);
+        break;
+      }
+    }
+    Ok(())
+  }
+
+  pub(crate) fn memory(&self) -> Option<&Memory> {
+    self.memory.get_ref()
+  }
+
+  // allocates and writes the string, 0 if that failed
+  pub(crate) fn write(&self, value: &String) -> WasmerStringPtr {
+    let memory = match self.memory.get_ref() {
+      Some(memory) => memory,
+      None => {
+        error!("string written before instance was initialized");
+        return WasmerStringPtr::new(0);
+      }
+    };
+    let bytes = self.string_abi.encode(value);
+    let length = match u32::try_from(bytes.len()) {
+      Ok(length) => length,
+      Err(_) => {
+        error!("string of {} bytes too large for the guest", bytes.len());
+        return WasmerStringPtr::new(0);
+      }
+    };
+
+    let allocation = match self.string_abi {
+      StringAbi::Utf8ArrayBuffer => match (self.malloc.get_ref(), self.new.get_ref()) {
+        (Some(malloc), _) => Some(malloc.call(length)),
+        (None, Some(new)) => Some(new.call(length, self.array_buffer_class_id)),
+        (None, None) => None,
+      },
+      StringAbi::Utf16String => self
+        .new
+        .get_ref()
+        .map(|new| new.call(length, self.string_class_id)),
+      StringAbi::Utf8LengthPrefixed => match (length.checked_add(4), self.malloc.get_ref()) {
+        (None, _) => {
+          error!("string of {} bytes too large for the guest", length);
+          return WasmerStringPtr::new(0);
+        }
+        (Some(size), Some(malloc)) => Some(malloc.call(size)),
+        (Some(size), None) => self
+          .realloc
+          .get_ref()
+          .map(|realloc| realloc.call(0, 0, 4, size)),
+      },
+    };
+    let mut ptr = match allocation {
+      Some(Ok(ptr)) => ptr,
+      Some(Err(error)) => {
+        error!("unable to allocate string in guest");
+        error!("{}", error);
+        return WasmerStringPtr::new(0);
+      }
+      None => {
+        error!("no allocator export for {:?} strings", self.string_abi);
+        return WasmerStringPtr::new(0);
+      }
+    };
+
+    if self.string_abi == StringAbi::Utf8LengthPrefixed {
+      match ptr.deref(memory, 0, 4) {
+        Some(header) => {
+          for (cell, byte) in header.iter().zip(length.to_le_bytes()) {
+            cell.set(byte);
+          }
+        }
+        None => {
+          error!("allocated string outside of memory");
+          return WasmerStringPtr::new(0);
+        }
+      }
+      ptr = WasmerStringPtr::new(ptr.offset() + 4);
+    }
+    match ptr.deref(memory, 0, length) {
+      Some(values) => {
+        for (cell, byte) in values.iter().zip(bytes) {
+          cell.set(byte);
+        }
+        ptr
+      }
+      None => {
+        error!("allocated string outside of memory");
+        WasmerStringPtr::new(0)
+      }
+    }
+  }
+}
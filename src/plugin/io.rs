@@ -0,0 +1,260 @@
+use std::io::{Read, Write};
+use std::sync::{Arc, Mutex};
+
+use log::{debug, error, info};
+use wasmer::{Instance, NativeFunc};
+use wasmer_wasi::{WasiEnv, WasiError};
+
+use crate::plugin::default::instantiate;
+use crate::plugin::events::LifecycleState;
+use crate::plugin::function_table::FunctionTable;
+use crate::plugin::limits::check_size;
+use crate::plugin::middleware::CallContext;
+use crate::plugin::scratch::ScratchDir;
+use crate::plugin::{helper_get_function, Plugin, PluginError, PluginOptions, WasmerStringPtr};
+
+// plugin for guests which read requests from stdin and write responses to
+// stdout instead of exporting an execute function
+//
+// all data is exchanged as frames: a little endian u32 byte length followed by
+// the utf-8 bytes. a request is two frames (key, payload), a response is one
+// frame. guest logs have to go to stderr, stdout belongs to the protocol
+//
+// - without loop function `_start` (see `set_start_function_name`) is called
+//   for every request. stdin holds the config frame of `init` followed by the
+//   request, the guest answers every request until stdin is empty and returns
+//   or exits with code 0
+// - with a loop function (see `set_loop_function_name`) `_start` is called once
+//   on `init` with the config frame in stdin, afterwards the loop function is
+//   called for every request and answers the requests waiting in stdin
+#[derive(Clone)]
+pub struct IoPlugin {
+  options: PluginOptions,
+  instance: Instance,
+  environment: WasiEnv,
+  functions: FunctionTable,
+  exit_code: Arc<Mutex<Option<u32>>>,
+  config: Arc<Mutex<Option<String>>>,
+  scratch_dir: Option<Arc<ScratchDir>>,
+}
+
+impl Plugin for IoPlugin {
+  fn get_environment(&self) -> &WasiEnv {
+    &self.environment
+  }
+  fn get_instance(&self) -> &Instance {
+    &self.instance
+  }
+  fn get_malloc_fn(&self) -> Option<&NativeFunc<u32, WasmerStringPtr>> {
+    None
+  }
+  fn get_options(&self) -> &PluginOptions {
+    &self.options
+  }
+  fn last_exit_code(&self) -> Option<u32> {
+    *self.exit_code.lock().unwrap()
+  }
+  fn record_exit_code(&self, code: u32) {
+    *self.exit_code.lock().unwrap() = Some(code);
+  }
+  fn get_function_table(&self) -> Option<&FunctionTable> {
+    Some(&self.functions)
+  }
+
+  fn create(mut options: PluginOptions) -> Result<Self, PluginError> {
+    info!(
+      "WASM:{} start create stdin/stdout plugin from \"{}\"",
+      &options.module_name, options.file
+    );
+    let (instance, environment, scratch_dir) = instantiate(&mut options)?;
+
+    let entry = match &options.loop_function_name {
+      Some(name) => name,
+      None => &options.start_function_name,
+    };
+    helper_get_function::<(), ()>(&instance, &options, entry)?;
+    let functions = FunctionTable::for_plugin(&instance, &options);
+
+    let plugin = Self {
+      options,
+      instance,
+      environment,
+      functions,
+      exit_code: Arc::new(Mutex::new(None)),
+      config: Arc::new(Mutex::new(None)),
+      scratch_dir,
+    };
+    plugin.emit_lifecycle(LifecycleState::Created);
+    Ok(plugin)
+  }
+
+  fn init(&self, config: &String) -> Result<(), PluginError> {
+    *self.config.lock().unwrap() = Some(config.clone());
+    if self.options.loop_function_name.is_some() {
+      self.write_frames(&[config])?;
+      self.run_entry(&self.options.start_function_name)?;
+      self.emit_lifecycle(LifecycleState::Started);
+    }
+    self.emit_lifecycle(LifecycleState::Initialized);
+    Ok(())
+  }
+}
+
+impl IoPlugin {
+  pub fn execute(&self, key: &String, payload: &String) -> Result<String, PluginError> {
+    let ctx = CallContext::new(&self.options.module_name, key, payload);
+    let ctx = match self.options.call_timeout {
+      Some(timeout) => ctx.with_timeout(timeout),
+      None => ctx,
+    };
+    if let Some(limiter) = &self.options.rate_limiter {
+      limiter.acquire(&self.options.module_name)?;
+    }
+
+    let result = self.options.middlewares.run(&ctx, &|ctx| {
+      self.options.host_env.enter(ctx);
+      let result = self.call_execute(ctx);
+      self.options.host_env.leave();
+      result
+    });
+    if let Some(dead_letters) = &self.options.dead_letters {
+      dead_letters.finish(&ctx, &result);
+    }
+    match &self.options.fallback {
+      Some(fallback) => fallback.resolve(&ctx, result),
+      None => result,
+    }
+  }
+
+  fn call_execute(&self, ctx: &CallContext) -> Result<String, PluginError> {
+    let key = &self.options.guest_key(ctx);
+    let payload = &ctx.payload;
+    let limits = &self.options.size_limits;
+    check_size(&self.options.module_name, "key", key.len(), limits.key)?;
+    check_size(
+      &self.options.module_name,
+      "payload",
+      payload.len(),
+      limits.payload,
+    )?;
+
+    let entry = match &self.options.loop_function_name {
+      Some(name) => name,
+      None => {
+        let config = self.config.lock().unwrap().clone().unwrap_or_default();
+        self.write_frames(&[&config])?;
+        &self.options.start_function_name
+      }
+    };
+    self.write_frames(&[key, payload])?;
+    self.run_entry(entry)?;
+
+    let mut frames = decode_frames(&self.read_stdout_bytes())
+      .map_err(|reason| self.invalid_frame(entry, reason))?;
+    if frames.len() != 1 {
+      return Err(self.invalid_frame(entry, "expected exactly one response frame"));
+    }
+    let result = frames.remove(0);
+    check_size(
+      &self.options.module_name,
+      "result",
+      result.len(),
+      limits.result,
+    )?;
+
+    self.check_memory_limit()?;
+    if let Some(scratch_dir) = &self.scratch_dir {
+      scratch_dir.check_quota(&self.options.module_name)?;
+    }
+    Ok(result)
+  }
+
+  // a `proc_exit(0)` ends a command guest normally, it is not an error here
+  fn run_entry(&self, name: &String) -> Result<(), PluginError> {
+    let entry = self.get_function::<(), ()>(name)?;
+    let result = match self.guest_call(name, String::new, || entry.call()) {
+      Ok(_) => Ok(()),
+      Err(error) => match error.clone().downcast::<WasiError>() {
+        Ok(WasiError::Exit(0)) => {
+          debug!("{} guest exited with code 0", self.log_prefix(name));
+          Ok(())
+        }
+        _ => {
+          // stdout of a failed call is no valid response
+          self.read_stdout_bytes();
+          Err(self.log_and_transform_error(error, name))
+        }
+      },
+    };
+    self.log_guest_stderr(name);
+    result
+  }
+
+  fn write_frames(&self, frames: &[&String]) -> Result<(), PluginError> {
+    let mut state = self.environment.state();
+    let stdin = state.fs.stdin_mut().unwrap().as_mut().unwrap();
+    for frame in frames {
+      let written = stdin
+        .write_all(&(frame.len() as u32).to_le_bytes())
+        .and_then(|_| stdin.write_all(frame.as_bytes()));
+      if let Err(error) = written {
+        error!(
+          "WASM:{} writing frame to stdin failed",
+          self.options.module_name
+        );
+        error!("{}", error);
+        return Err(PluginError::RuntimeError);
+      }
+    }
+    Ok(())
+  }
+
+  fn read_stdout_bytes(&self) -> Vec<u8> {
+    let mut state = self.environment.state();
+    let stdout = state.fs.stdout_mut().unwrap().as_mut().unwrap();
+    let mut buf = vec![];
+    match stdout.read_to_end(&mut buf) {
+      Ok(_) => buf,
+      Err(_) => vec![],
+    }
+  }
+
+  // `log_guest_output` would drain stdout as well, so only stderr is logged
+  fn log_guest_stderr(&self, name: &String) {
+    if let Some(out) = self.read_from_stderr() {
+      self.options.guest_log.emit(
+        &self.options.module_name,
+        &self.log_prefix(name),
+        &out,
+        self.options.guest_log.stderr_level,
+      );
+    }
+  }
+
+  fn invalid_frame(&self, name: &String, reason: &str) -> PluginError {
+    error!("{} invalid response: {}", self.log_prefix(name), reason);
+    PluginError::InvalidFrame
+  }
+}
+
+// splits length prefixed frames, trailing bytes are an error
+pub fn decode_frames(bytes: &[u8]) -> Result<Vec<String>, &'static str> {
+  let mut frames = vec![];
+  let mut rest = bytes;
+  while !rest.is_empty() {
+    if rest.len() < 4 {
+      return Err("truncated frame header");
+    }
+    let length = u32::from_le_bytes([rest[0], rest[1], rest[2], rest[3]]) as usize;
+    rest = &rest[4..];
+    if rest.len() < length {
+      return Err("frame longer than output");
+    }
+    match std::str::from_utf8(&rest[..length]) {
+      Ok(frame) => frames.push(String::from(frame)),
+      Err(_) => return Err("frame is no valid utf-8"),
+    }
+    rest = &rest[length..];
+  }
+  Ok(frames)
+}
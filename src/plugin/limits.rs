@@ -0,0 +1,29 @@
+use log::error;
+
+use crate::plugin::PluginError;
+
+// maximum sizes in bytes of the strings crossing the guest boundary
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct SizeLimits {
+  pub key: Option<usize>,
+  pub payload: Option<usize>,
+  pub result: Option<usize>,
+}
+
+pub fn check_size(
+  module_name: &String,
+  what: &str,
+  size: usize,
+  limit: Option<usize>,
+) -> Result<(), PluginError> {
+  match limit {
+    Some(limit) if size > limit => {
+      error!(
+        "WASM:{} {} too large - {} bytes exceed the limit of {} bytes",
+        module_name, what, size, limit
+      );
+      Err(PluginError::PayloadTooLarge { size, limit })
+    }
+    _ => Ok(()),
+  }
+}
@@ -0,0 +1,854 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
+
+use log::{error, info, warn};
+use semver::Version;
+
+use crate::plugin::artifact::{normalize_path, ArtifactHeader, NEUTRAL_ARTIFACT_EXTENSION};
+use crate::plugin::canary::{CanaryJob, CanaryRunner};
+use crate::plugin::default::DefaultPlugin;
+use crate::plugin::gate::{CallGate, PauseMode};
+use crate::plugin::manifest::PluginManifest;
+use crate::plugin::metrics::Metrics;
+use crate::plugin::module_cache::ModuleCache;
+use crate::plugin::shared::SharedPlugin;
+use crate::plugin::template::WarmTemplate;
+use crate::plugin::tenant::{TenantNamespace, TenantQuota};
+use crate::plugin::validate::{validate_plugin, ValidationReport};
+use crate::plugin::{PluginError, PluginOptions};
+
+pub type ConfigureFn = Arc<dyn Fn(&mut PluginOptions) + Send + Sync>;
+
+// how `PluginManager::load_dir` finds and configures plugins
+#[derive(Clone)]
+pub struct DiscoveryOptions {
+  recursive: bool,
+  extensions: Vec<String>,
+  execute_function_name: String,
+  threads: usize,
+  configure: Option<ConfigureFn>,
+  init_config: String,
+}
+
+impl DiscoveryOptions {
+  pub fn new() -> Self {
+    Self {
+      recursive: false,
+      extensions: vec![
+        String::from(ArtifactHeader::host().extension()),
+        String::from(NEUTRAL_ARTIFACT_EXTENSION),
+      ],
+      execute_function_name: String::from("transform"),
+      threads: 0,
+      configure: None,
+      init_config: String::new(),
+    }
+  }
+
+  pub fn set_recursive(&mut self, recursive: bool) -> &mut Self {
+    self.recursive = recursive;
+    self
+  }
+
+  // file extension of compiled plugin artifacts, without leading dot
+  // defaults to the library suffix of the host ("so", "dylib" or "dll") and "wmod"
+  pub fn set_extension(&mut self, extension: &String) -> &mut Self {
+    self.extensions = vec![extension.clone()];
+    self
+  }
+
+  pub fn add_extension(&mut self, extension: &String) -> &mut Self {
+    self.extensions.push(extension.clone());
+    self
+  }
+
+  pub fn set_execute_function_name(&mut self, name: &String) -> &mut Self {
+    self.execute_function_name = name.clone();
+    self
+  }
+
+  // number of threads used to load plugins - 0 uses one thread per cpu
+  pub fn set_threads(&mut self, threads: usize) -> &mut Self {
+    self.threads = threads;
+    self
+  }
+
+  // called for every discovered plugin to register host functions, envs, middlewares...
+  pub fn set_configure<F: Fn(&mut PluginOptions) + Send + Sync + 'static>(
+    &mut self,
+    configure: F,
+  ) -> &mut Self {
+    self.configure = Some(Arc::new(configure));
+    self
+  }
+
+  // config every discovered plugin is initialized with before it is
+  // registered, empty if not set. `spawn` and watchdog restarts replay it
+  pub fn set_init_config(&mut self, config: &String) -> &mut Self {
+    self.init_config = config.clone();
+    self
+  }
+}
+
+impl Default for DiscoveryOptions {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl fmt::Debug for DiscoveryOptions {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.debug_struct("DiscoveryOptions")
+      .field("recursive", &self.recursive)
+      .field("extensions", &self.extensions)
+      .field("execute_function_name", &self.execute_function_name)
+      .field("threads", &self.threads)
+      .field("configure", &self.configure.is_some())
+      .field("init_config", &self.init_config)
+      .finish()
+  }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct LoadReport {
+  pub loaded: Vec<String>,
+  pub failed: Vec<(PathBuf, PluginError)>,
+}
+
+// all registered versions of one plugin name
+// calls are routed to the active version, previously active versions are kept
+// as rollback history
+struct PluginVersions {
+  versions: Vec<(Version, SharedPlugin)>,
+  active: Version,
+  history: Vec<Version>,
+  canary: Option<Canary>,
+}
+
+// candidate version which additionally receives a share of the calls
+#[derive(Debug, Clone, PartialEq)]
+pub struct Canary {
+  pub version: Version,
+  pub percentage: f64,
+}
+
+impl PluginVersions {
+  fn get(&self, version: &Version) -> Option<&SharedPlugin> {
+    self
+      .versions
+      .iter()
+      .find(|(v, _)| v == version)
+      .map(|(_, plugin)| plugin)
+  }
+}
+
+// active version and the sampled canary candidate of a call
+type Route = (SharedPlugin, Option<(Version, SharedPlugin)>);
+
+// plugins are held as `SharedPlugin` - a call clones the handle of the active version,
+// so promoting, rolling back or removing a version never interrupts in-flight
+// calls and the old instance is dropped when the last call has finished
+#[derive(Default)]
+pub struct PluginManager {
+  plugins: RwLock<HashMap<String, PluginVersions>>,
+  metrics: Metrics,
+  tenants: RwLock<HashMap<String, Arc<TenantNamespace>>>,
+  module_cache: Arc<ModuleCache>,
+  gates: Mutex<HashMap<String, Arc<CallGate>>>,
+  canary_runner: CanaryRunner,
+}
+
+impl PluginManager {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  // canary results are counted as `<plugin>.canary_calls` and `<plugin>.canary_mismatches`,
+  // comparisons skipped under load as `<plugin>.canary_dropped` and `<plugin>.canary_timeouts`
+  pub fn with_metrics(metrics: &Metrics) -> Self {
+    Self {
+      plugins: RwLock::new(HashMap::new()),
+      metrics: metrics.clone(),
+      tenants: RwLock::new(HashMap::new()),
+      module_cache: Arc::new(ModuleCache::new()),
+      gates: Mutex::new(HashMap::new()),
+      canary_runner: CanaryRunner::default(),
+    }
+  }
+
+  // tenant namespaces share the module cache of their manager
+  pub(crate) fn with_module_cache(metrics: &Metrics, module_cache: &Arc<ModuleCache>) -> Self {
+    Self {
+      plugins: RwLock::new(HashMap::new()),
+      metrics: metrics.clone(),
+      tenants: RwLock::new(HashMap::new()),
+      module_cache: module_cache.clone(),
+      gates: Mutex::new(HashMap::new()),
+      canary_runner: CanaryRunner::default(),
+    }
+  }
+
+  pub fn metrics(&self) -> &Metrics {
+    &self.metrics
+  }
+
+  // plugins loaded by `load_dir` share compiled modules of identical artifacts
+  // through this cache, use it with `PluginOptions::set_module_cache` for
+  // plugins created elsewhere
+  pub fn module_cache(&self) -> &Arc<ModuleCache> {
+    &self.module_cache
+  }
+
+  // registers the plugin with the version declared in its manifest (0.0.0 if none)
+  // the first registered version of a name becomes active
+  pub fn register(&self, name: &String, plugin: DefaultPlugin) -> &Self {
+    self.register_shared(name, SharedPlugin::new(plugin))
+  }
+
+  // instantiates and initializes the plugin once as warm template and
+  // registers an instance cloned from it, `spawn` and watchdog restarts clone
+  // further instances without running `_start` and `init` again
+  pub fn register_warm(
+    &self,
+    name: &String,
+    options: PluginOptions,
+    config: &String,
+  ) -> Result<Arc<WarmTemplate>, PluginError> {
+    let template = Arc::new(WarmTemplate::prepare(options, config)?);
+    self.register_shared(name, SharedPlugin::from_template(&template)?);
+    Ok(template)
+  }
+
+  // new instance of the active version for scaling up, eg a pool of workers
+  // cloned from the warm template if the plugin was registered with
+  // `register_warm`, otherwise created and initialized with the last init config
+  pub fn spawn(&self, name: &String) -> Option<Result<SharedPlugin, PluginError>> {
+    self.get(name).map(|plugin| plugin.spawn())
+  }
+
+  fn register_shared(&self, name: &String, plugin: SharedPlugin) -> &Self {
+    let version = plugin_version(plugin.metadata());
+    // the gate exists before the plugin can be looked up
+    self.gates.lock().unwrap().entry(name.clone()).or_default();
+
+    let mut plugins = self.plugins.write().unwrap();
+    match plugins.get_mut(name) {
+      Some(entry) => {
+        match entry.versions.iter_mut().find(|(v, _)| v == &version) {
+          Some(existing) => {
+            warn!(
+              "WASM:{}@{} replaced already registered plugin",
+              name, version
+            );
+            existing.1 = plugin;
+          }
+          None => entry.versions.push((version, plugin)),
+        };
+      }
+      None => {
+        plugins.insert(
+          name.clone(),
+          PluginVersions {
+            versions: vec![(version.clone(), plugin)],
+            active: version,
+            history: vec![],
+            canary: None,
+          },
+        );
+      }
+    };
+    self
+  }
+
+  // returns the active version of the plugin, its execute calls are held
+  // back while the plugin is paused like the ones of `execute`
+  pub fn get(&self, name: &String) -> Option<SharedPlugin> {
+    let plugins = self.plugins.read().unwrap();
+    let entry = plugins.get(name)?;
+    let plugin = entry.get(&entry.active)?;
+    Some(plugin.with_gate(&self.gate(name)?))
+  }
+
+  pub fn get_version(&self, name: &String, version: &Version) -> Option<SharedPlugin> {
+    let plugins = self.plugins.read().unwrap();
+    let plugin = plugins.get(name)?.get(version)?;
+    Some(plugin.with_gate(&self.gate(name)?))
+  }
+
+  pub fn names(&self) -> Vec<String> {
+    let plugins = self.plugins.read().unwrap();
+    let mut names: Vec<String> = plugins.keys().cloned().collect();
+    names.sort();
+    names
+  }
+
+  // every registered version of every plugin
+  pub fn instances(&self) -> Vec<(String, Version, SharedPlugin)> {
+    let plugins = self.plugins.read().unwrap();
+    let mut instances = vec![];
+    for (name, entry) in plugins.iter() {
+      let gate = self.gate(name);
+      for (version, plugin) in entry.versions.iter() {
+        let plugin = match &gate {
+          Some(gate) => plugin.with_gate(gate),
+          None => plugin.clone(),
+        };
+        instances.push((name.clone(), version.clone(), plugin));
+      }
+    }
+    instances
+  }
+
+  // instantiates every registered version once more, resolves the required
+  // exports and runs the optional `self_test` export, the registered
+  // instances keep serving and are not called
+  pub fn validate_all(&self) -> ValidationReport {
+    let mut instances = self.instances();
+    instances.sort_by(|(a, av, _), (b, bv, _)| a.cmp(b).then(av.cmp(bv)));
+    let mut report = ValidationReport::default();
+    for (name, version, plugin) in instances {
+      let validation = validate_plugin(&name, &version, plugin.options());
+      if validation.passed() {
+        info!("WASM:{}@{} validation passed", name, version);
+      } else {
+        warn!("WASM:{}@{} validation failed", name, version);
+      }
+      report.plugins.push(validation);
+    }
+    report
+  }
+
+  // swaps the instance of a version, unless it was replaced in the meantime
+  pub fn replace_instance(
+    &self,
+    name: &String,
+    version: &Version,
+    current: &SharedPlugin,
+    replacement: SharedPlugin,
+  ) -> bool {
+    let mut plugins = self.plugins.write().unwrap();
+    let entry = match plugins.get_mut(name) {
+      Some(entry) => entry,
+      None => return false,
+    };
+    match entry
+      .versions
+      .iter_mut()
+      .find(|(v, plugin)| v == version && plugin.ptr_eq(current))
+    {
+      Some(existing) => {
+        existing.1 = replacement;
+        true
+      }
+      None => false,
+    }
+  }
+
+  pub fn versions(&self, name: &String) -> Vec<Version> {
+    let plugins = self.plugins.read().unwrap();
+    let mut versions: Vec<Version> = match plugins.get(name) {
+      Some(entry) => entry.versions.iter().map(|(v, _)| v.clone()).collect(),
+      None => vec![],
+    };
+    versions.sort();
+    versions
+  }
+
+  pub fn active_version(&self, name: &String) -> Option<Version> {
+    let plugins = self.plugins.read().unwrap();
+    plugins.get(name).map(|entry| entry.active.clone())
+  }
+
+  // routes all following calls to the given version
+  pub fn promote(&self, name: &String, version: &Version) -> Result<(), PluginError> {
+    let mut plugins = self.plugins.write().unwrap();
+    let entry = match plugins.get_mut(name) {
+      Some(entry) => entry,
+      None => {
+        error!("WASM:{} plugin not registered", name);
+        return Err(PluginError::PluginNotFound);
+      }
+    };
+    if entry.get(version).is_none() {
+      error!("WASM:{}@{} version not registered", name, version);
+      return Err(PluginError::VersionNotFound);
+    }
+    if &entry.active != version {
+      let previous = std::mem::replace(&mut entry.active, version.clone());
+      entry.history.push(previous);
+    }
+    info!("WASM:{}@{} promoted to active version", name, version);
+    Ok(())
+  }
+
+  // switches back to the version which was active before the last promote
+  pub fn rollback(&self, name: &String) -> Result<Version, PluginError> {
+    let mut plugins = self.plugins.write().unwrap();
+    let entry = match plugins.get_mut(name) {
+      Some(entry) => entry,
+      None => {
+        error!("WASM:{} plugin not registered", name);
+        return Err(PluginError::PluginNotFound);
+      }
+    };
+    while let Some(previous) = entry.history.pop() {
+      // versions removed in the meantime are skipped
+      if entry.get(&previous).is_some() {
+        entry.active = previous.clone();
+        info!("WASM:{}@{} rolled back to version", name, previous);
+        return Ok(previous);
+      }
+    }
+    error!("WASM:{} no previous version to roll back to", name);
+    Err(PluginError::VersionNotFound)
+  }
+
+  // removes an inactive version, in-flight calls against it still finish
+  pub fn remove_version(&self, name: &String, version: &Version) -> Result<(), PluginError> {
+    let mut plugins = self.plugins.write().unwrap();
+    let entry = match plugins.get_mut(name) {
+      Some(entry) => entry,
+      None => return Err(PluginError::PluginNotFound),
+    };
+    if &entry.active == version {
+      error!(
+        "WASM:{}@{} active version can not be removed",
+        name, version
+      );
+      return Err(PluginError::VersionActive);
+    }
+    let count = entry.versions.len();
+    entry.versions.retain(|(v, _)| v != version);
+    if entry.versions.len() == count {
+      return Err(PluginError::VersionNotFound);
+    }
+    Ok(())
+  }
+
+  // given percentage of calls is additionally executed against the candidate version
+  // on a background thread, the caller always gets the result of the active
+  // version without waiting for the candidate. mismatches are logged, counted
+  // and emitted as `GuestEvent::CanaryMismatch` on the candidate - a percentage
+  // of 100 is a full shadow mode
+  pub fn set_canary(
+    &self,
+    name: &String,
+    version: &Version,
+    percentage: f64,
+  ) -> Result<(), PluginError> {
+    let mut plugins = self.plugins.write().unwrap();
+    let entry = match plugins.get_mut(name) {
+      Some(entry) => entry,
+      None => return Err(PluginError::PluginNotFound),
+    };
+    if entry.get(version).is_none() {
+      error!("WASM:{}@{} version not registered", name, version);
+      return Err(PluginError::VersionNotFound);
+    }
+    entry.canary = Some(Canary {
+      version: version.clone(),
+      percentage: percentage.clamp(0.0, 100.0),
+    });
+    Ok(())
+  }
+
+  pub fn clear_canary(&self, name: &String) {
+    let mut plugins = self.plugins.write().unwrap();
+    if let Some(entry) = plugins.get_mut(name) {
+      entry.canary = None;
+    }
+  }
+
+  pub fn canary(&self, name: &String) -> Option<Canary> {
+    let plugins = self.plugins.read().unwrap();
+    plugins.get(name).and_then(|entry| entry.canary.clone())
+  }
+
+  pub fn execute(
+    &self,
+    name: &String,
+    key: &String,
+    payload: &String,
+  ) -> Result<String, PluginError> {
+    let _pass = CallGate::enter(&self.registered_gate(name)?)?;
+    let (plugin, candidate) = self.route(name)?;
+    let result = plugin.execute(key, payload);
+    if let Some((version, candidate)) = candidate {
+      self.canary_runner.submit(
+        &self.metrics,
+        CanaryJob {
+          name: name.clone(),
+          version,
+          caller: None,
+          key: key.clone(),
+          payload: payload.clone(),
+          expected: result.clone(),
+          candidate,
+        },
+      );
+    }
+    result
+  }
+
+  // execute accounted to given caller for per caller rate limits
+  pub fn execute_as(
+    &self,
+    caller: &String,
+    name: &String,
+    key: &String,
+    payload: &String,
+  ) -> Result<String, PluginError> {
+    let _pass = CallGate::enter(&self.registered_gate(name)?)?;
+    let (plugin, candidate) = self.route(name)?;
+    let result = plugin.execute_as(caller, key, payload);
+    if let Some((version, candidate)) = candidate {
+      self.canary_runner.submit(
+        &self.metrics,
+        CanaryJob {
+          name: name.clone(),
+          version,
+          caller: Some(caller.clone()),
+          key: key.clone(),
+          payload: payload.clone(),
+          expected: result.clone(),
+          candidate,
+        },
+      );
+    }
+    result
+  }
+
+  // holds back new execute calls of the plugin, eg during a reload, restore
+  // or other maintenance, and waits up to `drain_timeout` for the running
+  // calls. if they do not finish in time `PluginError::DrainTimeout` is
+  // returned and the plugin stays paused, call `resume` to give up
+  pub fn pause(
+    &self,
+    name: &String,
+    mode: PauseMode,
+    drain_timeout: Duration,
+  ) -> Result<(), PluginError> {
+    let gate = self.registered_gate(name)?;
+    info!("WASM:{} paused ({:?})", name, mode);
+    match gate.pause(mode, drain_timeout) {
+      Ok(()) => Ok(()),
+      Err(in_flight) => {
+        warn!(
+          "WASM:{} {} calls still running after {:?}",
+          name, in_flight, drain_timeout
+        );
+        Err(PluginError::DrainTimeout)
+      }
+    }
+  }
+
+  // releases queued calls and accepts new ones
+  pub fn resume(&self, name: &String) {
+    if let Some(gate) = self.gate(name) {
+      gate.resume();
+      info!("WASM:{} resumed", name);
+    }
+  }
+
+  pub fn is_paused(&self, name: &String) -> bool {
+    match self.gate(name) {
+      Some(gate) => gate.is_paused(),
+      None => false,
+    }
+  }
+
+  // execute calls of the plugin running right now
+  pub fn in_flight(&self, name: &String) -> usize {
+    match self.gate(name) {
+      Some(gate) => gate.in_flight(),
+      None => 0,
+    }
+  }
+
+  // runs `maintenance` while the plugin is paused and drained, the plugin is
+  // resumed afterwards and also if draining failed
+  pub fn while_paused<R>(
+    &self,
+    name: &String,
+    mode: PauseMode,
+    drain_timeout: Duration,
+    maintenance: impl FnOnce() -> R,
+  ) -> Result<R, PluginError> {
+    if let Err(error) = self.pause(name, mode, drain_timeout) {
+      self.resume(name);
+      return Err(error);
+    }
+    let result = maintenance();
+    self.resume(name);
+    Ok(result)
+  }
+
+  // gates are only created by `register`, so unknown names from outside, eg
+  // url paths of the server, do not grow the map
+  fn gate(&self, name: &String) -> Option<Arc<CallGate>> {
+    self.gates.lock().unwrap().get(name).cloned()
+  }
+
+  fn registered_gate(&self, name: &String) -> Result<Arc<CallGate>, PluginError> {
+    match self.gate(name) {
+      Some(gate) => Ok(gate),
+      None => {
+        error!("WASM:{} plugin not registered", name);
+        Err(PluginError::PluginNotFound)
+      }
+    }
+  }
+
+  // namespace with own plugins, metrics and limits for one tenant, the
+  // plugins registered directly on the manager are not visible to tenants
+  pub fn add_tenant(&self, tenant: &String, quota: TenantQuota) -> Arc<TenantNamespace> {
+    let namespace = Arc::new(TenantNamespace::new(tenant, quota, &self.module_cache));
+    let mut tenants = self.tenants.write().unwrap();
+    if tenants.insert(tenant.clone(), namespace.clone()).is_some() {
+      warn!("tenant {} replaced together with its plugins", tenant);
+    }
+    namespace
+  }
+
+  pub fn tenant(&self, tenant: &String) -> Option<Arc<TenantNamespace>> {
+    self.tenants.read().unwrap().get(tenant).cloned()
+  }
+
+  pub fn remove_tenant(&self, tenant: &String) -> Option<Arc<TenantNamespace>> {
+    self.tenants.write().unwrap().remove(tenant)
+  }
+
+  pub fn tenants(&self) -> Vec<String> {
+    let mut tenants: Vec<String> = self.tenants.read().unwrap().keys().cloned().collect();
+    tenants.sort();
+    tenants
+  }
+
+  // registers the plugin in the namespace of the tenant, see `TenantQuota::set_max_plugins`
+  pub fn register_for_tenant(
+    &self,
+    tenant: &String,
+    name: &String,
+    plugin: DefaultPlugin,
+  ) -> Result<(), PluginError> {
+    self.tenant_namespace(tenant)?.register(name, plugin)
+  }
+
+  // executes the plugin of the tenant, the call is counted against its quota
+  // and runs with the tenant id as caller. pausing a plugin registered on this
+  // manager also holds back the calls of all tenants to the same name
+  pub fn execute_for_tenant(
+    &self,
+    tenant: &String,
+    name: &String,
+    key: &String,
+    payload: &String,
+  ) -> Result<String, PluginError> {
+    let _pass = match self.gate(name) {
+      Some(gate) => Some(CallGate::enter(&gate)?),
+      None => None,
+    };
+    let namespace = self.tenant_namespace(tenant)?;
+    namespace.acquire()?;
+    let start = Instant::now();
+    let result = namespace.plugins().execute_as(tenant, name, key, payload);
+    namespace.record(start.elapsed());
+    result
+  }
+
+  fn tenant_namespace(&self, tenant: &String) -> Result<Arc<TenantNamespace>, PluginError> {
+    match self.tenant(tenant) {
+      Some(namespace) => Ok(namespace),
+      None => {
+        error!("tenant {} not registered", tenant);
+        Err(PluginError::TenantNotFound)
+      }
+    }
+  }
+
+  // active version and - if sampled - the canary candidate for this call
+  fn route(&self, name: &String) -> Result<Route, PluginError> {
+    let plugins = self.plugins.read().unwrap();
+    let entry = match plugins.get(name) {
+      Some(entry) => entry,
+      None => {
+        error!("WASM:{} plugin not registered", name);
+        return Err(PluginError::PluginNotFound);
+      }
+    };
+    let plugin = match entry.get(&entry.active) {
+      Some(plugin) => plugin.clone(),
+      None => return Err(PluginError::VersionNotFound),
+    };
+
+    let candidate = match &entry.canary {
+      Some(canary) if canary.version != entry.active => {
+        if rand::random::<f64>() * 100.0 < canary.percentage {
+          entry
+            .get(&canary.version)
+            .map(|candidate| (canary.version.clone(), candidate.clone()))
+        } else {
+          None
+        }
+      }
+      _ => None,
+    };
+    Ok((plugin, candidate))
+  }
+
+  // scans the directory for compiled plugins, initializes them with the init
+  // config of `discovery` and registers them by file name
+  // `plugins/enrich.so` is registered as plugin `enrich` unless the sidecar
+  // manifest `plugins/enrich.toml` declares another name
+  pub fn load_dir(&self, path: &Path, discovery: &DiscoveryOptions) -> LoadReport {
+    let mut report = LoadReport::default();
+
+    let mut files = vec![];
+    if let Err(error) = collect_files(path, discovery, &mut files) {
+      error!("scanning plugin directory {:?} failed", path);
+      error!("{}", error);
+      report
+        .failed
+        .push((path.to_path_buf(), PluginError::LoadingError));
+      return report;
+    }
+    files.sort();
+
+    let mut candidates: Vec<(PathBuf, String, Option<PluginManifest>)> = vec![];
+    for file in files {
+      let manifest_file = PluginManifest::sidecar_file(&file);
+      let manifest = if manifest_file.is_file() {
+        match PluginManifest::read_from_file(&manifest_file) {
+          Ok(manifest) => Some(manifest),
+          Err(error) => {
+            report.failed.push((file, error));
+            continue;
+          }
+        }
+      } else {
+        None
+      };
+
+      let file_name = file.file_stem().and_then(|stem| stem.to_str());
+      let name = match (manifest.as_ref().and_then(|m| m.name.clone()), file_name) {
+        (Some(name), _) => name,
+        (None, Some(name)) => String::from(name),
+        (None, None) => {
+          error!("unable to infer plugin name from {:?}", file);
+          report.failed.push((file, PluginError::LoadingError));
+          continue;
+        }
+      };
+      // the same name may be loaded in several versions, see `promote`
+      let version = manifest_version(&manifest);
+      let duplicate = candidates
+        .iter()
+        .any(|(_, n, m)| n == &name && manifest_version(m) == version);
+      if duplicate || self.get_version(&name, &version).is_some() {
+        error!(
+          "WASM:{}@{} duplicate plugin version for {:?}",
+          name, version, file
+        );
+        report.failed.push((file, PluginError::LoadingError));
+        continue;
+      }
+      candidates.push((file, name, manifest));
+    }
+
+    let options: Vec<PluginOptions> = candidates
+      .iter()
+      .map(|(file, name, manifest)| {
+        let mut options = PluginOptions::new(
+          name,
+          &normalize_path(file),
+          &discovery.execute_function_name,
+        );
+        options.set_module_cache(&self.module_cache);
+        if let Some(configure) = &discovery.configure {
+          configure(&mut options);
+        }
+        if let Some(manifest) = manifest {
+          options.apply_manifest(manifest);
+        }
+        options
+      })
+      .collect();
+
+    let results = DefaultPlugin::create_all(options, discovery.threads);
+    for ((file, name, _), result) in candidates.into_iter().zip(results) {
+      let plugin = match result {
+        Ok(plugin) => SharedPlugin::new(plugin),
+        Err(error) => {
+          report.failed.push((file, error));
+          continue;
+        }
+      };
+      // runs `_start` and `init`, a plugin failing them is not registered
+      match plugin.init(&discovery.init_config) {
+        Ok(()) => {
+          self.register_shared(&name, plugin);
+          report.loaded.push(name);
+        }
+        Err(error) => {
+          error!("WASM:{} init of {:?} failed", name, file);
+          report.failed.push((file, error));
+        }
+      }
+    }
+
+    info!(
+      "loaded {} plugins from {:?}, {} failed",
+      report.loaded.len(),
+      path,
+      report.failed.len()
+    );
+    report
+  }
+}
+
+fn manifest_version(manifest: &Option<PluginManifest>) -> Version {
+  match manifest.as_ref().and_then(|m| m.version.clone()) {
+    Some(version) => version,
+    None => Version::new(0, 0, 0),
+  }
+}
+
+fn plugin_version(metadata: &PluginManifest) -> Version {
+  match &metadata.version {
+    Some(version) => version.clone(),
+    None => Version::new(0, 0, 0),
+  }
+}
+
+fn collect_files(
+  path: &Path,
+  discovery: &DiscoveryOptions,
+  files: &mut Vec<PathBuf>,
+) -> std::io::Result<()> {
+  for entry in fs::read_dir(path)? {
+    let file = entry?.path();
+    if file.is_dir() {
+      if discovery.recursive {
+        collect_files(&file, discovery, files)?;
+      }
+      continue;
+    }
+    match file.extension().and_then(|extension| extension.to_str()) {
+      // windows file names are case insensitive, eg `ENRICH.DLL`
+      Some(extension)
+        if discovery
+          .extensions
+          .iter()
+          .any(|expected| expected.eq_ignore_ascii_case(extension)) =>
+      {
+        files.push(file)
+      }
+      _ => (),
+    }
+  }
+  Ok(())
+}
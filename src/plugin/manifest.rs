@@ -0,0 +1,182 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use log::error;
+use semver::Version;
+use serde::Deserialize;
+use toml::value::{Table, Value};
+
+use crate::plugin::sections::ModuleSections;
+use crate::plugin::syscall::{check_pattern, parse_rule, SyscallPolicy};
+use crate::plugin::PluginError;
+
+// optional sidecar file `<plugin>.toml` next to the compiled plugin
+//
+// name = "enrich"
+// version = "1.2.0"
+//
+// [functions]
+// start = "_start"
+// init = "init"
+// execute = "transform"
+// allocate = "malloc"
+// memory = "memory"
+//
+// [host]
+// required = ["tests", "tests2"]
+//
+// [env]
+// LOG_LEVEL = "debug"
+//
+// [limits]
+// memory_pages = 16
+//
+// [syscalls]
+// fd_write = "allow fds 1,2"
+// "clock_*" = "allow"
+// "*" = "deny"
+//
+// unknown tables and keys are rejected, so is `fuel` in `[limits]` - the host
+// does not meter guest calls and would not apply it
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PluginManifest {
+  pub name: Option<String>,
+  pub version: Option<Version>,
+  pub start_function_name: Option<String>,
+  pub init_function_name: Option<String>,
+  pub execute_function_name: Option<String>,
+  pub allocate_function_name: Option<String>,
+  pub memory_name: Option<String>,
+  pub required_host_functions: Vec<String>,
+  pub envs: Vec<(String, String)>,
+  pub memory_limit_pages: Option<u32>,
+  // `None` without `[syscalls]` table
+  pub syscall_policy: Option<SyscallPolicy>,
+  // custom sections of the module, filled when the plugin is created
+  pub sections: ModuleSections,
+}
+
+// the manifest as written, `env` and `syscalls` keep the order of the file
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ManifestFile {
+  name: Option<String>,
+  version: Option<String>,
+  #[serde(default)]
+  functions: FunctionsTable,
+  #[serde(default)]
+  host: HostTable,
+  #[serde(default)]
+  env: Table,
+  #[serde(default)]
+  limits: LimitsTable,
+  syscalls: Option<Table>,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct FunctionsTable {
+  start: Option<String>,
+  init: Option<String>,
+  execute: Option<String>,
+  allocate: Option<String>,
+  memory: Option<String>,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct HostTable {
+  #[serde(default)]
+  required: Vec<String>,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct LimitsTable {
+  memory_pages: Option<i64>,
+  fuel: Option<Value>,
+}
+
+impl PluginManifest {
+  // `plugins/enrich.so` -> `plugins/enrich.toml`
+  pub fn sidecar_file(artifact_file: &Path) -> PathBuf {
+    artifact_file.with_extension("toml")
+  }
+
+  pub fn read_from_file(file: &Path) -> Result<Self, PluginError> {
+    let content = match fs::read_to_string(file) {
+      Ok(content) => content,
+      Err(error) => {
+        error!("reading manifest {:?} failed", file);
+        error!("{}", error);
+        return Err(PluginError::InvalidManifest);
+      }
+    };
+    match Self::parse(&content) {
+      Ok(manifest) => Ok(manifest),
+      Err(message) => {
+        error!("invalid manifest {:?}: {}", file, message);
+        Err(PluginError::InvalidManifest)
+      }
+    }
+  }
+
+  pub fn parse(content: &String) -> Result<Self, String> {
+    let file: ManifestFile = toml::from_str(content).map_err(|error| error.to_string())?;
+    let mut manifest = Self {
+      name: file.name,
+      start_function_name: file.functions.start,
+      init_function_name: file.functions.init,
+      execute_function_name: file.functions.execute,
+      allocate_function_name: file.functions.allocate,
+      memory_name: file.functions.memory,
+      required_host_functions: file.host.required,
+      ..Self::default()
+    };
+
+    if let Some(version) = file.version {
+      match Version::parse(&version) {
+        Ok(v) => manifest.version = Some(v),
+        Err(error) => return Err(format!("invalid version \"{}\": {}", version, error)),
+      }
+    }
+    for (key, value) in file.env.iter() {
+      manifest.envs.push((key.clone(), as_string(key, value)?));
+    }
+    if let Some(pages) = file.limits.memory_pages {
+      match u32::try_from(pages) {
+        Ok(pages) => manifest.memory_limit_pages = Some(pages),
+        Err(_) => {
+          return Err(format!(
+            "\"memory_pages\" must be between 0 and {}, got {}",
+            u32::MAX,
+            pages
+          ))
+        }
+      }
+    }
+    if file.limits.fuel.is_some() {
+      return Err(String::from(
+        "\"fuel\" is not supported, the host does not meter guest calls",
+      ));
+    }
+    for (key, value) in file.syscalls.iter().flatten() {
+      check_pattern(key)?;
+      let rule = parse_rule(&as_string(key, value)?)
+        .map_err(|message| format!("syscall \"{}\": {}", key, message))?;
+      manifest
+        .syscall_policy
+        .get_or_insert_with(SyscallPolicy::allow_all)
+        .add_rule(key, rule);
+    }
+
+    Ok(manifest)
+  }
+}
+
+fn as_string(key: &String, value: &Value) -> Result<String, String> {
+  match value {
+    Value::String(s) => Ok(s.clone()),
+    _ => Err(format!("\"{}\" must be a string", key)),
+  }
+}
@@ -0,0 +1,153 @@
+use std::collections::{BTreeMap, HashMap};
+use std::io::Write;
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+
+use log::error;
+use wasmer_vfs::mem_fs;
+use wasmer_vfs::FileSystem;
+
+use crate::plugin::PluginError;
+
+// in-memory file tree mounted read-only into the guest
+// eg lookup tables or model weights shipped together with the plugin
+#[derive(Debug, Clone)]
+pub struct MemoryFs {
+  mount: String,
+  files: BTreeMap<String, Arc<Vec<u8>>>,
+}
+
+impl MemoryFs {
+  // `mount` is the absolute path under which the guest sees the files, eg `/assets`
+  pub fn new(mount: &String) -> Self {
+    Self {
+      mount: mount.clone(),
+      files: BTreeMap::new(),
+    }
+  }
+
+  // paths of the map are relative to the mount point
+  pub fn from_map(mount: &String, files: HashMap<String, Vec<u8>>) -> Self {
+    let mut fs = Self::new(mount);
+    for (path, content) in files {
+      fs.add_file(&path, content);
+    }
+    fs
+  }
+
+  // reads the regular files of an uncompressed (us)tar archive
+  pub fn from_tar(mount: &String, archive: &[u8]) -> Result<Self, PluginError> {
+    let mut fs = Self::new(mount);
+    let mut offset = 0;
+
+    while offset < archive.len() {
+      if offset + 512 > archive.len() {
+        // padding after the end of archive blocks is fine, a partial header is not
+        if archive[offset..].iter().all(|b| *b == 0) {
+          break;
+        }
+        error!("tar archive truncated in header at offset {}", offset);
+        return Err(PluginError::InvalidArchive);
+      }
+      let header = &archive[offset..offset + 512];
+      if header.iter().all(|b| *b == 0) {
+        break;
+      }
+
+      let mut name = tar_string(&header[0..100]);
+      if &header[257..262] == b"ustar" {
+        let prefix = tar_string(&header[345..500]);
+        if !prefix.is_empty() {
+          name = format!("{}/{}", prefix, name);
+        }
+      }
+      // the files must stay below the mount point
+      if Path::new(&name)
+        .components()
+        .any(|component| matches!(component, Component::ParentDir))
+      {
+        error!("tar entry \"{}\" leaves the mount point", name);
+        return Err(PluginError::InvalidArchive);
+      }
+      let size = match usize::from_str_radix(tar_string(&header[124..136]).trim(), 8) {
+        Ok(size) => size,
+        Err(_) => {
+          error!("invalid tar header for \"{}\"", name);
+          return Err(PluginError::InvalidArchive);
+        }
+      };
+
+      let start = offset + 512;
+      let end = match start.checked_add(size) {
+        Some(end) if end <= archive.len() => end,
+        _ => {
+          error!("tar archive truncated in \"{}\"", name);
+          return Err(PluginError::InvalidArchive);
+        }
+      };
+      // regular files only, directories are created implicitly
+      if header[156] == b'0' || header[156] == 0 {
+        fs.add_file(&name, archive[start..end].to_vec());
+      }
+
+      offset = start + size.div_ceil(512) * 512;
+    }
+
+    Ok(fs)
+  }
+
+  pub fn add_file(&mut self, path: &String, content: Vec<u8>) -> &mut Self {
+    let path = String::from(path.trim_start_matches("./").trim_start_matches('/'));
+    self.files.insert(path, Arc::new(content));
+    self
+  }
+
+  pub fn mount(&self) -> &String {
+    &self.mount
+  }
+
+  pub fn paths(&self) -> Vec<String> {
+    self.files.keys().cloned().collect()
+  }
+
+  // creates the wasi file system backing containing all files below the mount point
+  pub(crate) fn build(&self) -> Result<mem_fs::FileSystem, String> {
+    let fs = mem_fs::FileSystem::default();
+    let mount = PathBuf::from(&self.mount);
+    create_dir_all(&fs, &mount)?;
+
+    for (path, content) in self.files.iter() {
+      let file_path = mount.join(path);
+      if let Some(parent) = file_path.parent() {
+        create_dir_all(&fs, parent)?;
+      }
+      let mut file = fs
+        .new_open_options()
+        .write(true)
+        .create(true)
+        .open(&file_path)
+        .map_err(|error| format!("creating {:?} failed: {}", file_path, error))?;
+      file
+        .write_all(content)
+        .map_err(|error| format!("writing {:?} failed: {}", file_path, error))?;
+    }
+    Ok(fs)
+  }
+}
+
+fn create_dir_all(fs: &mem_fs::FileSystem, path: &Path) -> Result<(), String> {
+  let mut current = PathBuf::from("/");
+  for component in path.components().skip(1) {
+    current.push(component);
+    if fs.read_dir(&current).is_err() {
+      fs.create_dir(&current)
+        .map_err(|error| format!("creating {:?} failed: {}", current, error))?;
+    }
+  }
+  Ok(())
+}
+
+fn tar_string(bytes: &[u8]) -> String {
+  let end = bytes.iter().position(|b| *b == 0).unwrap_or(bytes.len());
+  String::from_utf8_lossy(&bytes[..end]).into_owned()
+}
@@ -0,0 +1,53 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+#[derive(Debug, Default)]
+pub struct Counter {
+  value: AtomicU64,
+}
+
+impl Counter {
+  pub fn increment(&self) {
+    self.add(1);
+  }
+
+  pub fn add(&self, value: u64) {
+    self.value.fetch_add(value, Ordering::Relaxed);
+  }
+
+  pub fn get(&self) -> u64 {
+    self.value.load(Ordering::Relaxed)
+  }
+}
+
+// simple registry of named counters which can be shared between plugins and middlewares
+#[derive(Debug, Clone, Default)]
+pub struct Metrics {
+  counters: Arc<Mutex<HashMap<String, Arc<Counter>>>>,
+}
+
+impl Metrics {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  // returns the counter with given name - it is created on first access
+  pub fn counter(&self, name: &String) -> Arc<Counter> {
+    let mut counters = self.counters.lock().unwrap();
+    counters
+      .entry(name.clone())
+      .or_insert_with(|| Arc::new(Counter::default()))
+      .clone()
+  }
+
+  pub fn snapshot(&self) -> Vec<(String, u64)> {
+    let counters = self.counters.lock().unwrap();
+    let mut result: Vec<(String, u64)> = counters
+      .iter()
+      .map(|(name, counter)| (name.clone(), counter.get()))
+      .collect();
+    result.sort();
+    result
+  }
+}
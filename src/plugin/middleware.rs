@@ -0,0 +1,151 @@
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::plugin::PluginError;
+
+static NEXT_CALL_ID: AtomicU64 = AtomicU64::new(1);
+
+// everything middlewares and host functions get to know about the current execute call
+#[derive(Debug, Clone)]
+pub struct CallContext {
+  pub module_name: String,
+  pub call_id: u64,
+  pub trace_id: String,
+  pub caller: Option<String>,
+  pub key: String,
+  pub payload: String,
+  pub deadline: Option<Instant>,
+}
+
+impl CallContext {
+  // the trace id defaults to the call id until one is attached with `with_trace_id`
+  pub fn new(module_name: &String, key: &String, payload: &String) -> Self {
+    let call_id = NEXT_CALL_ID.fetch_add(1, Ordering::Relaxed);
+    Self {
+      module_name: module_name.clone(),
+      call_id,
+      trace_id: format!("{:016x}", call_id),
+      caller: None,
+      key: key.clone(),
+      payload: payload.clone(),
+      deadline: None,
+    }
+  }
+
+  pub fn with_trace_id(mut self, trace_id: &String) -> Self {
+    self.trace_id = trace_id.clone();
+    self
+  }
+
+  pub fn with_timeout(mut self, timeout: Duration) -> Self {
+    self.deadline = Some(Instant::now() + timeout);
+    self
+  }
+
+  // time left until the deadline, `None` if the call has no deadline
+  pub fn remaining(&self) -> Option<Duration> {
+    self
+      .deadline
+      .map(|deadline| deadline.saturating_duration_since(Instant::now()))
+  }
+
+  pub fn with_caller(mut self, caller: &String) -> Self {
+    self.caller = Some(caller.clone());
+    self
+  }
+}
+
+// a layer wrapped around the execute call of a plugin
+// a middleware can inspect or modify the call, short-circuit it by returning
+// without calling `next.run` or post-process the result of the inner layers
+pub trait Middleware: Send + Sync {
+  fn handle(&self, ctx: &CallContext, next: Next) -> Result<String, PluginError>;
+}
+
+// the remaining part of the chain - the last element is the real guest call
+pub struct Next<'a> {
+  middlewares: &'a [Arc<dyn Middleware>],
+  endpoint: &'a dyn Fn(&CallContext) -> Result<String, PluginError>,
+}
+
+impl<'a> Next<'a> {
+  pub fn run(self, ctx: &CallContext) -> Result<String, PluginError> {
+    match self.middlewares.split_first() {
+      Some((current, rest)) => current.handle(
+        ctx,
+        Next {
+          middlewares: rest,
+          endpoint: self.endpoint,
+        },
+      ),
+      None => (self.endpoint)(ctx),
+    }
+  }
+}
+
+#[derive(Clone, Default)]
+pub struct MiddlewareChain {
+  middlewares: Vec<Arc<dyn Middleware>>,
+}
+
+impl MiddlewareChain {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  // middlewares are called in the order they are added
+  pub fn add(&mut self, middleware: Arc<dyn Middleware>) -> &mut Self {
+    self.middlewares.push(middleware);
+    self
+  }
+
+  pub fn run(
+    &self,
+    ctx: &CallContext,
+    endpoint: &dyn Fn(&CallContext) -> Result<String, PluginError>,
+  ) -> Result<String, PluginError> {
+    Next {
+      middlewares: &self.middlewares,
+      endpoint,
+    }
+    .run(ctx)
+  }
+}
+
+impl fmt::Debug for MiddlewareChain {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.debug_struct("MiddlewareChain")
+      .field("len", &self.middlewares.len())
+      .finish()
+  }
+}
+
+// rejects calls for which the validation function returns an error,
+// eg to check the payload against a schema before it reaches the guest
+pub struct ValidationMiddleware<F>
+where
+  F: Fn(&CallContext) -> Result<(), PluginError> + Send + Sync,
+{
+  validate: F,
+}
+
+impl<F> ValidationMiddleware<F>
+where
+  F: Fn(&CallContext) -> Result<(), PluginError> + Send + Sync,
+{
+  pub fn new(validate: F) -> Self {
+    Self { validate }
+  }
+}
+
+impl<F> Middleware for ValidationMiddleware<F>
+where
+  F: Fn(&CallContext) -> Result<(), PluginError> + Send + Sync,
+{
+  fn handle(&self, ctx: &CallContext, next: Next) -> Result<String, PluginError> {
+    (self.validate)(ctx)?;
+    next.run(ctx)
+  }
+}
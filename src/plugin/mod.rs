@@ -1,14 +1,101 @@
+pub mod actor;
+pub mod affinity;
+pub mod arena;
+pub mod artifact;
+pub mod audit;
+pub mod cache;
+pub mod canary;
+pub mod checkpoint;
+pub mod compiler;
+pub mod dead_letter;
 pub mod default;
+pub mod diff;
+pub mod errors;
+pub mod events;
+pub mod fallback;
+pub mod features;
+pub mod function_table;
+#[cfg(feature = "fuzzing")]
+pub mod fuzz;
+pub mod gate;
+pub mod globals;
+pub mod guest_log;
+pub mod heap;
+pub mod host;
+pub mod io;
+pub mod limits;
+pub mod manager;
+pub mod manifest;
+pub mod memfs;
+pub mod metrics;
+pub mod middleware;
+pub mod module_cache;
+pub mod network;
+pub mod priority;
+pub mod probe;
+pub mod profile;
+pub mod rate_limit;
+pub mod recorder;
+pub mod repl;
+pub mod report;
+pub mod scratch;
+pub mod sections;
+pub mod shared;
+pub mod string_abi;
+pub mod syscall;
+pub mod template;
+pub mod tenant;
+pub mod validate;
+pub mod wasi;
+pub mod watchdog;
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::Receiver;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 use wasmer::{
-  Array, Exports, Function, HostFunction, Instance, Memory, NativeFunc, RuntimeError, Store,
-  Universal, WasmPtr, WasmTypeList,
+  Array, Exports, Function, FunctionType, HostFunction, Instance, Memory, NativeFunc, RuntimeError,
+  Store, Universal, WasmPtr, WasmTypeList,
 };
-use wasmer_wasi::WasiEnv;
+use wasmer_wasi::{WasiEnv, WasiError};
+
+use log::{debug, error, info, warn};
 
-use log::{error, info};
+use affinity::{ThreadConfig, ThreadPriority};
+use arena::CallArena;
+use audit::{now_ms, AuditDirection, AuditLog, AuditRecord};
+use checkpoint::{artifact_fingerprint, Checkpoint};
+use dead_letter::DeadLetterQueue;
+use errors::{ErrorSlots, GuestError, HostErrorEnv};
+use events::{EventBus, EventKind, GuestEvent, GuestStream, LifecycleState};
+use fallback::FallbackStrategy;
+use features::WasmFeatures;
+use function_table::FunctionTable;
+use guest_log::GuestLogConfig;
+use host::HostEnv;
+use limits::SizeLimits;
+use manifest::PluginManifest;
+use memfs::MemoryFs;
+use middleware::{CallContext, Middleware, MiddlewareChain};
+use module_cache::ModuleCache;
+use network::NetworkPolicy;
+use priority::{Priority, SchedulingConfig};
+use profile::AbiProfile;
+use rate_limit::{RateLimit, RateLimiter};
+use recorder::{DynamicHostFn, DynamicHostFunction, HostTape, Recorder};
+use report::ReportCapture;
+use scratch::ScratchDirConfig;
+use string_abi::{
+  string_length, ResultAbi, StringAbi, AS_ARRAY_BUFFER_CLASS_ID, AS_STRING_CLASS_ID,
+};
+use syscall::{PolicyViolation, SyscallPolicy};
+use wasi::StubBehavior;
 
 pub type WasmerStringPtr = WasmPtr<u8, Array>;
+// `canonical_abi_realloc(old_ptr, old_size, align, new_size) -> ptr`
+pub type ReallocFn = NativeFunc<(u32, u32, u32, u32), WasmerStringPtr>;
 
 #[derive(Debug, Clone)]
 pub struct PluginOptions {
@@ -16,13 +103,53 @@ pub struct PluginOptions {
   module_name: String,
   file: String,
   envs: Vec<(String, String)>,
+  wasi_stubs: Vec<(String, StubBehavior)>,
+  network_policy: NetworkPolicy,
+  scratch_dir: Option<ScratchDirConfig>,
+  memory_fs: Option<MemoryFs>,
   args: Vec<String>,
   start_function_name: String,
+  loop_function_name: Option<String>,
   init_function_name: String,
   allocate_utf8array_function_name: String,
+  new_function_name: String,
+  pin_function_name: String,
+  unpin_function_name: String,
+  free_function_name: String,
+  collect_function_name: Option<String>,
+  array_buffer_class_id: u32,
+  string_abi: StringAbi,
+  result_abis: HashMap<String, ResultAbi>,
+  string_class_id: u32,
   execute_function_name: String,
   memory_name: String,
   custom_exports: Exports,
+  dynamic_host_functions: Vec<DynamicHostFunction>,
+  recorder: Option<Arc<Recorder>>,
+  audit_log: Option<Arc<AuditLog>>,
+  syscall_policy: Option<SyscallPolicy>,
+  host_tape: HostTape,
+  host_env: HostEnv,
+  error_slots: ErrorSlots,
+  call_timeout: Option<Duration>,
+  fallback: Option<FallbackStrategy>,
+  dead_letters: Option<Arc<DeadLetterQueue>>,
+  thread_config: ThreadConfig,
+  priority: Priority,
+  scheduling: SchedulingConfig,
+  trace_id_key_prefix: bool,
+  guest_log: GuestLogConfig,
+  middlewares: MiddlewareChain,
+  rate_limiter: Option<Arc<RateLimiter>>,
+  caller_rate_limiter: Option<Arc<RateLimiter>>,
+  memory_limit_pages: Option<u32>,
+  call_arena_size: Option<u32>,
+  wasm_features: WasmFeatures,
+  module_cache: Option<Arc<ModuleCache>>,
+  size_limits: SizeLimits,
+  metadata: PluginManifest,
+  events: EventBus,
+  checkpoint_file: Option<PathBuf>,
 }
 
 impl PluginOptions {
@@ -38,15 +165,55 @@ impl PluginOptions {
     Self {
       store,
       custom_exports,
+      dynamic_host_functions: vec![],
+      recorder: None,
+      audit_log: None,
+      syscall_policy: None,
+      host_tape: HostTape::default(),
       module_name: module_name.clone(),
       file: file.clone(),
       envs: vec![],
+      wasi_stubs: vec![],
+      network_policy: NetworkPolicy::deny_all(),
+      scratch_dir: None,
+      memory_fs: None,
       args: vec![],
       start_function_name,
+      loop_function_name: None,
       init_function_name,
       allocate_utf8array_function_name,
+      new_function_name: String::from("__new"),
+      pin_function_name: String::from("__pin"),
+      unpin_function_name: String::from("__unpin"),
+      free_function_name: String::from("free"),
+      collect_function_name: Some(String::from("__collect")),
+      array_buffer_class_id: AS_ARRAY_BUFFER_CLASS_ID,
+      string_abi: StringAbi::default(),
+      result_abis: HashMap::new(),
+      string_class_id: AS_STRING_CLASS_ID,
       execute_function_name: execute_function_name.clone(),
       memory_name,
+      host_env: HostEnv::new(),
+      error_slots: ErrorSlots::default(),
+      call_timeout: None,
+      fallback: None,
+      dead_letters: None,
+      thread_config: ThreadConfig::default(),
+      priority: Priority::default(),
+      scheduling: SchedulingConfig::default(),
+      trace_id_key_prefix: false,
+      guest_log: GuestLogConfig::default(),
+      middlewares: MiddlewareChain::new(),
+      rate_limiter: None,
+      caller_rate_limiter: None,
+      memory_limit_pages: None,
+      call_arena_size: None,
+      wasm_features: WasmFeatures::default(),
+      module_cache: None,
+      size_limits: SizeLimits::default(),
+      metadata: PluginManifest::default(),
+      events: EventBus::new(),
+      checkpoint_file: None,
     }
   }
 
@@ -65,11 +232,158 @@ impl PluginOptions {
     self
   }
 
+  // registers a host function which gets the `HostEnv` as first parameter
+  // eg `fn lookup(env: &HostEnv, id: i32) -> i32` and can read the current `CallContext`
+  pub fn add_host_function_with_context<
+    F: HostFunction<Args, Rets, wasmer::internals::WithEnv, HostEnv>,
+    Args: WasmTypeList,
+    Rets: WasmTypeList,
+  >(
+    &mut self,
+    name: String,
+    value: F,
+  ) -> &mut Self {
+    let c = Function::new_native_with_env(&self.store, self.host_env.clone(), value);
+    self.custom_exports.insert(name.clone(), c);
+    self
+  }
+
+  // registers a host function which gets the `HostErrorEnv` as first parameter
+  // and can fail without trapping the guest, eg
+  // `fn lookup(env: &HostErrorEnv, id: i32) -> i32 { env.status(find(id)) }`
+  // the guest gets the negative code and reads the message with `last_host_error`
+  pub fn add_host_function_with_errors<
+    F: HostFunction<Args, Rets, wasmer::internals::WithEnv, HostErrorEnv>,
+    Args: WasmTypeList,
+    Rets: WasmTypeList,
+  >(
+    &mut self,
+    name: String,
+    value: F,
+  ) -> &mut Self {
+    let env = HostErrorEnv::new(&self.host_env, &self.error_slots);
+    let c = Function::new_native_with_env(&self.store, env, value);
+    self.custom_exports.insert(name.clone(), c);
+    self
+  }
+
+  // host function with runtime checked signature, eg
+  // `FunctionType::new(vec![Type::I32], vec![Type::I32])` and `|args| Ok(vec![args[0].clone()])`
+  // calls of these functions are captured by the recorder and answered from the journal on replay
+  pub fn add_dynamic_host_function<F>(
+    &mut self,
+    name: String,
+    ty: FunctionType,
+    func: F,
+  ) -> &mut Self
+  where
+    F: Fn(&[wasmer::Val]) -> Result<Vec<wasmer::Val>, RuntimeError> + Send + Sync + 'static,
+  {
+    let func: Arc<DynamicHostFn> = Arc::new(func);
+    self
+      .dynamic_host_functions
+      .push(DynamicHostFunction { name, ty, func });
+    self
+  }
+
+  // journals all execute calls, see `recorder::replay` to run them again
+  pub fn set_recorder(&mut self, recorder: Arc<Recorder>) -> &mut Self {
+    self.recorder = Some(recorder.clone());
+    self.middlewares.add(recorder);
+    self
+  }
+
+  // records every call into the guest and of dynamic host functions, see `AuditLog`
+  pub fn set_audit_log(&mut self, audit_log: Arc<AuditLog>) -> &mut Self {
+    self.audit_log = Some(audit_log);
+    self
+  }
+
+  // restricts the wasi imports of the guest, see `SyscallPolicy`
+  // violations trap the guest call with `PluginError::PolicyViolation`
+  pub fn set_syscall_policy(&mut self, policy: SyscallPolicy) -> &mut Self {
+    self.syscall_policy = Some(policy);
+    self
+  }
+
+  // deadline handed to middlewares and host functions via `CallContext`
+  // the guest call itself is not interrupted when the deadline is exceeded
+  pub fn set_call_timeout(&mut self, timeout: Duration) -> &mut Self {
+    self.call_timeout = Some(timeout);
+    self
+  }
+
+  // answer of `execute` when the call fails or exceeds the call timeout
+  pub fn set_fallback(&mut self, fallback: FallbackStrategy) -> &mut Self {
+    self.fallback = Some(fallback);
+    self
+  }
+
+  // captures key, payload, error and guest stderr of failed execute calls
+  pub fn set_dead_letters(&mut self, dead_letters: Arc<DeadLetterQueue>) -> &mut Self {
+    self.dead_letters = Some(dead_letters);
+    self
+  }
+
+  // pins the worker thread of the plugin to the cores, see `ThreadConfig`
+  pub fn set_cpu_affinity(&mut self, cpus: &[usize]) -> &mut Self {
+    self.thread_config.cpus = cpus.to_vec();
+    self
+  }
+
+  pub fn set_thread_priority(&mut self, priority: ThreadPriority) -> &mut Self {
+    self.thread_config.priority = Some(priority);
+    self
+  }
+
+  pub fn thread_config(&self) -> &ThreadConfig {
+    &self.thread_config
+  }
+
+  // class of calls queued on the `PluginActor` of the plugin without own
+  // priority, see `ExecuteMsg::with_priority`
+  pub fn set_priority(&mut self, priority: Priority) -> &mut Self {
+    self.priority = priority;
+    self
+  }
+
+  pub fn set_scheduling(&mut self, scheduling: SchedulingConfig) -> &mut Self {
+    self.scheduling = scheduling;
+    self
+  }
+
+  // passes the key as `<trace id>|<key>` to the guest, for guests which can not
+  // import the `get_trace_id` host function
+  pub fn set_trace_id_key_prefix(&mut self, enabled: bool) -> &mut Self {
+    self.trace_id_key_prefix = enabled;
+    self
+  }
+
+  // key of the call as the guest gets it, see `set_trace_id_key_prefix`
+  pub(crate) fn guest_key(&self, ctx: &CallContext) -> String {
+    match self.trace_id_key_prefix {
+      true => format!("{}|{}", ctx.trace_id, ctx.key),
+      false => ctx.key.clone(),
+    }
+  }
+
+  // how guest stdout/stderr lines are mapped to host log levels
+  pub fn set_guest_log(&mut self, config: GuestLogConfig) -> &mut Self {
+    self.guest_log = config;
+    self
+  }
+
   pub fn set_start_function_name(&mut self, name: &String) -> &mut Self {
     self.start_function_name = name.clone();
     self
   }
 
+  // export called per request by `IoPlugin`, without it `_start` is called per request
+  pub fn set_loop_function_name(&mut self, name: &String) -> &mut Self {
+    self.loop_function_name = Some(name.clone());
+    self
+  }
+
   pub fn set_init_function_name(&mut self, name: &String) -> &mut Self {
     self.init_function_name = name.clone();
     self
@@ -80,6 +394,74 @@ impl PluginOptions {
     self
   }
 
+  // AssemblyScript runtime allocation function used by `StringAbi::Utf16String`
+  pub fn set_new_function_name(&mut self, name: &String) -> &mut Self {
+    self.new_function_name = name.clone();
+    self
+  }
+
+  // objects allocated with `__new` are pinned while the guest call runs,
+  // so an incremental gc can not free them - ignored if the guest has no `__pin`
+  pub fn set_pin_function_names(&mut self, pin: &String, unpin: &String) -> &mut Self {
+    self.pin_function_name = pin.clone();
+    self.unpin_function_name = unpin.clone();
+    self
+  }
+
+  pub fn set_collect_function_name(&mut self, name: &String) -> &mut Self {
+    self.collect_function_name = Some(name.clone());
+    self
+  }
+
+  // for guests which manage their memory on their own
+  pub fn disable_garbage_collector(&mut self) -> &mut Self {
+    self.collect_function_name = None;
+    self
+  }
+
+  // only used with `StringAbi::Utf8LengthPrefixed`
+  pub fn set_free_function_name(&mut self, name: &String) -> &mut Self {
+    self.free_function_name = name.clone();
+    self
+  }
+
+  // switches export names and string abi to the ones of a guest toolchain
+  pub fn apply_abi_profile(&mut self, profile: &AbiProfile) -> &mut Self {
+    self.start_function_name = profile.start_function_name.clone();
+    self.allocate_utf8array_function_name = profile.allocate_function_name.clone();
+    if let Some(name) = &profile.free_function_name {
+      self.free_function_name = name.clone();
+    }
+    self.collect_function_name = profile.collect_function_name.clone();
+    self.string_abi = profile.string_abi;
+    self
+  }
+
+  pub fn set_string_abi(&mut self, abi: StringAbi) -> &mut Self {
+    self.string_abi = abi;
+    self
+  }
+
+  // result convention of given guest function, `ResultAbi::LengthHeader` if not set
+  pub fn set_result_abi(&mut self, function_name: &String, abi: ResultAbi) -> &mut Self {
+    self.result_abis.insert(function_name.clone(), abi);
+    self
+  }
+
+  pub fn result_abi(&self, function_name: &String) -> ResultAbi {
+    self
+      .result_abis
+      .get(function_name)
+      .copied()
+      .unwrap_or_default()
+  }
+
+  // class id passed to `__new` for strings, `idof<String>()` in the guest
+  pub fn set_string_class_id(&mut self, id: u32) -> &mut Self {
+    self.string_class_id = id;
+    self
+  }
+
   pub fn set_memory_name(&mut self, name: &String) -> &mut Self {
     self.memory_name = name.clone();
     self
@@ -94,17 +476,199 @@ impl PluginOptions {
     self.args.push(arg.clone());
     self
   }
+
+  // replaces the wasi import `name` by a host stub, eg `proc_exit` or `sock_*`
+  pub fn stub_wasi_call(&mut self, name: &String, behavior: StubBehavior) -> &mut Self {
+    self.wasi_stubs.push((name.clone(), behavior));
+    self
+  }
+
+  // network access of the guest, denied by default
+  pub fn set_network_policy(&mut self, policy: NetworkPolicy) -> &mut Self {
+    self.network_policy = policy;
+    self
+  }
+
+  // creates a temporary directory for the plugin which the guest sees as `alias`
+  // eg `/tmp` - it is removed together with the plugin
+  pub fn enable_scratch_dir(&mut self, alias: &String, quota_bytes: Option<u64>) -> &mut Self {
+    self.scratch_dir = Some(ScratchDirConfig {
+      alias: alias.clone(),
+      quota_bytes,
+    });
+    self
+  }
+
+  // mounts the in-memory files read-only into the guest instead of host directories
+  // can not be combined with `enable_scratch_dir`
+  pub fn mount_memory_fs(&mut self, fs: MemoryFs) -> &mut Self {
+    self.memory_fs = Some(fs);
+    self
+  }
+
+  // middlewares wrap each execute call in the order they are added
+  pub fn add_middleware<M: Middleware + 'static>(&mut self, middleware: M) -> &mut Self {
+    self.middlewares.add(Arc::new(middleware));
+    self
+  }
+
+  // maximum size of the guest memory in wasm pages (64KiB), checked after each execute
+  pub fn set_memory_limit_pages(&mut self, pages: u32) -> &mut Self {
+    self.memory_limit_pages = Some(pages);
+    self
+  }
+
+  // key and payload of execute calls are placed in a block of `size_bytes`
+  // reserved once in the guest, see `CallArena`
+  pub fn set_call_arena(&mut self, size_bytes: u32) -> &mut Self {
+    self.call_arena_size = Some(size_bytes);
+    self
+  }
+
+  // wasm features the artifact may have been compiled with, eg
+  // `WasmFeatures::none()` to only load mvp modules for untrusted code
+  pub fn set_wasm_features(&mut self, features: WasmFeatures) -> &mut Self {
+    self.wasm_features = features;
+    self
+  }
+
+  pub fn wasm_features(&self) -> &WasmFeatures {
+    &self.wasm_features
+  }
+
+  // shares the compiled module with other plugins of the cache loading the
+  // same artifact content, must be set before host functions are added as
+  // the options switch to the store of the cache
+  pub fn set_module_cache(&mut self, cache: &Arc<ModuleCache>) -> &mut Self {
+    if !self.custom_exports.is_empty() || !self.dynamic_host_functions.is_empty() {
+      warn!(
+        "WASM:{} module cache set after host functions were added - not used",
+        self.module_name
+      );
+      return self;
+    }
+    self.store = cache.store().clone();
+    self.module_cache = Some(cache.clone());
+    self
+  }
+
+  // limits are checked before the strings are copied into the guest
+  pub fn set_max_key_size(&mut self, bytes: usize) -> &mut Self {
+    self.size_limits.key = Some(bytes);
+    self
+  }
+
+  pub fn set_max_payload_size(&mut self, bytes: usize) -> &mut Self {
+    self.size_limits.payload = Some(bytes);
+    self
+  }
+
+  // checked against the length header of the result before it is read
+  pub fn set_max_result_size(&mut self, bytes: usize) -> &mut Self {
+    self.size_limits.result = Some(bytes);
+    self
+  }
+
+  // takes over function names, envs and limits declared in the manifest
+  // the manifest is available afterwards via `plugin.metadata()`
+  pub fn apply_manifest(&mut self, manifest: &PluginManifest) -> &mut Self {
+    if let Some(name) = &manifest.start_function_name {
+      self.start_function_name = name.clone();
+    }
+    if let Some(name) = &manifest.init_function_name {
+      self.init_function_name = name.clone();
+    }
+    if let Some(name) = &manifest.execute_function_name {
+      self.execute_function_name = name.clone();
+    }
+    if let Some(name) = &manifest.allocate_function_name {
+      self.allocate_utf8array_function_name = name.clone();
+    }
+    if let Some(name) = &manifest.memory_name {
+      self.memory_name = name.clone();
+    }
+    if let Some(pages) = manifest.memory_limit_pages {
+      self.memory_limit_pages = Some(pages);
+    }
+    if let Some(policy) = &manifest.syscall_policy {
+      self.syscall_policy = Some(policy.clone());
+    }
+    self.envs.extend(manifest.envs.iter().cloned());
+    self.metadata = manifest.clone();
+    self
+  }
+
+  // `init` restores the guest state from this file if it was written for the
+  // same artifact and config, otherwise runs the guest `init` and writes it
+  // see `Checkpoint` for what can be restored
+  pub fn set_checkpoint_file(&mut self, file: &Path) -> &mut Self {
+    self.checkpoint_file = Some(file.to_path_buf());
+    self
+  }
+
+  // subscribing on the options also receives the events of `create`
+  pub fn subscribe(&self, kind: EventKind) -> Receiver<GuestEvent> {
+    self.events.subscribe(kind)
+  }
+
+  // limits all execute calls of the plugin
+  pub fn set_rate_limit(&mut self, limit: RateLimit) -> &mut Self {
+    self.rate_limiter = Some(Arc::new(RateLimiter::new(limit)));
+    self
+  }
+
+  // limits execute calls per caller key - see `DefaultPlugin::execute_as`
+  pub fn set_caller_rate_limit(&mut self, limit: RateLimit) -> &mut Self {
+    self.caller_rate_limiter = Some(Arc::new(RateLimiter::new(limit)));
+    self
+  }
 }
 
 #[derive(PartialEq, PartialOrd, Debug, Clone)]
 pub enum PluginError {
+  CompileFailed,
   LoadingError,
+  IncompatibleArtifact(String),
   InitWasiEnvFailed,
   InstanceInitFailed,
   WasiImportObjectFailed,
   RuntimeError,
+  GuestExited(u32),
   FunctionNotFound,
   FunctionInvalidParameter,
+  PluginNotFound,
+  VersionNotFound,
+  VersionActive,
+  InvalidManifest,
+  HostFunctionMissing,
+  MemoryLimitExceeded,
+  ScratchQuotaExceeded,
+  InvalidArchive,
+  PayloadTooLarge { size: usize, limit: usize },
+  InvalidPointer,
+  RateLimited { retry_after: Duration },
+  // rate of `RateLimit::new` not positive or not finite
+  InvalidRateLimit,
+  InvalidJournal,
+  ActorStopped,
+  InvalidCheckpoint,
+  ServerFailed,
+  ConnectorFailed,
+  InvalidFrame,
+  // name of the wasi import the syscall policy rejected
+  PolicyViolation(String),
+  InvalidDeadLetter,
+  ThreadConfigFailed,
+  TenantNotFound,
+  TenantQuotaExceeded,
+  PluginPaused,
+  // running calls did not finish in time, see `PluginManager::pause`
+  DrainTimeout,
+  // raised by the guest with `raise_error` during the call
+  GuestError(GuestError),
+  // queued batch call dropped for an interactive call, see `SchedulingConfig`
+  Preempted,
+  QueueFull,
 }
 
 pub fn helper_get_function<T: WasmTypeList, O: WasmTypeList>(
@@ -140,9 +704,34 @@ pub trait Plugin {
   fn get_environment(&self) -> &WasiEnv;
   fn get_instance(&self) -> &Instance;
 
-  fn get_malloc_fn(&self) -> &NativeFunc<u32, WasmerStringPtr>;
+  // `None` if the guest has no malloc export, eg when using `StringAbi::Utf16String`
+  fn get_malloc_fn(&self) -> Option<&NativeFunc<u32, WasmerStringPtr>>;
+
+  // `canonical_abi_realloc` of component model guests without malloc, see `probe`
+  fn get_realloc_fn(&self) -> Option<&ReallocFn> {
+    None
+  }
+
   fn get_options(&self) -> &PluginOptions;
 
+  // exports resolved on create, see `FunctionTable`
+  fn get_function_table(&self) -> Option<&FunctionTable> {
+    None
+  }
+
+  fn get_call_arena(&self) -> Option<&CallArena> {
+    None
+  }
+
+  // set while a call with report is running, see `CallReport`
+  fn get_report_capture(&self) -> Option<&ReportCapture> {
+    None
+  }
+
+  fn metadata(&self) -> &PluginManifest {
+    &self.get_options().metadata
+  }
+
   fn get_memory(&self) -> &Memory {
     self
       .get_instance()
@@ -151,25 +740,195 @@ pub trait Plugin {
       .unwrap()
   }
 
-  fn get_function<T: WasmTypeList, O: WasmTypeList>(
+  fn get_function<T: WasmTypeList + 'static, O: WasmTypeList + 'static>(
     &self,
     name: &String,
   ) -> Result<NativeFunc<T, O>, PluginError> {
+    if let Some(function) = self
+      .get_function_table()
+      .and_then(|table| table.get::<T, O>(name))
+    {
+      return Ok(function);
+    }
     helper_get_function(self.get_instance(), self.get_options(), name)
   }
 
+  // runs a call into the guest, written to the audit log if one is set
+  // `args` is only evaluated when auditing
+  fn guest_call<R>(
+    &self,
+    name: &String,
+    args: impl FnOnce() -> String,
+    call: impl FnOnce() -> Result<R, RuntimeError>,
+  ) -> Result<R, RuntimeError> {
+    let options = self.get_options();
+    let audit_log = match &options.audit_log {
+      Some(audit_log) => audit_log,
+      None => return call(),
+    };
+    let timestamp_ms = now_ms();
+    let start = Instant::now();
+    let result = call();
+    let ctx = options.host_env.call_context();
+    audit_log.record(&AuditRecord {
+      timestamp_ms,
+      module_name: options.module_name.clone(),
+      call_id: ctx.as_ref().map(|ctx| ctx.call_id),
+      trace_id: ctx.map(|ctx| ctx.trace_id),
+      direction: AuditDirection::HostToGuest,
+      function: name.clone(),
+      args: args(),
+      duration: start.elapsed(),
+      error: result.as_ref().err().map(|error| error.message()),
+    });
+    result
+  }
+
+  // `WASM:<module>:<function>` extended by the trace id while an execute call is running
+  fn log_prefix(&self, name: &String) -> String {
+    match self.get_options().host_env.call_context() {
+      Some(ctx) => format!(
+        "WASM:{}:{} [{}]",
+        self.get_options().module_name,
+        name,
+        ctx.trace_id
+      ),
+      None => format!("WASM:{}:{}", self.get_options().module_name, name),
+    }
+  }
+
+  // exit code of the last `proc_exit` call of the guest
+  fn last_exit_code(&self) -> Option<u32> {
+    None
+  }
+
+  fn record_exit_code(&self, _code: u32) {}
+
   fn log_and_transform_error(&self, error: RuntimeError, name: &String) -> PluginError {
-    error!(
-      "WASM:{}:{} {:?}",
-      self.get_options().module_name,
-      name,
-      error
-    );
-    match self.read_from_stderr() {
-      Some(out) => error!("{}", out),
-      None => (),
+    // `proc_exit` unwinds the guest call with a trap, the host and other plugins keep running
+    let plugin_error = match error.downcast::<WasiError>() {
+      Ok(WasiError::Exit(code)) => {
+        warn!("{} guest exited with code {}", self.log_prefix(name), code);
+        self.log_guest_output(name);
+        self.record_exit_code(code);
+        self.emit_lifecycle(LifecycleState::Exited(code));
+        PluginError::GuestExited(code)
+      }
+      Ok(other) => {
+        error!("{} {}", self.log_prefix(name), other);
+        self.log_guest_output(name);
+        PluginError::RuntimeError
+      }
+      Err(error) => match error.downcast::<PolicyViolation>() {
+        Ok(violation) => {
+          error!("{} {}", self.log_prefix(name), violation);
+          self.log_guest_output(name);
+          PluginError::PolicyViolation(violation.function)
+        }
+        Err(error) => {
+          error!("{} {:?}", self.log_prefix(name), error);
+          self.log_guest_output(name);
+          PluginError::RuntimeError
+        }
+      },
     };
-    PluginError::RuntimeError
+
+    let options = self.get_options();
+    if options.events.has_subscribers(EventKind::Error) {
+      options.events.emit(GuestEvent::Error {
+        module_name: options.module_name.clone(),
+        trace_id: self.current_trace_id(),
+        function: name.clone(),
+        error: plugin_error.clone(),
+      });
+    }
+    plugin_error
+  }
+
+  // drains guest stdout and stderr into the host log, see `GuestLogConfig`
+  // and hands the lines to `EventKind::Log` subscribers
+  fn log_guest_output(&self, name: &String) {
+    let options = self.get_options();
+    let prefix = self.log_prefix(name);
+    let streams = [
+      (
+        GuestStream::Stdout,
+        self.read_from_stdout(),
+        options.guest_log.stdout_level,
+      ),
+      (
+        GuestStream::Stderr,
+        self.read_from_stderr(),
+        options.guest_log.stderr_level,
+      ),
+    ];
+    for (stream, out, level) in streams {
+      let out = match out {
+        Some(out) => out,
+        None => continue,
+      };
+      if let Some(capture) = self.get_report_capture() {
+        capture.record_output(stream, &out);
+      }
+      if let (GuestStream::Stderr, Some(dead_letters)) = (stream, &options.dead_letters) {
+        if let Some(ctx) = options.host_env.call_context() {
+          dead_letters.capture_stderr(ctx.call_id, &out);
+        }
+      }
+      let lines = options
+        .guest_log
+        .emit(&options.module_name, &prefix, &out, level);
+      if !options.events.has_subscribers(EventKind::Log) {
+        continue;
+      }
+      for (level, message) in lines {
+        options.events.emit(GuestEvent::Log {
+          module_name: options.module_name.clone(),
+          trace_id: self.current_trace_id(),
+          stream,
+          level,
+          message,
+        });
+      }
+    }
+  }
+
+  fn subscribe(&self, kind: EventKind) -> Receiver<GuestEvent> {
+    self.get_options().events.subscribe(kind)
+  }
+
+  fn emit_lifecycle(&self, state: LifecycleState) {
+    let options = self.get_options();
+    options.events.emit(GuestEvent::Lifecycle {
+      module_name: options.module_name.clone(),
+      state,
+    });
+  }
+
+  fn current_trace_id(&self) -> Option<String> {
+    self
+      .get_options()
+      .host_env
+      .call_context()
+      .map(|ctx| ctx.trace_id)
+  }
+
+  fn check_memory_limit(&self) -> Result<(), PluginError> {
+    let limit = match self.get_options().memory_limit_pages {
+      Some(limit) => limit,
+      None => return Ok(()),
+    };
+    let pages = self.get_memory().size().0;
+    if pages > limit {
+      error!(
+        "WASM:{} memory limit exceeded - {} of {} pages used",
+        self.get_options().module_name,
+        pages,
+        limit
+      );
+      return Err(PluginError::MemoryLimitExceeded);
+    }
+    Ok(())
   }
 
   fn write_to_stdin(&self, payload: &String) {
@@ -212,86 +971,377 @@ pub trait Plugin {
     }
   }
 
-  fn get_string(&self, ptr: WasmerStringPtr) -> String {
+  // AssemblyScript stores the byte length of an ArrayBuffer in front of its data
+  // the pointer and the length header are validated against the guest memory,
+  // so a buggy or malicious guest can not make the host read out of bounds
+  fn get_string_length(&self, ptr: WasmerStringPtr) -> Result<u32, PluginError> {
     let memory = self.get_memory();
-    let length = memory
-      .view::<u32>()
-      .get(ptr.offset() as usize / (32 / 8) - 1)
-      .unwrap()
-      .get();
+    let view = memory.view::<u32>();
+    let read_header = |index: usize| view.get(index).map(|cell| cell.get());
+    match string_length(ptr.offset(), read_header, memory.data_size()) {
+      Ok(length) => Ok(length),
+      Err(reason) => Err(self.invalid_pointer(ptr, reason)),
+    }
+  }
 
-    let buf = ptr.deref(memory, 0, length).unwrap();
+  fn get_string(&self, ptr: WasmerStringPtr) -> Result<String, PluginError> {
+    let length = self.get_string_length(ptr)?;
+    self.get_string_with_length(ptr, length)
+  }
+
+  // for results without length header, see `ResultAbi`
+  fn get_string_with_length(
+    &self,
+    ptr: WasmerStringPtr,
+    length: u32,
+  ) -> Result<String, PluginError> {
+    let memory = self.get_memory();
+    let buf = match ptr.deref(memory, 0, length) {
+      Some(buf) => buf,
+      None => return Err(self.invalid_pointer(ptr, "string outside of memory")),
+    };
     let input: Vec<u8> = buf.iter().map(|b| b.get()).collect();
-    return String::from(String::from_utf8_lossy(&input));
+    Ok(self.get_options().string_abi.decode(&input))
+  }
+
+  fn invalid_pointer(&self, ptr: WasmerStringPtr, reason: &str) -> PluginError {
+    error!(
+      "WASM:{} invalid pointer {}: {}",
+      self.get_options().module_name,
+      ptr.offset(),
+      reason
+    );
+    PluginError::InvalidPointer
   }
 
-  fn allocate_string(&self, input: &String) -> WasmerStringPtr {
-    let length = input.len();
-    let ptr = match self.get_malloc_fn().call(u32::try_from(length).unwrap()) {
+  fn allocate_string(&self, input: &String) -> Result<WasmerStringPtr, PluginError> {
+    let options = self.get_options();
+    let new_str = options.string_abi.encode(input);
+    let length = match u32::try_from(new_str.len()) {
+      Ok(length) => length,
+      Err(_) => {
+        return Err(PluginError::PayloadTooLarge {
+          size: new_str.len(),
+          limit: u32::MAX as usize,
+        })
+      }
+    };
+
+    if let Some(capture) = self.get_report_capture() {
+      capture.record_allocation(length);
+    }
+    if let Some(arena) = self.get_call_arena() {
+      if let Some(ptr) = self.allocate_in_arena(arena, length)? {
+        self.write_string(ptr, &new_str)?;
+        return Ok(ptr);
+      }
+    }
+    let ptr = self.allocate_block(length)?;
+    self.write_string(ptr, &new_str)?;
+    self.pin(ptr)?;
+    Ok(ptr)
+  }
+
+  fn write_string(&self, ptr: WasmerStringPtr, bytes: &[u8]) -> Result<(), PluginError> {
+    let values = match ptr.deref(self.get_memory(), 0, bytes.len() as u32) {
+      Some(values) => values,
+      None => return Err(self.invalid_pointer(ptr, "allocated string outside of memory")),
+    };
+    for (cell, byte) in values.iter().zip(bytes) {
+      cell.set(*byte);
+    }
+    Ok(())
+  }
+
+  // `length` bytes from the guest allocator, for length prefixed strings the
+  // length header is written and the pointer after it is returned
+  fn allocate_block(&self, length: u32) -> Result<WasmerStringPtr, PluginError> {
+    let options = self.get_options();
+    let (name, allocation) = match (options.string_abi, self.get_malloc_fn()) {
+      (StringAbi::Utf8ArrayBuffer, Some(malloc)) => (
+        &options.allocate_utf8array_function_name,
+        self.guest_call(
+          &options.allocate_utf8array_function_name,
+          || format!("size {}", length),
+          || malloc.call(length),
+        ),
+      ),
+      (StringAbi::Utf8ArrayBuffer, None) => {
+        let name = &options.new_function_name;
+        let new = self.get_function::<(u32, u32), WasmerStringPtr>(name)?;
+        let id = options.array_buffer_class_id;
+        let args = || format!("size {}, class {}", length, id);
+        (name, self.guest_call(name, args, || new.call(length, id)))
+      }
+      (StringAbi::Utf16String, _) => {
+        let name = &options.new_function_name;
+        let new = self.get_function::<(u32, u32), WasmerStringPtr>(name)?;
+        let id = options.string_class_id;
+        let args = || format!("size {}, class {}", length, id);
+        (name, self.guest_call(name, args, || new.call(length, id)))
+      }
+      (StringAbi::Utf8LengthPrefixed, malloc) => {
+        let name = &options.allocate_utf8array_function_name;
+        let size = match length.checked_add(4) {
+          Some(size) => size,
+          None => {
+            return Err(PluginError::PayloadTooLarge {
+              size: length as usize,
+              limit: u32::MAX as usize - 4,
+            })
+          }
+        };
+        match (malloc, self.get_realloc_fn()) {
+          (Some(malloc), _) => (
+            name,
+            self.guest_call(name, || format!("size {}", size), || malloc.call(size)),
+          ),
+          (None, Some(realloc)) => (
+            name,
+            self.guest_call(
+              name,
+              || format!("size {}", size),
+              || realloc.call(0, 0, 4, size),
+            ),
+          ),
+          (None, None) => return Err(PluginError::FunctionNotFound),
+        }
+      }
+    };
+    let mut ptr = match allocation {
       Ok(result) => result,
-      Err(error) => {
-        error!("{}", error);
-        panic!(
-          "WASM:{} Unable to allocate string",
-          self.get_options().module_name
+      Err(error) => return Err(self.log_and_transform_error(error, name)),
+    };
+
+    let memory = self.get_memory();
+
+    if options.string_abi == StringAbi::Utf8LengthPrefixed {
+      match ptr.deref(memory, 0, 4) {
+        Some(header) => {
+          for (cell, byte) in header.iter().zip(length.to_le_bytes()) {
+            cell.set(byte);
+          }
+        }
+        None => return Err(self.invalid_pointer(ptr, "allocated string outside of memory")),
+      }
+      ptr = WasmerStringPtr::new(ptr.offset() + 4);
+    }
+
+    Ok(ptr)
+  }
+
+  // the string is placed in the arena of the running execute call, the block
+  // of the arena is allocated and pinned on first use
+  fn allocate_in_arena(
+    &self,
+    arena: &CallArena,
+    length: u32,
+  ) -> Result<Option<WasmerStringPtr>, PluginError> {
+    let options = self.get_options();
+    if !arena.is_active() {
+      return Ok(None);
+    }
+    if !arena.is_reserved() {
+      let block = self.allocate_block(arena.capacity())?;
+      self.pin(block)?;
+      arena.set_region(block.offset());
+      debug!(
+        "WASM:{} call arena of {} bytes reserved at {}",
+        options.module_name,
+        arena.capacity(),
+        block.offset()
+      );
+    }
+    // length prefix or AssemblyScript object header, the runtime reads the
+    // class id at -8 and the byte length at -4
+    let (header, class_id) = match options.string_abi {
+      StringAbi::Utf8LengthPrefixed => (4, None),
+      StringAbi::Utf8ArrayBuffer => (20, Some(options.array_buffer_class_id)),
+      StringAbi::Utf16String => (20, Some(options.string_class_id)),
+    };
+    let ptr = match arena.alloc(header, length) {
+      Some(ptr) => ptr,
+      None => {
+        debug!(
+          "WASM:{} call arena full - string of {} bytes allocated by the guest",
+          options.module_name, length
         );
+        return Ok(None);
       }
     };
+    let mut bytes = vec![0u8; header as usize];
+    let size = length.to_le_bytes();
+    bytes[header as usize - 4..].copy_from_slice(&size);
+    if let Some(class_id) = class_id {
+      bytes[header as usize - 8..header as usize - 4].copy_from_slice(&class_id.to_le_bytes());
+    }
+    self.write_string(WasmerStringPtr::new(ptr - header), &bytes)?;
+    Ok(Some(WasmerStringPtr::new(ptr)))
+  }
 
-    let memory = self.get_memory();
+  // strings in the call arena are released together by its reset
+  fn in_call_arena(&self, ptr: WasmerStringPtr) -> bool {
+    match self.get_call_arena() {
+      Some(arena) => arena.contains(ptr.offset()),
+      None => false,
+    }
+  }
+
+  // true if strings are allocated through the AssemblyScript runtime `__new`
+  fn uses_runtime_allocator(&self) -> bool {
+    match self.get_options().string_abi {
+      StringAbi::Utf8ArrayBuffer => self.get_malloc_fn().is_none(),
+      StringAbi::Utf16String => true,
+      StringAbi::Utf8LengthPrefixed => false,
+    }
+  }
 
-    let new_str = input.as_bytes();
-    let values = ptr.deref(memory, 0, new_str.len() as u32).unwrap();
-    for i in 0..new_str.len() {
-      values[i].set(new_str[i]);
+  fn has_export(&self, name: &String) -> bool {
+    match self.get_function_table() {
+      Some(table) if table.contains(name) => true,
+      _ => self.get_instance().exports.get_function(name).is_ok(),
+    }
+  }
+
+  fn pin(&self, ptr: WasmerStringPtr) -> Result<(), PluginError> {
+    let name = &self.get_options().pin_function_name;
+    if !self.uses_runtime_allocator() || !self.has_export(name) {
+      return Ok(());
+    }
+    let pin = self.get_function::<WasmerStringPtr, WasmerStringPtr>(name)?;
+    match self.guest_call(name, || format!("ptr {}", ptr.offset()), || pin.call(ptr)) {
+      Ok(_) => Ok(()),
+      Err(error) => Err(self.log_and_transform_error(error, name)),
+    }
+  }
+
+  // counterpart of the pin in `allocate_string`, to be called once the guest call returned
+  // length prefixed strings are owned by the host and freed instead
+  fn release_string(&self, ptr: WasmerStringPtr) -> Result<(), PluginError> {
+    if self.in_call_arena(ptr) {
+      return Ok(());
+    }
+    if self.get_options().string_abi == StringAbi::Utf8LengthPrefixed {
+      return self.free_string(ptr);
+    }
+    let name = &self.get_options().unpin_function_name;
+    if !self.uses_runtime_allocator() || !self.has_export(name) {
+      return Ok(());
+    }
+    let unpin = self.get_function::<WasmerStringPtr, ()>(name)?;
+    match self.guest_call(name, || format!("ptr {}", ptr.offset()), || unpin.call(ptr)) {
+      Ok(_) => Ok(()),
+      Err(error) => Err(self.log_and_transform_error(error, name)),
+    }
+  }
+
+  // results of length prefixed guests are handed over to the host
+  // all other abis leave them to the guest gc
+  fn release_result(&self, ptr: WasmerStringPtr) -> Result<(), PluginError> {
+    if self.in_call_arena(ptr) {
+      return Ok(());
+    }
+    match self.get_options().string_abi {
+      StringAbi::Utf8LengthPrefixed => self.free_string(ptr),
+      _ => Ok(()),
     }
+  }
 
-    return ptr;
+  fn free_string(&self, ptr: WasmerStringPtr) -> Result<(), PluginError> {
+    let name = &self.get_options().free_function_name;
+    // eg `canonical_abi_realloc` guests have no free, the blocks stay with the guest
+    if !self.has_export(name) {
+      return Ok(());
+    }
+    let free = self.get_function::<u32, ()>(name)?;
+    let block = ptr.offset().saturating_sub(4);
+    match self.guest_call(name, || format!("ptr {}", block), || free.call(block)) {
+      Ok(_) => Ok(()),
+      Err(error) => Err(self.log_and_transform_error(error, name)),
+    }
   }
 
   fn init(&self, config: &String) -> Result<(), PluginError> {
     let start = self.get_function::<(), ()>(&self.get_options().start_function_name)?;
 
-    match start.call() {
+    match self.guest_call(&self.get_options().start_function_name, String::new, || {
+      start.call()
+    }) {
       Ok(_) => {
-        match self.read_from_stdout() {
-          Some(out) => info!(
-            "WASM:{}:{} {}",
-            self.get_options().module_name,
-            self.get_options().start_function_name,
-            out
-          ),
-          None => (),
-        };
+        self.log_guest_output(&self.get_options().start_function_name);
+        self.emit_lifecycle(LifecycleState::Started);
       }
       Err(error) => {
         return Err(self.log_and_transform_error(error, &self.get_options().start_function_name));
       }
     };
 
-    self.run_init(&config)
+    let checkpoint_file = match &self.get_options().checkpoint_file {
+      Some(file) => file,
+      None => return self.run_init(&config),
+    };
+    if checkpoint_file.exists() && self.restore(checkpoint_file, config).is_ok() {
+      info!(
+        "WASM:{} state restored from checkpoint",
+        self.get_options().module_name
+      );
+      self.emit_lifecycle(LifecycleState::Initialized);
+      return Ok(());
+    }
+    self.run_init(&config)?;
+    self.checkpoint(checkpoint_file, config)
+  }
+
+  // writes memory and mutable globals of the guest, `config` is the init
+  // config the state was built from
+  fn checkpoint(&self, file: &Path, config: &String) -> Result<(), PluginError> {
+    let options = self.get_options();
+    Checkpoint::capture(
+      &options.module_name,
+      &artifact_fingerprint(&options.file)?,
+      config,
+      self.get_instance(),
+      self.get_memory(),
+    )?
+    .write_to_file(file)
+  }
+
+  fn restore(&self, file: &Path, config: &String) -> Result<(), PluginError> {
+    let options = self.get_options();
+    let checkpoint = Checkpoint::read_from_file(file)?;
+    if &checkpoint.config != config {
+      info!(
+        "WASM:{} checkpoint was built from another config",
+        options.module_name
+      );
+      return Err(PluginError::InvalidCheckpoint);
+    }
+    checkpoint.apply(
+      &options.module_name,
+      &artifact_fingerprint(&options.file)?,
+      self.get_instance(),
+      self.get_memory(),
+    )
   }
 
   fn run_init(&self, config: &String) -> Result<(), PluginError> {
-    let config_ptr = self.allocate_string(config);
+    let config_ptr = self.allocate_string(config)?;
 
     let init = self.get_function::<WasmerStringPtr, ()>(&self.get_options().init_function_name)?;
-    match init.call(config_ptr) {
+    let name = &self.get_options().init_function_name;
+    let args = || format!("config {} bytes", config.len());
+    let result = match self.guest_call(name, args, || init.call(config_ptr)) {
       Ok(_) => {
-        match self.read_from_stdout() {
-          Some(out) => info!(
-            "WASM:{}:{} {}",
-            self.get_options().module_name,
-            self.get_options().init_function_name,
-            out
-          ),
-          None => (),
-        };
-        return Ok(());
+        self.log_guest_output(&self.get_options().init_function_name);
+        self.emit_lifecycle(LifecycleState::Initialized);
+        Ok(())
       }
       Err(error) => {
         Err(self.log_and_transform_error(error, &self.get_options().init_function_name))
       }
-    }
+    };
+
+    self.release_string(config_ptr)?;
+    result
   }
 }
@@ -0,0 +1,92 @@
+use std::collections::HashMap;
+use std::fs;
+use std::sync::Mutex;
+
+use log::{debug, error};
+use wasmer::{Module, Store, Universal};
+
+use crate::plugin::artifact::content_hash;
+use crate::plugin::PluginError;
+
+// compiled modules shared between plugins loading artifacts with the same
+// content, eg per tenant copies of one plugin, set with
+// `PluginOptions::set_module_cache`
+//
+// a module can only be instantiated with host functions of its own store, so
+// all plugins of a cache use the store of the cache. every instance still has
+// its own memory, globals and wasi state
+#[derive(Debug)]
+pub struct ModuleCache {
+  store: Store,
+  // by sha-256 of the artifact, the key alone decides which native code a
+  // plugin runs, so it must not collide even for crafted artifacts
+  modules: Mutex<HashMap<String, Module>>,
+}
+
+impl Default for ModuleCache {
+  fn default() -> Self {
+    Self {
+      store: Store::new(&Universal::headless().engine()),
+      modules: Mutex::new(HashMap::new()),
+    }
+  }
+}
+
+impl ModuleCache {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  pub fn store(&self) -> &Store {
+    &self.store
+  }
+
+  // number of distinct modules
+  pub fn len(&self) -> usize {
+    self.modules.lock().unwrap().len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.len() == 0
+  }
+
+  // drops the cached modules, instances created from them keep working
+  pub fn clear(&self) {
+    self.modules.lock().unwrap().clear();
+  }
+
+  // the cached module for the content of the artifact, deserialized on the first load
+  pub(crate) fn load(&self, module_name: &String, file: &String) -> Result<Module, PluginError> {
+    let bytes = match fs::read(file) {
+      Ok(bytes) => bytes,
+      Err(error) => {
+        error!("WASM:{} reading module file failed", module_name);
+        error!("{}", error);
+        return Err(PluginError::LoadingError);
+      }
+    };
+    let key = content_hash(&bytes);
+
+    if let Some(module) = self.modules.lock().unwrap().get(&key) {
+      debug!(
+        "WASM:{} module {} shared from module cache",
+        module_name, key
+      );
+      return Ok(module.clone());
+    }
+    let module = match unsafe { Module::deserialize(&self.store, &bytes) } {
+      Ok(module) => module,
+      Err(error) => {
+        error!("WASM:{} loading module failed", module_name);
+        error!("{}", error);
+        return Err(PluginError::LoadingError);
+      }
+    };
+    debug!("WASM:{} module {} added to module cache", module_name, key);
+    // plugins loaded in parallel may have deserialized the same content, the
+    // first one is kept
+    let mut modules = self.modules.lock().unwrap();
+    let module = modules.entry(key).or_insert(module).clone();
+    Ok(module)
+  }
+}
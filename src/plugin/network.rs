@@ -0,0 +1,29 @@
+use crate::plugin::wasi::StubBehavior;
+
+// wasi errno ENOTCAPABLE
+const ERRNO_NOTCAPABLE: u16 = 76;
+
+// network access granted to a plugin
+//
+// wasmer-wasi 2.1 has no socket support: guests can not open or connect sockets
+// and the `sock_recv`/`sock_send`/`sock_shutdown` imports are unimplemented in the
+// host and would panic. Until a wasmer version with wasix sockets is used, all
+// `sock_*` imports are therefore stubbed to return ENOTCAPABLE and deny-all is
+// the only policy. An allowlist and bandwidth caps need a socket implementation
+// enforcing them and are left out until then.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct NetworkPolicy {}
+
+impl NetworkPolicy {
+  pub fn deny_all() -> Self {
+    Self::default()
+  }
+
+  // stubs needed to enforce the policy on the wasi implementation in use
+  pub fn wasi_stubs(&self) -> Vec<(String, StubBehavior)> {
+    vec![(
+      String::from("sock_*"),
+      StubBehavior::Errno(ERRNO_NOTCAPABLE),
+    )]
+  }
+}
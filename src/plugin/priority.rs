@@ -0,0 +1,258 @@
+use std::collections::VecDeque;
+use std::sync::{Condvar, Mutex};
+
+// scheduling class of a call queued on a `PluginActor`
+// set per plugin with `PluginOptions::set_priority` and per call with
+// `ExecuteMsg::with_priority`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Priority {
+  // latency critical, runs before all queued batch calls
+  #[default]
+  Interactive,
+  // bulk work, eg reprocessing, runs when no interactive call is waiting
+  Batch,
+}
+
+// how the queue of a `PluginActor` orders and bounds calls
+// a running call is never interrupted, only queued batch calls are passed or dropped
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SchedulingConfig {
+  capacity: Option<usize>,
+  interactive_burst: usize,
+}
+
+impl Default for SchedulingConfig {
+  fn default() -> Self {
+    Self {
+      capacity: None,
+      interactive_burst: 8,
+    }
+  }
+}
+
+impl SchedulingConfig {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  // queued calls at most, when full an interactive call preempts the youngest
+  // queued batch call (`PluginError::Preempted`), other calls fail with
+  // `PluginError::QueueFull`
+  pub fn set_capacity(&mut self, capacity: usize) -> &mut Self {
+    self.capacity = Some(capacity);
+    self
+  }
+
+  // starvation protection: after this many interactive calls in a row while
+  // batch calls wait, the oldest batch call runs next. 0 is treated as 1
+  pub fn set_interactive_burst(&mut self, burst: usize) -> &mut Self {
+    self.interactive_burst = burst;
+    self
+  }
+}
+
+pub(crate) enum Pushed<T> {
+  Queued,
+  // queued, the returned batch item was dropped to make room
+  Preempted(T),
+  // not queued, the queue is full
+  Rejected(T),
+  // not queued, the worker is gone
+  Closed(T),
+}
+
+struct QueueState<T> {
+  interactive: VecDeque<T>,
+  batch: VecDeque<T>,
+  // interactive items taken while batch items were waiting
+  streak: usize,
+  closed: bool,
+}
+
+// blocking two class queue between callers and the worker thread of an actor
+pub(crate) struct PriorityQueue<T> {
+  config: SchedulingConfig,
+  state: Mutex<QueueState<T>>,
+  available: Condvar,
+}
+
+impl<T> PriorityQueue<T> {
+  pub(crate) fn new(config: SchedulingConfig) -> Self {
+    Self {
+      config,
+      state: Mutex::new(QueueState {
+        interactive: VecDeque::new(),
+        batch: VecDeque::new(),
+        streak: 0,
+        closed: false,
+      }),
+      available: Condvar::new(),
+    }
+  }
+
+  pub(crate) fn push(&self, item: T, priority: Priority) -> Pushed<T> {
+    let mut state = self.state.lock().unwrap();
+    if state.closed {
+      return Pushed::Closed(item);
+    }
+    let full = matches!(
+      self.config.capacity,
+      Some(capacity) if state.interactive.len() + state.batch.len() >= capacity
+    );
+    let pushed = match (priority, full) {
+      (Priority::Interactive, false) => {
+        state.interactive.push_back(item);
+        Pushed::Queued
+      }
+      (Priority::Batch, false) => {
+        state.batch.push_back(item);
+        Pushed::Queued
+      }
+      (Priority::Interactive, true) => match state.batch.pop_back() {
+        Some(preempted) => {
+          state.interactive.push_back(item);
+          Pushed::Preempted(preempted)
+        }
+        None => return Pushed::Rejected(item),
+      },
+      (Priority::Batch, true) => return Pushed::Rejected(item),
+    };
+    self.available.notify_one();
+    pushed
+  }
+
+  // waits for the next item, `None` once the queue is closed and empty
+  pub(crate) fn pop(&self) -> Option<T> {
+    let mut state = self.state.lock().unwrap();
+    loop {
+      let starved = !state.batch.is_empty()
+        && (state.interactive.is_empty() || state.streak >= self.config.interactive_burst.max(1));
+      if starved {
+        state.streak = 0;
+        return state.batch.pop_front();
+      }
+      if let Some(item) = state.interactive.pop_front() {
+        if state.batch.is_empty() {
+          state.streak = 0;
+        } else {
+          state.streak += 1;
+        }
+        return Some(item);
+      }
+      if state.closed {
+        return None;
+      }
+      state = self.available.wait(state).unwrap();
+    }
+  }
+
+  // queued items are still handed out by `pop`, new ones are rejected
+  pub(crate) fn close(&self) {
+    self.state.lock().unwrap().closed = true;
+    self.available.notify_all();
+  }
+
+  // takes all queued items, eg to answer them when the worker is abandoned
+  pub(crate) fn drain(&self) -> Vec<T> {
+    let mut state = self.state.lock().unwrap();
+    let mut items: Vec<T> = state.interactive.drain(..).collect();
+    items.extend(state.batch.drain(..));
+    items
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn queue(configure: impl Fn(&mut SchedulingConfig)) -> PriorityQueue<&'static str> {
+    let mut config = SchedulingConfig::new();
+    configure(&mut config);
+    PriorityQueue::new(config)
+  }
+
+  fn pop_all(queue: &PriorityQueue<&'static str>) -> Vec<&'static str> {
+    queue.close();
+    let mut items = vec![];
+    while let Some(item) = queue.pop() {
+      items.push(item);
+    }
+    items
+  }
+
+  #[test]
+  fn interactive_runs_before_queued_batch() {
+    let queue = queue(|_| {});
+    queue.push("batch", Priority::Batch);
+    queue.push("interactive", Priority::Interactive);
+    assert_eq!(pop_all(&queue), vec!["interactive", "batch"]);
+  }
+
+  #[test]
+  fn batch_runs_after_interactive_burst() {
+    let queue = queue(|config| {
+      config.set_interactive_burst(2);
+    });
+    queue.push("b1", Priority::Batch);
+    queue.push("b2", Priority::Batch);
+    for item in ["i1", "i2", "i3", "i4", "i5"] {
+      queue.push(item, Priority::Interactive);
+    }
+    assert_eq!(
+      pop_all(&queue),
+      vec!["i1", "i2", "b1", "i3", "i4", "b2", "i5"]
+    );
+  }
+
+  #[test]
+  fn zero_burst_alternates() {
+    let queue = queue(|config| {
+      config.set_interactive_burst(0);
+    });
+    queue.push("b1", Priority::Batch);
+    queue.push("b2", Priority::Batch);
+    queue.push("i1", Priority::Interactive);
+    queue.push("i2", Priority::Interactive);
+    assert_eq!(pop_all(&queue), vec!["i1", "b1", "i2", "b2"]);
+  }
+
+  #[test]
+  fn interactive_preempts_youngest_batch_when_full() {
+    let queue = queue(|config| {
+      config.set_capacity(2);
+    });
+    assert!(matches!(queue.push("b1", Priority::Batch), Pushed::Queued));
+    assert!(matches!(queue.push("b2", Priority::Batch), Pushed::Queued));
+    assert!(matches!(
+      queue.push("b3", Priority::Batch),
+      Pushed::Rejected("b3")
+    ));
+    assert!(matches!(
+      queue.push("i1", Priority::Interactive),
+      Pushed::Preempted("b2")
+    ));
+    assert!(matches!(
+      queue.push("i2", Priority::Interactive),
+      Pushed::Preempted("b1")
+    ));
+    // nothing left to preempt
+    assert!(matches!(
+      queue.push("i3", Priority::Interactive),
+      Pushed::Rejected("i3")
+    ));
+    assert_eq!(pop_all(&queue), vec!["i1", "i2"]);
+  }
+
+  #[test]
+  fn closed_queue_hands_out_queued_items() {
+    let queue = queue(|_| {});
+    queue.push("b1", Priority::Batch);
+    queue.close();
+    assert!(matches!(
+      queue.push("i1", Priority::Interactive),
+      Pushed::Closed("i1")
+    ));
+    assert_eq!(queue.pop(), Some("b1"));
+    assert_eq!(queue.pop(), None);
+  }
+}
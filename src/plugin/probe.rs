@@ -0,0 +1,171 @@
+use std::fmt;
+
+use log::{debug, info};
+use wasmer::Instance;
+
+use crate::plugin::string_abi::StringAbi;
+use crate::plugin::PluginOptions;
+
+// tried in order when the configured execute export is missing
+pub const EXECUTE_CANDIDATES: [&str; 3] = ["transform", "process", "handle"];
+// tried in order when the configured malloc export is missing and `__new` is
+// not usable, they take the size and return the pointer like malloc
+pub const MALLOC_CANDIDATES: [&str; 2] = ["malloc", "allocate"];
+// component model allocator `(old_ptr, old_size, align, new_size) -> ptr`
+pub const REALLOC_CANDIDATES: [&str; 2] = ["canonical_abi_realloc", "cabi_realloc"];
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Allocator {
+  // `malloc(size)` or a compatible export
+  Malloc(String),
+  // AssemblyScript runtime `__new(size, class id)`
+  RuntimeNew(String),
+  // `canonical_abi_realloc(0, 0, align, size)`, strings are length prefixed
+  CanonicalRealloc(String),
+}
+
+// exports a plugin actually uses, see `DefaultPlugin::describe`
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExportConvention {
+  pub execute_function_name: String,
+  pub allocator: Allocator,
+  pub collect_function_name: Option<String>,
+  // only used by length prefixed strings, `None` if the guest keeps the blocks
+  pub free_function_name: Option<String>,
+  pub string_abi: StringAbi,
+  // what differs from the configuration, eg `execute: transform missing, using handle`
+  pub fallbacks: Vec<String>,
+}
+
+impl fmt::Display for ExportConvention {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "execute: {}, allocator: ", self.execute_function_name)?;
+    match &self.allocator {
+      Allocator::Malloc(name) => write!(f, "{} (malloc)", name)?,
+      Allocator::RuntimeNew(name) => write!(f, "{} (AssemblyScript runtime)", name)?,
+      Allocator::CanonicalRealloc(name) => write!(f, "{} (component model)", name)?,
+    }
+    match &self.collect_function_name {
+      Some(name) => write!(f, ", gc: {}", name)?,
+      None => write!(f, ", gc: none")?,
+    }
+    write!(f, ", strings: {:?}", self.string_abi)?;
+    if self.string_abi == StringAbi::Utf8LengthPrefixed {
+      match &self.free_function_name {
+        Some(name) => write!(f, ", free: {}", name)?,
+        None => write!(f, ", free: none")?,
+      }
+    }
+    for fallback in self.fallbacks.iter() {
+      write!(f, "\n  {}", fallback)?;
+    }
+    Ok(())
+  }
+}
+
+fn has_function(instance: &Instance, name: &str) -> bool {
+  instance.exports.get_function(name).is_ok()
+}
+
+fn find(instance: &Instance, configured: &String, candidates: &[&str]) -> Option<String> {
+  if has_function(instance, configured) {
+    return Some(configured.clone());
+  }
+  candidates
+    .iter()
+    .find(|name| has_function(instance, name))
+    .map(|name| String::from(*name))
+}
+
+// resolves the exports the configuration names, falls back to common
+// alternatives and updates the options with what was found
+// exports neither configured nor found are left as configured, so the
+// following lookups fail with the usual errors
+pub fn probe_exports(instance: &Instance, options: &mut PluginOptions) -> ExportConvention {
+  let mut fallbacks = vec![];
+
+  if let Some(name) = find(
+    instance,
+    &options.execute_function_name,
+    &EXECUTE_CANDIDATES,
+  ) {
+    if name != options.execute_function_name {
+      fallbacks.push(format!(
+        "execute: {} missing, using {}",
+        options.execute_function_name, name
+      ));
+      options.execute_function_name = name;
+    }
+  }
+
+  // order: configured malloc, `__new`, malloc like exports, component model realloc
+  let configured_malloc = options.allocate_utf8array_function_name.clone();
+  let runtime_new = has_function(instance, &options.new_function_name);
+  let allocator = if options.string_abi == StringAbi::Utf16String {
+    Allocator::RuntimeNew(options.new_function_name.clone())
+  } else if has_function(instance, &configured_malloc) {
+    Allocator::Malloc(configured_malloc.clone())
+  } else if options.string_abi == StringAbi::Utf8ArrayBuffer && runtime_new {
+    fallbacks.push(format!(
+      "allocator: {} missing, using {}",
+      configured_malloc, options.new_function_name
+    ));
+    Allocator::RuntimeNew(options.new_function_name.clone())
+  } else {
+    let found = match find(instance, &configured_malloc, &MALLOC_CANDIDATES) {
+      Some(name) => Some((Allocator::Malloc(name.clone()), name)),
+      None => REALLOC_CANDIDATES
+        .iter()
+        .find(|name| has_function(instance, name))
+        .map(|name| {
+          let name = String::from(*name);
+          (Allocator::CanonicalRealloc(name.clone()), name)
+        }),
+    };
+    match found {
+      Some((allocator, name)) => {
+        fallbacks.push(format!(
+          "allocator: {} missing, using {}",
+          configured_malloc, name
+        ));
+        options.allocate_utf8array_function_name = name;
+        // only the AssemblyScript allocators write the buffer header themselves
+        if options.string_abi != StringAbi::Utf8LengthPrefixed {
+          fallbacks.push(String::from(
+            "strings: allocator without runtime header, using Utf8LengthPrefixed",
+          ));
+          options.string_abi = StringAbi::Utf8LengthPrefixed;
+        }
+        allocator
+      }
+      None => Allocator::Malloc(configured_malloc),
+    }
+  };
+
+  if let Some(name) = &options.collect_function_name {
+    if !has_function(instance, name) {
+      fallbacks.push(format!("gc: {} missing, not collecting", name));
+      options.collect_function_name = None;
+    }
+  }
+
+  let free_function_name = if has_function(instance, &options.free_function_name) {
+    Some(options.free_function_name.clone())
+  } else {
+    None
+  };
+
+  let convention = ExportConvention {
+    execute_function_name: options.execute_function_name.clone(),
+    allocator,
+    collect_function_name: options.collect_function_name.clone(),
+    free_function_name,
+    string_abi: options.string_abi,
+    fallbacks,
+  };
+  for fallback in convention.fallbacks.iter() {
+    info!("WASM:{} {}", options.module_name, fallback);
+  }
+  debug!("WASM:{} exports: {}", options.module_name, convention);
+  convention
+}
@@ -0,0 +1,50 @@
+use crate::plugin::string_abi::StringAbi;
+
+// export names and string abi of the toolchain a guest was built with
+// applied with `PluginOptions::apply_abi_profile`
+#[derive(Debug, Clone, PartialEq)]
+pub struct AbiProfile {
+  pub start_function_name: String,
+  pub allocate_function_name: String,
+  pub free_function_name: Option<String>,
+  pub collect_function_name: Option<String>,
+  pub string_abi: StringAbi,
+}
+
+impl AbiProfile {
+  // defaults of `PluginOptions` - build with `--exportRuntime --explicitStart`
+  pub fn assemblyscript() -> Self {
+    Self {
+      start_function_name: String::from("_start"),
+      allocate_function_name: String::from("malloc"),
+      free_function_name: None,
+      collect_function_name: Some(String::from("__collect")),
+      string_abi: StringAbi::Utf8ArrayBuffer,
+    }
+  }
+
+  // TinyGo built as wasi reactor (`-target=wasi -buildmode=c-shared`)
+  // `_initialize` runs the package initializers without calling `main`,
+  // strings are allocated with the exported libc `malloc` and released with `free`,
+  // there is no gc export as the go gc runs on its own
+  pub fn tinygo() -> Self {
+    Self {
+      start_function_name: String::from("_initialize"),
+      allocate_function_name: String::from("malloc"),
+      free_function_name: Some(String::from("free")),
+      collect_function_name: None,
+      string_abi: StringAbi::Utf8LengthPrefixed,
+    }
+  }
+
+  // rust guests built as `cdylib` for `wasm32-wasi` with the `assemblytest-guest` crate
+  pub fn rust() -> Self {
+    Self {
+      start_function_name: String::from("_initialize"),
+      allocate_function_name: String::from("plugin_alloc"),
+      free_function_name: Some(String::from("plugin_free")),
+      collect_function_name: None,
+      string_abi: StringAbi::Utf8LengthPrefixed,
+    }
+  }
+}
@@ -0,0 +1,146 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use log::error;
+
+use crate::plugin::PluginError;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RateLimit {
+  pub calls_per_second: f64,
+  pub burst: u32,
+}
+
+impl RateLimit {
+  // `PluginError::InvalidRateLimit` unless the rate is positive and finite
+  pub fn new(calls_per_second: f64, burst: u32) -> Result<Self, PluginError> {
+    if !calls_per_second.is_finite() || calls_per_second <= 0.0 {
+      error!(
+        "invalid rate limit of {} calls per second",
+        calls_per_second
+      );
+      return Err(PluginError::InvalidRateLimit);
+    }
+    Ok(Self {
+      calls_per_second,
+      burst,
+    })
+  }
+}
+
+// callers kept by a limiter if not set otherwise, see `RateLimiter::with_max_callers`
+pub const DEFAULT_MAX_CALLERS: usize = 10_000;
+// full buckets are dropped at most this often while the limiter is in use
+const SWEEP_INTERVAL: Duration = Duration::from_secs(60);
+
+#[derive(Debug)]
+struct TokenBucket {
+  tokens: f64,
+  last_refill: Instant,
+}
+
+impl TokenBucket {
+  // a refilled bucket is the same as a new one, so it can be dropped
+  fn is_full(&self, now: Instant, limit: &RateLimit, burst: f64) -> bool {
+    let elapsed = now.duration_since(self.last_refill).as_secs_f64();
+    self.tokens + elapsed * limit.calls_per_second >= burst
+  }
+}
+
+#[derive(Debug)]
+struct Buckets {
+  by_caller: HashMap<String, TokenBucket>,
+  last_sweep: Instant,
+}
+
+// token bucket rate limiter with one bucket per caller key
+// buckets of idle callers are dropped once they are refilled, at most
+// `max_callers` buckets are kept - over it the least recently used one is
+// dropped and its caller starts with a full bucket again
+#[derive(Debug)]
+pub struct RateLimiter {
+  limit: RateLimit,
+  max_callers: usize,
+  buckets: Mutex<Buckets>,
+}
+
+impl RateLimiter {
+  pub fn new(limit: RateLimit) -> Self {
+    Self::with_max_callers(limit, DEFAULT_MAX_CALLERS)
+  }
+
+  pub fn with_max_callers(limit: RateLimit, max_callers: usize) -> Self {
+    Self {
+      limit,
+      max_callers: max_callers.max(1),
+      buckets: Mutex::new(Buckets {
+        by_caller: HashMap::new(),
+        last_sweep: Instant::now(),
+      }),
+    }
+  }
+
+  pub fn limit(&self) -> RateLimit {
+    self.limit
+  }
+
+  // number of callers with a bucket
+  pub fn callers(&self) -> usize {
+    self.buckets.lock().unwrap().by_caller.len()
+  }
+
+  // takes one token from the bucket of given caller
+  // returns `PluginError::RateLimited` with the time until the next token is available
+  pub fn acquire(&self, caller: &String) -> Result<(), PluginError> {
+    let burst = f64::from(self.limit.burst.max(1));
+    let now = Instant::now();
+
+    let mut buckets = self.buckets.lock().unwrap();
+    let is_new = !buckets.by_caller.contains_key(caller);
+    if now.duration_since(buckets.last_sweep) >= SWEEP_INTERVAL
+      || (is_new && buckets.by_caller.len() >= self.max_callers)
+    {
+      self.sweep(&mut buckets, now, burst);
+    }
+    if is_new && buckets.by_caller.len() >= self.max_callers {
+      let oldest = buckets
+        .by_caller
+        .iter()
+        .min_by_key(|(_, bucket)| bucket.last_refill)
+        .map(|(caller, _)| caller.clone());
+      if let Some(oldest) = oldest {
+        buckets.by_caller.remove(&oldest);
+      }
+    }
+
+    let bucket = buckets
+      .by_caller
+      .entry(caller.clone())
+      .or_insert(TokenBucket {
+        tokens: burst,
+        last_refill: now,
+      });
+
+    let elapsed = now.duration_since(bucket.last_refill).as_secs_f64();
+    bucket.tokens = (bucket.tokens + elapsed * self.limit.calls_per_second).min(burst);
+    bucket.last_refill = now;
+
+    if bucket.tokens >= 1.0 {
+      bucket.tokens -= 1.0;
+      return Ok(());
+    }
+
+    // tiny rates wait longer than a `Duration` can hold
+    let wait = (1.0 - bucket.tokens) / self.limit.calls_per_second;
+    let retry_after = Duration::try_from_secs_f64(wait).unwrap_or(Duration::MAX);
+    Err(PluginError::RateLimited { retry_after })
+  }
+
+  fn sweep(&self, buckets: &mut Buckets, now: Instant, burst: f64) {
+    buckets
+      .by_caller
+      .retain(|_, bucket| !bucket.is_full(now, &self.limit, burst));
+    buckets.last_sweep = now;
+  }
+}
@@ -0,0 +1,471 @@
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, ErrorKind, Read, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use log::error;
+use wasmer::{Function, FunctionType, RuntimeError, Val};
+
+use crate::plugin::audit::{now_ms, summarize_values, AuditDirection, AuditRecord};
+use crate::plugin::default::DefaultPlugin;
+use crate::plugin::middleware::{CallContext, Middleware, Next};
+use crate::plugin::{Plugin, PluginError, PluginOptions};
+
+const JOURNAL_MAGIC: &[u8; 4] = b"ASJ1";
+
+pub type DynamicHostFn = dyn Fn(&[Val]) -> Result<Vec<Val>, RuntimeError> + Send + Sync;
+
+// host function registered with `PluginOptions::add_dynamic_host_function`
+// unlike native host functions their calls can be recorded and replayed
+#[derive(Clone)]
+pub struct DynamicHostFunction {
+  pub name: String,
+  pub ty: FunctionType,
+  pub func: Arc<DynamicHostFn>,
+}
+
+impl fmt::Debug for DynamicHostFunction {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.debug_struct("DynamicHostFunction")
+      .field("name", &self.name)
+      .field("ty", &self.ty)
+      .finish()
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum HostValue {
+  I32(i32),
+  I64(i64),
+  F32(f32),
+  F64(f64),
+}
+
+impl HostValue {
+  // reference and vector types can not be journaled
+  pub fn from_val(val: &Val) -> Option<Self> {
+    match val {
+      Val::I32(v) => Some(HostValue::I32(*v)),
+      Val::I64(v) => Some(HostValue::I64(*v)),
+      Val::F32(v) => Some(HostValue::F32(*v)),
+      Val::F64(v) => Some(HostValue::F64(*v)),
+      _ => None,
+    }
+  }
+
+  pub fn to_val(&self) -> Val {
+    match self {
+      HostValue::I32(v) => Val::I32(*v),
+      HostValue::I64(v) => Val::I64(*v),
+      HostValue::F32(v) => Val::F32(*v),
+      HostValue::F64(v) => Val::F64(*v),
+    }
+  }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct HostCall {
+  pub function: String,
+  pub args: Vec<HostValue>,
+  pub results: Vec<HostValue>,
+}
+
+// result of a call, errors are kept as their debug representation
+pub type Outcome = Result<String, String>;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct JournalEntry {
+  pub call_id: u64,
+  pub trace_id: String,
+  pub caller: Option<String>,
+  pub key: String,
+  pub payload: String,
+  pub host_calls: Vec<HostCall>,
+  pub outcome: Outcome,
+  pub duration_us: u64,
+}
+
+// records every execute call passing through it into a binary journal
+// registered with `PluginOptions::set_recorder`, which also adds it as middleware
+// host calls are only captured for dynamic host functions
+pub struct Recorder {
+  writer: Mutex<Box<dyn Write + Send>>,
+  pending: Mutex<HashMap<u64, Vec<HostCall>>>,
+}
+
+impl fmt::Debug for Recorder {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.debug_struct("Recorder").finish()
+  }
+}
+
+impl Recorder {
+  pub fn new(mut writer: Box<dyn Write + Send>) -> std::io::Result<Self> {
+    writer.write_all(JOURNAL_MAGIC)?;
+    Ok(Self {
+      writer: Mutex::new(writer),
+      pending: Mutex::new(HashMap::new()),
+    })
+  }
+
+  pub fn to_file(path: &Path) -> std::io::Result<Self> {
+    Self::new(Box::new(BufWriter::new(File::create(path)?)))
+  }
+
+  pub fn flush(&self) -> std::io::Result<()> {
+    self.writer.lock().unwrap().flush()
+  }
+
+  fn record_host_call(&self, call_id: u64, call: HostCall) {
+    self
+      .pending
+      .lock()
+      .unwrap()
+      .entry(call_id)
+      .or_default()
+      .push(call);
+  }
+
+  fn write_entry(&self, entry: &JournalEntry) {
+    let mut body = vec![];
+    encode_entry(&mut body, entry);
+    let mut writer = self.writer.lock().unwrap();
+    let written = writer
+      .write_all(&(body.len() as u32).to_le_bytes())
+      .and_then(|_| writer.write_all(&body));
+    if let Err(error) = written {
+      error!(
+        "WASM:recorder writing journal entry {} failed",
+        entry.call_id
+      );
+      error!("{}", error);
+    }
+  }
+}
+
+impl Middleware for Recorder {
+  fn handle(&self, ctx: &CallContext, next: Next) -> Result<String, PluginError> {
+    let start = Instant::now();
+    let result = next.run(ctx);
+    let host_calls = self
+      .pending
+      .lock()
+      .unwrap()
+      .remove(&ctx.call_id)
+      .unwrap_or_default();
+    self.write_entry(&JournalEntry {
+      call_id: ctx.call_id,
+      trace_id: ctx.trace_id.clone(),
+      caller: ctx.caller.clone(),
+      key: ctx.key.clone(),
+      payload: ctx.payload.clone(),
+      host_calls,
+      outcome: to_outcome(&result),
+      duration_us: start.elapsed().as_micros() as u64,
+    });
+    result
+  }
+}
+
+pub(crate) fn to_outcome(result: &Result<String, PluginError>) -> Outcome {
+  match result {
+    Ok(value) => Ok(value.clone()),
+    Err(error) => Err(format!("{:?}", error)),
+  }
+}
+
+// host call results fed to dynamic host functions while a journal entry is replayed
+#[derive(Debug, Clone, Default)]
+pub struct HostTape {
+  calls: Arc<Mutex<Option<VecDeque<HostCall>>>>,
+}
+
+impl HostTape {
+  fn load(&self, calls: &[HostCall]) {
+    *self.calls.lock().unwrap() = Some(calls.iter().cloned().collect());
+  }
+
+  fn eject(&self) {
+    *self.calls.lock().unwrap() = None;
+  }
+
+  // `None` while not replaying
+  fn next(&self, function: &String) -> Option<Result<HostCall, RuntimeError>> {
+    let mut calls = self.calls.lock().unwrap();
+    let calls = calls.as_mut()?;
+    match calls.pop_front() {
+      Some(call) if &call.function == function => Some(Ok(call)),
+      Some(call) => Some(Err(RuntimeError::new(format!(
+        "replay diverged: expected host call {} but guest called {}",
+        call.function, function
+      )))),
+      None => Some(Err(RuntimeError::new(format!(
+        "replay diverged: unexpected host call {}",
+        function
+      )))),
+    }
+  }
+}
+
+// builds the import of a dynamic host function, recording its calls if a
+// recorder is set, answering from the tape while replaying and auditing the
+// calls if an audit log is set
+pub(crate) fn wrap_host_function(
+  options: &PluginOptions,
+  function: &DynamicHostFunction,
+) -> Function {
+  let name = function.name.clone();
+  let func = function.func.clone();
+  let module_name = options.module_name.clone();
+  let host_env = options.host_env.clone();
+  let recorder = options.recorder.clone();
+  let audit_log = options.audit_log.clone();
+  let tape = options.host_tape.clone();
+  Function::new(&options.store, &function.ty, move |args| {
+    if let Some(replayed) = tape.next(&name) {
+      return replayed.map(|call| call.results.iter().map(|v| v.to_val()).collect());
+    }
+    let timestamp_ms = now_ms();
+    let start = Instant::now();
+    let results = func(args);
+    if let Some(audit_log) = &audit_log {
+      let ctx = host_env.call_context();
+      audit_log.record(&AuditRecord {
+        timestamp_ms,
+        module_name: module_name.clone(),
+        call_id: ctx.as_ref().map(|ctx| ctx.call_id),
+        trace_id: ctx.map(|ctx| ctx.trace_id),
+        direction: AuditDirection::GuestToHost,
+        function: name.clone(),
+        args: summarize_values(args),
+        duration: start.elapsed(),
+        error: results.as_ref().err().map(|error| error.message()),
+      });
+    }
+    let results = results?;
+    if let (Some(recorder), Some(ctx)) = (&recorder, host_env.call_context()) {
+      recorder.record_host_call(
+        ctx.call_id,
+        HostCall {
+          function: name.clone(),
+          args: args.iter().filter_map(HostValue::from_val).collect(),
+          results: results.iter().filter_map(HostValue::from_val).collect(),
+        },
+      );
+    }
+    Ok(results)
+  })
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReplayResult {
+  pub call_id: u64,
+  pub expected: Outcome,
+  pub actual: Outcome,
+}
+
+impl ReplayResult {
+  pub fn matches(&self) -> bool {
+    self.expected == self.actual
+  }
+}
+
+// executes the journaled calls again, host functions answer with the journaled results
+pub fn replay(plugin: &DefaultPlugin, journal: &[JournalEntry]) -> Vec<ReplayResult> {
+  let tape = &plugin.get_options().host_tape;
+  journal
+    .iter()
+    .map(|entry| {
+      tape.load(&entry.host_calls);
+      let result = plugin.execute_traced(&entry.trace_id, &entry.key, &entry.payload);
+      tape.eject();
+      ReplayResult {
+        call_id: entry.call_id,
+        expected: entry.outcome.clone(),
+        actual: to_outcome(&result),
+      }
+    })
+    .collect()
+}
+
+pub fn read_journal(path: &Path) -> Result<Vec<JournalEntry>, PluginError> {
+  match File::open(path) {
+    Ok(file) => read_journal_from(&mut BufReader::new(file)),
+    Err(error) => {
+      error!("reading journal {:?} failed", path);
+      error!("{}", error);
+      Err(PluginError::InvalidJournal)
+    }
+  }
+}
+
+pub fn read_journal_from(reader: &mut dyn Read) -> Result<Vec<JournalEntry>, PluginError> {
+  let mut magic = [0u8; 4];
+  if reader.read_exact(&mut magic).is_err() || &magic != JOURNAL_MAGIC {
+    error!("journal has no valid header");
+    return Err(PluginError::InvalidJournal);
+  }
+
+  let mut entries = vec![];
+  loop {
+    let mut length = [0u8; 4];
+    match reader.read_exact(&mut length) {
+      Ok(()) => (),
+      Err(error) if error.kind() == ErrorKind::UnexpectedEof => break,
+      Err(error) => {
+        error!("reading journal failed");
+        error!("{}", error);
+        return Err(PluginError::InvalidJournal);
+      }
+    }
+    let mut body = vec![0u8; u32::from_le_bytes(length) as usize];
+    if reader.read_exact(&mut body).is_err() {
+      error!("journal entry {} truncated", entries.len());
+      return Err(PluginError::InvalidJournal);
+    }
+    match decode_entry(&mut Decoder { bytes: &body }) {
+      Some(entry) => entries.push(entry),
+      None => {
+        error!("journal entry {} invalid", entries.len());
+        return Err(PluginError::InvalidJournal);
+      }
+    }
+  }
+  Ok(entries)
+}
+
+fn encode_entry(out: &mut Vec<u8>, entry: &JournalEntry) {
+  out.extend(entry.call_id.to_le_bytes());
+  encode_string(out, &entry.trace_id);
+  match &entry.caller {
+    Some(caller) => {
+      out.push(1);
+      encode_string(out, caller);
+    }
+    None => out.push(0),
+  }
+  encode_string(out, &entry.key);
+  encode_string(out, &entry.payload);
+  out.extend((entry.host_calls.len() as u32).to_le_bytes());
+  for call in entry.host_calls.iter() {
+    encode_string(out, &call.function);
+    encode_values(out, &call.args);
+    encode_values(out, &call.results);
+  }
+  match &entry.outcome {
+    Ok(value) => {
+      out.push(0);
+      encode_string(out, value);
+    }
+    Err(error) => {
+      out.push(1);
+      encode_string(out, error);
+    }
+  }
+  out.extend(entry.duration_us.to_le_bytes());
+}
+
+pub(crate) fn encode_string(out: &mut Vec<u8>, value: &String) {
+  out.extend((value.len() as u32).to_le_bytes());
+  out.extend(value.as_bytes());
+}
+
+pub(crate) fn encode_values(out: &mut Vec<u8>, values: &[HostValue]) {
+  out.extend((values.len() as u32).to_le_bytes());
+  for value in values {
+    let (tag, bits) = match value {
+      HostValue::I32(v) => (0u8, *v as i64 as u64),
+      HostValue::I64(v) => (1, *v as u64),
+      HostValue::F32(v) => (2, v.to_bits() as u64),
+      HostValue::F64(v) => (3, v.to_bits()),
+    };
+    out.push(tag);
+    out.extend(bits.to_le_bytes());
+  }
+}
+
+pub(crate) struct Decoder<'a> {
+  pub(crate) bytes: &'a [u8],
+}
+
+impl<'a> Decoder<'a> {
+  pub(crate) fn take(&mut self, count: usize) -> Option<&'a [u8]> {
+    if self.bytes.len() < count {
+      return None;
+    }
+    let (head, rest) = self.bytes.split_at(count);
+    self.bytes = rest;
+    Some(head)
+  }
+
+  pub(crate) fn u8(&mut self) -> Option<u8> {
+    self.take(1).map(|bytes| bytes[0])
+  }
+
+  pub(crate) fn u32(&mut self) -> Option<u32> {
+    Some(u32::from_le_bytes(self.take(4)?.try_into().ok()?))
+  }
+
+  pub(crate) fn u64(&mut self) -> Option<u64> {
+    Some(u64::from_le_bytes(self.take(8)?.try_into().ok()?))
+  }
+
+  pub(crate) fn string(&mut self) -> Option<String> {
+    let length = self.u32()? as usize;
+    String::from_utf8(self.take(length)?.to_vec()).ok()
+  }
+
+  pub(crate) fn values(&mut self) -> Option<Vec<HostValue>> {
+    let count = self.u32()?;
+    let mut values = vec![];
+    for _ in 0..count {
+      let tag = self.u8()?;
+      let bits = self.u64()?;
+      values.push(match tag {
+        0 => HostValue::I32(bits as i32),
+        1 => HostValue::I64(bits as i64),
+        2 => HostValue::F32(f32::from_bits(bits as u32)),
+        3 => HostValue::F64(f64::from_bits(bits)),
+        _ => return None,
+      });
+    }
+    Some(values)
+  }
+}
+
+fn decode_entry(decoder: &mut Decoder) -> Option<JournalEntry> {
+  let call_id = decoder.u64()?;
+  let trace_id = decoder.string()?;
+  let caller = match decoder.u8()? {
+    0 => None,
+    _ => Some(decoder.string()?),
+  };
+  let key = decoder.string()?;
+  let payload = decoder.string()?;
+  let mut host_calls = vec![];
+  for _ in 0..decoder.u32()? {
+    host_calls.push(HostCall {
+      function: decoder.string()?,
+      args: decoder.values()?,
+      results: decoder.values()?,
+    });
+  }
+  let outcome = match decoder.u8()? {
+    0 => Ok(decoder.string()?),
+    _ => Err(decoder.string()?),
+  };
+  let duration_us = decoder.u64()?;
+  Some(JournalEntry {
+    call_id,
+    trace_id,
+    caller,
+    key,
+    payload,
+    host_calls,
+    outcome,
+    duration_us,
+  })
+}
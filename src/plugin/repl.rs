@@ -0,0 +1,190 @@
+use std::io::{self, BufRead, Write};
+use std::path::Path;
+use std::sync::mpsc::Receiver;
+use std::time::{Duration, Instant};
+
+use log::{error, info};
+use tempfile::TempDir;
+
+use crate::plugin::artifact::ArtifactHeader;
+use crate::plugin::compiler::{compile_to_file, CompileOptions};
+use crate::plugin::default::DefaultPlugin;
+use crate::plugin::events::{EventKind, GuestEvent, GuestStream};
+use crate::plugin::{Plugin, PluginError, PluginOptions};
+
+const HELP: &str = "<key> <payload>   execute the plugin, the key ends at the first space
+:reload           reload the module file, eg after a rebuild
+:init <config>    call init again with another config
+:heap             heap statistics of the guest
+:help             this help
+:quit             exit";
+
+// interactive loop for plugin development, see `assemblytest repl`
+//
+// `.wasm` and `.wat` files are compiled into a temporary artifact on every
+// (re)load, compiled artifacts are loaded as they are. guest output of each
+// call is printed below its result
+pub struct Repl {
+  options: PluginOptions,
+  // file given by the user, `options.file` may point to the compiled artifact
+  source: String,
+  config: String,
+  artifact_dir: Option<TempDir>,
+  plugin: DefaultPlugin,
+  logs: Receiver<GuestEvent>,
+}
+
+impl Repl {
+  pub fn start(options: PluginOptions, config: &String) -> Result<Self, PluginError> {
+    let source = options.file.clone();
+    let logs = options.events.subscribe(EventKind::Log);
+    let (plugin, artifact_dir) = load(&options, &source, config)?;
+    Ok(Self {
+      options,
+      source,
+      config: config.clone(),
+      artifact_dir,
+      plugin,
+      logs,
+    })
+  }
+
+  pub fn plugin(&self) -> &DefaultPlugin {
+    &self.plugin
+  }
+
+  // replaces the instance by one of the current module file, the old
+  // instance is kept if loading fails
+  pub fn reload(&mut self) -> Result<Duration, PluginError> {
+    let start = Instant::now();
+    let (plugin, artifact_dir) = load(&self.options, &self.source, &self.config)?;
+    self.plugin = plugin;
+    self.artifact_dir = artifact_dir;
+    info!(
+      "WASM:{} reloaded \"{}\"",
+      self.options.module_name, self.source
+    );
+    Ok(start.elapsed())
+  }
+
+  // evaluates one input line, returns false on `:quit`
+  pub fn eval(&mut self, line: &str, out: &mut impl Write) -> io::Result<bool> {
+    let line = line.trim();
+    match line.split_once(' ').unwrap_or((line, "")) {
+      ("", _) => {}
+      (":quit", _) | (":q", _) | (":exit", _) => return Ok(false),
+      (":help", _) => writeln!(out, "{}", HELP)?,
+      (":reload", _) => match self.reload() {
+        Ok(duration) => writeln!(out, "reloaded in {:?}", duration)?,
+        Err(error) => writeln!(out, "reload failed: {:?}", error)?,
+      },
+      (":init", config) => {
+        let start = Instant::now();
+        let result = self.plugin.init(&String::from(config));
+        self.print_logs(out)?;
+        match result {
+          Ok(()) => {
+            self.config = String::from(config);
+            writeln!(out, "initialized in {:?}", start.elapsed())?;
+          }
+          Err(error) => writeln!(out, "init failed: {:?}", error)?,
+        }
+      }
+      (":heap", _) => match self.plugin.heap_stats() {
+        Ok(stats) => writeln!(out, "{:#?}", stats)?,
+        Err(error) => writeln!(out, "heap stats failed: {:?}", error)?,
+      },
+      (command, _) if command.starts_with(':') => {
+        writeln!(out, "unknown command {}, see :help", command)?
+      }
+      (key, payload) => {
+        let start = Instant::now();
+        let result = self
+          .plugin
+          .execute(&String::from(key), &String::from(payload));
+        let duration = start.elapsed();
+        match result {
+          Ok(result) => writeln!(out, "{}", result)?,
+          Err(error) => writeln!(out, "error: {:?}", error)?,
+        }
+        self.print_logs(out)?;
+        writeln!(out, "({:?})", duration)?;
+      }
+    }
+    Ok(true)
+  }
+
+  // reads lines until `:quit` or the end of the input
+  pub fn run(&mut self, input: impl BufRead, out: &mut impl Write) -> io::Result<()> {
+    writeln!(
+      out,
+      "{} loaded from \"{}\", :help for commands",
+      self.options.module_name, self.source
+    )?;
+    let mut lines = input.lines();
+    loop {
+      write!(out, "> ")?;
+      out.flush()?;
+      let line = match lines.next() {
+        Some(line) => line?,
+        None => break,
+      };
+      if !self.eval(&line, out)? {
+        break;
+      }
+    }
+    Ok(())
+  }
+
+  fn print_logs(&self, out: &mut impl Write) -> io::Result<()> {
+    for event in self.logs.try_iter() {
+      if let GuestEvent::Log {
+        stream,
+        level,
+        message,
+        ..
+      } = event
+      {
+        let stream = match stream {
+          GuestStream::Stdout => "stdout",
+          GuestStream::Stderr => "stderr",
+        };
+        writeln!(out, "  [{} {}] {}", stream, level, message)?;
+      }
+    }
+    Ok(())
+  }
+}
+
+// creates and initializes an instance of the source, compiling it first if
+// it is a wasm module
+fn load(
+  options: &PluginOptions,
+  source: &String,
+  config: &String,
+) -> Result<(DefaultPlugin, Option<TempDir>), PluginError> {
+  let mut options = options.clone();
+  let extension = Path::new(source).extension().and_then(|ext| ext.to_str());
+  let artifact_dir = match extension {
+    Some("wasm") | Some("wat") => {
+      let dir = TempDir::new().map_err(|error| {
+        error!(
+          "WASM:{} creating artifact directory failed",
+          options.module_name
+        );
+        error!("{}", error);
+        PluginError::CompileFailed
+      })?;
+      let artifact = ArtifactHeader::host().artifact_file(dir.path(), "plugin");
+      let mut compile_options = CompileOptions::new();
+      compile_options.set_wasm_features(options.wasm_features);
+      compile_to_file(source, &artifact, &compile_options)?;
+      options.file = artifact;
+      Some(dir)
+    }
+    _ => None,
+  };
+  let plugin = DefaultPlugin::create(options)?;
+  plugin.init(config)?;
+  Ok((plugin, artifact_dir))
+}
@@ -0,0 +1,68 @@
+use std::cell::RefCell;
+use std::time::Duration;
+
+use crate::plugin::events::GuestStream;
+use crate::plugin::middleware::CallContext;
+
+// diagnostics of one execute call, see `DefaultPlugin::execute_with_report`
+// collected only for that call, so no global tracing or event subscription is needed
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CallReport {
+  pub call_id: u64,
+  pub trace_id: String,
+  // including middlewares, rate limits and fallbacks
+  pub duration: Duration,
+  // output the guest wrote during the call, also logged as usual
+  pub stdout: String,
+  pub stderr: String,
+  // strings written into guest memory for the call (key, payload, out buffer)
+  pub allocations: u64,
+  pub allocated_bytes: u64,
+  pub gc_runs: u64,
+  pub gc_time: Duration,
+}
+
+// report of the running call, the plugin is not `Sync` so a `RefCell` is enough
+#[derive(Debug, Clone, Default)]
+pub struct ReportCapture {
+  report: RefCell<Option<CallReport>>,
+}
+
+impl ReportCapture {
+  pub(crate) fn begin(&self, ctx: &CallContext) {
+    *self.report.borrow_mut() = Some(CallReport {
+      call_id: ctx.call_id,
+      trace_id: ctx.trace_id.clone(),
+      ..CallReport::default()
+    });
+  }
+
+  pub(crate) fn finish(&self, duration: Duration) -> CallReport {
+    let mut report = self.report.borrow_mut().take().unwrap_or_default();
+    report.duration = duration;
+    report
+  }
+
+  pub(crate) fn record_output(&self, stream: GuestStream, output: &String) {
+    if let Some(report) = self.report.borrow_mut().as_mut() {
+      match stream {
+        GuestStream::Stdout => report.stdout.push_str(output),
+        GuestStream::Stderr => report.stderr.push_str(output),
+      }
+    }
+  }
+
+  pub(crate) fn record_allocation(&self, bytes: u32) {
+    if let Some(report) = self.report.borrow_mut().as_mut() {
+      report.allocations += 1;
+      report.allocated_bytes += bytes as u64;
+    }
+  }
+
+  pub(crate) fn record_gc(&self, duration: Duration) {
+    if let Some(report) = self.report.borrow_mut().as_mut() {
+      report.gc_runs += 1;
+      report.gc_time += duration;
+    }
+  }
+}
@@ -0,0 +1,131 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use log::{debug, error};
+use tempfile::TempDir;
+
+use crate::plugin::PluginError;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScratchDirConfig {
+  // path under which the guest sees the directory, eg `/tmp`
+  pub alias: String,
+  pub quota_bytes: Option<u64>,
+}
+
+// host managed temporary directory preopened for one plugin
+// the directory and its content are removed when the plugin is dropped
+#[derive(Debug)]
+pub struct ScratchDir {
+  dir: TempDir,
+  alias: String,
+  quota_bytes: Option<u64>,
+}
+
+impl ScratchDir {
+  pub fn create(module_name: &String, config: &ScratchDirConfig) -> Result<Self, PluginError> {
+    let prefix: String = module_name
+      .chars()
+      .map(
+        |c| match c.is_ascii_alphanumeric() || c == '-' || c == '_' {
+          true => c,
+          false => '_',
+        },
+      )
+      .collect();
+
+    match tempfile::Builder::new()
+      .prefix(&format!("wasm-{}-", prefix))
+      .tempdir()
+    {
+      Ok(dir) => {
+        debug!(
+          "WASM:{} scratch dir {:?} mapped to {}",
+          module_name,
+          dir.path(),
+          config.alias
+        );
+        Ok(Self {
+          dir,
+          alias: config.alias.clone(),
+          quota_bytes: config.quota_bytes,
+        })
+      }
+      Err(error) => {
+        error!("WASM:{} creating scratch dir failed", module_name);
+        error!("{}", error);
+        Err(PluginError::InitWasiEnvFailed)
+      }
+    }
+  }
+
+  pub fn path(&self) -> &Path {
+    self.dir.path()
+  }
+
+  pub fn alias(&self) -> &String {
+    &self.alias
+  }
+
+  pub fn size(&self) -> io::Result<u64> {
+    dir_size(self.dir.path())
+  }
+
+  // removes everything inside the directory, the directory itself stays preopened
+  pub fn clear(&self) -> io::Result<()> {
+    for entry in fs::read_dir(self.dir.path())? {
+      let path = entry?.path();
+      if path.is_dir() {
+        fs::remove_dir_all(path)?;
+      } else {
+        fs::remove_file(path)?;
+      }
+    }
+    Ok(())
+  }
+
+  // the quota can not be enforced while the guest writes, so it is checked after
+  // each call - when exceeded the directory is cleared and the call fails
+  pub fn check_quota(&self, module_name: &String) -> Result<(), PluginError> {
+    let quota = match self.quota_bytes {
+      Some(quota) => quota,
+      None => return Ok(()),
+    };
+    let size = match self.size() {
+      Ok(size) => size,
+      Err(error) => {
+        error!("WASM:{} reading scratch dir size failed", module_name);
+        error!("{}", error);
+        return Err(PluginError::ScratchQuotaExceeded);
+      }
+    };
+    if size <= quota {
+      return Ok(());
+    }
+
+    error!(
+      "WASM:{} scratch dir quota exceeded - {} of {} bytes used",
+      module_name, size, quota
+    );
+    if let Err(error) = self.clear() {
+      error!("WASM:{} clearing scratch dir failed", module_name);
+      error!("{}", error);
+    }
+    Err(PluginError::ScratchQuotaExceeded)
+  }
+}
+
+fn dir_size(path: &Path) -> io::Result<u64> {
+  let mut size = 0;
+  for entry in fs::read_dir(path)? {
+    let entry = entry?;
+    let metadata = entry.metadata()?;
+    if metadata.is_dir() {
+      size += dir_size(&entry.path())?;
+    } else {
+      size += metadata.len();
+    }
+  }
+  Ok(size)
+}
@@ -0,0 +1,321 @@
+use std::collections::BTreeMap;
+
+use log::warn;
+use wasmer::Module;
+
+// custom sections of the wasm module, read from the loaded artifact
+//
+// - `name`: the module name of the name section
+// - `producers`: toolchain provenance, eg `language: [("AssemblyScript", "0.19")]`
+// - `plugin-meta`: json object defined by the plugin project, eg
+//   `{"source":"git@...#a1b2c3","capabilities":["network","scratch"]}`
+//
+// other sections are kept as raw bytes, dwarf sections (`.debug_*`) are skipped
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ModuleSections {
+  pub module_name: Option<String>,
+  pub producers: Producers,
+  pub plugin_meta: Option<JsonValue>,
+  pub custom: BTreeMap<String, Vec<u8>>,
+}
+
+// field (`language`, `processed-by`, `sdk`) with its (name, version) pairs
+pub type Producers = Vec<(String, Vec<(String, String)>)>;
+
+pub const PLUGIN_META_SECTION: &str = "plugin-meta";
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum JsonValue {
+  Null,
+  Bool(bool),
+  Number(f64),
+  String(String),
+  Array(Vec<JsonValue>),
+  Object(Vec<(String, JsonValue)>),
+}
+
+impl JsonValue {
+  pub fn get(&self, key: &str) -> Option<&JsonValue> {
+    match self {
+      JsonValue::Object(fields) => fields.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+      _ => None,
+    }
+  }
+
+  pub fn as_str(&self) -> Option<&str> {
+    match self {
+      JsonValue::String(s) => Some(s),
+      _ => None,
+    }
+  }
+}
+
+impl ModuleSections {
+  pub fn read(module_name: &String, module: &Module) -> Self {
+    let mut sections = Self::default();
+    let names: Vec<String> = module.info().custom_sections.keys().cloned().collect();
+    for name in names {
+      if name.starts_with(".debug") {
+        continue;
+      }
+      for data in module.custom_sections(&name) {
+        match name.as_str() {
+          "name" => sections.module_name = parse_module_name(&data),
+          "producers" => match parse_producers(&data) {
+            Some(producers) => sections.producers = producers,
+            None => warn!("WASM:{} invalid producers section", module_name),
+          },
+          PLUGIN_META_SECTION => match parse_json(&String::from_utf8_lossy(&data)) {
+            Ok(meta) => sections.plugin_meta = Some(meta),
+            Err(message) => warn!(
+              "WASM:{} invalid {} section: {}",
+              module_name, PLUGIN_META_SECTION, message
+            ),
+          },
+          _ => {
+            sections.custom.insert(name.clone(), data.to_vec());
+          }
+        }
+      }
+    }
+    sections
+  }
+
+  // `capabilities` of the plugin-meta section
+  pub fn capabilities(&self) -> Vec<String> {
+    match self
+      .plugin_meta
+      .as_ref()
+      .and_then(|meta| meta.get("capabilities"))
+    {
+      Some(JsonValue::Array(items)) => items
+        .iter()
+        .filter_map(|item| item.as_str().map(String::from))
+        .collect(),
+      _ => vec![],
+    }
+  }
+}
+
+struct Reader<'a> {
+  bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+  fn leb(&mut self) -> Option<u32> {
+    let mut result: u32 = 0;
+    for shift in (0..35).step_by(7) {
+      let (byte, rest) = self.bytes.split_first()?;
+      self.bytes = rest;
+      result |= ((byte & 0x7f) as u32).checked_shl(shift)?;
+      if byte & 0x80 == 0 {
+        return Some(result);
+      }
+    }
+    None
+  }
+
+  fn take(&mut self, length: usize) -> Option<&'a [u8]> {
+    if self.bytes.len() < length {
+      return None;
+    }
+    let (taken, rest) = self.bytes.split_at(length);
+    self.bytes = rest;
+    Some(taken)
+  }
+
+  fn name(&mut self) -> Option<String> {
+    let length = self.leb()? as usize;
+    let bytes = self.take(length)?;
+    String::from_utf8(bytes.to_vec()).ok()
+  }
+}
+
+// subsection 0 of the name section
+pub fn parse_module_name(data: &[u8]) -> Option<String> {
+  let mut reader = Reader { bytes: data };
+  while !reader.bytes.is_empty() {
+    let id = reader.take(1)?[0];
+    let size = reader.leb()? as usize;
+    let content = reader.take(size)?;
+    if id == 0 {
+      return Reader { bytes: content }.name();
+    }
+  }
+  None
+}
+
+pub fn parse_producers(data: &[u8]) -> Option<Producers> {
+  let mut reader = Reader { bytes: data };
+  let mut fields = vec![];
+  for _ in 0..reader.leb()? {
+    let field = reader.name()?;
+    let mut values = vec![];
+    for _ in 0..reader.leb()? {
+      values.push((reader.name()?, reader.name()?));
+    }
+    fields.push((field, values));
+  }
+  Some(fields)
+}
+
+// json of the plugin-meta section, objects keep the order of their fields
+pub fn parse_json(input: &str) -> Result<JsonValue, String> {
+  match serde_json::from_str::<serde_json::Value>(input) {
+    Ok(value) => Ok(JsonValue::from(value)),
+    Err(error) => Err(error.to_string()),
+  }
+}
+
+impl From<serde_json::Value> for JsonValue {
+  fn from(value: serde_json::Value) -> Self {
+    match value {
+      serde_json::Value::Null => JsonValue::Null,
+      serde_json::Value::Bool(value) => JsonValue::Bool(value),
+      serde_json::Value::Number(number) => JsonValue::Number(number.as_f64().unwrap_or(f64::NAN)),
+      serde_json::Value::String(value) => JsonValue::String(value),
+      serde_json::Value::Array(items) => {
+        JsonValue::Array(items.into_iter().map(JsonValue::from).collect())
+      }
+      serde_json::Value::Object(fields) => JsonValue::Object(
+        fields
+          .into_iter()
+          .map(|(key, value)| (key, JsonValue::from(value)))
+          .collect(),
+      ),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  // length prefixed utf-8 name
+  fn name(value: &str) -> Vec<u8> {
+    let mut bytes = vec![value.len() as u8];
+    bytes.extend(value.as_bytes());
+    bytes
+  }
+
+  fn name_section(module_name: &str) -> Vec<u8> {
+    let content = name(module_name);
+    let mut data = vec![0, content.len() as u8];
+    data.extend(content);
+    data
+  }
+
+  #[test]
+  fn module_name() {
+    assert_eq!(
+      parse_module_name(&name_section("enrich")),
+      Some(String::from("enrich"))
+    );
+    // function names (subsection 1) before the module name are skipped
+    let mut data = vec![1, 2, 0xaa, 0xbb];
+    data.extend(name_section("enrich"));
+    assert_eq!(parse_module_name(&data), Some(String::from("enrich")));
+    assert_eq!(parse_module_name(&[]), None);
+  }
+
+  #[test]
+  fn truncated_leb_sizes() {
+    // continuation bit set on the last byte
+    assert_eq!(parse_module_name(&[0, 0x80]), None);
+    assert_eq!(parse_module_name(&[0, 0x80, 0x80]), None);
+    assert_eq!(parse_producers(&[0x81]), None);
+    // more than 5 bytes does not fit into u32
+    assert_eq!(parse_producers(&[0x80, 0x80, 0x80, 0x80, 0x80, 0x00]), None);
+    assert_eq!(parse_producers(&[0xff, 0xff, 0xff, 0xff, 0x7f]), None);
+  }
+
+  #[test]
+  fn oversized_lengths() {
+    // subsection and name longer than the data
+    assert_eq!(
+      parse_module_name(&[0, 0xff, 0xff, 0xff, 0xff, 0x0f, 1]),
+      None
+    );
+    assert_eq!(parse_module_name(&[0, 3, 10, b'a', b'b']), None);
+    // a field count the data can not hold
+    assert_eq!(parse_producers(&[0xff, 0xff, 0xff, 0xff, 0x0f]), None);
+    let mut data = vec![1];
+    data.extend(name("language"));
+    data.push(0xff);
+    assert_eq!(parse_producers(&data), None);
+  }
+
+  #[test]
+  fn bad_utf8() {
+    assert_eq!(parse_module_name(&[0, 3, 2, 0xc3, 0x28]), None);
+    let mut data = vec![1, 2, 0xff, 0xfe];
+    data.push(0);
+    assert_eq!(parse_producers(&data), None);
+  }
+
+  #[test]
+  fn producers() {
+    let mut data = vec![1];
+    data.extend(name("language"));
+    data.push(1);
+    data.extend(name("AssemblyScript"));
+    data.extend(name("0.19"));
+    assert_eq!(
+      parse_producers(&data),
+      Some(vec![(
+        String::from("language"),
+        vec![(String::from("AssemblyScript"), String::from("0.19"))]
+      )])
+    );
+    assert_eq!(parse_producers(&[0]), Some(vec![]));
+  }
+
+  #[test]
+  fn nested_and_escaped_json() {
+    let meta = parse_json(
+      r#"{"source": "git@host:a\"b\".git#a1b2", "capabilities": ["network", "scratch"],
+          "nested": {"list": [1, -2.5e3, true, null, {"emoji": "\ud83d\udd11", "tab": "\t"}]}}"#,
+    )
+    .unwrap();
+    assert_eq!(
+      meta.get("source").and_then(JsonValue::as_str),
+      Some("git@host:a\"b\".git#a1b2")
+    );
+    assert_eq!(
+      meta.get("nested").and_then(|nested| nested.get("list")),
+      Some(&JsonValue::Array(vec![
+        JsonValue::Number(1.0),
+        JsonValue::Number(-2500.0),
+        JsonValue::Bool(true),
+        JsonValue::Null,
+        JsonValue::Object(vec![
+          (String::from("emoji"), JsonValue::String(String::from("🔑"))),
+          (String::from("tab"), JsonValue::String(String::from("\t"))),
+        ]),
+      ]))
+    );
+    let sections = ModuleSections {
+      plugin_meta: Some(meta),
+      ..ModuleSections::default()
+    };
+    assert_eq!(sections.capabilities(), vec!["network", "scratch"]);
+  }
+
+  #[test]
+  fn invalid_json() {
+    for input in [
+      "",
+      "{",
+      "{\"a\": }",
+      "[1, 2",
+      "\"unterminated",
+      "\"bad escape \\x\"",
+      "{} trailing",
+      "nul",
+    ] {
+      assert!(parse_json(input).is_err(), "{:?}", input);
+    }
+    // deep nesting fails instead of overflowing the stack
+    assert!(parse_json(&"[".repeat(100_000)).is_err());
+  }
+}
@@ -0,0 +1,211 @@
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use crate::plugin::default::DefaultPlugin;
+use crate::plugin::events::GuestEvent;
+use crate::plugin::gate::{CallGate, GatePass};
+use crate::plugin::manifest::PluginManifest;
+use crate::plugin::report::CallReport;
+use crate::plugin::template::WarmTemplate;
+use crate::plugin::{Plugin, PluginError, PluginOptions};
+
+// handle to a plugin which can be cloned and used from any thread
+//
+// a guest instance has one linear memory and one stack, so it must never run
+// two calls at the same time - `DefaultPlugin` is `Send` but not `Sync` for
+// that reason. `SharedPlugin` serializes all guest calls with a mutex, callers
+// on other threads wait until the running call has finished. for parallel
+// calls create one plugin per thread, see `DefaultPlugin::create_all`
+#[derive(Clone)]
+pub struct SharedPlugin {
+  inner: Arc<Mutex<DefaultPlugin>>,
+  module_name: String,
+  metadata: PluginManifest,
+  // kept outside of the mutex to re-instantiate a plugin stuck in a call
+  options: PluginOptions,
+  init_config: Arc<Mutex<Option<String>>>,
+  busy_since: Arc<Mutex<Option<Instant>>>,
+  // set for instances spawned from a warm template, see `from_template`
+  template: Option<Arc<WarmTemplate>>,
+  // pause gate of the manager for handles it hands out, see `PluginManager::pause`
+  gate: Option<Arc<CallGate>>,
+}
+
+impl SharedPlugin {
+  pub fn new(plugin: DefaultPlugin) -> Self {
+    Self {
+      module_name: plugin.get_options().module_name.clone(),
+      metadata: plugin.metadata().clone(),
+      options: plugin.get_options().clone(),
+      inner: Arc::new(Mutex::new(plugin)),
+      init_config: Arc::new(Mutex::new(None)),
+      busy_since: Arc::new(Mutex::new(None)),
+      template: None,
+      gate: None,
+    }
+  }
+
+  // initialized instance cloned from the template, re-instantiating it spawns
+  // from the template again
+  pub fn from_template(template: &Arc<WarmTemplate>) -> Result<Self, PluginError> {
+    let mut plugin = SharedPlugin::new(template.spawn()?);
+    *plugin.init_config.lock().unwrap() = Some(template.config().clone());
+    plugin.template = Some(template.clone());
+    Ok(plugin)
+  }
+
+  pub fn template(&self) -> Option<&Arc<WarmTemplate>> {
+    self.template.as_ref()
+  }
+
+  pub fn module_name(&self) -> &String {
+    &self.module_name
+  }
+
+  pub fn metadata(&self) -> &PluginManifest {
+    &self.metadata
+  }
+
+  // options the instance was created with
+  pub fn options(&self) -> &PluginOptions {
+    &self.options
+  }
+
+  pub fn init(&self, config: &String) -> Result<(), PluginError> {
+    self.call(|plugin| plugin.init(config))?;
+    *self.init_config.lock().unwrap() = Some(config.clone());
+    Ok(())
+  }
+
+  pub fn execute(&self, key: &String, payload: &String) -> Result<String, PluginError> {
+    let _pass = self.enter_gate()?;
+    self.call(|plugin| plugin.execute(key, payload))
+  }
+
+  pub fn execute_traced(
+    &self,
+    trace_id: &String,
+    key: &String,
+    payload: &String,
+  ) -> Result<String, PluginError> {
+    let _pass = self.enter_gate()?;
+    self.call(|plugin| plugin.execute_traced(trace_id, key, payload))
+  }
+
+  pub fn execute_as(
+    &self,
+    caller: &String,
+    key: &String,
+    payload: &String,
+  ) -> Result<String, PluginError> {
+    let _pass = self.enter_gate()?;
+    self.call(|plugin| plugin.execute_as(caller, key, payload))
+  }
+
+  pub fn execute_with_report(
+    &self,
+    key: &String,
+    payload: &String,
+  ) -> (Result<String, PluginError>, CallReport) {
+    let _pass = match self.enter_gate() {
+      Ok(pass) => pass,
+      Err(error) => return (Err(error), CallReport::default()),
+    };
+    self.call(|plugin| plugin.execute_with_report(key, payload))
+  }
+
+  // exclusive access for everything else, eg to read the scratch dir between calls
+  pub fn with<R>(&self, f: impl FnOnce(&DefaultPlugin) -> R) -> R {
+    f(&self.lock())
+  }
+
+  // like `with` but returns `None` instead of waiting for a running call
+  pub fn try_with<R>(&self, f: impl FnOnce(&DefaultPlugin) -> R) -> Option<R> {
+    match self.inner.try_lock() {
+      Ok(guard) => Some(f(&guard)),
+      Err(std::sync::TryLockError::Poisoned(poisoned)) => Some(f(&poisoned.into_inner())),
+      Err(std::sync::TryLockError::WouldBlock) => None,
+    }
+  }
+
+  // how long the running guest call takes so far, `None` while idle
+  pub fn busy_for(&self) -> Option<Duration> {
+    self.busy_since.lock().unwrap().map(|start| start.elapsed())
+  }
+
+  pub fn ptr_eq(&self, other: &SharedPlugin) -> bool {
+    Arc::ptr_eq(&self.inner, &other.inner)
+  }
+
+  // new instance from the same module and options, `init` is replayed with the
+  // config of the last successful init, instances of a warm template are
+  // spawned from it again
+  pub fn spawn(&self) -> Result<SharedPlugin, PluginError> {
+    if let Some(template) = &self.template {
+      return SharedPlugin::from_template(template);
+    }
+    let plugin = DefaultPlugin::create(self.options.clone())?;
+    let init_config = self.init_config.lock().unwrap().clone();
+    if let Some(config) = &init_config {
+      plugin.init(config)?;
+    }
+    let spawned = SharedPlugin::new(plugin);
+    *spawned.init_config.lock().unwrap() = init_config;
+    Ok(spawned)
+  }
+
+  // `spawn` reporting a restart. the old instance is left to the call that may
+  // still be stuck in it and dropped once that call returns
+  pub fn reinstantiate(&self, reason: &String) -> Result<SharedPlugin, PluginError> {
+    let restarted = self.spawn()?;
+    self.options.events.emit(GuestEvent::PluginRestarted {
+      module_name: self.module_name.clone(),
+      reason: reason.clone(),
+    });
+    Ok(restarted)
+  }
+
+  // same instance, its execute calls are held back while the gate is paused
+  pub(crate) fn with_gate(&self, gate: &Arc<CallGate>) -> SharedPlugin {
+    let mut plugin = self.clone();
+    plugin.gate = Some(gate.clone());
+    plugin
+  }
+
+  // only execute calls pass the gate, `init` and `with` stay usable for
+  // maintenance while the plugin is paused
+  fn enter_gate(&self) -> Result<Option<GatePass>, PluginError> {
+    match &self.gate {
+      Some(gate) => CallGate::enter(gate).map(Some),
+      None => Ok(None),
+    }
+  }
+
+  fn call<R>(&self, f: impl FnOnce(&DefaultPlugin) -> R) -> R {
+    let plugin = self.lock();
+    *self.busy_since.lock().unwrap() = Some(Instant::now());
+    let result = f(&plugin);
+    *self.busy_since.lock().unwrap() = None;
+    result
+  }
+
+  // a panic in a host function poisons the mutex, the instance itself is
+  // still usable as the panic unwound the guest call
+  fn lock(&self) -> MutexGuard<'_, DefaultPlugin> {
+    match self.inner.lock() {
+      Ok(guard) => guard,
+      Err(poisoned) => poisoned.into_inner(),
+    }
+  }
+}
+
+impl From<DefaultPlugin> for SharedPlugin {
+  fn from(plugin: DefaultPlugin) -> Self {
+    Self::new(plugin)
+  }
+}
+
+const _: fn() = || {
+  fn assert_send_sync<T: Send + Sync>() {}
+  assert_send_sync::<SharedPlugin>();
+};
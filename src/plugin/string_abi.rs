@@ -0,0 +1,159 @@
+// how strings are passed between host and guest
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum StringAbi {
+  // utf-8 bytes in an ArrayBuffer allocated by the guest `malloc` export, or by
+  // the runtime export `__new(size, idof<ArrayBuffer>)` if there is no malloc
+  // the guest converts with `String.UTF8.decode`/`String.UTF8.encode`
+  #[default]
+  Utf8ArrayBuffer,
+  // native AssemblyScript `string` objects (utf-16) allocated via the runtime
+  // export `__new(size, id)` - needs a build with `--exportRuntime`
+  Utf16String,
+  // utf-8 bytes behind a little endian u32 length, both in one `malloc` block
+  // the pointer handed to the guest points behind the length, like for
+  // AssemblyScript buffers - used by guests without a runtime header (eg TinyGo)
+  // results must be returned the same way and are released with `free`
+  Utf8LengthPrefixed,
+}
+
+// how a guest function hands its string result back to the host
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum ResultAbi {
+  // returns a pointer, the byte length is the u32 header in front of it
+  #[default]
+  LengthHeader,
+  // returns two i32 (pointer, byte length) - the guest needs multi-value
+  // the data stays owned by the guest, it is neither freed nor unpinned
+  PointerLength,
+  // the host allocates a buffer of `capacity` bytes and passes pointer and
+  // capacity as additional parameters, the guest writes the result into it and
+  // returns the byte length, a negative length signals an error
+  OutBuffer {
+    capacity: u32,
+  },
+}
+
+// `idof<ArrayBuffer>()` and `idof<String>()` of the AssemblyScript runtime
+pub const AS_ARRAY_BUFFER_CLASS_ID: u32 = 0;
+pub const AS_STRING_CLASS_ID: u32 = 1;
+
+impl StringAbi {
+  pub fn encode(&self, input: &String) -> Vec<u8> {
+    match self {
+      StringAbi::Utf8ArrayBuffer | StringAbi::Utf8LengthPrefixed => input.as_bytes().to_vec(),
+      StringAbi::Utf16String => input
+        .encode_utf16()
+        .flat_map(|unit| unit.to_le_bytes())
+        .collect(),
+    }
+  }
+
+  pub fn decode(&self, bytes: &[u8]) -> String {
+    match self {
+      StringAbi::Utf8ArrayBuffer | StringAbi::Utf8LengthPrefixed => {
+        String::from(String::from_utf8_lossy(bytes))
+      }
+      StringAbi::Utf16String => {
+        let units: Vec<u16> = bytes
+          .chunks_exact(2)
+          .map(|unit| u16::from_le_bytes([unit[0], unit[1]]))
+          .collect();
+        String::from_utf16_lossy(&units)
+      }
+    }
+  }
+}
+
+// validates a pointer to string data with a u32 length header in front, as
+// used by all string abis, and returns the byte length
+// `read_header` reads the u32 at given index of the memory viewed as u32 array
+// `is_multiple_of` would need rust 1.87
+#[allow(unknown_lints, clippy::manual_is_multiple_of)]
+pub fn string_length(
+  ptr: u32,
+  read_header: impl Fn(usize) -> Option<u32>,
+  memory_size: u64,
+) -> Result<u32, &'static str> {
+  let offset = ptr as usize;
+  if offset < 4 || offset % 4 != 0 {
+    return Err("pointer not aligned to a length header");
+  }
+  let length = match read_header(offset / 4 - 1) {
+    Some(length) => length,
+    None => return Err("pointer outside of memory"),
+  };
+  if offset as u64 + length as u64 > memory_size {
+    return Err("length header exceeds memory");
+  }
+  Ok(length)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  // memory of 64 bytes with a string of 8 bytes at offset 16
+  fn memory(index: usize) -> Option<u32> {
+    match index {
+      3 => Some(8),
+      7 => Some(1000),
+      15 => Some(u32::MAX),
+      0..=15 => Some(0),
+      _ => None,
+    }
+  }
+
+  #[test]
+  fn valid_pointer() {
+    assert_eq!(string_length(16, memory, 64), Ok(8));
+    // empty string right at the end of memory
+    assert_eq!(string_length(64, |_| Some(0), 64), Ok(0));
+  }
+
+  #[test]
+  fn null_pointer() {
+    assert_eq!(
+      string_length(0, memory, 64),
+      Err("pointer not aligned to a length header")
+    );
+  }
+
+  #[test]
+  fn misaligned_pointer() {
+    for ptr in [1, 2, 3, 17, 18, 19] {
+      assert_eq!(
+        string_length(ptr, memory, 64),
+        Err("pointer not aligned to a length header")
+      );
+    }
+  }
+
+  #[test]
+  fn header_outside_of_memory() {
+    assert_eq!(
+      string_length(68, memory, 64),
+      Err("pointer outside of memory")
+    );
+    assert_eq!(
+      string_length(u32::MAX - 3, memory, 64),
+      Err("pointer outside of memory")
+    );
+  }
+
+  #[test]
+  fn length_past_memory() {
+    assert_eq!(
+      string_length(32, memory, 64),
+      Err("length header exceeds memory")
+    );
+    // no overflow with the largest header at the end of memory
+    assert_eq!(
+      string_length(64, memory, 64),
+      Err("length header exceeds memory")
+    );
+    assert_eq!(
+      string_length(16, memory, 23),
+      Err("length header exceeds memory")
+    );
+  }
+}
@@ -0,0 +1,503 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+
+use log::{error, warn};
+use wasmer::{Exports, Function, FunctionType, ImportObject, Module, RuntimeError, Type, Val};
+
+use crate::plugin::audit::{now_ms, summarize_values, AuditDirection, AuditLog, AuditRecord};
+use crate::plugin::host::HostEnv;
+use crate::plugin::wasi::{is_wasi_namespace, matches_pattern};
+
+// what happens with a wasi call matching a rule
+#[derive(Debug, Clone, PartialEq)]
+pub enum SyscallRule {
+  Allow,
+  // traps the guest call with `PluginError::PolicyViolation`
+  Deny,
+  // allows the call only if its first argument, the fd, is one of the list
+  AllowFds(Vec<u32>),
+}
+
+// imports of wasi_unstable and wasi_snapshot_preview1, rule patterns have to
+// match at least one of them so a typo does not silently never match
+const WASI_FUNCTIONS: &[&str] = &[
+  "args_get",
+  "args_sizes_get",
+  "environ_get",
+  "environ_sizes_get",
+  "clock_res_get",
+  "clock_time_get",
+  "fd_advise",
+  "fd_allocate",
+  "fd_close",
+  "fd_datasync",
+  "fd_fdstat_get",
+  "fd_fdstat_set_flags",
+  "fd_fdstat_set_rights",
+  "fd_filestat_get",
+  "fd_filestat_set_size",
+  "fd_filestat_set_times",
+  "fd_pread",
+  "fd_prestat_get",
+  "fd_prestat_dir_name",
+  "fd_pwrite",
+  "fd_read",
+  "fd_readdir",
+  "fd_renumber",
+  "fd_seek",
+  "fd_sync",
+  "fd_tell",
+  "fd_write",
+  "path_create_directory",
+  "path_filestat_get",
+  "path_filestat_set_times",
+  "path_link",
+  "path_open",
+  "path_readlink",
+  "path_remove_directory",
+  "path_rename",
+  "path_symlink",
+  "path_unlink_file",
+  "poll_oneoff",
+  "proc_exit",
+  "proc_raise",
+  "sched_yield",
+  "random_get",
+  "sock_recv",
+  "sock_send",
+  "sock_shutdown",
+];
+
+// seccomp like rules for the wasi imports of a plugin
+//
+// rules are checked in the order they were added, the first rule whose
+// pattern matches the import decides, imports without matching rule get the
+// default. `clock_*` matches all imports starting with `clock_`
+//
+// as text, one rule per line, `#` starts a comment:
+//
+// fd_write: allow fds 1,2
+// path_open: deny
+// clock_*: allow
+// *: deny
+#[derive(Debug, Clone, PartialEq)]
+pub struct SyscallPolicy {
+  rules: Vec<(String, SyscallRule)>,
+  default: SyscallRule,
+}
+
+impl Default for SyscallPolicy {
+  fn default() -> Self {
+    Self::allow_all()
+  }
+}
+
+impl SyscallPolicy {
+  pub fn allow_all() -> Self {
+    Self {
+      rules: vec![],
+      default: SyscallRule::Allow,
+    }
+  }
+
+  pub fn deny_all() -> Self {
+    Self {
+      rules: vec![],
+      default: SyscallRule::Deny,
+    }
+  }
+
+  pub fn allow(&mut self, pattern: &String) -> &mut Self {
+    self.add_rule(pattern, SyscallRule::Allow)
+  }
+
+  pub fn deny(&mut self, pattern: &String) -> &mut Self {
+    self.add_rule(pattern, SyscallRule::Deny)
+  }
+
+  pub fn allow_fds(&mut self, pattern: &String, fds: &[u32]) -> &mut Self {
+    self.add_rule(pattern, SyscallRule::AllowFds(fds.to_vec()))
+  }
+
+  pub fn add_rule(&mut self, pattern: &String, rule: SyscallRule) -> &mut Self {
+    if pattern == "*" {
+      self.default = rule;
+    } else {
+      self.rules.push((pattern.clone(), rule));
+    }
+    self
+  }
+
+  pub fn rule_for(&self, name: &str) -> &SyscallRule {
+    self
+      .rules
+      .iter()
+      .find(|(pattern, _)| matches_pattern(pattern, name))
+      .map(|(_, rule)| rule)
+      .unwrap_or(&self.default)
+  }
+
+  pub fn parse(text: &String) -> Result<Self, String> {
+    let mut policy = Self::allow_all();
+    for (index, line) in text.lines().enumerate() {
+      let line = match line.split_once('#') {
+        Some((line, _)) => line.trim(),
+        None => line.trim(),
+      };
+      if line.is_empty() {
+        continue;
+      }
+      let (pattern, rule) = match line.split_once(':') {
+        Some((pattern, rule)) => (pattern.trim(), rule.trim()),
+        None => return Err(format!("line {}: expected <import>: <rule>", index + 1)),
+      };
+      check_pattern(pattern).map_err(|message| format!("line {}: {}", index + 1, message))?;
+      let rule = parse_rule(rule).map_err(|message| format!("line {}: {}", index + 1, message))?;
+      policy.add_rule(&String::from(pattern), rule);
+    }
+    Ok(policy)
+  }
+}
+
+// `*`, a wasi import or a prefix of wasi imports like `clock_*`
+pub fn check_pattern(pattern: &str) -> Result<(), String> {
+  let pattern = String::from(pattern);
+  if pattern == "*"
+    || WASI_FUNCTIONS
+      .iter()
+      .any(|name| matches_pattern(&pattern, name))
+  {
+    return Ok(());
+  }
+  Err(format!("unknown syscall \"{}\"", pattern))
+}
+
+// `allow`, `deny` or `allow fds 1,2`
+pub fn parse_rule(rule: &str) -> Result<SyscallRule, String> {
+  match rule {
+    "allow" => return Ok(SyscallRule::Allow),
+    "deny" => return Ok(SyscallRule::Deny),
+    _ => (),
+  }
+  let fds = match rule.strip_prefix("allow fds") {
+    Some(fds) => fds,
+    None => return Err(format!("unknown rule \"{}\"", rule)),
+  };
+  let fds: Result<Vec<u32>, _> = fds.split(',').map(|fd| fd.trim().parse::<u32>()).collect();
+  match fds {
+    Ok(fds) if !fds.is_empty() => Ok(SyscallRule::AllowFds(fds)),
+    _ => Err(format!("invalid fd list in \"{}\"", rule)),
+  }
+}
+
+// error the guest call is trapped with, turned into `PluginError::PolicyViolation`
+#[derive(Debug, Clone, PartialEq)]
+pub struct PolicyViolation {
+  pub function: String,
+  pub reason: String,
+}
+
+impl fmt::Display for PolicyViolation {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "policy violation in {}: {}", self.function, self.reason)
+  }
+}
+
+impl Error for PolicyViolation {}
+
+type Check = Arc<dyn Fn(&[Val]) -> Result<(), RuntimeError> + Send + Sync>;
+
+trait FromVal: Sized {
+  const TYPE: Type;
+  fn from_val(val: Option<&Val>) -> Self;
+}
+
+impl FromVal for i32 {
+  const TYPE: Type = Type::I32;
+  fn from_val(val: Option<&Val>) -> Self {
+    val.and_then(|val| val.i32()).unwrap_or_default()
+  }
+}
+
+impl FromVal for i64 {
+  const TYPE: Type = Type::I64;
+  fn from_val(val: Option<&Val>) -> Self {
+    val.and_then(|val| val.i64()).unwrap_or_default()
+  }
+}
+
+trait IntoVals {
+  const TYPES: &'static [Type];
+  fn into_vals(self) -> Vec<Val>;
+}
+
+impl IntoVals for i32 {
+  const TYPES: &'static [Type] = &[Type::I32];
+  fn into_vals(self) -> Vec<Val> {
+    vec![Val::I32(self)]
+  }
+}
+
+impl IntoVals for () {
+  const TYPES: &'static [Type] = &[];
+  fn into_vals(self) -> Vec<Val> {
+    vec![]
+  }
+}
+
+// the original wasi functions are native host functions, they can only be
+// called through a typed `NativeFunc`, so the forwarder is generated for all
+// signatures used by wasi_unstable and wasi_snapshot_preview1
+macro_rules! forwarders {
+  ($( ($($arg:ident),*) -> $ret:ty; )*) => {
+    #[allow(unused_parens)]
+    fn forwarder(original: &Function, check: Check) -> Option<Function> {
+      let ty = original.ty().clone();
+      $(
+        if ty.params() == [$(<$arg as FromVal>::TYPE),*] && ty.results() == <$ret as IntoVals>::TYPES {
+          let native = original.native::<($($arg),*), $ret>().ok()?;
+          return Some(Function::new(original.store(), &ty, move |args| {
+            check(args)?;
+            #[allow(unused_mut, unused_variables)]
+            let mut args = args.iter();
+            let result = native.call($(<$arg as FromVal>::from_val(args.next())),*)?;
+            Ok(result.into_vals())
+          }));
+        }
+      )*
+      None
+    }
+  };
+}
+
+forwarders! {
+  () -> i32;
+  (i32) -> i32;
+  (i32) -> ();
+  (i32, i32) -> i32;
+  (i32, i32, i32) -> i32;
+  (i32, i32, i32, i32) -> i32;
+  (i32, i32, i32, i32, i32) -> i32;
+  (i32, i32, i32, i32, i32, i32) -> i32;
+  (i32, i32, i32, i32, i32, i32, i32) -> i32;
+  (i32, i64) -> i32;
+  (i32, i64, i32) -> i32;
+  (i32, i64, i32, i32) -> i32;
+  (i32, i64, i64) -> i32;
+  (i32, i64, i64, i32) -> i32;
+  (i32, i32, i32, i64, i32) -> i32;
+  (i32, i32, i32, i32, i64, i64, i32) -> i32;
+  (i32, i32, i32, i32, i32, i64, i64, i32, i32) -> i32;
+}
+
+// reports violations to the log and the audit log
+#[derive(Clone)]
+struct Enforcer {
+  module_name: String,
+  host_env: HostEnv,
+  audit_log: Option<Arc<AuditLog>>,
+}
+
+impl Enforcer {
+  fn violation(&self, function: &String, reason: String, args: &[Val]) -> RuntimeError {
+    let violation = PolicyViolation {
+      function: function.clone(),
+      reason,
+    };
+    warn!("WASM:{} {}", self.module_name, violation);
+    if let Some(audit_log) = &self.audit_log {
+      let ctx = self.host_env.call_context();
+      audit_log.record(&AuditRecord {
+        timestamp_ms: now_ms(),
+        module_name: self.module_name.clone(),
+        call_id: ctx.as_ref().map(|ctx| ctx.call_id),
+        trace_id: ctx.map(|ctx| ctx.trace_id),
+        direction: AuditDirection::GuestToHost,
+        function: function.clone(),
+        args: summarize_values(args),
+        duration: Duration::ZERO,
+        error: Some(violation.to_string()),
+      });
+    }
+    RuntimeError::user(Box::new(violation))
+  }
+
+  fn deny(&self, module: &Module, ty: &FunctionType, name: &String) -> Function {
+    let enforcer = self.clone();
+    let name = name.clone();
+    Function::new(module.store(), ty, move |args| {
+      Err(enforcer.violation(&name, String::from("denied"), args))
+    })
+  }
+
+  fn allow_fds(&self, original: &Function, name: &String, fds: &[u32]) -> Option<Function> {
+    let enforcer = self.clone();
+    let name = name.clone();
+    let fds = fds.to_vec();
+    let check: Check = Arc::new(move |args| match args.first().and_then(|fd| fd.i32()) {
+      Some(fd) if fd >= 0 && fds.contains(&(fd as u32)) => Ok(()),
+      fd => Err(enforcer.violation(&name, format!("fd {} not allowed", fd.unwrap_or(-1)), args)),
+    });
+    forwarder(original, check)
+  }
+}
+
+// wraps the wasi imports of the module according to the policy
+pub fn apply_syscall_policy(
+  module_name: &String,
+  import_object: &mut ImportObject,
+  module: &Module,
+  policy: &SyscallPolicy,
+  host_env: &HostEnv,
+  audit_log: &Option<Arc<AuditLog>>,
+) {
+  let enforcer = Enforcer {
+    module_name: module_name.clone(),
+    host_env: host_env.clone(),
+    audit_log: audit_log.clone(),
+  };
+  let mut namespaces: HashMap<String, Exports> = HashMap::new();
+  for import in module.imports().functions() {
+    if !is_wasi_namespace(import.module()) {
+      continue;
+    }
+    let name = String::from(import.name());
+    let replacement = match policy.rule_for(&name) {
+      SyscallRule::Allow => continue,
+      SyscallRule::Deny => enforcer.deny(module, import.ty(), &name),
+      SyscallRule::AllowFds(fds) => {
+        let original = import_object
+          .get_namespace_exports(import.module())
+          .and_then(|exports| exports.get_function(&name).ok().cloned());
+        match original.and_then(|original| enforcer.allow_fds(&original, &name, fds)) {
+          Some(function) => function,
+          None => {
+            // fail closed, an unknown signature can not be forwarded
+            error!(
+              "WASM:{} wasi import {} can not be forwarded - denied",
+              module_name, name
+            );
+            enforcer.deny(module, import.ty(), &name)
+          }
+        }
+      }
+    };
+
+    let exports = namespaces
+      .entry(String::from(import.module()))
+      .or_insert_with(|| {
+        import_object
+          .get_namespace_exports(import.module())
+          .unwrap_or_default()
+      });
+    exports.insert(name, replacement);
+  }
+
+  for (namespace, exports) in namespaces {
+    import_object.register(namespace, exports);
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn parse(text: &str) -> Result<SyscallPolicy, String> {
+    SyscallPolicy::parse(&String::from(text))
+  }
+
+  #[test]
+  fn parses_valid_rules() {
+    let policy = parse(
+      "# stdout and stderr only\n\
+       fd_write: allow fds 1, 2\n\
+       \n\
+       path_open: deny # no files\n\
+       clock_*: allow\n\
+       *: deny",
+    )
+    .unwrap();
+
+    let mut expected = SyscallPolicy::deny_all();
+    expected
+      .allow_fds(&String::from("fd_write"), &[1, 2])
+      .deny(&String::from("path_open"))
+      .allow(&String::from("clock_*"));
+    assert_eq!(policy, expected);
+    assert_eq!(
+      policy.rule_for("fd_write"),
+      &SyscallRule::AllowFds(vec![1, 2])
+    );
+    assert_eq!(policy.rule_for("clock_time_get"), &SyscallRule::Allow);
+    assert_eq!(policy.rule_for("path_open"), &SyscallRule::Deny);
+    assert_eq!(policy.rule_for("random_get"), &SyscallRule::Deny);
+  }
+
+  #[test]
+  fn empty_policy_allows_all() {
+    assert_eq!(parse("").unwrap(), SyscallPolicy::allow_all());
+    assert_eq!(parse("# nothing\n\n").unwrap(), SyscallPolicy::allow_all());
+  }
+
+  #[test]
+  fn rejects_unknown_syscalls() {
+    assert_eq!(
+      parse("fd_write: allow\nfd_wirte: deny"),
+      Err(String::from("line 2: unknown syscall \"fd_wirte\""))
+    );
+    assert_eq!(
+      parse("socket_*: deny"),
+      Err(String::from("line 1: unknown syscall \"socket_*\""))
+    );
+    assert!(check_pattern("sock_*").is_ok());
+    assert!(check_pattern("*").is_ok());
+  }
+
+  #[test]
+  fn rejects_malformed_lines() {
+    assert_eq!(
+      parse("fd_write allow"),
+      Err(String::from("line 1: expected <import>: <rule>"))
+    );
+    assert_eq!(
+      parse("path_open: forbid"),
+      Err(String::from("line 1: unknown rule \"forbid\""))
+    );
+    assert_eq!(
+      parse("fd_write: allow fds"),
+      Err(String::from("line 1: invalid fd list in \"allow fds\""))
+    );
+    assert_eq!(
+      parse("\nfd_write: allow fds 1,x"),
+      Err(String::from("line 2: invalid fd list in \"allow fds 1,x\""))
+    );
+    assert_eq!(
+      parse("fd_write: allow fds -1"),
+      Err(String::from("line 1: invalid fd list in \"allow fds -1\""))
+    );
+  }
+
+  #[test]
+  fn first_matching_rule_wins() {
+    let policy = parse("fd_read: deny\nfd_*: allow\nfd_read: allow").unwrap();
+    assert_eq!(policy.rule_for("fd_read"), &SyscallRule::Deny);
+    assert_eq!(policy.rule_for("fd_write"), &SyscallRule::Allow);
+
+    let policy = parse("fd_*: allow fds 1\nfd_write: deny").unwrap();
+    assert_eq!(policy.rule_for("fd_write"), &SyscallRule::AllowFds(vec![1]));
+  }
+
+  #[test]
+  fn wildcard_sets_default_wherever_it_is() {
+    let policy = parse("*: deny\nclock_*: allow").unwrap();
+    assert_eq!(policy.rule_for("clock_res_get"), &SyscallRule::Allow);
+    assert_eq!(policy.rule_for("proc_exit"), &SyscallRule::Deny);
+
+    // a later `*` replaces the earlier default
+    let policy = parse("*: deny\n*: allow").unwrap();
+    assert_eq!(policy, SyscallPolicy::allow_all());
+  }
+}
@@ -0,0 +1,104 @@
+use std::time::Instant;
+
+use log::{debug, error, info};
+use rayon::prelude::*;
+use rayon::ThreadPoolBuilder;
+
+use crate::plugin::checkpoint::{artifact_fingerprint, Checkpoint};
+use crate::plugin::default::DefaultPlugin;
+use crate::plugin::events::LifecycleState;
+use crate::plugin::{Plugin, PluginError, PluginOptions};
+
+// initialized plugin state new instances are cloned from
+//
+// `prepare` creates one instance, runs `_start` and `init` and snapshots its
+// memory and mutable globals. `spawn` creates a new instance and copies the
+// snapshot into it instead of running `_start` and `init` again, so scaling up
+// a plugin with a heavy init only costs the instantiation
+//
+// the same limits as for `Checkpoint` apply: the runtime state of the guest
+// (eg the AssemblyScript allocator) lives in globals which are only reachable
+// in artifacts of `compile_to_file` with `CompileOptions::enable_state_globals`,
+// `prepare` rejects other artifacts. the
+// wasi state (open files) is not part of the snapshot, the guest must keep the
+// state built by `init` in memory
+//
+// the artifact is hashed once in `prepare`, replacing the artifact file of a
+// prepared template is not detected by `spawn`
+#[derive(Debug)]
+pub struct WarmTemplate {
+  options: PluginOptions,
+  config: String,
+  fingerprint: String,
+  snapshot: Checkpoint,
+}
+
+impl WarmTemplate {
+  pub fn prepare(options: PluginOptions, config: &String) -> Result<Self, PluginError> {
+    let start = Instant::now();
+    let fingerprint = artifact_fingerprint(&options.file)?;
+    let plugin = DefaultPlugin::create(options.clone())?;
+    plugin.init(config)?;
+    let snapshot = Checkpoint::capture(
+      &options.module_name,
+      &fingerprint,
+      config,
+      plugin.get_instance(),
+      plugin.get_memory(),
+    )?;
+    info!(
+      "WASM:{} warm template prepared in {:?} ({} bytes memory)",
+      options.module_name,
+      start.elapsed(),
+      snapshot.memory.len()
+    );
+    Ok(Self {
+      options,
+      config: config.clone(),
+      fingerprint,
+      snapshot,
+    })
+  }
+
+  pub fn options(&self) -> &PluginOptions {
+    &self.options
+  }
+
+  // `init` config the snapshot was built from
+  pub fn config(&self) -> &String {
+    &self.config
+  }
+
+  // new initialized instance, `init` must not be called on it again
+  pub fn spawn(&self) -> Result<DefaultPlugin, PluginError> {
+    let start = Instant::now();
+    let plugin = DefaultPlugin::create(self.options.clone())?;
+    self.snapshot.apply(
+      &self.options.module_name,
+      &self.fingerprint,
+      plugin.get_instance(),
+      plugin.get_memory(),
+    )?;
+    plugin.emit_lifecycle(LifecycleState::Started);
+    plugin.emit_lifecycle(LifecycleState::Initialized);
+    debug!(
+      "WASM:{} instance spawned from warm template in {:?}",
+      self.options.module_name,
+      start.elapsed()
+    );
+    Ok(plugin)
+  }
+
+  // spawns `count` instances in parallel, `threads` of 0 uses one thread per cpu
+  pub fn spawn_all(&self, count: usize, threads: usize) -> Vec<Result<DefaultPlugin, PluginError>> {
+    let pool = match ThreadPoolBuilder::new().num_threads(threads).build() {
+      Ok(pool) => pool,
+      Err(error) => {
+        error!("creating plugin spawn thread pool failed");
+        error!("{}", error);
+        return (0..count).map(|_| Err(PluginError::LoadingError)).collect();
+      }
+    };
+    pool.install(|| (0..count).into_par_iter().map(|_| self.spawn()).collect())
+  }
+}
@@ -0,0 +1,186 @@
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use log::warn;
+
+use crate::plugin::default::DefaultPlugin;
+use crate::plugin::manager::PluginManager;
+use crate::plugin::metrics::{Counter, Metrics};
+use crate::plugin::module_cache::ModuleCache;
+use crate::plugin::rate_limit::{RateLimit, RateLimiter};
+use crate::plugin::PluginError;
+
+// limits of one tenant, calls over a limit fail with
+// `PluginError::TenantQuotaExceeded` (`PluginError::RateLimited` for the rate limit)
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TenantQuota {
+  max_plugins: Option<usize>,
+  rate_limit: Option<RateLimit>,
+  // calls and summed call time per period
+  max_calls: Option<u64>,
+  max_execution_time: Option<Duration>,
+  period: Option<Duration>,
+}
+
+impl TenantQuota {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  // number of plugin names the tenant may register
+  pub fn set_max_plugins(&mut self, max_plugins: usize) -> &mut Self {
+    self.max_plugins = Some(max_plugins);
+    self
+  }
+
+  pub fn set_rate_limit(&mut self, limit: RateLimit) -> &mut Self {
+    self.rate_limit = Some(limit);
+    self
+  }
+
+  // calls per period, the period defaults to a minute
+  pub fn set_max_calls(&mut self, max_calls: u64) -> &mut Self {
+    self.max_calls = Some(max_calls);
+    self
+  }
+
+  // guest time per period, a running call is not interrupted
+  pub fn set_max_execution_time(&mut self, time: Duration) -> &mut Self {
+    self.max_execution_time = Some(time);
+    self
+  }
+
+  pub fn set_period(&mut self, period: Duration) -> &mut Self {
+    self.period = Some(period);
+    self
+  }
+
+  fn period(&self) -> Duration {
+    self.period.unwrap_or(Duration::from_secs(60))
+  }
+}
+
+#[derive(Debug)]
+struct Usage {
+  period_start: Instant,
+  calls: u64,
+  execution_time: Duration,
+}
+
+// plugins, metrics and limits of one tenant, see `PluginManager::add_tenant`
+//
+// the plugins of a tenant live in their own `PluginManager` with its own
+// metrics, so names, versions and canaries never collide between tenants.
+// calls run as `execute_as` with the tenant id as caller: per caller rate
+// limits of the plugins are partitioned by tenant. the crate has no kv store,
+// host functions keeping state have to partition it themselves by
+// `HostEnv::call_context().caller`
+pub struct TenantNamespace {
+  id: String,
+  quota: TenantQuota,
+  plugins: PluginManager,
+  rate_limiter: Option<RateLimiter>,
+  usage: Mutex<Usage>,
+  // held from the `max_plugins` check until the plugin is registered
+  registering: Mutex<()>,
+}
+
+impl TenantNamespace {
+  pub(crate) fn new(id: &String, quota: TenantQuota, module_cache: &Arc<ModuleCache>) -> Self {
+    Self {
+      id: id.clone(),
+      rate_limiter: quota.rate_limit.map(RateLimiter::new),
+      quota,
+      plugins: PluginManager::with_module_cache(&Metrics::new(), module_cache),
+      usage: Mutex::new(Usage {
+        period_start: Instant::now(),
+        calls: 0,
+        execution_time: Duration::ZERO,
+      }),
+      registering: Mutex::new(()),
+    }
+  }
+
+  pub fn id(&self) -> &String {
+    &self.id
+  }
+
+  pub fn quota(&self) -> &TenantQuota {
+    &self.quota
+  }
+
+  // plugins of the tenant, for promote, rollback, canaries...
+  // register through `PluginManager::register_for_tenant` to enforce `max_plugins`
+  pub fn plugins(&self) -> &PluginManager {
+    &self.plugins
+  }
+
+  pub fn metrics(&self) -> &Metrics {
+    self.plugins.metrics()
+  }
+
+  // checks `max_plugins` and registers under one lock, so concurrent
+  // registrations of new names can not both take the last free slot
+  pub(crate) fn register(&self, name: &String, plugin: DefaultPlugin) -> Result<(), PluginError> {
+    let _registering = self.registering.lock().unwrap();
+    self.check_plugin_quota(name)?;
+    self.plugins.register(name, plugin);
+    Ok(())
+  }
+
+  fn check_plugin_quota(&self, name: &String) -> Result<(), PluginError> {
+    let max_plugins = match self.quota.max_plugins {
+      Some(max_plugins) => max_plugins,
+      None => return Ok(()),
+    };
+    let names = self.plugins.names();
+    if names.contains(name) || names.len() < max_plugins {
+      return Ok(());
+    }
+    warn!(
+      "tenant {} plugin quota of {} exceeded by {}",
+      self.id, max_plugins, name
+    );
+    Err(PluginError::TenantQuotaExceeded)
+  }
+
+  // counts the call against the quota before it runs
+  pub(crate) fn acquire(&self) -> Result<(), PluginError> {
+    if let Some(limiter) = &self.rate_limiter {
+      limiter.acquire(&self.id)?;
+    }
+    let mut usage = self.usage.lock().unwrap();
+    if usage.period_start.elapsed() >= self.quota.period() {
+      usage.period_start = Instant::now();
+      usage.calls = 0;
+      usage.execution_time = Duration::ZERO;
+    }
+    let calls_exceeded = matches!(self.quota.max_calls, Some(max) if usage.calls >= max);
+    let time_exceeded =
+      matches!(self.quota.max_execution_time, Some(max) if usage.execution_time >= max);
+    if calls_exceeded || time_exceeded {
+      self.counter("quota_exceeded").increment();
+      warn!(
+        "tenant {} quota exceeded: {} calls, {:?} execution time in the current period",
+        self.id, usage.calls, usage.execution_time
+      );
+      return Err(PluginError::TenantQuotaExceeded);
+    }
+    usage.calls += 1;
+    self.counter("calls").increment();
+    Ok(())
+  }
+
+  pub(crate) fn record(&self, duration: Duration) {
+    self.usage.lock().unwrap().execution_time += duration;
+    self
+      .counter("execution_time_us")
+      .add(duration.as_micros() as u64);
+  }
+
+  fn counter(&self, name: &str) -> Arc<Counter> {
+    self
+      .metrics()
+      .counter(&format!("tenant.{}.{}", self.id, name))
+  }
+}
@@ -0,0 +1,219 @@
+use std::path::PathBuf;
+
+use log::{error, info};
+use semver::Version;
+use wasmer::Memory;
+
+use crate::plugin::audit::json_string;
+use crate::plugin::default::DefaultPlugin;
+use crate::plugin::manager::LoadReport;
+use crate::plugin::{Plugin, PluginError, PluginOptions, WasmerStringPtr};
+
+// optional guest export `self_test() -> i32` run by the validation, 0 passes
+pub const SELF_TEST_FUNCTION: &str = "self_test";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ValidationCheck {
+  pub name: String,
+  pub passed: bool,
+  // why the check failed or was skipped
+  pub message: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PluginValidation {
+  pub name: String,
+  pub version: Version,
+  pub file: String,
+  pub checks: Vec<ValidationCheck>,
+}
+
+impl PluginValidation {
+  pub fn passed(&self) -> bool {
+    self.checks.iter().all(|check| check.passed)
+  }
+}
+
+// result of `PluginManager::validate_all`
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ValidationReport {
+  pub plugins: Vec<PluginValidation>,
+  // plugins which could not be loaded or instantiated, see `with_load_report`
+  pub load_failures: Vec<(PathBuf, PluginError)>,
+}
+
+impl ValidationReport {
+  pub fn passed(&self) -> bool {
+    self.load_failures.is_empty() && self.plugins.iter().all(|plugin| plugin.passed())
+  }
+
+  // adds the failures of `PluginManager::load_dir`
+  pub fn with_load_report(mut self, load: &LoadReport) -> Self {
+    self.load_failures.extend(load.failed.iter().cloned());
+    self
+  }
+
+  // machine readable summary, eg for ci
+  pub fn to_json(&self) -> String {
+    let plugins: Vec<String> = self
+      .plugins
+      .iter()
+      .map(|plugin| {
+        let checks: Vec<String> = plugin
+          .checks
+          .iter()
+          .map(|check| {
+            format!(
+              "{{\"name\":{},\"passed\":{},\"message\":{}}}",
+              json_string(&check.name),
+              check.passed,
+              match &check.message {
+                Some(message) => json_string(message),
+                None => String::from("null"),
+              }
+            )
+          })
+          .collect();
+        format!(
+          "{{\"name\":{},\"version\":{},\"file\":{},\"passed\":{},\"checks\":[{}]}}",
+          json_string(&plugin.name),
+          json_string(&plugin.version.to_string()),
+          json_string(&plugin.file),
+          plugin.passed(),
+          checks.join(",")
+        )
+      })
+      .collect();
+    let load_failures: Vec<String> = self
+      .load_failures
+      .iter()
+      .map(|(file, error)| {
+        format!(
+          "{{\"file\":{},\"error\":{}}}",
+          json_string(&file.to_string_lossy()),
+          json_string(&format!("{:?}", error))
+        )
+      })
+      .collect();
+    format!(
+      "{{\"passed\":{},\"plugins\":[{}],\"load_failures\":[{}]}}",
+      self.passed(),
+      plugins.join(","),
+      load_failures.join(",")
+    )
+  }
+}
+
+fn check(name: &str, result: Result<(), String>) -> ValidationCheck {
+  ValidationCheck {
+    name: String::from(name),
+    passed: result.is_ok(),
+    message: result.err(),
+  }
+}
+
+// instantiates a fresh instance from the options of a registered plugin and
+// checks its exports, the registered instance is not touched
+pub fn validate_plugin(
+  name: &String,
+  version: &Version,
+  options: &PluginOptions,
+) -> PluginValidation {
+  let mut validation = PluginValidation {
+    name: name.clone(),
+    version: version.clone(),
+    file: options.file.clone(),
+    checks: vec![],
+  };
+
+  let plugin = match DefaultPlugin::create(options.clone()) {
+    Ok(plugin) => {
+      validation.checks.push(check("instantiate", Ok(())));
+      plugin
+    }
+    Err(error) => {
+      validation
+        .checks
+        .push(check("instantiate", Err(format!("{:?}", error))));
+      return validation;
+    }
+  };
+
+  // create already fails without an execute export, the detected convention is logged
+  let exports = plugin.describe();
+  info!("WASM:{} validation: {}", options.module_name, exports);
+  validation.checks.push(check(
+    "execute",
+    plugin
+      .get_instance()
+      .exports
+      .get_function(&exports.execute_function_name)
+      .map(|_| ())
+      .map_err(|error| error.to_string()),
+  ));
+  validation.checks.push(check(
+    "memory",
+    plugin
+      .get_instance()
+      .exports
+      .get::<Memory>(&options.memory_name)
+      .map(|_| ())
+      .map_err(|error| error.to_string()),
+  ));
+  validation.checks.push(check(
+    "start",
+    plugin
+      .get_function::<(), ()>(&options.start_function_name)
+      .map(|_| ())
+      .map_err(|error| format!("{:?}", error)),
+  ));
+  validation.checks.push(check(
+    "init",
+    plugin
+      .get_function::<WasmerStringPtr, ()>(&options.init_function_name)
+      .map(|_| ())
+      .map_err(|error| format!("{:?}", error)),
+  ));
+
+  let self_test = String::from(SELF_TEST_FUNCTION);
+  if plugin
+    .get_instance()
+    .exports
+    .get_function(&self_test)
+    .is_ok()
+  {
+    validation
+      .checks
+      .push(check("self_test", run_self_test(&plugin, &self_test)));
+  }
+  validation
+}
+
+// `_start` followed by `self_test`, `init` is not called
+fn run_self_test(plugin: &DefaultPlugin, name: &String) -> Result<(), String> {
+  let options = plugin.get_options();
+  let self_test = plugin
+    .get_function::<(), i32>(name)
+    .map_err(|error| format!("{:?}", error))?;
+  if let Ok(start) = plugin.get_function::<(), ()>(&options.start_function_name) {
+    if let Err(error) =
+      plugin.guest_call(&options.start_function_name, String::new, || start.call())
+    {
+      return Err(format!(
+        "{:?}",
+        plugin.log_and_transform_error(error, &options.start_function_name)
+      ));
+    }
+    plugin.log_guest_output(&options.start_function_name);
+  }
+  let result = plugin.guest_call(name, String::new, || self_test.call());
+  plugin.log_guest_output(name);
+  match result {
+    Ok(0) => Ok(()),
+    Ok(code) => {
+      error!("WASM:{} {} returned {}", options.module_name, name, code);
+      Err(format!("returned {}", code))
+    }
+    Err(error) => Err(format!("{:?}", plugin.log_and_transform_error(error, name))),
+  }
+}
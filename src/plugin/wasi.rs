@@ -0,0 +1,154 @@
+use std::collections::HashMap;
+
+use log::{debug, error, warn};
+use wasmer::{Exports, Function, ImportObject, Module, RuntimeError, Type, Val};
+use wasmer_wasi::{generate_import_object_from_env, get_wasi_versions, WasiEnv, WasiVersion};
+
+use crate::plugin::PluginError;
+
+// replacement for a wasi import the sandbox must not provide
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StubBehavior {
+  // returns the given wasi errno, eg 52 (ENOSYS) or 63 (EPERM)
+  Errno(u16),
+  // traps the guest call with an error naming the forbidden import
+  Trap,
+}
+
+pub(crate) fn is_wasi_namespace(namespace: &str) -> bool {
+  namespace == "wasi_unstable" || namespace == "wasi_snapshot_preview1"
+}
+
+// `sock_*` matches all imports starting with `sock_`, other patterns must match exactly
+pub(crate) fn matches_pattern(pattern: &String, name: &str) -> bool {
+  match pattern.strip_suffix('*') {
+    Some(prefix) => name.starts_with(prefix),
+    None => pattern == name,
+  }
+}
+
+pub fn wasi_namespace(version: WasiVersion) -> &'static str {
+  match version {
+    WasiVersion::Snapshot0 => "wasi_unstable",
+    WasiVersion::Snapshot1 | WasiVersion::Latest => "wasi_snapshot_preview1",
+  }
+}
+
+// detects the wasi namespaces imported by the module and builds an import object
+// providing all of them, so `wasi_unstable` modules and modules mixing both
+// namespaces can be instantiated
+// modules without any wasi import get `wasi_snapshot_preview1`
+pub fn wasi_import_object(
+  module_name: &String,
+  environment: &WasiEnv,
+  module: &Module,
+) -> Result<ImportObject, PluginError> {
+  let mut versions: Vec<WasiVersion> = match get_wasi_versions(module, false) {
+    Some(versions) => versions.into_iter().collect(),
+    None => {
+      error!("WASM:{} detecting wasi version failed", module_name);
+      return Err(PluginError::WasiImportObjectFailed);
+    }
+  };
+  if versions.is_empty() {
+    debug!(
+      "WASM:{} no wasi imports found - using wasi_snapshot_preview1",
+      module_name
+    );
+    versions.push(WasiVersion::Snapshot1);
+  }
+
+  let mut import_object = ImportObject::new();
+  for version in versions {
+    let namespace = wasi_namespace(version);
+    let generated = generate_import_object_from_env(module.store(), environment.clone(), version);
+    match generated.get_namespace_exports(namespace) {
+      Some(exports) => {
+        debug!(
+          "WASM:{} providing wasi namespace {}",
+          module_name, namespace
+        );
+        import_object.register(namespace, exports);
+      }
+      None => {
+        error!(
+          "WASM:{} wasi namespace {} not available",
+          module_name, namespace
+        );
+        return Err(PluginError::WasiImportObjectFailed);
+      }
+    }
+  }
+  Ok(import_object)
+}
+
+// replaces the wasi imports of the module matching one of the stub patterns
+pub fn apply_wasi_stubs(
+  module_name: &String,
+  import_object: &mut ImportObject,
+  module: &Module,
+  stubs: &[(String, StubBehavior)],
+) {
+  if stubs.is_empty() {
+    return;
+  }
+
+  let mut namespaces: HashMap<String, Exports> = HashMap::new();
+  for import in module.imports().functions() {
+    if !is_wasi_namespace(import.module()) {
+      continue;
+    }
+    let behavior = match stubs
+      .iter()
+      .find(|(pattern, _)| matches_pattern(pattern, import.name()))
+    {
+      Some((_, behavior)) => *behavior,
+      None => continue,
+    };
+
+    let exports = namespaces
+      .entry(String::from(import.module()))
+      .or_insert_with(
+        || match import_object.get_namespace_exports(import.module()) {
+          Some(exports) => exports,
+          None => Exports::new(),
+        },
+      );
+
+    let name = String::from(import.name());
+    let trap_message = format!("WASM:{} forbidden wasi call {}", module_name, name);
+    let results: Vec<Type> = import.ty().results().to_vec();
+    let stub = Function::new(
+      module.store(),
+      import.ty().clone(),
+      move |_args| match behavior {
+        StubBehavior::Trap => Err(RuntimeError::new(trap_message.clone())),
+        StubBehavior::Errno(errno) => Ok(
+          results
+            .iter()
+            .map(|ty| match ty {
+              Type::I32 => Val::I32(i32::from(errno)),
+              Type::I64 => Val::I64(i64::from(errno)),
+              Type::F32 => Val::F32(0.0),
+              Type::F64 => Val::F64(0.0),
+              _ => Val::null(),
+            })
+            .collect(),
+        ),
+      },
+    );
+
+    warn!(
+      "WASM:{} wasi import {}::{} replaced by stub {:?}",
+      module_name,
+      import.module(),
+      name,
+      behavior
+    );
+    exports.insert(name, stub);
+  }
+
+  for (namespace, exports) in namespaces {
+    import_object.register(namespace, exports);
+  }
+}
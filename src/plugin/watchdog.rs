@@ -0,0 +1,138 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use log::{error, warn};
+use semver::Version;
+
+use crate::plugin::manager::PluginManager;
+use crate::plugin::shared::SharedPlugin;
+use crate::plugin::Plugin;
+
+#[derive(Debug, Clone)]
+pub struct WatchdogConfig {
+  pub interval: Duration,
+  // a call running longer is considered wedged
+  pub max_call_duration: Option<Duration>,
+  // checked between calls, instances above are restarted
+  pub max_memory_pages: Option<u32>,
+}
+
+impl Default for WatchdogConfig {
+  fn default() -> Self {
+    Self {
+      interval: Duration::from_secs(1),
+      max_call_duration: Some(Duration::from_secs(30)),
+      max_memory_pages: None,
+    }
+  }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Restart {
+  pub name: String,
+  pub version: Version,
+  pub reason: String,
+}
+
+// checks all instances of a manager and replaces wedged or bloated ones
+//
+// wasmer can not interrupt a running guest, so a wedged call is abandoned: the
+// calling thread stays blocked in the old instance, while all new calls are
+// routed to a fresh instance with `init` replayed. `PluginRestarted` is emitted
+// on the event bus of the plugin
+pub struct Watchdog {
+  running: Arc<AtomicBool>,
+  handle: Option<JoinHandle<()>>,
+}
+
+impl Watchdog {
+  pub fn start(manager: Arc<PluginManager>, config: WatchdogConfig) -> Self {
+    let running = Arc::new(AtomicBool::new(true));
+    let thread_running = running.clone();
+    let handle = thread::Builder::new()
+      .name(String::from("wasm-watchdog"))
+      .spawn(move || {
+        while thread_running.load(Ordering::Relaxed) {
+          check(&manager, &config);
+          thread::sleep(config.interval);
+        }
+      });
+    let handle = match handle {
+      Ok(handle) => Some(handle),
+      Err(error) => {
+        error!("spawning watchdog thread failed");
+        error!("{}", error);
+        None
+      }
+    };
+    Self { running, handle }
+  }
+
+  pub fn stop(mut self) {
+    self.shutdown();
+  }
+
+  fn shutdown(&mut self) {
+    self.running.store(false, Ordering::Relaxed);
+    if let Some(handle) = self.handle.take() {
+      let _ = handle.join();
+    }
+  }
+}
+
+impl Drop for Watchdog {
+  fn drop(&mut self) {
+    self.shutdown();
+  }
+}
+
+// one round of checks, returns the restarted instances
+pub fn check(manager: &PluginManager, config: &WatchdogConfig) -> Vec<Restart> {
+  let mut restarts = vec![];
+  for (name, version, plugin) in manager.instances() {
+    let reason = match violation(&plugin, config) {
+      Some(reason) => reason,
+      None => continue,
+    };
+    warn!("WASM:{}@{} watchdog: {}", name, version, reason);
+    match plugin.reinstantiate(&reason) {
+      Ok(replacement) => {
+        if manager.replace_instance(&name, &version, &plugin, replacement) {
+          restarts.push(Restart {
+            name,
+            version,
+            reason,
+          });
+        }
+      }
+      Err(error) => error!(
+        "WASM:{}@{} watchdog restart failed: {:?}",
+        name, version, error
+      ),
+    }
+  }
+  restarts
+}
+
+fn violation(plugin: &SharedPlugin, config: &WatchdogConfig) -> Option<String> {
+  if let (Some(max), Some(busy)) = (config.max_call_duration, plugin.busy_for()) {
+    if busy > max {
+      return Some(format!("call running for {:?}", busy));
+    }
+  }
+  if let Some(max) = config.max_memory_pages {
+    let pages = plugin.try_with(|plugin| plugin.get_memory().size().0)?;
+    if pages > max {
+      return Some(format!("memory grew to {} pages", pages));
+    }
+  }
+  None
+}
+
+impl PluginManager {
+  pub fn start_watchdog(self: &Arc<Self>, config: WatchdogConfig) -> Watchdog {
+    Watchdog::start(self.clone(), config)
+  }
+}
@@ -0,0 +1,473 @@
+// http front end for a `PluginManager`, enabled with feature `server`
+//
+// POST /plugins/{name}/execute   key from header `x-key` or query `?key=`, body is the payload
+//                                optional header `x-caller` for per caller rate limits
+//                                optional header `x-tenant` with `authorization: Bearer <token>`
+//                                for the plugin of a tenant, see `ServerOptions::add_tenant_token`
+// GET  /health                   200 with the registered plugin names
+// GET  /metrics                  counters of the manager in prometheus text format
+
+use std::collections::HashMap;
+use std::io::Read;
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+
+use log::{debug, error, info};
+use tiny_http::{Header, Method, Request, Response, Server};
+
+use crate::plugin::manager::PluginManager;
+use crate::plugin::PluginError;
+
+// request bodies up to 1 MiB by default
+pub const DEFAULT_MAX_BODY_SIZE: usize = 1024 * 1024;
+
+#[derive(Debug, Clone)]
+pub struct ServerOptions {
+  threads: usize,
+  max_body_size: usize,
+  tenant_tokens: HashMap<String, String>,
+}
+
+impl Default for ServerOptions {
+  fn default() -> Self {
+    Self {
+      threads: 1,
+      max_body_size: DEFAULT_MAX_BODY_SIZE,
+      tenant_tokens: HashMap::new(),
+    }
+  }
+}
+
+impl ServerOptions {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  pub fn set_threads(&mut self, threads: usize) -> &mut Self {
+    self.threads = threads;
+    self
+  }
+
+  // larger payloads are answered with 413 without reading them
+  pub fn set_max_body_size(&mut self, max_body_size: usize) -> &mut Self {
+    self.max_body_size = max_body_size;
+    self
+  }
+
+  // IMPORTANT: the `x-tenant` header selects whose plugins and quota a request
+  // runs with, so it is rejected (403) unless the tenant has a token here and
+  // the request sends it as `authorization: Bearer <token>`
+  pub fn add_tenant_token(&mut self, tenant: &String, token: &String) -> &mut Self {
+    self.tenant_tokens.insert(tenant.clone(), token.clone());
+    self
+  }
+}
+
+pub struct PluginServer {
+  server: Arc<Server>,
+  workers: Vec<JoinHandle<()>>,
+}
+
+impl PluginServer {
+  // listens on `addr` (eg "0.0.0.0:8080") and handles requests on `threads` worker threads
+  // with the default `ServerOptions`, tenant requests are rejected
+  pub fn start(
+    manager: Arc<PluginManager>,
+    addr: &String,
+    threads: usize,
+  ) -> Result<Self, PluginError> {
+    let mut options = ServerOptions::new();
+    options.set_threads(threads);
+    Self::start_with_options(manager, addr, &options)
+  }
+
+  pub fn start_with_options(
+    manager: Arc<PluginManager>,
+    addr: &String,
+    options: &ServerOptions,
+  ) -> Result<Self, PluginError> {
+    let server = match Server::http(addr.as_str()) {
+      Ok(server) => Arc::new(server),
+      Err(error) => {
+        error!("starting plugin server on {} failed", addr);
+        error!("{}", error);
+        return Err(PluginError::ServerFailed);
+      }
+    };
+    info!("plugin server listening on {}", addr);
+
+    let workers = (0..options.threads.max(1))
+      .map(|_| {
+        let server = server.clone();
+        let manager = manager.clone();
+        let options = options.clone();
+        thread::spawn(move || {
+          while let Ok(request) = server.recv() {
+            handle(&manager, &options, request);
+          }
+        })
+      })
+      .collect();
+    Ok(Self { server, workers })
+  }
+
+  pub fn stop(self) {
+    for _ in self.workers.iter() {
+      self.server.unblock();
+    }
+    for worker in self.workers {
+      let _ = worker.join();
+    }
+  }
+}
+
+fn handle(manager: &PluginManager, options: &ServerOptions, mut request: Request) {
+  let (path, query) = match request.url().split_once('?') {
+    Some((path, query)) => (String::from(path), String::from(query)),
+    None => (String::from(request.url()), String::new()),
+  };
+  debug!("{} {}", request.method(), path);
+  let segments: Vec<&str> = path.trim_matches('/').split('/').collect();
+
+  let response = match (request.method(), segments.as_slice()) {
+    (Method::Get, ["health"]) => Response::from_string(manager.names().join("\n")),
+    (Method::Get, ["metrics"]) => Response::from_string(prometheus(manager)),
+    (Method::Post, ["plugins", name, "execute"]) => execute(
+      manager,
+      options,
+      &mut request,
+      &percent_decode(name),
+      &query,
+    ),
+    _ => Response::from_string("not found").with_status_code(404),
+  };
+
+  if let Err(error) = request.respond(response) {
+    error!("sending response failed");
+    error!("{}", error);
+  }
+}
+
+type HttpResponse = Response<std::io::Cursor<Vec<u8>>>;
+
+fn execute(
+  manager: &PluginManager,
+  options: &ServerOptions,
+  request: &mut Request,
+  name: &String,
+  query: &String,
+) -> HttpResponse {
+  let key = header(request, "x-key").or_else(|| query_param(query, "key"));
+  let caller = header(request, "x-caller");
+  let tenant = header(request, "x-tenant");
+  if let Some(tenant) = &tenant {
+    let authorization = header(request, "authorization");
+    if !tenant_authorized(options, tenant, authorization.as_deref()) {
+      return Response::from_string("tenant not authorized").with_status_code(403);
+    }
+  }
+  // unknown names are answered before the body is read
+  if !is_registered(manager, tenant.as_ref(), name) {
+    return Response::from_string("plugin not found").with_status_code(404);
+  }
+  let key = match key {
+    Some(key) => key,
+    None => return Response::from_string("missing key").with_status_code(400),
+  };
+  let payload = match read_body(request, options.max_body_size) {
+    Ok(payload) => payload,
+    Err(response) => return response,
+  };
+
+  let result = match (&tenant, &caller) {
+    (Some(tenant), _) => manager.execute_for_tenant(tenant, name, &key, &payload),
+    (None, Some(caller)) => manager.execute_as(caller, name, &key, &payload),
+    (None, None) => manager.execute(name, &key, &payload),
+  };
+  match result {
+    Ok(result) => Response::from_string(result),
+    Err(error) => error_response(&error),
+  }
+}
+
+fn is_registered(manager: &PluginManager, tenant: Option<&String>, name: &String) -> bool {
+  match tenant {
+    Some(tenant) => match manager.tenant(tenant) {
+      Some(namespace) => namespace.plugins().active_version(name).is_some(),
+      None => false,
+    },
+    None => manager.active_version(name).is_some(),
+  }
+}
+
+fn read_body(request: &mut Request, limit: usize) -> Result<String, HttpResponse> {
+  let length = request.body_length();
+  read_limited(request.as_reader(), length, limit)
+}
+
+// reads at most `limit` bytes, the announced length is checked first so large
+// bodies are not read at all
+fn read_limited(
+  reader: impl Read,
+  length: Option<usize>,
+  limit: usize,
+) -> Result<String, HttpResponse> {
+  let too_large = || Response::from_string("payload too large").with_status_code(413);
+  if matches!(length, Some(length) if length > limit) {
+    return Err(too_large());
+  }
+  let mut body = vec![];
+  let read = reader.take(limit as u64 + 1).read_to_end(&mut body);
+  if let Err(error) = read {
+    error!("reading request body failed");
+    error!("{}", error);
+    return Err(Response::from_string("reading body failed").with_status_code(400));
+  }
+  if body.len() > limit {
+    return Err(too_large());
+  }
+  String::from_utf8(body)
+    .map_err(|_| Response::from_string("payload is not utf-8").with_status_code(400))
+}
+
+// `authorization` is the value of the header, eg `Bearer <token>`
+fn tenant_authorized(
+  options: &ServerOptions,
+  tenant: &String,
+  authorization: Option<&str>,
+) -> bool {
+  let expected = match options.tenant_tokens.get(tenant) {
+    Some(token) => token,
+    None => return false,
+  };
+  let token = match authorization.and_then(|value| value.strip_prefix("Bearer ")) {
+    Some(token) => token,
+    None => return false,
+  };
+  // compares all bytes, the time does not tell how much of the token matched
+  token.len() == expected.len()
+    && token
+      .bytes()
+      .zip(expected.bytes())
+      .fold(0, |diff, (a, b)| diff | (a ^ b))
+      == 0
+}
+
+fn error_response(error: &PluginError) -> HttpResponse {
+  let body = format!("{:?}", error);
+  match error {
+    PluginError::PluginNotFound | PluginError::VersionNotFound | PluginError::TenantNotFound => {
+      Response::from_string(body).with_status_code(404)
+    }
+    PluginError::PayloadTooLarge { .. } => Response::from_string(body).with_status_code(413),
+    PluginError::RateLimited { retry_after } => {
+      let seconds = retry_after.as_secs_f64().ceil().to_string();
+      let response = Response::from_string(body).with_status_code(429);
+      match Header::from_bytes("Retry-After", seconds) {
+        Ok(header) => response.with_header(header),
+        Err(_) => response,
+      }
+    }
+    PluginError::TenantQuotaExceeded => Response::from_string(body).with_status_code(429),
+    PluginError::PluginPaused => Response::from_string(body).with_status_code(503),
+    PluginError::GuestError(_) => Response::from_string(body).with_status_code(422),
+    _ => Response::from_string(body).with_status_code(500),
+  }
+}
+
+fn prometheus(manager: &PluginManager) -> String {
+  let mut metrics = manager.metrics().snapshot();
+  // counters of tenant plugins are prefixed with the tenant
+  for tenant in manager.tenants() {
+    if let Some(namespace) = manager.tenant(&tenant) {
+      let prefix = format!("tenant.{}.", tenant);
+      metrics.extend(
+        namespace
+          .metrics()
+          .snapshot()
+          .into_iter()
+          .map(|(name, value)| match name.starts_with(&prefix) {
+            true => (name, value),
+            false => (format!("{}{}", prefix, name), value),
+          }),
+      );
+    }
+  }
+  let mut out = String::new();
+  for (name, value) in metrics {
+    let name: String = name
+      .chars()
+      .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+      .collect();
+    out.push_str(&format!("# TYPE {} counter\n{} {}\n", name, name, value));
+  }
+  out
+}
+
+fn header(request: &Request, name: &str) -> Option<String> {
+  request
+    .headers()
+    .iter()
+    .find(|header| header.field.as_str().as_str().eq_ignore_ascii_case(name))
+    .map(|header| String::from(header.value.as_str()))
+}
+
+fn query_param(query: &String, name: &str) -> Option<String> {
+  query
+    .split('&')
+    .filter_map(|pair| pair.split_once('='))
+    .find(|(key, _)| *key == name)
+    .map(|(_, value)| percent_decode(&value.replace('+', " ")))
+}
+
+// only `%XX`, a `+` is a plus sign in paths and only means space in queries
+fn percent_decode(value: &str) -> String {
+  let bytes = value.as_bytes();
+  let mut out = vec![];
+  let mut index = 0;
+  while index < bytes.len() {
+    match bytes[index] {
+      b'%' if index + 2 < bytes.len() => {
+        let (high, low) = (bytes[index + 1], bytes[index + 2]);
+        match (hex_value(high), hex_value(low)) {
+          (Some(high), Some(low)) => {
+            out.push((high << 4) | low);
+            index += 3;
+            continue;
+          }
+          _ => out.push(b'%'),
+        }
+      }
+      byte => out.push(byte),
+    }
+    index += 1;
+  }
+  String::from_utf8_lossy(&out).into_owned()
+}
+
+fn hex_value(digit: u8) -> Option<u8> {
+  match digit {
+    b'0'..=b'9' => Some(digit - b'0'),
+    b'a'..=b'f' => Some(digit - b'a' + 10),
+    b'A'..=b'F' => Some(digit - b'A' + 10),
+    _ => None,
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::io;
+
+  use super::*;
+
+  // fails the test if the body is read at all
+  struct Unread;
+
+  impl Read for Unread {
+    fn read(&mut self, _: &mut [u8]) -> io::Result<usize> {
+      panic!("body was read");
+    }
+  }
+
+  fn status(result: Result<String, HttpResponse>) -> u16 {
+    match result {
+      Ok(_) => 200,
+      Err(response) => response.status_code().0,
+    }
+  }
+
+  #[test]
+  fn percent_decode_escapes() {
+    assert_eq!(percent_decode("a%20b"), "a b");
+    assert_eq!(percent_decode("%2Fkey%2f"), "/key/");
+    assert_eq!(percent_decode("%C3%A4"), "ä");
+    // a plus sign is only a space in queries
+    assert_eq!(percent_decode("a+b"), "a+b");
+    assert_eq!(
+      query_param(&String::from("x=1&key=a+b%2B"), "key"),
+      Some(String::from("a b+"))
+    );
+  }
+
+  #[test]
+  fn percent_decode_keeps_malformed_escapes() {
+    assert_eq!(percent_decode("%"), "%");
+    assert_eq!(percent_decode("100%"), "100%");
+    assert_eq!(percent_decode("%4"), "%4");
+    assert_eq!(percent_decode("%zz"), "%zz");
+    assert_eq!(percent_decode("%+1"), "%+1");
+    assert_eq!(percent_decode("%-1"), "%-1");
+    assert_eq!(percent_decode("%%41"), "%A");
+    // invalid utf-8 is replaced, not passed on
+    assert_eq!(percent_decode("%ff"), "\u{fffd}");
+  }
+
+  #[test]
+  fn body_within_limit() {
+    assert_eq!(
+      read_limited(&b"{}"[..], Some(2), 2).ok(),
+      Some(String::from("{}"))
+    );
+    assert_eq!(
+      read_limited(&b"{}"[..], None, 2).ok(),
+      Some(String::from("{}"))
+    );
+    assert_eq!(read_limited(&b""[..], Some(0), 0).ok(), Some(String::new()));
+  }
+
+  #[test]
+  fn body_over_limit() {
+    // announced too large, rejected without reading
+    assert_eq!(status(read_limited(Unread, Some(3), 2)), 413);
+    // chunked or lying about its length
+    assert_eq!(status(read_limited(&b"{ }"[..], None, 2)), 413);
+    assert_eq!(status(read_limited(&b"{ }"[..], Some(1), 2)), 413);
+  }
+
+  #[test]
+  fn body_not_utf8() {
+    assert_eq!(status(read_limited(&[0xff, 0xfe][..], Some(2), 10)), 400);
+  }
+
+  #[test]
+  fn tenant_needs_its_token() {
+    let acme = String::from("acme");
+    let other = String::from("other");
+    let mut options = ServerOptions::new();
+    options
+      .add_tenant_token(&acme, &String::from("secret"))
+      .add_tenant_token(&other, &String::from("other-secret"));
+
+    assert!(tenant_authorized(&options, &acme, Some("Bearer secret")));
+    assert!(!tenant_authorized(&options, &acme, None));
+    assert!(!tenant_authorized(&options, &acme, Some("secret")));
+    assert!(!tenant_authorized(&options, &acme, Some("Basic secret")));
+    assert!(!tenant_authorized(&options, &acme, Some("Bearer secre")));
+    assert!(!tenant_authorized(&options, &acme, Some("Bearer secret2")));
+    assert!(!tenant_authorized(
+      &options,
+      &acme,
+      Some("Bearer other-secret")
+    ));
+    assert!(!tenant_authorized(&options, &acme, Some("Bearer ")));
+  }
+
+  #[test]
+  fn tenant_without_token_is_rejected() {
+    let options = ServerOptions::new();
+    let acme = String::from("acme");
+    assert!(!tenant_authorized(&options, &acme, Some("Bearer ")));
+    assert!(!tenant_authorized(&options, &acme, Some("Bearer secret")));
+  }
+
+  #[test]
+  fn unknown_plugins_are_not_registered() {
+    let manager = PluginManager::new();
+    let name = String::from("unknown");
+    let tenant = String::from("acme");
+    assert!(!is_registered(&manager, None, &name));
+    assert!(!is_registered(&manager, Some(&tenant), &name));
+    manager.add_tenant(&tenant, crate::plugin::tenant::TenantQuota::new());
+    assert!(!is_registered(&manager, Some(&tenant), &name));
+  }
+}
@@ -0,0 +1,88 @@
+use std::fs;
+use std::thread;
+use std::time::Duration;
+
+use tempfile::TempDir;
+use wasmertest::plugin::actor::{ExecuteMsg, PluginActor};
+use wasmertest::plugin::artifact::ArtifactHeader;
+use wasmertest::plugin::compiler::{compile_to_file, CompileOptions};
+use wasmertest::plugin::string_abi::StringAbi;
+use wasmertest::plugin::{PluginError, PluginOptions};
+
+// shutdown and restart of the mailbox of a plugin actor
+
+// returns the key, keys starting with "l" loop forever
+const GUEST: &str = r#"
+(module
+  (memory (export "memory") 1)
+  (global $next (mut i32) (i32.const 1024))
+  (func (export "__new") (param $size i32) (param $id i32) (result i32)
+    (local $ptr i32)
+    (local.set $ptr (i32.add (global.get $next) (i32.const 4)))
+    (i32.store (i32.sub (local.get $ptr) (i32.const 4)) (local.get $size))
+    (global.set $next
+      (i32.and
+        (i32.add (i32.add (local.get $ptr) (local.get $size)) (i32.const 7))
+        (i32.const -8)))
+    (local.get $ptr))
+  (func (export "_start"))
+  (func (export "init") (param i32))
+  (func (export "transform") (param $key i32) (param i32) (result i32)
+    (if (i32.eq (i32.load16_u (local.get $key)) (i32.const 108))
+      (then (loop $forever (br $forever))))
+    (local.get $key)))
+"#;
+
+// the temp dir has to outlive the actor
+fn spawn() -> (TempDir, PluginActor) {
+  let dir = tempfile::tempdir().unwrap();
+  let source = dir.path().join("guest.wat").to_string_lossy().to_string();
+  fs::write(&source, GUEST).unwrap();
+  let artifact = ArtifactHeader::host().artifact_file(dir.path(), "plugin");
+  compile_to_file(&source, &artifact, &CompileOptions::new()).unwrap();
+
+  let mut options = PluginOptions::new(
+    &String::from("actor_test"),
+    &artifact,
+    &String::from("transform"),
+  );
+  options
+    .set_string_abi(StringAbi::Utf16String)
+    .disable_garbage_collector();
+  let actor = PluginActor::spawn(options, &String::from("config")).unwrap();
+  (dir, actor)
+}
+
+#[test]
+fn drains_queued_calls_on_drop() {
+  let (_dir, actor) = spawn();
+  let payload = String::from("{}");
+  let results: Vec<_> = (0..10)
+    .map(|x| actor.send(ExecuteMsg::new(&format!("/some/test/{}", x), &payload)))
+    .collect();
+  drop(actor);
+
+  // the worker finishes the queue before it stops
+  for (x, result) in results.into_iter().enumerate() {
+    assert_eq!(result.recv().unwrap(), Ok(format!("/some/test/{}", x)));
+  }
+}
+
+#[test]
+fn restart_answers_calls_queued_for_the_wedged_thread() {
+  let (_dir, actor) = spawn();
+  let payload = String::from("{}");
+  let _wedged = actor.send(ExecuteMsg::new(&String::from("loop"), &payload));
+  while !actor.is_wedged(Duration::from_millis(10)) {
+    thread::sleep(Duration::from_millis(5));
+  }
+  let queued = actor.send(ExecuteMsg::new(&String::from("/some/test/1"), &payload));
+  assert_eq!(actor.queued(), 2);
+
+  actor.restart().unwrap();
+  assert_eq!(queued.recv().unwrap(), Err(PluginError::ActorStopped));
+  // the new thread serves with a fresh instance
+  let key = String::from("/some/test/2");
+  assert_eq!(actor.execute(&key, &payload), Ok(key));
+  assert_eq!(actor.queued(), 0);
+}
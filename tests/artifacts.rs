@@ -0,0 +1,147 @@
+use std::fs;
+use std::path::Path;
+
+use wasmertest::plugin::artifact::{
+  artifact_extension, content_hash, normalize_path, ArtifactHeader, NEUTRAL_ARTIFACT_EXTENSION,
+};
+use wasmertest::plugin::compiler::{compile_to_file, CompileOptions};
+use wasmertest::plugin::manager::{DiscoveryOptions, PluginManager};
+use wasmertest::plugin::PluginOptions;
+
+// artifact naming and the compile + load flow with the file names of the
+// platform the tests run on
+
+fn tests(i: i32) -> i32 {
+  i + 1
+}
+
+fn tests2(i: i64) -> i64 {
+  i + 2
+}
+
+fn configure(options: &mut PluginOptions) {
+  options.add_host_function("tests".into(), tests);
+  options.add_host_function("tests2".into(), tests2);
+}
+
+#[test]
+fn extension_by_target() {
+  assert_eq!(artifact_extension("x86_64-unknown-linux-gnu"), "so");
+  assert_eq!(artifact_extension("aarch64-unknown-linux-musl"), "so");
+  assert_eq!(artifact_extension("aarch64-apple-darwin"), "dylib");
+  assert_eq!(artifact_extension("x86_64-apple-darwin"), "dylib");
+  assert_eq!(artifact_extension("x86_64-pc-windows-msvc"), "dll");
+  assert_eq!(artifact_extension("x86_64-pc-windows-gnu"), "dll");
+}
+
+#[test]
+fn host_artifact_file() {
+  let header = ArtifactHeader::host();
+  let expected = if cfg!(windows) {
+    "dll"
+  } else if cfg!(target_os = "macos") {
+    "dylib"
+  } else {
+    "so"
+  };
+  assert_eq!(header.extension(), expected);
+
+  let file = header.artifact_file(Path::new("plugins"), "enrich");
+  assert_eq!(
+    Path::new(&file),
+    Path::new("plugins").join(format!("enrich.{}", expected))
+  );
+}
+
+#[test]
+fn normalized_paths() {
+  assert_eq!(
+    normalize_path(Path::new(r"\\?\C:\plugins\enrich.dll")),
+    r"C:\plugins\enrich.dll"
+  );
+  // unc paths keep the prefix, without it they would no longer be absolute
+  assert_eq!(
+    normalize_path(Path::new(r"\\?\UNC\server\plugins\enrich.dll")),
+    r"\\?\UNC\server\plugins\enrich.dll"
+  );
+  if cfg!(windows) {
+    assert_eq!(
+      normalize_path(Path::new("plugins/enrich.dll")),
+      r"plugins\enrich.dll"
+    );
+  } else {
+    assert_eq!(
+      normalize_path(Path::new("plugins/enrich.so")),
+      "plugins/enrich.so"
+    );
+  }
+}
+
+#[test]
+fn sha256_content_hash() {
+  assert_eq!(
+    content_hash(b"abc"),
+    "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+  );
+  assert_ne!(content_hash(b"abc"), content_hash(b"abd"));
+}
+
+#[test]
+fn header_next_to_platform_artifact() {
+  let dir = tempfile::tempdir().unwrap();
+  for (triple, name) in [
+    ("aarch64-apple-darwin", "macos"),
+    ("x86_64-pc-windows-msvc", "windows"),
+  ] {
+    let mut header = ArtifactHeader::host();
+    header.target_triple = String::from(triple);
+    let file = header.artifact_file(dir.path(), name);
+    header.write_to_file(&file).unwrap();
+    assert_eq!(ArtifactHeader::read_from_file(&file).unwrap(), header);
+    assert!(ArtifactHeader::header_file(&file).ends_with(&format!(
+      "{}.{}.header",
+      name,
+      artifact_extension(triple)
+    )));
+  }
+}
+
+#[test]
+fn compile_and_load_with_host_names() {
+  let dir = tempfile::tempdir().unwrap();
+  let wasm_file = String::from("./assemblytest/build/optimized.wat");
+  let header = ArtifactHeader::host();
+
+  let native = header.artifact_file(dir.path(), "native");
+  compile_to_file(&wasm_file, &native, &CompileOptions::new()).unwrap();
+  let neutral = normalize_path(
+    &dir
+      .path()
+      .join(format!("neutral.{}", NEUTRAL_ARTIFACT_EXTENSION)),
+  );
+  compile_to_file(&wasm_file, &neutral, &CompileOptions::new()).unwrap();
+  fs::write(dir.path().join("notes.txt"), "not a plugin").unwrap();
+
+  let manager = PluginManager::new();
+  let mut discovery = DiscoveryOptions::new();
+  discovery.set_configure(configure);
+  let report = manager.load_dir(dir.path(), &discovery);
+  assert!(report.failed.is_empty(), "{:?}", report.failed);
+  let mut loaded = report.loaded.clone();
+  loaded.sort();
+  assert_eq!(
+    loaded,
+    vec![String::from("native"), String::from("neutral")]
+  );
+
+  let key = String::from("/some/test/1");
+  let payload = String::from("{\"temperature\": 1 }");
+  for name in loaded {
+    let plugin = manager.get(&name).unwrap();
+    plugin.init(&String::from("config")).unwrap();
+    assert_eq!(
+      manager.execute(&name, &key, &payload).unwrap(),
+      format!("transform: {} for payload {}", key, payload)
+    );
+  }
+}
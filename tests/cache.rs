@@ -0,0 +1,89 @@
+use std::cell::Cell;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use wasmertest::plugin::cache::CacheMiddleware;
+use wasmertest::plugin::middleware::{CallContext, MiddlewareChain};
+use wasmertest::plugin::PluginError;
+
+// the cache in front of an endpoint which counts the calls reaching it
+
+fn chain(cache: &Arc<CacheMiddleware>) -> MiddlewareChain {
+  let mut chain = MiddlewareChain::new();
+  chain.add(cache.clone());
+  chain
+}
+
+fn context(key: &str) -> CallContext {
+  CallContext::new(
+    &String::from("cache_test"),
+    &String::from(key),
+    &String::from("{}"),
+  )
+}
+
+// runs the call and returns whether it reached the endpoint
+fn reached(chain: &MiddlewareChain, key: &str) -> bool {
+  let calls = Cell::new(0);
+  let result = chain
+    .run(&context(key), &|ctx| {
+      calls.set(calls.get() + 1);
+      Ok(format!("result of {}", ctx.key))
+    })
+    .unwrap();
+  assert_eq!(result, format!("result of {}", key));
+  calls.get() == 1
+}
+
+#[test]
+fn evicts_least_recently_used_over_capacity() {
+  let cache = Arc::new(CacheMiddleware::new(2, None));
+  let chain = chain(&cache);
+  assert!(reached(&chain, "a"));
+  assert!(reached(&chain, "b"));
+  // the hit on `a` refreshes it, `b` is the least recently used one now
+  assert!(!reached(&chain, "a"));
+  assert!(reached(&chain, "c"));
+
+  assert!(!reached(&chain, "a"));
+  assert!(!reached(&chain, "c"));
+  assert!(reached(&chain, "b"));
+  assert_eq!(cache.hits(), 3);
+  assert_eq!(cache.misses(), 4);
+}
+
+#[test]
+fn expires_after_ttl() {
+  let cache = Arc::new(CacheMiddleware::new(10, Some(Duration::from_millis(20))));
+  let chain = chain(&cache);
+  assert!(reached(&chain, "a"));
+  assert!(!reached(&chain, "a"));
+  thread::sleep(Duration::from_millis(30));
+  assert!(reached(&chain, "a"));
+  assert!(!reached(&chain, "a"));
+}
+
+#[test]
+fn does_not_cache_errors() {
+  let cache = Arc::new(CacheMiddleware::new(10, None));
+  let chain = chain(&cache);
+  let calls = Cell::new(0);
+  for _ in 0..2 {
+    let result = chain.run(&context("a"), &|_| {
+      calls.set(calls.get() + 1);
+      Err(PluginError::RuntimeError)
+    });
+    assert_eq!(result, Err(PluginError::RuntimeError));
+  }
+  assert_eq!(calls.get(), 2);
+  assert_eq!(cache.hits(), 0);
+}
+
+#[test]
+fn zero_capacity_does_not_cache() {
+  let cache = Arc::new(CacheMiddleware::new(0, None));
+  let chain = chain(&cache);
+  assert!(reached(&chain, "a"));
+  assert!(reached(&chain, "a"));
+}
@@ -0,0 +1,119 @@
+use tempfile::TempDir;
+use wasmer::Extern;
+use wasmertest::plugin::artifact::ArtifactHeader;
+use wasmertest::plugin::checkpoint::{artifact_fingerprint, Checkpoint};
+use wasmertest::plugin::compiler::{compile_to_file, CompileOptions};
+use wasmertest::plugin::default::DefaultPlugin;
+use wasmertest::plugin::globals::STATE_GLOBAL_PREFIX;
+use wasmertest::plugin::{Plugin, PluginError, PluginOptions};
+
+// checkpoints of the checked-in AssemblyScript guest, its allocator and gc
+// state lives in globals the guest does not export
+
+fn tests(i: i32) -> i32 {
+  i + 1
+}
+
+fn tests2(i: i64) -> i64 {
+  i + 2
+}
+
+fn options(dir: &TempDir) -> PluginOptions {
+  let artifact = ArtifactHeader::host().artifact_file(dir.path(), "plugin");
+  let mut options = PluginOptions::new(
+    &String::from("checkpoint_test"),
+    &artifact,
+    &String::from("transform"),
+  );
+  options.add_host_function("tests".into(), tests);
+  options.add_host_function("tests2".into(), tests2);
+  options.set_checkpoint_file(&dir.path().join("plugin.checkpoint"));
+  options
+}
+
+fn compiled_with(compile_options: &CompileOptions) -> TempDir {
+  let dir = tempfile::tempdir().unwrap();
+  let artifact = ArtifactHeader::host().artifact_file(dir.path(), "plugin");
+  compile_to_file(
+    &String::from("./assemblytest/build/optimized.wat"),
+    &artifact,
+    compile_options,
+  )
+  .unwrap();
+  dir
+}
+
+fn compiled() -> TempDir {
+  compiled_with(CompileOptions::new().enable_state_globals())
+}
+
+fn assert_allocates(plugin: &DefaultPlugin) {
+  for x in 0..200 {
+    let key = format!("/some/test/{}", x);
+    let payload = format!("{{\"values\": \"{}\" }}", "x".repeat(x * 13));
+    assert_eq!(
+      plugin.execute(&key, &payload).unwrap(),
+      format!("transform: {} for payload {}", key, payload)
+    );
+  }
+}
+
+#[test]
+fn runtime_globals_are_exported() {
+  let dir = compiled();
+  let plugin = DefaultPlugin::create(options(&dir)).unwrap();
+  let state_globals = plugin
+    .get_instance()
+    .exports
+    .iter()
+    .filter(|(name, export)| {
+      name.starts_with(STATE_GLOBAL_PREFIX) && matches!(export, Extern::Global(_))
+    })
+    .count();
+  // tlsf root, the tcms spaces, white and total and `~started`
+  assert_eq!(state_globals, 7);
+}
+
+#[test]
+fn roundtrip_allocates_after_restore() {
+  let dir = compiled();
+  let config = String::from("config");
+
+  let first = DefaultPlugin::create(options(&dir)).unwrap();
+  first.init(&config).unwrap();
+  assert_allocates(&first);
+
+  let checkpoint = Checkpoint::read_from_file(&dir.path().join("plugin.checkpoint")).unwrap();
+  assert!(checkpoint
+    .globals
+    .iter()
+    .any(|(name, _)| name.starts_with(STATE_GLOBAL_PREFIX)));
+
+  // restored instead of initialized, the heap has to stay usable
+  let restored = DefaultPlugin::create(options(&dir)).unwrap();
+  restored.init(&config).unwrap();
+  assert_allocates(&restored);
+  assert_allocates(&restored);
+}
+
+#[test]
+fn hidden_globals_are_rejected() {
+  // without `enable_state_globals` the artifact keeps the globals hidden
+  let dir = compiled_with(&CompileOptions::new());
+  let plugin = DefaultPlugin::create(options(&dir)).unwrap();
+  assert_eq!(
+    plugin.init(&String::from("config")),
+    Err(PluginError::InvalidCheckpoint)
+  );
+  assert!(!dir.path().join("plugin.checkpoint").exists());
+}
+
+#[test]
+fn missing_artifact_has_no_fingerprint() {
+  let dir = tempfile::tempdir().unwrap();
+  let artifact = ArtifactHeader::host().artifact_file(dir.path(), "missing");
+  assert_eq!(
+    artifact_fingerprint(&artifact),
+    Err(PluginError::InvalidCheckpoint)
+  );
+}
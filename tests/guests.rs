@@ -0,0 +1,169 @@
+use std::path::Path;
+use std::process::Command;
+
+use tempfile::TempDir;
+use wasmertest::plugin::artifact::ArtifactHeader;
+use wasmertest::plugin::compiler::{compile_to_file, CompileOptions};
+use wasmertest::plugin::default::DefaultPlugin;
+use wasmertest::plugin::profile::AbiProfile;
+use wasmertest::plugin::{Plugin, PluginOptions};
+
+// round trips through create, init and execute of the guests in `examples/guests`
+// guests whose toolchain is not installed are skipped, the checked-in
+// AssemblyScript build of `assemblytest` always runs
+
+fn installed(program: &str, args: &[&str]) -> bool {
+  match Command::new(program).args(args).output() {
+    Ok(output) => output.status.success(),
+    Err(_) => false,
+  }
+}
+
+fn build(guest: &str) -> String {
+  let status = Command::new(format!("./examples/guests/{}/build.sh", guest))
+    .status()
+    .unwrap();
+  assert!(status.success(), "building the {} guest failed", guest);
+  format!("./examples/guests/{}/build/plugin.wasm", guest)
+}
+
+// the temp dir has to outlive the plugin
+fn compile(wasm_file: &String) -> (TempDir, String) {
+  let dir = tempfile::tempdir().unwrap();
+  let artifact = ArtifactHeader::host().artifact_file(dir.path(), "plugin");
+  compile_to_file(wasm_file, &artifact, &CompileOptions::new()).unwrap();
+  (dir, artifact)
+}
+
+fn assert_roundtrip(plugin: &DefaultPlugin) {
+  plugin.init(&String::from("config")).unwrap();
+  for x in 0..100 {
+    let key = format!("/some/test/{}", x);
+    let payload = format!("{{\"temperature\": {} }}", x);
+    let result = plugin.execute(&key, &payload).unwrap();
+    assert_eq!(
+      result,
+      format!("transform: {} for payload {}", key, payload)
+    );
+  }
+  // multi byte characters have to survive both directions
+  let key = String::from("/ключ/🔑");
+  let payload = String::from("{\"name\": \"Größe\"}");
+  assert_eq!(
+    plugin.execute(&key, &payload).unwrap(),
+    format!("transform: {} for payload {}", key, payload)
+  );
+}
+
+fn create(name: &str, artifact: &String, profile: &AbiProfile) -> DefaultPlugin {
+  let mut options = PluginOptions::new(&String::from(name), artifact, &String::from("transform"));
+  options.apply_abi_profile(profile);
+  DefaultPlugin::create(options).unwrap()
+}
+
+#[test]
+fn assemblyscript_checked_in_roundtrip() {
+  fn tests(i: i32) -> i32 {
+    i + 1
+  }
+
+  fn tests2(i: i64) -> i64 {
+    i + 2
+  }
+
+  let (_dir, artifact) = compile(&String::from("./assemblytest/build/optimized.wat"));
+  let mut options = PluginOptions::new(
+    &String::from("assemblyscript_checked_in_test"),
+    &artifact,
+    &String::from("transform"),
+  );
+  options.add_host_function("tests".into(), tests);
+  options.add_host_function("tests2".into(), tests2);
+  let plugin = DefaultPlugin::create(options).unwrap();
+  assert_roundtrip(&plugin);
+}
+
+#[test]
+fn trace_id_key_prefix() {
+  fn tests(i: i32) -> i32 {
+    i + 1
+  }
+
+  fn tests2(i: i64) -> i64 {
+    i + 2
+  }
+
+  let (_dir, artifact) = compile(&String::from("./assemblytest/build/optimized.wat"));
+  let mut options = PluginOptions::new(
+    &String::from("trace_id_key_prefix_test"),
+    &artifact,
+    &String::from("transform"),
+  );
+  options.add_host_function("tests".into(), tests);
+  options.add_host_function("tests2".into(), tests2);
+  options.set_trace_id_key_prefix(true);
+  let plugin = DefaultPlugin::create(options).unwrap();
+  plugin.init(&String::from("config")).unwrap();
+
+  // the checked-in guest echoes the key it got
+  let key = String::from("/some/test/1");
+  let payload = String::from("{}");
+  assert_eq!(
+    plugin
+      .execute_traced(&String::from("trace-42"), &key, &payload)
+      .unwrap(),
+    format!("transform: trace-42|{} for payload {}", key, payload)
+  );
+}
+
+#[test]
+fn assemblyscript_guest_roundtrip() {
+  let guest_dir = Path::new("./examples/guests/assemblyscript");
+  if !guest_dir.join("node_modules/.bin/asc").exists() {
+    eprintln!(
+      "assemblyscript not installed (npm install in examples/guests/assemblyscript) - skipping"
+    );
+    return;
+  }
+
+  let (_dir, artifact) = compile(&build("assemblyscript"));
+  let plugin = create(
+    "assemblyscript_test",
+    &artifact,
+    &AbiProfile::assemblyscript(),
+  );
+  assert_roundtrip(&plugin);
+}
+
+#[test]
+fn rust_guest_roundtrip() {
+  let targets = Command::new("rustup")
+    .args(["target", "list", "--installed"])
+    .output();
+  let wasm_target = match targets {
+    Ok(output) => String::from_utf8_lossy(&output.stdout)
+      .lines()
+      .any(|target| target.trim() == "wasm32-wasi"),
+    Err(_) => false,
+  };
+  if !wasm_target {
+    eprintln!("wasm32-wasi target not installed - skipping");
+    return;
+  }
+
+  let (_dir, artifact) = compile(&build("rust"));
+  let plugin = create("rust_test", &artifact, &AbiProfile::rust());
+  assert_roundtrip(&plugin);
+}
+
+#[test]
+fn tinygo_guest_roundtrip() {
+  if !installed("tinygo", &["version"]) {
+    eprintln!("tinygo not installed - skipping");
+    return;
+  }
+
+  let (_dir, artifact) = compile(&build("tinygo"));
+  let plugin = create("tinygo_test", &artifact, &AbiProfile::tinygo());
+  assert_roundtrip(&plugin);
+}
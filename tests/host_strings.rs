@@ -0,0 +1,81 @@
+use std::fs;
+
+use tempfile::TempDir;
+use wasmertest::plugin::artifact::ArtifactHeader;
+use wasmertest::plugin::compiler::{compile_to_file, CompileOptions};
+use wasmertest::plugin::default::DefaultPlugin;
+use wasmertest::plugin::string_abi::StringAbi;
+use wasmertest::plugin::{Plugin, PluginOptions};
+
+// strings written by host functions, here `get_trace_id`, have to follow the
+// string abi of the plugin. the guest returns the trace id as its result, so
+// the host reads it back with the same abi
+
+// bump allocator which keeps the block size in front of the block like the
+// AssemblyScript runtime, `malloc` and `__new` share it
+const GUEST: &str = r#"
+(module
+  (import "custom" "get_trace_id" (func $trace_id (result i32)))
+  (memory (export "memory") 1)
+  (global $next (mut i32) (i32.const 1024))
+  (func $alloc (param $size i32) (result i32)
+    (local $ptr i32)
+    (local.set $ptr (i32.add (global.get $next) (i32.const 4)))
+    (i32.store (i32.sub (local.get $ptr) (i32.const 4)) (local.get $size))
+    (global.set $next
+      (i32.and
+        (i32.add (i32.add (local.get $ptr) (local.get $size)) (i32.const 7))
+        (i32.const -8)))
+    (local.get $ptr))
+  (func (export "malloc") (param i32) (result i32) (call $alloc (local.get 0)))
+  (func (export "__new") (param i32 i32) (result i32) (call $alloc (local.get 0)))
+  (func (export "free") (param i32))
+  (func (export "transform") (param i32 i32) (result i32) (call $trace_id)))
+"#;
+
+// the temp dir has to outlive the plugin
+fn create(abi: StringAbi) -> (TempDir, DefaultPlugin) {
+  let dir = tempfile::tempdir().unwrap();
+  let source = dir.path().join("guest.wat").to_string_lossy().to_string();
+  fs::write(&source, GUEST).unwrap();
+  let artifact = ArtifactHeader::host().artifact_file(dir.path(), "plugin");
+  compile_to_file(&source, &artifact, &CompileOptions::new()).unwrap();
+
+  let mut options = PluginOptions::new(
+    &String::from("host_strings_test"),
+    &artifact,
+    &String::from("transform"),
+  );
+  options.set_string_abi(abi).disable_garbage_collector();
+  (dir, DefaultPlugin::create(options).unwrap())
+}
+
+fn assert_trace_id(abi: StringAbi) {
+  let (_dir, plugin) = create(abi);
+  let key = String::from("/some/test/1");
+  let payload = String::from("{}");
+  for trace_id in ["trace-42", "ключ-🔑"] {
+    let trace_id = String::from(trace_id);
+    assert_eq!(
+      plugin.execute_traced(&trace_id, &key, &payload).unwrap(),
+      trace_id,
+      "{:?}",
+      abi
+    );
+  }
+}
+
+#[test]
+fn trace_id_utf8_array_buffer() {
+  assert_trace_id(StringAbi::Utf8ArrayBuffer);
+}
+
+#[test]
+fn trace_id_utf16_string() {
+  assert_trace_id(StringAbi::Utf16String);
+}
+
+#[test]
+fn trace_id_utf8_length_prefixed() {
+  assert_trace_id(StringAbi::Utf8LengthPrefixed);
+}
@@ -0,0 +1,313 @@
+use std::fs;
+use std::path::Path;
+
+use semver::Version;
+use tempfile::TempDir;
+use wasmertest::plugin::artifact::ArtifactHeader;
+use wasmertest::plugin::compiler::{compile_to_file, CompileOptions};
+use wasmertest::plugin::manager::{DiscoveryOptions, PluginManager};
+use wasmertest::plugin::manifest::PluginManifest;
+use wasmertest::plugin::syscall::SyscallRule;
+use wasmertest::plugin::PluginError;
+
+// `load_dir` with and without sidecar manifests next to the compiled
+// AssemblyScript guest
+
+fn tests(i: i32) -> i32 {
+  i + 1
+}
+
+fn tests2(i: i64) -> i64 {
+  i + 2
+}
+
+// every name gets its own copy of the compiled guest and its header
+fn plugin_dir(names: &[&str]) -> TempDir {
+  let dir = tempfile::tempdir().unwrap();
+  let compiled = tempfile::tempdir().unwrap();
+  let artifact = ArtifactHeader::host().artifact_file(compiled.path(), "plugin");
+  compile_to_file(
+    &String::from("./assemblytest/build/optimized.wat"),
+    &artifact,
+    &CompileOptions::new(),
+  )
+  .unwrap();
+  for name in names {
+    let copy = ArtifactHeader::host().artifact_file(dir.path(), name);
+    fs::copy(&artifact, &copy).unwrap();
+    fs::copy(
+      ArtifactHeader::header_file(&artifact),
+      ArtifactHeader::header_file(&copy),
+    )
+    .unwrap();
+  }
+  dir
+}
+
+fn write_manifest(dir: &Path, name: &str, content: &str) {
+  fs::write(dir.join(format!("{}.toml", name)), content).unwrap();
+}
+
+fn discovery() -> DiscoveryOptions {
+  let mut discovery = DiscoveryOptions::new();
+  discovery.set_configure(|options| {
+    options.add_host_function("tests".into(), tests);
+    options.add_host_function("tests2".into(), tests2);
+  });
+  discovery
+}
+
+fn failed(dir: &Path, manager: &PluginManager) -> Vec<(String, PluginError)> {
+  let report = manager.load_dir(dir, &discovery());
+  report
+    .failed
+    .into_iter()
+    .map(|(file, error)| {
+      let name = file.file_stem().unwrap().to_string_lossy().to_string();
+      (name, error)
+    })
+    .collect()
+}
+
+#[test]
+fn loads_without_manifest() {
+  let dir = plugin_dir(&["enrich"]);
+  let manager = PluginManager::new();
+  let report = manager.load_dir(dir.path(), &discovery());
+  assert_eq!(report.loaded, vec![String::from("enrich")]);
+  assert!(report.failed.is_empty());
+
+  let plugin = manager.get(&String::from("enrich")).unwrap();
+  assert_eq!(plugin.metadata().name, None);
+  assert_eq!(plugin.metadata().version, None);
+  assert_eq!(
+    manager.versions(&String::from("enrich")),
+    vec![Version::new(0, 0, 0)]
+  );
+}
+
+#[test]
+fn applies_manifest() {
+  let dir = plugin_dir(&["enrich"]);
+  write_manifest(
+    dir.path(),
+    "enrich",
+    "name = \"enricher\" # renamed\n\
+     version = \"1.2.0\"\n\
+     \n\
+     [functions]\n\
+     execute = \"transform\"\n\
+     \n\
+     [host]\n\
+     required = [\"tests\", \"tests2\"]\n\
+     \n\
+     [env]\n\
+     LOG_LEVEL = \"debug\"\n\
+     \n\
+     [limits]\n\
+     memory_pages = 1_000\n",
+  );
+  let manager = PluginManager::new();
+  let report = manager.load_dir(dir.path(), &discovery());
+  assert_eq!(report.loaded, vec![String::from("enricher")]);
+  assert!(manager.get(&String::from("enrich")).is_none());
+
+  let plugin = manager.get(&String::from("enricher")).unwrap();
+  let metadata = plugin.metadata();
+  assert_eq!(metadata.version, Some(Version::new(1, 2, 0)));
+  assert_eq!(
+    metadata.required_host_functions,
+    vec![String::from("tests"), String::from("tests2")]
+  );
+  assert_eq!(
+    metadata.envs,
+    vec![(String::from("LOG_LEVEL"), String::from("debug"))]
+  );
+  assert_eq!(metadata.memory_limit_pages, Some(1000));
+}
+
+#[test]
+fn rejects_invalid_manifests() {
+  let dir = plugin_dir(&[
+    "no_value",
+    "bad_version",
+    "bad_type",
+    "bad_syscall",
+    "valid",
+  ]);
+  write_manifest(dir.path(), "no_value", "name\n");
+  write_manifest(dir.path(), "bad_version", "version = \"1.x\"\n");
+  write_manifest(
+    dir.path(),
+    "bad_type",
+    "[limits]\nmemory_pages = \"many\"\n",
+  );
+  write_manifest(
+    dir.path(),
+    "bad_syscall",
+    "[syscalls]\nfd_wirte = \"deny\"\n",
+  );
+
+  let manager = PluginManager::new();
+  let mut failed = failed(dir.path(), &manager);
+  failed.sort_by(|a, b| a.0.cmp(&b.0));
+  assert_eq!(
+    failed,
+    vec![
+      (String::from("bad_syscall"), PluginError::InvalidManifest),
+      (String::from("bad_type"), PluginError::InvalidManifest),
+      (String::from("bad_version"), PluginError::InvalidManifest),
+      (String::from("no_value"), PluginError::InvalidManifest),
+    ]
+  );
+  // the broken ones do not keep the others from loading
+  assert!(manager.get(&String::from("valid")).is_some());
+  assert!(manager.get(&String::from("no_value")).is_none());
+}
+
+#[test]
+fn rejects_unknown_keys() {
+  let dir = plugin_dir(&["top_level", "in_table", "unknown_table"]);
+  write_manifest(dir.path(), "top_level", "nmae = \"enrich\"\n");
+  write_manifest(
+    dir.path(),
+    "in_table",
+    "[functions]\nexecutee = \"transform\"\n",
+  );
+  write_manifest(dir.path(), "unknown_table", "[limit]\nfuel = 10\n");
+
+  let manager = PluginManager::new();
+  let failed = failed(dir.path(), &manager);
+  assert_eq!(failed.len(), 3);
+  assert!(failed
+    .iter()
+    .all(|(_, error)| error == &PluginError::InvalidManifest));
+  let error =
+    PluginManifest::parse(&String::from("[functions]\nexecutee = \"transform\"\n")).unwrap_err();
+  assert!(error.contains("unknown field `executee`"), "{}", error);
+}
+
+#[test]
+fn parses_full_toml() {
+  let manifest = PluginManifest::parse(&String::from(
+    "functions.execute = \"transform\"\n\
+     syscalls = { fd_write = \"allow fds 1,2\", \"*\" = \"deny\" }\n\
+     \n\
+     [host]\n\
+     required = [\n\
+       \"tests\", # used by transform\n\
+       \"tests2\",\n\
+     ]\n\
+     \n\
+     [env]\n\
+     GREETING = \"say \\\"hi\\\"\\n\"\n\
+     PATH = 'C:\\tmp'\n",
+  ))
+  .unwrap();
+  assert_eq!(
+    manifest.execute_function_name,
+    Some(String::from("transform"))
+  );
+  assert_eq!(
+    manifest.required_host_functions,
+    vec![String::from("tests"), String::from("tests2")]
+  );
+  assert_eq!(
+    manifest.envs,
+    vec![
+      (String::from("GREETING"), String::from("say \"hi\"\n")),
+      (String::from("PATH"), String::from("C:\\tmp")),
+    ]
+  );
+  let policy = manifest.syscall_policy.unwrap();
+  assert_eq!(
+    policy.rule_for("fd_write"),
+    &SyscallRule::AllowFds(vec![1, 2])
+  );
+  assert_eq!(policy.rule_for("fd_read"), &SyscallRule::Deny);
+}
+
+#[test]
+fn keeps_syscall_rule_order() {
+  let manifest = PluginManifest::parse(&String::from(
+    "[syscalls]\nfd_read = \"deny\"\n\"fd_*\" = \"allow\"\n",
+  ))
+  .unwrap();
+  let policy = manifest.syscall_policy.unwrap();
+  assert_eq!(policy.rule_for("fd_read"), &SyscallRule::Deny);
+  assert_eq!(policy.rule_for("fd_write"), &SyscallRule::Allow);
+}
+
+#[test]
+fn rejects_limits_which_are_not_applied() {
+  let error =
+    PluginManifest::parse(&String::from("[limits]\nmemory_pages = 4294967296\n")).unwrap_err();
+  assert!(
+    error.contains("must be between 0 and 4294967295"),
+    "{}",
+    error
+  );
+  let error = PluginManifest::parse(&String::from("[limits]\nmemory_pages = -1\n")).unwrap_err();
+  assert!(
+    error.contains("must be between 0 and 4294967295"),
+    "{}",
+    error
+  );
+  let error = PluginManifest::parse(&String::from("[limits]\nfuel = 1000000\n")).unwrap_err();
+  assert!(error.contains("\"fuel\" is not supported"), "{}", error);
+}
+
+#[test]
+fn rejects_version_conflicts() {
+  let dir = plugin_dir(&["a", "b", "c"]);
+  write_manifest(dir.path(), "a", "name = \"enrich\"\nversion = \"1.0.0\"\n");
+  write_manifest(dir.path(), "b", "name = \"enrich\"\nversion = \"1.0.0\"\n");
+  write_manifest(dir.path(), "c", "name = \"enrich\"\nversion = \"2.0.0\"\n");
+
+  let manager = PluginManager::new();
+  let report = manager.load_dir(dir.path(), &discovery());
+  // files are loaded in order, the second 1.0.0 is the duplicate
+  assert_eq!(
+    report.loaded,
+    vec![String::from("enrich"), String::from("enrich")]
+  );
+  assert_eq!(
+    report.failed,
+    vec![(
+      ArtifactHeader::host().artifact_file(dir.path(), "b").into(),
+      PluginError::LoadingError
+    )]
+  );
+  let mut versions = manager.versions(&String::from("enrich"));
+  versions.sort();
+  assert_eq!(versions, vec![Version::new(1, 0, 0), Version::new(2, 0, 0)]);
+
+  // versions already registered conflict as well
+  let report = manager.load_dir(dir.path(), &discovery());
+  assert!(report.loaded.is_empty());
+  assert_eq!(report.failed.len(), 3);
+}
+
+#[test]
+fn initializes_before_register() {
+  let dir = plugin_dir(&["enrich", "broken"]);
+  // the init export of `broken` does not exist
+  write_manifest(dir.path(), "broken", "[functions]\ninit = \"missing\"\n");
+  let manager = PluginManager::new();
+  let mut discovery = discovery();
+  discovery.set_init_config(&String::from("config"));
+  let report = manager.load_dir(dir.path(), &discovery);
+  assert_eq!(report.loaded, vec![String::from("enrich")]);
+  assert_eq!(report.failed.len(), 1);
+  assert!(manager.get(&String::from("broken")).is_none());
+
+  // the checked-in guest only executes after `_start` and `init`
+  let name = String::from("enrich");
+  let key = String::from("/some/test/1");
+  let payload = String::from("{}");
+  let expected = format!("transform: {} for payload {}", key, payload);
+  assert_eq!(manager.execute(&name, &key, &payload).unwrap(), expected);
+  // spawned instances replay the init config
+  let spawned = manager.spawn(&name).unwrap().unwrap();
+  assert_eq!(spawned.execute(&key, &payload).unwrap(), expected);
+}
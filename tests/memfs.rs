@@ -0,0 +1,104 @@
+use wasmertest::plugin::memfs::MemoryFs;
+use wasmertest::plugin::PluginError;
+
+// the tar reader behind `MemoryFs::from_tar`, archives are built by hand
+
+fn header(name: &str, size: usize) -> Vec<u8> {
+  let mut header = vec![0u8; 512];
+  header[..name.len()].copy_from_slice(name.as_bytes());
+  let size = format!("{:011o}", size);
+  header[124..135].copy_from_slice(size.as_bytes());
+  header[156] = b'0';
+  header[257..262].copy_from_slice(b"ustar");
+  header
+}
+
+fn entry(name: &str, content: &[u8]) -> Vec<u8> {
+  let mut entry = header(name, content.len());
+  entry.extend_from_slice(content);
+  entry.resize(512 + content.len().div_ceil(512) * 512, 0);
+  entry
+}
+
+fn archive(entries: &[Vec<u8>]) -> Vec<u8> {
+  let mut archive: Vec<u8> = entries.concat();
+  // end of archive
+  archive.extend_from_slice(&[0u8; 1024]);
+  archive
+}
+
+fn mount() -> String {
+  String::from("/assets")
+}
+
+#[test]
+fn reads_regular_files() {
+  let tar = archive(&[
+    entry("lookup.csv", b"a,1\nb,2\n"),
+    entry("./models/weights.bin", &[7u8; 700]),
+  ]);
+  let fs = MemoryFs::from_tar(&mount(), &tar).unwrap();
+  assert_eq!(
+    fs.paths(),
+    vec![
+      String::from("lookup.csv"),
+      String::from("models/weights.bin")
+    ]
+  );
+}
+
+#[test]
+fn empty_archive() {
+  let fs = MemoryFs::from_tar(&mount(), &[]).unwrap();
+  assert!(fs.paths().is_empty());
+}
+
+#[test]
+fn rejects_truncated_header() {
+  let mut tar = entry("lookup.csv", b"a,1\n");
+  tar.extend_from_slice(&header("second.csv", 4)[..300]);
+  assert_eq!(
+    MemoryFs::from_tar(&mount(), &tar).unwrap_err(),
+    PluginError::InvalidArchive
+  );
+}
+
+#[test]
+fn rejects_entry_larger_than_archive() {
+  let mut tar = header("huge.bin", 4096);
+  tar.extend_from_slice(&[1u8; 512]);
+  assert_eq!(
+    MemoryFs::from_tar(&mount(), &tar).unwrap_err(),
+    PluginError::InvalidArchive
+  );
+
+  // the largest size the header can hold must not overflow
+  let tar = header("huge.bin", 0o77777777777);
+  assert_eq!(
+    MemoryFs::from_tar(&mount(), &tar).unwrap_err(),
+    PluginError::InvalidArchive
+  );
+}
+
+#[test]
+fn rejects_invalid_size() {
+  let mut tar = header("lookup.csv", 0);
+  tar[124..135].copy_from_slice(b"not a size ");
+  assert_eq!(
+    MemoryFs::from_tar(&mount(), &tar).unwrap_err(),
+    PluginError::InvalidArchive
+  );
+}
+
+#[test]
+fn rejects_path_traversal() {
+  for name in ["../secret", "assets/../../etc/passwd", "/../secret"] {
+    let tar = archive(&[entry(name, b"x")]);
+    assert_eq!(
+      MemoryFs::from_tar(&mount(), &tar).unwrap_err(),
+      PluginError::InvalidArchive,
+      "{}",
+      name
+    );
+  }
+}
@@ -0,0 +1,93 @@
+use std::sync::{Arc, Mutex};
+
+use wasmertest::plugin::middleware::{CallContext, Middleware, MiddlewareChain, Next};
+use wasmertest::plugin::PluginError;
+
+// stacked middlewares wrap each other in the order they were added
+
+// records when it is entered and left, `answer` returns without calling
+// the inner layers
+struct Recorder {
+  name: &'static str,
+  log: Arc<Mutex<Vec<String>>>,
+  answer: Option<String>,
+}
+
+impl Middleware for Recorder {
+  fn handle(&self, ctx: &CallContext, next: Next) -> Result<String, PluginError> {
+    self
+      .log
+      .lock()
+      .unwrap()
+      .push(format!("enter {}", self.name));
+    if let Some(answer) = &self.answer {
+      return Ok(answer.clone());
+    }
+    let result = next
+      .run(ctx)
+      .map(|result| format!("{}({})", self.name, result));
+    self
+      .log
+      .lock()
+      .unwrap()
+      .push(format!("leave {}", self.name));
+    result
+  }
+}
+
+fn chain(log: &Arc<Mutex<Vec<String>>>, answer: Option<&str>) -> MiddlewareChain {
+  let mut chain = MiddlewareChain::new();
+  chain
+    .add(Arc::new(Recorder {
+      name: "outer",
+      log: log.clone(),
+      answer: None,
+    }))
+    .add(Arc::new(Recorder {
+      name: "inner",
+      log: log.clone(),
+      answer: answer.map(String::from),
+    }));
+  chain
+}
+
+fn run(chain: &MiddlewareChain, log: &Arc<Mutex<Vec<String>>>) -> Result<String, PluginError> {
+  let ctx = CallContext::new(
+    &String::from("middleware_test"),
+    &String::from("/some/test/1"),
+    &String::from("{}"),
+  );
+  chain.run(&ctx, &|ctx| {
+    log.lock().unwrap().push(String::from("guest"));
+    Ok(ctx.key.clone())
+  })
+}
+
+#[test]
+fn runs_in_order_added() {
+  let log = Arc::new(Mutex::new(vec![]));
+  let chain = chain(&log, None);
+  assert_eq!(run(&chain, &log).unwrap(), "outer(inner(/some/test/1))");
+  assert_eq!(
+    *log.lock().unwrap(),
+    vec![
+      "enter outer",
+      "enter inner",
+      "guest",
+      "leave inner",
+      "leave outer"
+    ]
+  );
+}
+
+#[test]
+fn early_return_skips_inner_layers() {
+  let log = Arc::new(Mutex::new(vec![]));
+  let chain = chain(&log, Some("short-circuit"));
+  assert_eq!(run(&chain, &log).unwrap(), "outer(short-circuit)");
+  // neither the guest nor the rest of the inner middleware ran
+  assert_eq!(
+    *log.lock().unwrap(),
+    vec!["enter outer", "enter inner", "leave outer"]
+  );
+}
@@ -0,0 +1,119 @@
+use std::time::Duration;
+
+use tempfile::TempDir;
+use wasmertest::plugin::artifact::ArtifactHeader;
+use wasmertest::plugin::compiler::{compile_to_file, CompileOptions};
+use wasmertest::plugin::default::DefaultPlugin;
+use wasmertest::plugin::gate::PauseMode;
+use wasmertest::plugin::manager::PluginManager;
+use wasmertest::plugin::tenant::TenantQuota;
+use wasmertest::plugin::{Plugin, PluginError, PluginOptions};
+
+// every way to execute a plugin of a manager is held back while it is paused
+
+fn tests(i: i32) -> i32 {
+  i + 1
+}
+
+fn tests2(i: i64) -> i64 {
+  i + 2
+}
+
+fn compiled() -> (TempDir, String) {
+  let dir = tempfile::tempdir().unwrap();
+  let artifact = ArtifactHeader::host().artifact_file(dir.path(), "plugin");
+  compile_to_file(
+    &String::from("./assemblytest/build/optimized.wat"),
+    &artifact,
+    &CompileOptions::new(),
+  )
+  .unwrap();
+  (dir, artifact)
+}
+
+fn plugin(artifact: &String) -> DefaultPlugin {
+  let mut options = PluginOptions::new(
+    &String::from("pause_test"),
+    artifact,
+    &String::from("transform"),
+  );
+  options.add_host_function("tests".into(), tests);
+  options.add_host_function("tests2".into(), tests2);
+  let plugin = DefaultPlugin::create(options).unwrap();
+  plugin.init(&String::from("config")).unwrap();
+  plugin
+}
+
+#[test]
+fn pause_holds_back_all_entry_points() {
+  let (_dir, artifact) = compiled();
+  let name = String::from("enrich");
+  let tenant = String::from("acme");
+  let key = String::from("/some/test/1");
+  let payload = String::from("{}");
+  let expected = format!("transform: {} for payload {}", key, payload);
+
+  let manager = PluginManager::new();
+  manager.register(&name, plugin(&artifact));
+  manager.add_tenant(&tenant, TenantQuota::new());
+  manager
+    .register_for_tenant(&tenant, &name, plugin(&artifact))
+    .unwrap();
+  // handles taken before the pause are held back as well
+  let handle = manager.get(&name).unwrap();
+
+  manager
+    .pause(&name, PauseMode::Reject, Duration::from_secs(1))
+    .unwrap();
+  assert_eq!(
+    manager.execute(&name, &key, &payload),
+    Err(PluginError::PluginPaused)
+  );
+  assert_eq!(
+    manager.execute_as(&tenant, &name, &key, &payload),
+    Err(PluginError::PluginPaused)
+  );
+  assert_eq!(
+    manager.execute_for_tenant(&tenant, &name, &key, &payload),
+    Err(PluginError::PluginPaused)
+  );
+  assert_eq!(
+    handle.execute(&key, &payload),
+    Err(PluginError::PluginPaused)
+  );
+  assert_eq!(
+    manager.get(&name).unwrap().execute(&key, &payload),
+    Err(PluginError::PluginPaused)
+  );
+
+  manager.resume(&name);
+  assert_eq!(handle.execute(&key, &payload).unwrap(), expected);
+  assert_eq!(
+    manager
+      .execute_for_tenant(&tenant, &name, &key, &payload)
+      .unwrap(),
+    expected
+  );
+}
+
+#[test]
+fn unknown_plugins_are_not_found() {
+  let manager = PluginManager::new();
+  let name = String::from("unknown");
+  let key = String::from("/some/test/1");
+  let payload = String::from("{}");
+  assert_eq!(
+    manager.pause(&name, PauseMode::Reject, Duration::from_secs(1)),
+    Err(PluginError::PluginNotFound)
+  );
+  assert!(!manager.is_paused(&name));
+  assert_eq!(
+    manager.execute(&name, &key, &payload),
+    Err(PluginError::PluginNotFound)
+  );
+  assert_eq!(
+    manager.execute_as(&String::from("caller"), &name, &key, &payload),
+    Err(PluginError::PluginNotFound)
+  );
+  assert!(manager.get(&name).is_none());
+}
@@ -0,0 +1,85 @@
+use std::fs;
+
+use tempfile::TempDir;
+use wasmertest::plugin::artifact::ArtifactHeader;
+use wasmertest::plugin::compiler::{compile_to_file, CompileOptions};
+use wasmertest::plugin::default::DefaultPlugin;
+use wasmertest::plugin::string_abi::StringAbi;
+use wasmertest::plugin::{Plugin, PluginError, PluginOptions};
+
+// strings allocated for execute are pinned until the call returned, they have
+// to be unpinned again when a later allocation fails
+
+// bump allocator which traps on blocks over 1 KiB and counts the pinned
+// strings, the guest returns the key as its result
+const GUEST: &str = r#"
+(module
+  (memory (export "memory") 1)
+  (global $next (mut i32) (i32.const 1024))
+  (global $pinned (mut i32) (i32.const 0))
+  (func (export "__new") (param $size i32) (param $id i32) (result i32)
+    (local $ptr i32)
+    (if (i32.gt_u (local.get $size) (i32.const 1024)) (then unreachable))
+    (local.set $ptr (i32.add (global.get $next) (i32.const 4)))
+    (i32.store (i32.sub (local.get $ptr) (i32.const 4)) (local.get $size))
+    (global.set $next
+      (i32.and
+        (i32.add (i32.add (local.get $ptr) (local.get $size)) (i32.const 7))
+        (i32.const -8)))
+    (local.get $ptr))
+  (func (export "__pin") (param $ptr i32) (result i32)
+    (global.set $pinned (i32.add (global.get $pinned) (i32.const 1)))
+    (local.get $ptr))
+  (func (export "__unpin") (param i32)
+    (global.set $pinned (i32.sub (global.get $pinned) (i32.const 1))))
+  (func (export "pinned") (result i32) (global.get $pinned))
+  (func (export "transform") (param i32 i32) (result i32) (local.get 0)))
+"#;
+
+// the temp dir has to outlive the plugin
+fn create() -> (TempDir, DefaultPlugin) {
+  let dir = tempfile::tempdir().unwrap();
+  let source = dir.path().join("guest.wat").to_string_lossy().to_string();
+  fs::write(&source, GUEST).unwrap();
+  let artifact = ArtifactHeader::host().artifact_file(dir.path(), "plugin");
+  compile_to_file(&source, &artifact, &CompileOptions::new()).unwrap();
+
+  let mut options = PluginOptions::new(
+    &String::from("pinning_test"),
+    &artifact,
+    &String::from("transform"),
+  );
+  options
+    .set_string_abi(StringAbi::Utf16String)
+    .disable_garbage_collector();
+  (dir, DefaultPlugin::create(options).unwrap())
+}
+
+fn pinned(plugin: &DefaultPlugin) -> i32 {
+  let pinned = plugin
+    .get_instance()
+    .exports
+    .get_native_function::<(), i32>("pinned")
+    .unwrap();
+  pinned.call().unwrap()
+}
+
+#[test]
+fn unpins_after_execute() {
+  let (_dir, plugin) = create();
+  let key = String::from("/some/test/1");
+  assert_eq!(plugin.execute(&key, &String::from("{}")).unwrap(), key);
+  assert_eq!(pinned(&plugin), 0);
+}
+
+#[test]
+fn unpins_key_when_payload_allocation_fails() {
+  let (_dir, plugin) = create();
+  // utf-16, the payload needs 2 KiB
+  let payload = "x".repeat(1024);
+  assert_eq!(
+    plugin.execute(&String::from("/some/test/1"), &payload),
+    Err(PluginError::RuntimeError)
+  );
+  assert_eq!(pinned(&plugin), 0);
+}
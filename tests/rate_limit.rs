@@ -0,0 +1,60 @@
+use std::thread;
+use std::time::Duration;
+
+use wasmertest::plugin::rate_limit::{RateLimit, RateLimiter};
+use wasmertest::plugin::PluginError;
+
+// buckets of callers are dropped again, so many short lived callers do not
+// grow the limiter without bound
+
+#[test]
+fn refilled_buckets_are_dropped() {
+  let limiter = RateLimiter::with_max_callers(RateLimit::new(1000.0, 1).unwrap(), 10);
+  for x in 0..10 {
+    limiter.acquire(&format!("caller-{}", x)).unwrap();
+  }
+  assert_eq!(limiter.callers(), 10);
+
+  // all buckets are full again after 1ms, the next new caller sweeps them
+  thread::sleep(Duration::from_millis(20));
+  limiter.acquire(&String::from("next")).unwrap();
+  assert_eq!(limiter.callers(), 1);
+}
+
+#[test]
+fn least_recently_used_bucket_is_dropped_over_the_cap() {
+  // no refill during the test, every caller has one call
+  let limiter = RateLimiter::with_max_callers(RateLimit::new(0.001, 1).unwrap(), 2);
+  let first = String::from("first");
+  let second = String::from("second");
+  limiter.acquire(&first).unwrap();
+  thread::sleep(Duration::from_millis(2));
+  limiter.acquire(&second).unwrap();
+  thread::sleep(Duration::from_millis(2));
+
+  limiter.acquire(&String::from("third")).unwrap();
+  assert_eq!(limiter.callers(), 2);
+  // `first` was used least recently, it got dropped and starts over
+  assert!(limiter.acquire(&second).is_err());
+  limiter.acquire(&first).unwrap();
+}
+
+#[test]
+fn rejects_rates_which_are_not_positive() {
+  for rate in [0.0, -1.0, f64::NAN, f64::INFINITY] {
+    assert_eq!(RateLimit::new(rate, 1), Err(PluginError::InvalidRateLimit));
+  }
+}
+
+#[test]
+fn tiny_rates_do_not_overflow() {
+  let limiter = RateLimiter::new(RateLimit::new(f64::MIN_POSITIVE, 1).unwrap());
+  let caller = String::from("caller");
+  limiter.acquire(&caller).unwrap();
+  assert_eq!(
+    limiter.acquire(&caller),
+    Err(PluginError::RateLimited {
+      retry_after: Duration::MAX
+    })
+  );
+}
@@ -0,0 +1,86 @@
+use tempfile::TempDir;
+use wasmertest::plugin::artifact::ArtifactHeader;
+use wasmertest::plugin::compiler::{compile_to_file, CompileOptions};
+use wasmertest::plugin::default::DefaultPlugin;
+use wasmertest::plugin::manager::PluginManager;
+use wasmertest::plugin::template::WarmTemplate;
+use wasmertest::plugin::PluginOptions;
+
+// instances spawned from a warm template of the checked-in AssemblyScript
+// guest, they skip `_start` and must still allocate on the copied heap
+
+fn tests(i: i32) -> i32 {
+  i + 1
+}
+
+fn tests2(i: i64) -> i64 {
+  i + 2
+}
+
+// the temp dir has to outlive the plugins
+fn options() -> (TempDir, PluginOptions) {
+  let dir = tempfile::tempdir().unwrap();
+  let artifact = ArtifactHeader::host().artifact_file(dir.path(), "plugin");
+  compile_to_file(
+    &String::from("./assemblytest/build/optimized.wat"),
+    &artifact,
+    CompileOptions::new().enable_state_globals(),
+  )
+  .unwrap();
+  let mut options = PluginOptions::new(
+    &String::from("template_test"),
+    &artifact,
+    &String::from("transform"),
+  );
+  options.add_host_function("tests".into(), tests);
+  options.add_host_function("tests2".into(), tests2);
+  (dir, options)
+}
+
+fn assert_allocates(plugin: &DefaultPlugin) {
+  for x in 0..200 {
+    let key = format!("/some/test/{}", x);
+    let payload = format!("{{\"values\": \"{}\" }}", "x".repeat(x * 13));
+    assert_eq!(
+      plugin.execute(&key, &payload).unwrap(),
+      format!("transform: {} for payload {}", key, payload)
+    );
+  }
+}
+
+#[test]
+fn spawned_instances_allocate() {
+  let (_dir, options) = options();
+  let template = WarmTemplate::prepare(options, &String::from("config")).unwrap();
+  let first = template.spawn().unwrap();
+  let second = template.spawn().unwrap();
+  assert_allocates(&first);
+  assert_allocates(&second);
+  assert_allocates(&first);
+}
+
+#[test]
+fn spawn_all_instances_allocate() {
+  let (_dir, options) = options();
+  let template = WarmTemplate::prepare(options, &String::from("config")).unwrap();
+  for plugin in template.spawn_all(4, 2) {
+    assert_allocates(&plugin.unwrap());
+  }
+}
+
+#[test]
+fn manager_spawns_from_warm_template() {
+  let (_dir, options) = options();
+  let name = String::from("template_test");
+  let manager = PluginManager::new();
+  manager
+    .register_warm(&name, options, &String::from("config"))
+    .unwrap();
+  let plugin = manager.spawn(&name).unwrap().unwrap();
+  let key = String::from("/some/test/1");
+  let payload = "x".repeat(4096);
+  assert_eq!(
+    plugin.execute(&key, &payload).unwrap(),
+    format!("transform: {} for payload {}", key, payload)
+  );
+}
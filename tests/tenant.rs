@@ -0,0 +1,180 @@
+use std::thread;
+use std::time::Duration;
+
+use tempfile::TempDir;
+use wasmertest::plugin::artifact::ArtifactHeader;
+use wasmertest::plugin::compiler::{compile_to_file, CompileOptions};
+use wasmertest::plugin::default::DefaultPlugin;
+use wasmertest::plugin::manager::PluginManager;
+use wasmertest::plugin::rate_limit::RateLimit;
+use wasmertest::plugin::tenant::TenantQuota;
+use wasmertest::plugin::{Plugin, PluginError, PluginOptions};
+
+// quotas of tenant namespaces with the checked-in AssemblyScript guest
+
+fn tests(i: i32) -> i32 {
+  i + 1
+}
+
+fn tests2(i: i64) -> i64 {
+  i + 2
+}
+
+fn compiled() -> (TempDir, String) {
+  let dir = tempfile::tempdir().unwrap();
+  let artifact = ArtifactHeader::host().artifact_file(dir.path(), "plugin");
+  compile_to_file(
+    &String::from("./assemblytest/build/optimized.wat"),
+    &artifact,
+    &CompileOptions::new(),
+  )
+  .unwrap();
+  (dir, artifact)
+}
+
+fn plugin(artifact: &String) -> DefaultPlugin {
+  let mut options = PluginOptions::new(
+    &String::from("tenant_test"),
+    artifact,
+    &String::from("transform"),
+  );
+  options.add_host_function("tests".into(), tests);
+  options.add_host_function("tests2".into(), tests2);
+  let plugin = DefaultPlugin::create(options).unwrap();
+  plugin.init(&String::from("config")).unwrap();
+  plugin
+}
+
+// manager with the plugin "enrich" registered for tenant "acme"
+fn manager(artifact: &String, quota: TenantQuota) -> PluginManager {
+  let manager = PluginManager::new();
+  manager.add_tenant(&String::from("acme"), quota);
+  manager
+    .register_for_tenant(
+      &String::from("acme"),
+      &String::from("enrich"),
+      plugin(artifact),
+    )
+    .unwrap();
+  manager
+}
+
+fn execute(manager: &PluginManager) -> Result<String, PluginError> {
+  manager.execute_for_tenant(
+    &String::from("acme"),
+    &String::from("enrich"),
+    &String::from("/some/test/1"),
+    &String::from("{}"),
+  )
+}
+
+#[test]
+fn max_plugins_counts_names() {
+  let (_dir, artifact) = compiled();
+  let tenant = String::from("acme");
+  let mut quota = TenantQuota::new();
+  quota.set_max_plugins(1);
+  let manager = manager(&artifact, quota);
+
+  // another version of a registered name does not take a slot
+  manager
+    .register_for_tenant(&tenant, &String::from("enrich"), plugin(&artifact))
+    .unwrap();
+  assert_eq!(
+    manager.register_for_tenant(&tenant, &String::from("other"), plugin(&artifact)),
+    Err(PluginError::TenantQuotaExceeded)
+  );
+  assert_eq!(
+    manager.tenant(&tenant).unwrap().plugins().names(),
+    vec![String::from("enrich")]
+  );
+}
+
+#[test]
+fn concurrent_registrations_respect_max_plugins() {
+  let (_dir, artifact) = compiled();
+  let tenant = String::from("acme");
+  let mut quota = TenantQuota::new();
+  quota.set_max_plugins(2);
+  let manager = PluginManager::new();
+  manager.add_tenant(&tenant, quota);
+
+  let plugins: Vec<DefaultPlugin> = (0..8).map(|_| plugin(&artifact)).collect();
+  let registered = thread::scope(|scope| {
+    let handles: Vec<_> = plugins
+      .into_iter()
+      .enumerate()
+      .map(|(x, plugin)| {
+        let manager = &manager;
+        let tenant = &tenant;
+        scope.spawn(move || manager.register_for_tenant(tenant, &format!("plugin-{}", x), plugin))
+      })
+      .collect();
+    handles
+      .into_iter()
+      .map(|handle| handle.join().unwrap())
+      .filter(|result| result.is_ok())
+      .count()
+  });
+  assert_eq!(registered, 2);
+  assert_eq!(manager.tenant(&tenant).unwrap().plugins().names().len(), 2);
+}
+
+#[test]
+fn max_calls_per_period() {
+  let (_dir, artifact) = compiled();
+  let mut quota = TenantQuota::new();
+  quota
+    .set_max_calls(2)
+    .set_period(Duration::from_millis(200));
+  let manager = manager(&artifact, quota);
+
+  execute(&manager).unwrap();
+  execute(&manager).unwrap();
+  assert_eq!(execute(&manager), Err(PluginError::TenantQuotaExceeded));
+
+  // the next period starts over
+  thread::sleep(Duration::from_millis(250));
+  execute(&manager).unwrap();
+}
+
+#[test]
+fn max_execution_time_per_period() {
+  let (_dir, artifact) = compiled();
+  let mut quota = TenantQuota::new();
+  quota.set_max_execution_time(Duration::from_nanos(1));
+  let manager = manager(&artifact, quota);
+
+  // the running call is not interrupted, the next one is rejected
+  execute(&manager).unwrap();
+  assert_eq!(execute(&manager), Err(PluginError::TenantQuotaExceeded));
+}
+
+#[test]
+fn rate_limit_of_the_tenant() {
+  let (_dir, artifact) = compiled();
+  let mut quota = TenantQuota::new();
+  quota.set_rate_limit(RateLimit::new(0.001, 1).unwrap());
+  let manager = manager(&artifact, quota);
+
+  execute(&manager).unwrap();
+  assert!(matches!(
+    execute(&manager),
+    Err(PluginError::RateLimited { .. })
+  ));
+}
+
+#[test]
+fn unknown_tenant_is_not_found() {
+  let (_dir, artifact) = compiled();
+  let manager = PluginManager::new();
+  assert_eq!(
+    manager.register_for_tenant(
+      &String::from("acme"),
+      &String::from("enrich"),
+      plugin(&artifact)
+    ),
+    Err(PluginError::TenantNotFound)
+  );
+  assert_eq!(execute(&manager), Err(PluginError::TenantNotFound));
+}
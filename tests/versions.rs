@@ -0,0 +1,105 @@
+use semver::Version;
+use tempfile::TempDir;
+use wasmertest::plugin::artifact::ArtifactHeader;
+use wasmertest::plugin::compiler::{compile_to_file, CompileOptions};
+use wasmertest::plugin::default::DefaultPlugin;
+use wasmertest::plugin::manager::PluginManager;
+use wasmertest::plugin::manifest::PluginManifest;
+use wasmertest::plugin::{Plugin, PluginError, PluginOptions};
+
+// promote, rollback and removal of the versions of one plugin name
+
+fn tests(i: i32) -> i32 {
+  i + 1
+}
+
+fn tests2(i: i64) -> i64 {
+  i + 2
+}
+
+fn compiled() -> (TempDir, String) {
+  let dir = tempfile::tempdir().unwrap();
+  let artifact = ArtifactHeader::host().artifact_file(dir.path(), "plugin");
+  compile_to_file(
+    &String::from("./assemblytest/build/optimized.wat"),
+    &artifact,
+    &CompileOptions::new(),
+  )
+  .unwrap();
+  (dir, artifact)
+}
+
+fn plugin(artifact: &String, version: &str) -> DefaultPlugin {
+  let manifest = PluginManifest::parse(&format!("version = \"{}\"\n", version)).unwrap();
+  let mut options = PluginOptions::new(
+    &String::from("versions_test"),
+    artifact,
+    &String::from("transform"),
+  );
+  options.add_host_function("tests".into(), tests);
+  options.add_host_function("tests2".into(), tests2);
+  options.apply_manifest(&manifest);
+  DefaultPlugin::create(options).unwrap()
+}
+
+// "enrich" with 1.0.0 active and 2.0.0 registered
+fn manager(artifact: &String) -> PluginManager {
+  let name = String::from("enrich");
+  let manager = PluginManager::new();
+  manager.register(&name, plugin(artifact, "1.0.0"));
+  manager.register(&name, plugin(artifact, "2.0.0"));
+  manager
+}
+
+#[test]
+fn rollback_without_previous_version() {
+  let (_dir, artifact) = compiled();
+  let name = String::from("enrich");
+  let manager = manager(&artifact);
+  assert_eq!(manager.rollback(&name), Err(PluginError::VersionNotFound));
+  assert_eq!(
+    manager.rollback(&String::from("unknown")),
+    Err(PluginError::PluginNotFound)
+  );
+
+  manager.promote(&name, &Version::new(2, 0, 0)).unwrap();
+  assert_eq!(manager.rollback(&name), Ok(Version::new(1, 0, 0)));
+  assert_eq!(manager.active_version(&name), Some(Version::new(1, 0, 0)));
+  // the history is used up
+  assert_eq!(manager.rollback(&name), Err(PluginError::VersionNotFound));
+}
+
+#[test]
+fn rollback_skips_removed_versions() {
+  let (_dir, artifact) = compiled();
+  let name = String::from("enrich");
+  let manager = manager(&artifact);
+  manager.register(&name, plugin(&artifact, "3.0.0"));
+  manager.promote(&name, &Version::new(2, 0, 0)).unwrap();
+  manager.promote(&name, &Version::new(3, 0, 0)).unwrap();
+  manager
+    .remove_version(&name, &Version::new(2, 0, 0))
+    .unwrap();
+  assert_eq!(manager.rollback(&name), Ok(Version::new(1, 0, 0)));
+}
+
+#[test]
+fn active_version_can_not_be_removed() {
+  let (_dir, artifact) = compiled();
+  let name = String::from("enrich");
+  let manager = manager(&artifact);
+  assert_eq!(
+    manager.remove_version(&name, &Version::new(1, 0, 0)),
+    Err(PluginError::VersionActive)
+  );
+  assert_eq!(manager.active_version(&name), Some(Version::new(1, 0, 0)));
+
+  manager
+    .remove_version(&name, &Version::new(2, 0, 0))
+    .unwrap();
+  assert_eq!(
+    manager.remove_version(&name, &Version::new(2, 0, 0)),
+    Err(PluginError::VersionNotFound)
+  );
+  assert_eq!(manager.versions(&name), vec![Version::new(1, 0, 0)]);
+}
@@ -0,0 +1,104 @@
+use std::fs;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use tempfile::TempDir;
+use wasmertest::plugin::artifact::ArtifactHeader;
+use wasmertest::plugin::compiler::{compile_to_file, CompileOptions};
+use wasmertest::plugin::default::DefaultPlugin;
+use wasmertest::plugin::events::{EventKind, GuestEvent};
+use wasmertest::plugin::manager::PluginManager;
+use wasmertest::plugin::string_abi::StringAbi;
+use wasmertest::plugin::watchdog::{check, WatchdogConfig};
+use wasmertest::plugin::{Plugin, PluginOptions};
+
+// the watchdog replaces instances stuck in a call by fresh ones
+
+// returns the key, keys starting with "l" loop forever
+const GUEST: &str = r#"
+(module
+  (memory (export "memory") 1)
+  (global $next (mut i32) (i32.const 1024))
+  (func (export "__new") (param $size i32) (param $id i32) (result i32)
+    (local $ptr i32)
+    (local.set $ptr (i32.add (global.get $next) (i32.const 4)))
+    (i32.store (i32.sub (local.get $ptr) (i32.const 4)) (local.get $size))
+    (global.set $next
+      (i32.and
+        (i32.add (i32.add (local.get $ptr) (local.get $size)) (i32.const 7))
+        (i32.const -8)))
+    (local.get $ptr))
+  (func (export "_start"))
+  (func (export "init") (param i32))
+  (func (export "transform") (param $key i32) (param i32) (result i32)
+    (if (i32.eq (i32.load16_u (local.get $key)) (i32.const 108))
+      (then (loop $forever (br $forever))))
+    (local.get $key)))
+"#;
+
+// the temp dir has to outlive the plugin
+fn create() -> (TempDir, PluginOptions) {
+  let dir = tempfile::tempdir().unwrap();
+  let source = dir.path().join("guest.wat").to_string_lossy().to_string();
+  fs::write(&source, GUEST).unwrap();
+  let artifact = ArtifactHeader::host().artifact_file(dir.path(), "plugin");
+  compile_to_file(&source, &artifact, &CompileOptions::new()).unwrap();
+
+  let mut options = PluginOptions::new(
+    &String::from("watchdog_test"),
+    &artifact,
+    &String::from("transform"),
+  );
+  options
+    .set_string_abi(StringAbi::Utf16String)
+    .disable_garbage_collector();
+  (dir, options)
+}
+
+#[test]
+fn restarts_wedged_instance() {
+  let (_dir, options) = create();
+  let events = options.subscribe(EventKind::Lifecycle);
+  let name = String::from("loop");
+  let payload = String::from("{}");
+  let manager = Arc::new(PluginManager::new());
+  manager.register(&name, DefaultPlugin::create(options).unwrap());
+  // initialized through the manager, so the restart replays the config
+  manager
+    .get(&name)
+    .unwrap()
+    .init(&String::from("config"))
+    .unwrap();
+
+  // the wedged call keeps its thread, it is abandoned together with the instance
+  let wedged = manager.clone();
+  let call_name = name.clone();
+  let call_payload = payload.clone();
+  thread::spawn(move || wedged.execute(&call_name, &String::from("loop"), &call_payload));
+
+  let config = WatchdogConfig {
+    interval: Duration::from_millis(5),
+    max_call_duration: Some(Duration::from_millis(20)),
+    max_memory_pages: None,
+  };
+  let restarts = loop {
+    let restarts = check(&manager, &config);
+    if !restarts.is_empty() {
+      break restarts;
+    }
+    thread::sleep(config.interval);
+  };
+  assert_eq!(restarts.len(), 1);
+  assert_eq!(restarts[0].name, name);
+  assert!(restarts[0].reason.starts_with("call running for"));
+
+  // new calls reach the replacement instead of waiting for the wedged one
+  let key = String::from("/some/test/1");
+  assert_eq!(manager.execute(&name, &key, &payload).unwrap(), key);
+  assert!(check(&manager, &config).is_empty());
+  assert!(events.try_iter().any(|event| matches!(
+    event,
+    GuestEvent::PluginRestarted { reason, .. } if reason == restarts[0].reason
+  )));
+}